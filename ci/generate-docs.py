@@ -174,6 +174,35 @@ local wezterm = require 'wezterm';
 
 at the top of your configuration file to enable it.
 
+## Available functions, constants
+""",
+                    ),
+                    Gen(
+                        "module: wezterm.mux",
+                        "config/lua/wezterm.mux",
+                        index="""
+# `wezterm.mux` module
+
+*Since: nightly builds only*
+
+The `wezterm.mux` module exposes functions that operate on the multiplexer layer.
+
+The multiplexer manages the set of running programs into panes, tabs and
+windows.
+
+The multiplexer may not be connected to a GUI so certain operations that require
+a running Window management system are not present in the interface exposed
+by this module.
+
+You will typically use something like:
+
+```lua
+local wezterm = require 'wezterm'
+local mux = wezterm.mux
+```
+
+at the top of your configuration file to access it.
+
 ## Available functions, constants
 """,
                     ),
@@ -239,6 +268,40 @@ introspect the state of the terminal emulation for that pane.
 
 ## Available methods
 
+""",
+                    ),
+                    Gen(
+                        "object: MuxWindow",
+                        "config/lua/MuxWindow",
+                        index="""
+# `MuxWindow` object
+
+*Since: nightly builds only*
+
+`MuxWindow` represents a window that is managed by the multiplexer.
+
+It has a number of methods that can be used to inspect and manipulate
+the window, its tabs and panes.
+
+## Available methods
+
+""",
+                    ),
+                    Gen(
+                        "object: MuxTab",
+                        "config/lua/MuxTab",
+                        index="""
+# `MuxTab` object
+
+*Since: nightly builds only*
+
+`MuxTab` represents a tab that is managed by the multiplexer.
+
+It has a number of methods that can be used to inspect and manipulate
+the tab and its panes.
+
+## Available methods
+
 """,
                     ),
                     Gen(
@@ -253,6 +316,15 @@ TermWindow running in the wezterm process.
 
 ## Available methods
 
+""",
+                    ),
+                    Gen(
+                        "events: Gui",
+                        "config/lua/gui-events",
+                        index="""
+# Events emitted by the GUI
+
+The following events can be handled using [wezterm.on](../wezterm/on.md):
 """,
                     ),
                     Gen(
@@ -12,11 +12,14 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
 
 use anyhow::{bail, Context as _, Error};
+use config::keyassignment::PaneDirection;
 use leb128;
 use mux::domain::DomainId;
 use mux::pane::PaneId;
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
+use mux::session::SessionSnapshot;
 use mux::tab::{PaneNode, SerdeUrl, SplitDirection, TabId};
+use mux::transfer::TransferDirection;
 use mux::window::WindowId;
 use portable_pty::{CommandBuilder, PtySize};
 use rangeset::*;
@@ -265,17 +268,75 @@ pub struct DecodedPdu {
 /// If the serialized size is larger than this, then we'll consider compressing it
 const COMPRESS_THRESH: usize = 32;
 
-fn serialize<T: serde::Serialize>(t: &T) -> Result<(Vec<u8>, bool), Error> {
+/// The range of zstd compression levels that we're prepared to use.
+/// Levels above 19 use a lot more memory for little gain.
+const MIN_ZSTD_LEVEL: i32 = 1;
+const MAX_ZSTD_LEVEL: i32 = 19;
+
+/// Describes how the sender of a PDU compresses it.
+/// The receiver doesn't need to know this in order to decode a PDU,
+/// as each frame indicates whether it is compressed, but a peer
+/// can negotiate the scheme that will be used for the frames that
+/// are sent to it via SetCompression.
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Compression {
+    /// Never compress
+    None,
+    /// Compress PDUs larger than COMPRESS_THRESH using zstd
+    /// at the specified level
+    Zstd { level: i32 },
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::Zstd {
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+impl Compression {
+    /// Returns the scheme requested by the configuration
+    pub fn from_config(config: &config::ConfigHandle) -> Self {
+        match config.mux_compression {
+            config::MuxCompression::None => Self::None,
+            config::MuxCompression::Zstd => Self::Zstd {
+                level: config.mux_compression_level,
+            },
+        }
+    }
+
+    /// Returns the scheme that we will actually use when asked to
+    /// use self; out of range levels are clamped to what we support.
+    pub fn normalize(self) -> Self {
+        match self {
+            Self::None => Self::None,
+            Self::Zstd { level } => Self::Zstd {
+                level: level.max(MIN_ZSTD_LEVEL).min(MAX_ZSTD_LEVEL),
+            },
+        }
+    }
+}
+
+fn serialize<T: serde::Serialize>(
+    t: &T,
+    compression: Compression,
+) -> Result<(Vec<u8>, bool), Error> {
     let mut uncompressed = Vec::new();
     let mut encode = varbincode::Serializer::new(&mut uncompressed);
     t.serialize(&mut encode)?;
 
+    let level = match compression {
+        Compression::None => return Ok((uncompressed, false)),
+        Compression::Zstd { level } => level,
+    };
+
     if uncompressed.len() <= COMPRESS_THRESH {
         return Ok((uncompressed, false));
     }
     // It's a little heavy; let's try compressing it
     let mut compressed = Vec::new();
-    let mut compress = zstd::Encoder::new(&mut compressed, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+    let mut compress = zstd::Encoder::new(&mut compressed, level)?;
     let mut encode = varbincode::Serializer::new(&mut compress);
     t.serialize(&mut encode)?;
     drop(encode);
@@ -324,7 +385,7 @@ macro_rules! pdu {
                     Pdu::Invalid{..} => bail!("attempted to serialize Pdu::Invalid"),
                     $(
                         Pdu::$name(s) => {
-                            let (data, is_compressed) = serialize(s)?;
+                            let (data, is_compressed) = serialize(s, Compression::default())?;
                             let encoded_size = encode_raw($vers, serial, &data, is_compressed, w)?;
                             metrics::histogram!("pdu.size", encoded_size as f64, "pdu" => stringify!($name));
                             Ok(())
@@ -334,11 +395,20 @@ macro_rules! pdu {
             }
 
             pub async fn encode_async<W: Unpin + AsyncWriteExt>(&self, w: &mut W, serial: u64) -> Result<(), Error> {
+                self.encode_async_with_compression(w, serial, Compression::default()).await
+            }
+
+            pub async fn encode_async_with_compression<W: Unpin + AsyncWriteExt>(
+                &self,
+                w: &mut W,
+                serial: u64,
+                compression: Compression,
+            ) -> Result<(), Error> {
                 match self {
                     Pdu::Invalid{..} => bail!("attempted to serialize Pdu::Invalid"),
                     $(
                         Pdu::$name(s) => {
-                            let (data, is_compressed) = serialize(s)?;
+                            let (data, is_compressed) = serialize(s, compression)?;
                             let encoded_size = encode_raw_async($vers, serial, &data, is_compressed, w).await?;
                             metrics::histogram!("pdu.size", encoded_size as f64, "pdu" => stringify!($name));
                             Ok(())
@@ -401,7 +471,7 @@ macro_rules! pdu {
 /// The overall version of the codec.
 /// This must be bumped when backwards incompatible changes
 /// are made to the types and protocol.
-pub const CODEC_VERSION: usize = 10;
+pub const CODEC_VERSION: usize = 19;
 
 // Defines the Pdu enum.
 // Each struct has an explicit identifying number.
@@ -440,6 +510,20 @@ pdu! {
     PaneRemoved: 37,
     SetPalette: 38,
     NotifyAlert: 39,
+    SetFocusedPane: 40,
+    ActivatePaneDirection: 41,
+    AdjustPaneSize: 42,
+    GetPaneRenderableDimensions: 43,
+    GetPaneRenderableDimensionsResponse: 44,
+    GetSessionSnapshot: 45,
+    GetSessionSnapshotResponse: 46,
+    RestoreSession: 47,
+    TransferFiles: 48,
+    TransferFilesResponse: 49,
+    SetCompression: 50,
+    SetCompressionResponse: 51,
+    AckPaneRenderChanges: 52,
+    SendKeyUp: 53,
 }
 
 impl Pdu {
@@ -538,6 +622,20 @@ pub struct GetCodecVersionResponse {
     pub version_string: String,
 }
 
+/// Asks the peer to use the specified compression for the PDUs
+/// that it sends to us from now on
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetCompression {
+    pub compression: Compression,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetCompressionResponse {
+    /// The compression that the peer will actually use,
+    /// which is also what we should use when sending to it
+    pub compression: Compression,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct Ping {}
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
@@ -572,6 +670,8 @@ pub struct Spawn {
     pub window_id: Option<WindowId>,
     pub command: Option<CommandBuilder>,
     pub command_dir: Option<String>,
+    /// The workspace to use when creating a new window
+    pub workspace: String,
     pub size: PtySize,
 }
 
@@ -591,6 +691,8 @@ pub struct SpawnV2 {
     pub window_id: Option<WindowId>,
     pub command: Option<CommandBuilder>,
     pub command_dir: Option<String>,
+    /// The workspace to use when creating a new window
+    pub workspace: String,
     pub size: PtySize,
 }
 
@@ -631,6 +733,12 @@ pub struct SendKeyDown {
     pub input_serial: InputSerial,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SendKeyUp {
+    pub pane_id: PaneId,
+    pub event: termwiz::input::KeyEvent,
+}
+
 /// InputSerial is used to sequence input requests with output events.
 /// It started life as a monotonic sequence number but evolved into
 /// the number of milliseconds since the unix epoch.
@@ -704,9 +812,81 @@ pub struct SetPaneZoomed {
     pub zoomed: bool,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct SetFocusedPane {
+    pub pane_id: PaneId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct ActivatePaneDirection {
+    pub pane_id: PaneId,
+    pub direction: PaneDirection,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct AdjustPaneSize {
+    pub pane_id: PaneId,
+    pub direction: PaneDirection,
+    pub amount: usize,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetPaneRenderableDimensions {
+    pub pane_id: PaneId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetPaneRenderableDimensionsResponse {
+    pub pane_id: PaneId,
+    pub cursor_position: StableCursorPosition,
+    pub dimensions: RenderableDimensions,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetSessionSnapshot {}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetSessionSnapshotResponse {
+    pub snapshot: SessionSnapshot,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct RestoreSession {
+    pub snapshot: SessionSnapshot,
+}
+
+/// Copy files between the local machine and the remote host of
+/// an ssh domain pane, over the ssh session of that pane
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct TransferFiles {
+    pub pane_id: PaneId,
+    pub direction: TransferDirection,
+    pub paths: Vec<String>,
+    pub destination: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct TransferFilesResponse {
+    /// The paths that were created in the destination
+    pub paths: Vec<String>,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct GetPaneRenderChanges {
     pub pane_id: PaneId,
+    /// The seqno of the most recent changes that the client has
+    /// applied.  After a reconnect this allows the server to send
+    /// just the lines that changed while the client was away.
+    pub seqno: SequenceNo,
+}
+
+/// Informs the server that the client has applied the render
+/// changes for a pane up to and including seqno.  The server
+/// uses this to limit how far ahead of the client it gets.
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct AckPaneRenderChanges {
+    pub pane_id: PaneId,
+    pub seqno: SequenceNo,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
@@ -719,6 +899,7 @@ pub struct LivenessResponse {
 pub struct GetPaneRenderChangesResponse {
     pub pane_id: PaneId,
     pub mouse_grabbed: bool,
+    pub wants_key_up_events: bool,
     pub cursor_position: StableCursorPosition,
     pub dimensions: RenderableDimensions,
     pub dirty_lines: Vec<Range<StableRowIndex>>,
@@ -1007,4 +1188,43 @@ mod test {
             Pdu::decode(encoded.as_slice()).unwrap()
         );
     }
+
+    #[test]
+    fn test_compression() {
+        let pdu = ErrorResponse {
+            reason: "a".repeat(1024),
+        };
+
+        let (data, is_compressed) = serialize(&pdu, Compression::None).unwrap();
+        assert!(!is_compressed);
+        assert_eq!(
+            deserialize::<ErrorResponse, _>(data.as_slice(), is_compressed).unwrap(),
+            pdu
+        );
+
+        let (data, is_compressed) = serialize(&pdu, Compression::Zstd { level: 9 }).unwrap();
+        assert!(is_compressed);
+        assert!(data.len() < pdu.reason.len());
+        assert_eq!(
+            deserialize::<ErrorResponse, _>(data.as_slice(), is_compressed).unwrap(),
+            pdu
+        );
+    }
+
+    #[test]
+    fn test_compression_normalize() {
+        assert_eq!(Compression::None.normalize(), Compression::None);
+        assert_eq!(
+            Compression::Zstd { level: 0 }.normalize(),
+            Compression::Zstd { level: 1 }
+        );
+        assert_eq!(
+            Compression::Zstd { level: 100 }.normalize(),
+            Compression::Zstd { level: 19 }
+        );
+        assert_eq!(
+            Compression::Zstd { level: 7 }.normalize(),
+            Compression::Zstd { level: 7 }
+        );
+    }
 }
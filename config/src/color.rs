@@ -76,6 +76,9 @@ pub struct Palette {
     /// The color of the visual bell. If unspecified, the foreground
     /// color is used instead.
     pub visual_bell: Option<RgbColor>,
+    /// The color of the border drawn around panes that are receiving
+    /// broadcast input.  If unspecified, ANSI red is used instead.
+    pub broadcast_indicator: Option<RgbColor>,
 }
 impl_lua_conversion!(Palette);
 
@@ -2,7 +2,9 @@ use crate::*;
 use bitflags::*;
 use enum_display_derive::Display;
 use luahelper::impl_lua_conversion;
+use ordered_float::NotNan;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::fmt::Display;
 use termwiz::color::RgbColor;
@@ -347,6 +349,10 @@ pub struct FontAttributes {
     pub freetype_render_target: Option<FreeTypeLoadTarget>,
     #[serde(default)]
     pub freetype_load_flags: Option<FreeTypeLoadFlags>,
+    /// Values for OpenType font variation axes, keyed by the
+    /// axis tag; eg: `wght` or `opsz`
+    #[serde(default)]
+    pub variations: Option<BTreeMap<String, NotNan<f64>>>,
 }
 impl_lua_conversion!(FontAttributes);
 
@@ -373,6 +379,7 @@ impl FontAttributes {
             freetype_load_target: None,
             freetype_render_target: None,
             freetype_load_flags: None,
+            variations: None,
         }
     }
 
@@ -388,6 +395,7 @@ impl FontAttributes {
             freetype_load_target: None,
             freetype_render_target: None,
             freetype_load_flags: None,
+            variations: None,
         }
     }
 }
@@ -405,6 +413,7 @@ impl Default for FontAttributes {
             freetype_load_target: None,
             freetype_render_target: None,
             freetype_load_flags: None,
+            variations: None,
         }
     }
 }
@@ -114,6 +114,48 @@ pub enum PaneDirection {
     Prev,
 }
 
+impl PaneDirection {
+    pub fn variants() -> Vec<&'static str> {
+        vec!["Up", "Down", "Left", "Right", "Next", "Prev"]
+    }
+}
+
+impl std::str::FromStr for PaneDirection {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "next" => Ok(Self::Next),
+            "prev" => Ok(Self::Prev),
+            _ => Err(anyhow::anyhow!(
+                "{} is not a valid PaneDirection variant, possible values are {:?}",
+                s,
+                Self::variants()
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum RotationDirection {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Which panes receive the input that is broadcast from the active pane
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub enum BroadcastScope {
+    /// All of the panes in the active tab
+    Tab,
+    /// All of the panes in all of the tabs in the window
+    Window,
+    /// The panes that were marked via TogglePaneBroadcastMark
+    Marked,
+}
+
 #[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub enum ScrollbackEraseMode {
     ScrollbackOnly,
@@ -190,7 +232,9 @@ pub enum KeyAssignment {
     DisableDefaultAssignment,
     Hide,
     Show,
-    CloseCurrentTab { confirm: bool },
+    CloseCurrentTab {
+        confirm: bool,
+    },
     ReloadConfiguration,
     MoveTabRelative(isize),
     MoveTab(usize),
@@ -222,17 +266,59 @@ pub enum KeyAssignment {
     AdjustPaneSize(PaneDirection, usize),
     ActivatePaneDirection(PaneDirection),
     TogglePaneZoomState,
-    CloseCurrentPane { confirm: bool },
+    CloseCurrentPane {
+        confirm: bool,
+    },
     EmitEvent(String),
     QuickSelect,
     QuickSelectArgs(QuickSelectArguments),
 
     Multiple(Vec<KeyAssignment>),
+
+    SwapActivePaneDirection {
+        direction: PaneDirection,
+        #[serde(default)]
+        keep_focus: bool,
+    },
+    RotatePanes(RotationDirection),
+    MovePaneToNewTab,
+    MovePaneToNewWindow,
+    JoinPaneIntoTab {
+        tab: isize,
+        direction: PaneDirection,
+    },
+
+    ActivateKeyTable {
+        name: String,
+        #[serde(default)]
+        timeout_milliseconds: Option<u64>,
+        #[serde(default = "crate::default_true")]
+        one_shot: bool,
+    },
+    PopKeyTable,
+
+    SwitchToWorkspace {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        spawn: Option<SpawnCommand>,
+    },
+    SwitchWorkspaceRelative(isize),
+
+    ActivateCommandPalette,
+
+    ToggleBroadcastInput {
+        scope: BroadcastScope,
+    },
+    TogglePaneBroadcastMark,
 }
 impl_lua_conversion!(KeyAssignment);
 
+pub type KeyTable = HashMap<(KeyCode, Modifiers), KeyAssignment>;
+
 pub struct InputMap {
-    keys: HashMap<(KeyCode, Modifiers), KeyAssignment>,
+    keys: KeyTable,
+    key_tables: HashMap<String, KeyTable>,
     mouse: HashMap<(MouseEventTrigger, Modifiers), KeyAssignment>,
     leader: Option<LeaderKey>,
 }
@@ -242,6 +328,7 @@ impl InputMap {
         let mut mouse = config.mouse_bindings();
 
         let mut keys = config.key_bindings();
+        let mut key_tables = config.key_table_bindings();
 
         let leader = config.leader.clone();
 
@@ -472,6 +559,7 @@ impl InputMap {
                     ActivatePaneDirection(PaneDirection::Down)
                 ],
                 [Modifiers::CTRL, KeyCode::Char('Z'), TogglePaneZoomState],
+                [Modifiers::CTRL, KeyCode::Char('P'), ActivateCommandPalette],
             );
 
             #[cfg(target_os = "macos")]
@@ -604,14 +692,52 @@ impl InputMap {
 
         keys.retain(|_, v| *v != KeyAssignment::DisableDefaultAssignment);
         mouse.retain(|_, v| *v != KeyAssignment::DisableDefaultAssignment);
+        for table in key_tables.values_mut() {
+            table.retain(|_, v| *v != KeyAssignment::DisableDefaultAssignment);
+        }
 
         Self {
             keys,
+            key_tables,
             leader,
             mouse,
         }
     }
 
+    pub fn has_table(&self, name: &str) -> bool {
+        self.key_tables.contains_key(name)
+    }
+
+    /// Returns the distinct set of actions that are bound in the
+    /// default key table
+    pub fn key_assignments(&self) -> Vec<KeyAssignment> {
+        let mut actions: Vec<KeyAssignment> = vec![];
+        for action in self.keys.values() {
+            if !actions.contains(action) {
+                actions.push(action.clone());
+            }
+        }
+        actions
+    }
+
+    /// Returns the key combinations that trigger `action` via the
+    /// default key table
+    pub fn locate_key_bindings(&self, action: &KeyAssignment) -> Vec<(KeyCode, Modifiers)> {
+        let mut bindings: Vec<(KeyCode, Modifiers)> = self
+            .keys
+            .iter()
+            .filter_map(|(key, value)| {
+                if value == action {
+                    Some(key.clone())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        bindings.sort_by_key(|(key, mods)| (mods.bits(), format!("{:?}", key)));
+        bindings
+    }
+
     pub fn is_leader(&self, key: &KeyCode, mods: Modifiers) -> Option<std::time::Duration> {
         if let Some(leader) = self.leader.as_ref() {
             if leader.key == *key && leader.mods == mods {
@@ -633,6 +759,18 @@ impl InputMap {
             .cloned()
     }
 
+    pub fn lookup_key_in_table(
+        &self,
+        table_name: &str,
+        key: &KeyCode,
+        mods: Modifiers,
+    ) -> Option<KeyAssignment> {
+        self.key_tables
+            .get(table_name)?
+            .get(&key.normalize_shift(Self::remove_positional_alt(mods)))
+            .cloned()
+    }
+
     pub fn lookup_mouse(&self, event: MouseEventTrigger, mods: Modifiers) -> Option<KeyAssignment> {
         self.mouse
             .get(&(event, Self::remove_positional_alt(mods)))
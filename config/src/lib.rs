@@ -1,6 +1,6 @@
 //! Configuration for the gui portion of the terminal
 
-use crate::keyassignment::{KeyAssignment, MouseEventTrigger, SpawnCommand};
+use crate::keyassignment::{KeyAssignment, KeyTable, MouseEventTrigger, SpawnCommand};
 use anyhow::{anyhow, bail, Context, Error};
 use lazy_static::lazy_static;
 use luahelper::impl_lua_conversion;
@@ -63,6 +63,7 @@ type ErrorCallback = fn(&str);
 lazy_static! {
     pub static ref HOME_DIR: PathBuf = dirs_next::home_dir().expect("can't find HOME dir");
     pub static ref CONFIG_DIR: PathBuf = xdg_config_home();
+    pub static ref DATA_DIR: PathBuf = xdg_data_home();
     pub static ref RUNTIME_DIR: PathBuf = compute_runtime_dir().unwrap();
     static ref CONFIG: Configuration = Configuration::new();
     static ref CONFIG_FILE_OVERRIDE: Mutex<Option<PathBuf>> = Mutex::new(None);
@@ -321,6 +322,13 @@ fn xdg_config_home() -> PathBuf {
     }
 }
 
+fn xdg_data_home() -> PathBuf {
+    match std::env::var_os("XDG_DATA_HOME").map(|s| PathBuf::from(s).join("wezterm")) {
+        Some(p) => p,
+        None => HOME_DIR.join(".local").join("share").join("wezterm"),
+    }
+}
+
 pub fn set_config_file_override(path: &Path) {
     CONFIG_FILE_OVERRIDE
         .lock()
@@ -718,6 +726,21 @@ impl Default for ExitBehavior {
     }
 }
 
+/// How the multiplexer compresses the data that it sends
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum MuxCompression {
+    /// Don't compress
+    None,
+    /// Compress larger PDUs using zstd at `mux_compression_level`
+    Zstd,
+}
+
+impl Default for MuxCompression {
+    fn default() -> Self {
+        MuxCompression::Zstd
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     /// The font size, measured in points
@@ -785,6 +808,12 @@ pub struct Config {
     #[serde(default = "default_scrollback_lines")]
     pub scrollback_lines: usize,
 
+    /// How many additional lines of scrollback to retain in a
+    /// compressed form on disk once they have scrolled beyond
+    /// `scrollback_lines`.  Zero disables the on-disk scrollback.
+    #[serde(default)]
+    pub scrollback_disk_lines: usize,
+
     /// If no `prog` is specified on the command line, use this
     /// instead of running the user's shell.
     /// For example, to have `wezterm` always run `top` by default,
@@ -823,6 +852,9 @@ pub struct Config {
     #[serde(default = "default_true")]
     pub enable_kitty_graphics: bool,
 
+    #[serde(default)]
+    pub enable_title_reporting: bool,
+
     /// Specifies the width of a new window, expressed in character cells
     #[serde(default = "default_initial_cols")]
     pub initial_cols: u16,
@@ -930,8 +962,46 @@ pub struct Config {
     #[serde(default = "default_mux_env_remove")]
     pub mux_env_remove: Vec<String>,
 
+    /// How often, in milliseconds, the multiplexer client sends a
+    /// heartbeat to the server to measure the round trip time.
+    /// If a reconnectable connection misses several heartbeats in
+    /// a row it is torn down and re-established.
+    /// Set to 0 to disable heartbeats.
+    #[serde(default = "default_mux_heartbeat_interval")]
+    pub mux_heartbeat_interval: u64,
+
+    /// The compression that the multiplexer client asks the server
+    /// to use when sending to it, and that the client uses in turn
+    #[serde(default)]
+    pub mux_compression: MuxCompression,
+
+    /// The zstd compression level used when `mux_compression`
+    /// is set to `Zstd`.  Values are clamped to the range 1-19.
+    #[serde(default = "default_mux_compression_level")]
+    pub mux_compression_level: i32,
+
+    /// The maximum number of render updates for a pane that the
+    /// multiplexer server will send to a client before waiting for
+    /// the client to acknowledge them.  While a client is this far
+    /// behind, the server stops reading output from the pane, which
+    /// applies backpressure to the program that is generating it.
+    /// Set to 0 to disable flow control.
+    #[serde(default = "default_mux_output_flow_control_window")]
+    pub mux_output_flow_control_window: usize,
+
+    /// When true, the layout of the windows, tabs and panes is saved
+    /// when the gui exits, so that it can be recreated later via
+    /// `wezterm cli restore-session`
+    #[serde(default)]
+    pub autosave_session_on_exit: bool,
+
     #[serde(default)]
     pub keys: Vec<Key>,
+    /// Named tables of key assignments that can be activated by
+    /// the ActivateKeyTable key assignment.  While a table is active
+    /// its assignments take precedence over those in `keys`.
+    #[serde(default)]
+    pub key_tables: HashMap<String, Vec<Key>>,
     #[serde(
         default = "default_bypass_mouse_reporting_modifiers",
         deserialize_with = "crate::keys::de_modifiers"
@@ -1266,6 +1336,18 @@ fn default_canonicalize_pasted_newlines() -> bool {
     cfg!(windows)
 }
 
+fn default_mux_heartbeat_interval() -> u64 {
+    5_000
+}
+
+fn default_mux_compression_level() -> i32 {
+    3
+}
+
+fn default_mux_output_flow_control_window() -> usize {
+    8
+}
+
 fn default_mux_env_remove() -> Vec<String> {
     vec![
         "SSH_AUTH_SOCK".to_string(),
@@ -1616,6 +1698,21 @@ impl Config {
         map
     }
 
+    pub fn key_table_bindings(&self) -> HashMap<String, KeyTable> {
+        let mut tables = HashMap::new();
+
+        for (name, keys) in &self.key_tables {
+            let mut map = HashMap::new();
+            for k in keys {
+                let (key, mods) = k.key.normalize_shift(k.mods);
+                map.insert((key, mods), k.action.clone());
+            }
+            tables.insert(name.to_string(), map);
+        }
+
+        tables
+    }
+
     pub fn mouse_bindings(&self) -> HashMap<(MouseEventTrigger, Modifiers), KeyAssignment> {
         let mut map = HashMap::new();
 
@@ -4,8 +4,10 @@ use anyhow::anyhow;
 use bstr::BString;
 pub use luahelper::*;
 use mlua::{FromLua, Lua, Table, ToLua, ToLuaMulti, Value, Variadic};
+use ordered_float::NotNan;
 use serde::*;
 use smol::prelude::*;
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::path::Path;
 use termwiz::cell::{grapheme_column_width, unicode_column_width, AttributeChange, CellAttributes};
@@ -483,6 +485,9 @@ struct TextStyleAttributes {
     /// useful in a `[[font_rules]]` section to implement changing
     /// the text color for eg: bold text.
     pub foreground: Option<termwiz::color::RgbColor>,
+    /// Values for OpenType font variation axes
+    #[serde(default)]
+    pub variations: Option<BTreeMap<String, NotNan<f64>>>,
 }
 impl_lua_conversion!(TextStyleAttributes);
 
@@ -507,6 +512,8 @@ struct LuaFontAttributes {
     pub freetype_render_target: Option<FreeTypeLoadTarget>,
     #[serde(default)]
     pub freetype_load_flags: Option<String>,
+    #[serde(default)]
+    pub variations: Option<BTreeMap<String, NotNan<f64>>>,
 }
 impl<'lua> FromLua<'lua> for LuaFontAttributes {
     fn from_lua(value: Value<'lua>, _lua: &'lua Lua) -> Result<Self, mlua::Error> {
@@ -545,6 +552,9 @@ fn font<'lua>(
         };
         attrs.stretch = map_defaults.stretch;
         attrs.italic = map_defaults.italic;
+        if attrs.variations.is_none() {
+            attrs.variations = map_defaults.variations;
+        }
         text_style.foreground = map_defaults.foreground;
     }
 
@@ -562,6 +572,7 @@ fn font<'lua>(
             Some(flags) => Some(TryFrom::try_from(flags).map_err(|e| mlua::Error::external(e))?),
             None => None,
         },
+        variations: attrs.variations,
     });
 
     Ok(text_style)
@@ -590,6 +601,9 @@ fn font_with_fallback<'lua>(
             };
             attrs.stretch = map_defaults.stretch;
             attrs.italic = map_defaults.italic;
+            if attrs.variations.is_none() {
+                attrs.variations = map_defaults.variations.clone();
+            }
             text_style.foreground = map_defaults.foreground;
         }
 
@@ -609,6 +623,7 @@ fn font_with_fallback<'lua>(
                 }
                 None => None,
             },
+            variations: attrs.variations,
         });
     }
 
@@ -645,6 +660,15 @@ fn action_callback<'lua>(
     return Ok(crate::KeyAssignment::EmitEvent(user_event_id));
 }
 
+/// Returns the event names that were allocated by `wezterm.action_callback`
+/// while loading the configuration
+pub fn user_callback_event_names(lua: &Lua) -> mlua::Result<Vec<String>> {
+    let callback_count: i32 = lua.named_registry_value(LUA_REGISTRY_USER_CALLBACK_COUNT)?;
+    Ok((0..callback_count)
+        .map(|idx| format!("user-defined-{}", idx))
+        .collect())
+}
+
 async fn read_dir<'lua>(_: &'lua Lua, path: String) -> mlua::Result<Vec<String>> {
     let mut dir = smol::fs::read_dir(path)
         .await
@@ -44,6 +44,10 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
         self.configuration().scrollback_lines
     }
 
+    fn scrollback_disk_size(&self) -> usize {
+        self.configuration().scrollback_disk_lines
+    }
+
     fn hyperlink_rules(&self) -> (usize, Vec<HyperlinkRule>) {
         let config = self.configuration();
         (config.generation(), config.hyperlink_rules.clone())
@@ -71,6 +75,10 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
         self.configuration().enable_kitty_graphics
     }
 
+    fn enable_title_reporting(&self) -> bool {
+        self.configuration().enable_title_reporting
+    }
+
     fn canonicalize_pasted_newlines(&self) -> bool {
         self.configuration().canonicalize_pasted_newlines
     }
@@ -6,11 +6,13 @@ use promise::spawn::block_on;
 use promise::Promise;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
-use termwiz::cell::{unicode_column_width, CellAttributes};
+use termwiz::cell::{unicode_column_width, AttributeChange, CellAttributes, Intensity};
+use termwiz::color::{AnsiColor, ColorAttribute};
 use termwiz::lineedit::*;
 use termwiz::surface::{Change, Position};
 use termwiz::terminal::*;
 use unicode_segmentation::UnicodeSegmentation;
+use wezterm_ssh::{HostKeyStatus, HostVerificationEvent};
 
 #[derive(Default)]
 struct PasswordPromptHost {
@@ -396,6 +398,17 @@ impl ConnectionUI {
         block_on(future)
     }
 
+    /// Presents a host key verification request to the user.
+    /// Returns true if they chose to trust the host.
+    pub fn verify_host(&self, verify: &HostVerificationEvent) -> anyhow::Result<bool> {
+        let (changes, prompt) = host_verification_changes(verify);
+        self.output(changes);
+        match prompt {
+            Some(prompt) => Ok(is_yes(&self.input(prompt)?)),
+            None => Ok(false),
+        }
+    }
+
     pub fn close(&self) {
         self.tx.send(UIRequest::Close).ok();
     }
@@ -409,6 +422,84 @@ impl ConnectionUI {
     }
 }
 
+/// Returns true if `answer` is an affirmative response to a y/n prompt
+pub fn is_yes(answer: &str) -> bool {
+    match answer.trim() {
+        "y" | "Y" | "yes" | "YES" => true,
+        _ => false,
+    }
+}
+
+/// Computes the changes that describe a host key verification request,
+/// along with the prompt for the user's answer.
+/// A changed or revoked key is shown on its own warning screen.
+/// There is no prompt for a revoked key, as it can never be trusted.
+pub fn host_verification_changes(
+    verify: &HostVerificationEvent,
+) -> (Vec<Change>, Option<&'static str>) {
+    let warning = |changes: &mut Vec<Change>, text: &str| {
+        changes.push(Change::ClearScreen(ColorAttribute::Default));
+        changes.push(AttributeChange::Intensity(Intensity::Bold).into());
+        changes.push(AttributeChange::Foreground(AnsiColor::Red.into()).into());
+        changes.push(Change::Text(format!("{}\r\n", text)));
+        changes.push(Change::AllAttributes(CellAttributes::default()));
+    };
+
+    let address = format!("{}:{}", verify.host, verify.port);
+    let mut changes = vec![];
+    let prompt = match &verify.status {
+        HostKeyStatus::New => {
+            changes.push(Change::Text(format!(
+                "The authenticity of host {} can't be established.\r\n\
+                 {} key fingerprint is {}.\r\n",
+                address, verify.key_type, verify.fingerprint
+            )));
+            Some("Trust and continue connecting? [y/n]> ")
+        }
+        HostKeyStatus::Changed(location) => {
+            warning(
+                &mut changes,
+                "@@@ WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED! @@@",
+            );
+            changes.push(Change::Text(format!(
+                "Someone could be eavesdropping on you right now \
+                 (man-in-the-middle attack)!\r\n\
+                 It is also possible that the host key has just been changed.\r\n\
+                 The {} key fingerprint sent by {} is\r\n  {}\r\n\
+                 The stale key for this host is recorded at {}.\r\n",
+                verify.key_type, address, verify.fingerprint, location
+            )));
+            Some("Replace the stale entry and continue connecting? [y/n]> ")
+        }
+        HostKeyStatus::OtherKeyTypeKnown(location) => {
+            warning(
+                &mut changes,
+                "@@@ WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED! @@@",
+            );
+            changes.push(Change::Text(format!(
+                "Someone could be eavesdropping on you right now \
+                 (man-in-the-middle attack)!\r\n\
+                 {} sent a {} key with fingerprint\r\n  {}\r\n\
+                 but only a key of another type is known for this host,\r\n\
+                 recorded at {}.\r\n",
+                address, verify.key_type, verify.fingerprint, location
+            )));
+            Some("Add the new key and continue connecting? [y/n]> ")
+        }
+        HostKeyStatus::Revoked(location) => {
+            warning(&mut changes, "@@@ WARNING: REVOKED HOST KEY DETECTED! @@@");
+            changes.push(Change::Text(format!(
+                "The {} key with fingerprint {} sent by {}\r\n\
+                 is marked as revoked at {}.\r\n\
+                 Refusing to connect.\r\n",
+                verify.key_type, verify.fingerprint, address, location
+            )));
+            None
+        }
+    };
+    (changes, prompt)
+}
+
 lazy_static::lazy_static! {
     static ref ERROR_WINDOW: Mutex<Option<ConnectionUI>> = Mutex::new(None);
 }
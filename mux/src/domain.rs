@@ -84,9 +84,31 @@ pub trait Domain: Downcast {
     /// This allows the domain the opportunity to eg: detach/hide
     /// its tabs/panes rather than actually killing them off
     fn local_window_is_closing(&self, _window_id: WindowId) {}
+
+    /// Returns an sftp client for the ssh session that connects this
+    /// domain to its remote host, if any.
+    /// This is used to copy files to and from that host.
+    fn sftp(&self) -> Option<wezterm_ssh::Sftp> {
+        None
+    }
 }
 impl_downcast!(Domain);
 
+/// Returns the argv of an explicitly specified command, or None if
+/// the command will run the default program
+fn spawn_argv_of(cmd: &CommandBuilder) -> Option<Vec<String>> {
+    if cmd.is_default_prog() {
+        None
+    } else {
+        Some(
+            cmd.get_argv()
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+}
+
 pub struct LocalDomain {
     pty_system: Box<dyn PtySystem>,
     id: DomainId,
@@ -130,6 +152,7 @@ impl Domain for LocalDomain {
         window: WindowId,
     ) -> Result<Rc<Tab>, Error> {
         let config = configuration();
+        let spawn_argv = command.as_ref().and_then(spawn_argv_of);
         let mut cmd = match command {
             Some(mut cmd) => {
                 config.apply_cmd_defaults(&mut cmd, config.default_cwd.as_ref());
@@ -171,13 +194,9 @@ impl Domain for LocalDomain {
             terminal.set_supress_initial_title_change();
         }
 
-        let pane: Rc<dyn Pane> = Rc::new(LocalPane::new(
-            pane_id,
-            terminal,
-            child,
-            pair.master,
-            self.id,
-        ));
+        let mut pane = LocalPane::new(pane_id, terminal, child, pair.master, self.id);
+        pane.set_spawn_argv(spawn_argv);
+        let pane: Rc<dyn Pane> = Rc::new(pane);
 
         let tab = Rc::new(Tab::new(&size));
         tab.assign_pane(&pane);
@@ -217,6 +236,7 @@ impl Domain for LocalDomain {
         };
 
         let config = configuration();
+        let spawn_argv = command.as_ref().and_then(spawn_argv_of);
         let mut cmd = match command {
             Some(mut cmd) => {
                 config.apply_cmd_defaults(&mut cmd, config.default_cwd.as_ref());
@@ -256,13 +276,9 @@ impl Domain for LocalDomain {
             terminal.set_supress_initial_title_change();
         }
 
-        let pane: Rc<dyn Pane> = Rc::new(LocalPane::new(
-            pane_id,
-            terminal,
-            child,
-            pair.master,
-            self.id,
-        ));
+        let mut pane = LocalPane::new(pane_id, terminal, child, pair.master, self.id);
+        pane.set_spawn_argv(spawn_argv);
+        let pane: Rc<dyn Pane> = Rc::new(pane);
 
         tab.split_and_insert(pane_index, direction, Rc::clone(&pane))?;
 
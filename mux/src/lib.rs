@@ -2,6 +2,7 @@ use crate::pane::{Pane, PaneId};
 use crate::tab::{Tab, TabId};
 use crate::window::{Window, WindowId};
 use anyhow::{anyhow, Context, Error};
+use config::keyassignment::PaneDirection;
 use config::{configuration, ExitBehavior};
 use domain::{Domain, DomainId};
 use filedescriptor::{socketpair, AsRawSocketDescriptor, FileDescriptor};
@@ -15,9 +16,9 @@ use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use termwiz::escape::csi::{DecPrivateMode, DecPrivateModeCode, Device, Mode};
 use termwiz::escape::{Action, CSI};
 use thiserror::*;
@@ -30,10 +31,14 @@ pub mod domain;
 pub mod localpane;
 pub mod pane;
 pub mod renderable;
+pub mod session;
 pub mod ssh;
 pub mod tab;
 pub mod termwiztermtab;
 pub mod tmux;
+mod tmux_commands;
+mod tmux_pty;
+pub mod transfer;
 pub mod window;
 
 use crate::activity::Activity;
@@ -46,10 +51,12 @@ pub enum MuxNotification {
     WindowCreated(WindowId),
     WindowRemoved(WindowId),
     WindowInvalidated(WindowId),
+    ActiveWorkspaceChanged(String),
     Alert {
         pane_id: PaneId,
         alert: wezterm_term::Alert,
     },
+    FileTransferStarted(Arc<transfer::FileTransfer>),
     Empty,
 }
 
@@ -64,10 +71,53 @@ pub struct Mux {
     domains_by_name: RefCell<HashMap<String, Arc<dyn Domain>>>,
     subscribers: RefCell<HashMap<usize, Box<dyn Fn(MuxNotification) -> bool>>>,
     banner: RefCell<Option<String>>,
+    active_workspace: RefCell<String>,
+    output_gates: RefCell<HashMap<PaneId, Arc<OutputGate>>>,
 }
 
+/// The name of the workspace that is active when none has been selected
+pub const DEFAULT_WORKSPACE: &str = "default";
+
 const BUFSIZE: usize = 1024 * 1024;
 
+/// Allows consumers of the output of a pane to apply backpressure
+/// by pausing reads from its pty; a multiplexer server does this
+/// when a client on a slow link falls too far behind.
+/// Each consumer that wants the output paused holds a pause, and
+/// reading resumes once all of the pauses have been released.
+#[derive(Default, Debug)]
+pub struct OutputGate {
+    pauses: Mutex<usize>,
+    cond: Condvar,
+}
+
+impl OutputGate {
+    pub fn pause(&self) {
+        *self.pauses.lock().unwrap() += 1;
+    }
+
+    pub fn resume(&self) {
+        let mut pauses = self.pauses.lock().unwrap();
+        *pauses = pauses.saturating_sub(1);
+        if *pauses == 0 {
+            self.cond.notify_all();
+        }
+    }
+
+    /// Blocks until no pauses are held, or until dead is set
+    fn wait_until_open(&self, dead: &AtomicBool) {
+        let mut pauses = self.pauses.lock().unwrap();
+        while *pauses > 0 && !dead.load(Ordering::Relaxed) {
+            // Wake up periodically so that we notice if the pane died
+            pauses = self
+                .cond
+                .wait_timeout(pauses, Duration::from_secs(1))
+                .unwrap()
+                .0;
+        }
+    }
+}
+
 /// This function bounces parsed actions over to the main thread to feed to
 /// the pty in the mux.
 /// It blocks until the mux has finished consuming the data, which provides
@@ -178,7 +228,12 @@ fn set_socket_buffer(fd: &mut FileDescriptor, option: i32, size: usize) -> anyho
 /// blocking reads from the pty (non-blocking reads are not portable to
 /// all platforms and pty/tty types), parse the escape sequences and
 /// relay the actions to the mux thread to apply them to the pane.
-fn read_from_pane_pty(pane_id: PaneId, banner: Option<String>, mut reader: Box<dyn std::io::Read>) {
+fn read_from_pane_pty(
+    pane_id: PaneId,
+    banner: Option<String>,
+    gate: Arc<OutputGate>,
+    mut reader: Box<dyn std::io::Read>,
+) {
     let mut buf = vec![0; BUFSIZE];
 
     // This is used to signal that an error occurred either in this thread,
@@ -199,6 +254,7 @@ fn read_from_pane_pty(pane_id: PaneId, banner: Option<String>, mut reader: Box<d
     }
 
     while !dead.load(Ordering::Relaxed) {
+        gate.wait_until_open(&dead);
         match reader.read(&mut buf) {
             Ok(size) if size == 0 => {
                 log::trace!("read_pty EOF: pane_id {}", pane_id);
@@ -317,6 +373,8 @@ impl Mux {
             domains: RefCell::new(domains),
             subscribers: RefCell::new(HashMap::new()),
             banner: RefCell::new(None),
+            active_workspace: RefCell::new(DEFAULT_WORKSPACE.to_string()),
+            output_gates: RefCell::new(HashMap::new()),
         }
     }
 
@@ -402,7 +460,11 @@ impl Mux {
         let pane_id = pane.pane_id();
         if let Some(reader) = pane.reader()? {
             let banner = self.banner.borrow().clone();
-            thread::spawn(move || read_from_pane_pty(pane_id, banner, reader));
+            let gate = Arc::new(OutputGate::default());
+            self.output_gates
+                .borrow_mut()
+                .insert(pane_id, Arc::clone(&gate));
+            thread::spawn(move || read_from_pane_pty(pane_id, banner, gate, reader));
         }
         self.notify(MuxNotification::PaneAdded(pane_id));
         Ok(())
@@ -420,8 +482,15 @@ impl Mux {
         self.add_pane(&pane)
     }
 
+    /// Returns the gate that controls reading from the pty of the
+    /// specified pane, if it has one
+    pub fn get_output_gate(&self, pane_id: PaneId) -> Option<Arc<OutputGate>> {
+        self.output_gates.borrow().get(&pane_id).cloned()
+    }
+
     fn remove_pane_internal(&self, pane_id: PaneId) {
         log::debug!("removing pane {}", pane_id);
+        self.output_gates.borrow_mut().remove(&pane_id);
         if let Some(pane) = self.panes.borrow_mut().remove(&pane_id) {
             log::debug!("killing pane {}", pane_id);
             pane.kill();
@@ -472,6 +541,9 @@ impl Mux {
     }
 
     pub fn remove_tab(&self, tab_id: TabId) -> Option<Rc<Tab>> {
+        if self.tabs.borrow().keys().all(|&id| id == tab_id) {
+            session::maybe_autosave(self);
+        }
         let tab = self.remove_tab_internal(tab_id);
         self.prune_dead_windows();
         tab
@@ -482,6 +554,14 @@ impl Mux {
             log::trace!("prune_dead_windows: Activity::count={}", Activity::count());
             return;
         }
+        let all_tabs_dead = {
+            let tabs = self.tabs.borrow();
+            !tabs.is_empty() && tabs.values().all(|tab| tab.is_dead())
+        };
+        if all_tabs_dead {
+            // Save while the dead panes are still part of the layout
+            session::maybe_autosave(self);
+        }
         let live_tab_ids: Vec<TabId> = self.tabs.borrow().keys().cloned().collect();
         let mut dead_windows = vec![];
         let dead_tab_ids: Vec<TabId>;
@@ -530,6 +610,9 @@ impl Mux {
     }
 
     pub fn kill_window(&self, window_id: WindowId) {
+        if self.windows.borrow().keys().all(|&id| id == window_id) {
+            session::maybe_autosave(self);
+        }
         self.remove_window_internal(window_id);
         self.prune_dead_windows();
     }
@@ -557,8 +640,59 @@ impl Mux {
         window.get_active().map(Rc::clone)
     }
 
-    pub fn new_empty_window(&self) -> MuxWindowBuilder {
-        let window = Window::new();
+    /// Returns the name of the workspace whose windows are shown
+    /// by the GUI
+    pub fn active_workspace(&self) -> String {
+        self.active_workspace.borrow().clone()
+    }
+
+    pub fn set_active_workspace(&self, workspace: &str) {
+        if *self.active_workspace.borrow() == workspace {
+            return;
+        }
+        *self.active_workspace.borrow_mut() = workspace.to_string();
+        self.notify(MuxNotification::ActiveWorkspaceChanged(
+            workspace.to_string(),
+        ));
+    }
+
+    /// Returns the sorted list of workspace names; this is made up
+    /// of the workspaces of all windows, plus the active workspace
+    /// even if it has no windows.
+    pub fn iter_workspaces(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .windows
+            .borrow()
+            .values()
+            .map(|w| w.get_workspace().to_string())
+            .collect();
+        names.push(self.active_workspace());
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn iter_windows_in_workspace(&self, workspace: &str) -> Vec<WindowId> {
+        let mut windows: Vec<WindowId> = self
+            .windows
+            .borrow()
+            .iter()
+            .filter_map(|(k, w)| {
+                if w.get_workspace() == workspace {
+                    Some(*k)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        windows.sort();
+        windows
+    }
+
+    /// Creates a new window in the specified workspace, or the active
+    /// workspace if `workspace` is None.
+    pub fn new_empty_window(&self, workspace: Option<String>) -> MuxWindowBuilder {
+        let window = Window::new(workspace.unwrap_or_else(|| self.active_workspace()));
         let window_id = window.window_id();
         self.windows.borrow_mut().insert(window_id, window);
         MuxWindowBuilder {
@@ -576,6 +710,179 @@ impl Mux {
         Ok(())
     }
 
+    /// Removes `pane_id` from its containing tab and places it into a new
+    /// tab.  If `window_id` is specified, the new tab is added to that
+    /// window, otherwise a new window is created to hold it.
+    /// Returns the new tab and the id of the window that contains it.
+    pub fn move_pane_to_new_tab(
+        &self,
+        pane_id: PaneId,
+        window_id: Option<WindowId>,
+    ) -> anyhow::Result<(Rc<Tab>, WindowId)> {
+        let (_domain_id, src_window_id, src_tab_id) = self
+            .resolve_pane_id(pane_id)
+            .ok_or_else(|| anyhow!("pane {} is not in any tab", pane_id))?;
+        let src_tab = self
+            .get_tab(src_tab_id)
+            .ok_or_else(|| anyhow!("no such tab {}", src_tab_id))?;
+
+        let is_only_pane = src_tab.count_panes() == 1;
+        if is_only_pane && window_id == Some(src_window_id) {
+            anyhow::bail!("pane {} is already the only pane in its tab", pane_id);
+        }
+
+        // Keep the builder alive until the window has been populated,
+        // so that the window isn't considered to be dead in the meantime
+        // A newly created window belongs to the same workspace as the
+        // window that currently holds the pane
+        let window_builder = match window_id {
+            Some(_) => None,
+            None => {
+                let workspace = self
+                    .get_window(src_window_id)
+                    .map(|w| w.get_workspace().to_string());
+                Some(self.new_empty_window(workspace))
+            }
+        };
+        let target_window_id = match window_builder.as_ref() {
+            Some(builder) => **builder,
+            None => window_id.unwrap(),
+        };
+
+        let tab = if is_only_pane {
+            // Rather than creating a new tab, move the existing tab
+            // into the target window
+            if let Some(mut src_window) = self.get_window_mut(src_window_id) {
+                src_window.remove_by_id(src_tab_id);
+                src_window.invalidate();
+            }
+            src_tab
+        } else {
+            let size = src_tab.get_size();
+            let pane = src_tab
+                .remove_pane(pane_id)
+                .ok_or_else(|| anyhow!("pane {} is not in tab {}", pane_id, src_tab_id))?;
+
+            let tab = Rc::new(Tab::new(&size));
+            tab.assign_pane(&pane);
+            pane.resize(size)?;
+            self.add_tab_no_panes(&tab);
+
+            if let Some(src_window) = self.get_window(src_window_id) {
+                src_window.invalidate();
+            }
+            tab
+        };
+
+        self.add_tab_to_window(&tab, target_window_id)?;
+        drop(window_builder);
+        self.prune_dead_windows();
+
+        Ok((tab, target_window_id))
+    }
+
+    /// Removes `pane_id` from its containing tab and inserts it into
+    /// the tab identified by `tab_id`, splitting that tab's active pane
+    /// and placing the moved pane on the side of it indicated by
+    /// `direction`.
+    pub fn move_pane_to_tab(
+        &self,
+        pane_id: PaneId,
+        tab_id: TabId,
+        direction: PaneDirection,
+    ) -> anyhow::Result<()> {
+        let (_domain_id, src_window_id, src_tab_id) = self
+            .resolve_pane_id(pane_id)
+            .ok_or_else(|| anyhow!("pane {} is not in any tab", pane_id))?;
+        if src_tab_id == tab_id {
+            anyhow::bail!("pane {} is already in tab {}", pane_id, tab_id);
+        }
+        let src_tab = self
+            .get_tab(src_tab_id)
+            .ok_or_else(|| anyhow!("no such tab {}", src_tab_id))?;
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+        let pane = self
+            .get_pane(pane_id)
+            .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+
+        // Insert into the destination first, so that a failure to
+        // split leaves the pane in its original tab
+        tab.set_zoomed(false);
+        tab.split_and_insert_direction(tab.get_active_idx(), direction, pane)?;
+        src_tab.remove_pane(pane_id);
+
+        for window_id in [Some(src_window_id), self.window_containing_tab(tab_id)]
+            .iter()
+            .flatten()
+        {
+            if let Some(window) = self.get_window(*window_id) {
+                window.invalidate();
+            }
+        }
+        self.prune_dead_windows();
+
+        Ok(())
+    }
+
+    /// Makes the specified pane the active pane of its tab, and makes
+    /// that tab the active tab of its window.
+    /// If the tab is zoomed on some other pane then it is unzoomed.
+    pub fn focus_pane_and_containing_tab(
+        &self,
+        pane_id: PaneId,
+    ) -> anyhow::Result<(Rc<Tab>, WindowId)> {
+        let pane = self
+            .get_pane(pane_id)
+            .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+        let (_domain_id, window_id, tab_id) = self
+            .resolve_pane_id(pane_id)
+            .ok_or_else(|| anyhow!("pane {} is not in any tab", pane_id))?;
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+
+        // A zoomed tab only exposes the zoomed pane, so unzoom it
+        // if we are focusing some other pane
+        if tab.iter_panes().iter().all(|p| p.pane.pane_id() != pane_id) {
+            tab.set_zoomed(false);
+        }
+        tab.set_active_pane(&pane);
+
+        let mut window = self
+            .get_window_mut(window_id)
+            .ok_or_else(|| anyhow!("no such window {}", window_id))?;
+        let tab_idx = window
+            .idx_by_id(tab_id)
+            .ok_or_else(|| anyhow!("tab {} is not in window {}", tab_id, window_id))?;
+        window.save_and_then_set_active(tab_idx);
+
+        Ok((tab, window_id))
+    }
+
+    /// Returns the tab and window containing `pane_id`, along with the
+    /// index of the pane within that tab.  Unlike
+    /// `focus_pane_and_containing_tab`, this doesn't change focus.
+    pub fn containing_tab_and_pane_index(
+        &self,
+        pane_id: PaneId,
+    ) -> anyhow::Result<(Rc<Tab>, WindowId, usize)> {
+        let (_domain_id, window_id, tab_id) = self
+            .resolve_pane_id(pane_id)
+            .ok_or_else(|| anyhow!("pane {} is not in any tab", pane_id))?;
+        let tab = self
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("no such tab {}", tab_id))?;
+        let pane_index = tab
+            .iter_panes_ignoring_zoom()
+            .iter()
+            .find(|p| p.pane.pane_id() == pane_id)
+            .map(|p| p.index)
+            .ok_or_else(|| anyhow!("pane {} is not in tab {}", pane_id, tab_id))?;
+        Ok((tab, window_id, pane_index))
+    }
+
     pub fn window_containing_tab(&self, tab_id: TabId) -> Option<WindowId> {
         for w in self.windows.borrow().values() {
             for t in w.iter() {
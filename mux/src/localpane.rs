@@ -48,6 +48,7 @@ pub struct LocalPane {
     pty: RefCell<Box<dyn MasterPty>>,
     domain_id: DomainId,
     tmux_domain: RefCell<Option<Arc<TmuxDomainState>>>,
+    spawn_argv: Option<Vec<String>>,
 }
 
 #[async_trait(?Send)]
@@ -232,9 +233,10 @@ impl Pane for LocalPane {
 
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
         if self.tmux_domain.borrow().is_some() {
-            log::error!("key: {:?}", key);
             if key == KeyCode::Char('q') {
-                self.terminal.borrow_mut().send_paste("detach\n")?;
+                if let Some(tmux) = self.tmux_domain.borrow().as_ref() {
+                    tmux.detach_client();
+                }
             }
             return Ok(());
         } else {
@@ -242,6 +244,22 @@ impl Pane for LocalPane {
         }
     }
 
+    fn key_up(&self, key: KeyCode, mods: KeyModifiers) -> Result<(), Error> {
+        if self.tmux_domain.borrow().is_some() {
+            Ok(())
+        } else {
+            self.terminal.borrow_mut().key_up(key, mods)
+        }
+    }
+
+    fn wants_key_up_events(&self) -> bool {
+        if self.tmux_domain.borrow().is_some() {
+            false
+        } else {
+            self.terminal.borrow().wants_key_up_events()
+        }
+    }
+
     fn resize(&self, size: PtySize) -> Result<(), Error> {
         self.pty.borrow_mut().resize(size)?;
         self.terminal.borrow_mut().resize(
@@ -332,6 +350,10 @@ impl Pane for LocalPane {
             .or_else(|| self.divine_current_working_dir())
     }
 
+    fn get_spawn_argv(&self) -> Option<Vec<String>> {
+        self.spawn_argv.clone()
+    }
+
     #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
     fn get_foreground_process_name(&self) -> Option<String> {
         None
@@ -558,9 +580,7 @@ impl Pane for LocalPane {
             }
         }
 
-        for (idx, line) in screen.lines.iter().enumerate() {
-            let stable_row = screen.phys_to_stable_row_index(idx);
-
+        for (stable_row, line) in screen.iter_stable_lines() {
             let mut wrapped = false;
             for (grapheme_idx, cell) in line.visible_cells() {
                 coords.push(Coord {
@@ -656,6 +676,7 @@ impl wezterm_term::DeviceControlHandler for LocalPaneDCSHandler {
             }
             DeviceControlMode::Exit => {
                 if let Some(tmux) = self.tmux_domain.take() {
+                    tmux.detached();
                     let mux = Mux::get().expect("to be called on main thread");
                     if let Some(pane) = mux.get_pane(self.pane_id) {
                         let pane = pane.downcast_ref::<LocalPane>().unwrap();
@@ -757,9 +778,16 @@ impl LocalPane {
             pty: RefCell::new(pty),
             domain_id,
             tmux_domain: RefCell::new(None),
+            spawn_argv: None,
         }
     }
 
+    /// Records the argv that was explicitly requested for this pane,
+    /// so that it can be respawned when restoring a session
+    pub fn set_spawn_argv(&mut self, argv: Option<Vec<String>>) {
+        self.spawn_argv = argv;
+    }
+
     #[cfg(target_os = "macos")]
     fn divine_current_working_dir_macos(&self) -> Option<Url> {
         if let Some(pid) = self.pty.borrow().process_group_leader() {
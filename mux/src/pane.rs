@@ -42,6 +42,24 @@ pub struct SearchResult {
 
 pub use config::keyassignment::Pattern;
 
+/// Describes the health of the connection to the mux server
+/// that hosts a remote pane
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionStatus {
+    /// Whether the connection is currently established
+    pub connected: bool,
+    /// The round trip time of the most recent heartbeat, in milliseconds
+    pub rtt_ms: Option<u64>,
+    /// How long ago data was last received from the server, in milliseconds
+    pub since_last_response_ms: u64,
+    /// Whether the pane has been waiting on the server for longer than
+    /// expected; this is what drives the lag indicator in the pane
+    pub is_tardy: bool,
+    /// How many times the connection has been re-established
+    pub reconnects: usize,
+}
+luahelper::impl_lua_conversion!(ConnectionStatus);
+
 /// Why a close request is being made
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum CloseReason {
@@ -322,6 +340,16 @@ pub trait Pane: Downcast {
     /// a zoom-to-fill-all-the-tab-space operation.
     fn set_zoomed(&self, _zoomed: bool) {}
     fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()>;
+    /// Called when a key is released; only panes that report key
+    /// releases to their application need to implement this
+    fn key_up(&self, _key: KeyCode, _mods: KeyModifiers) -> anyhow::Result<()> {
+        Ok(())
+    }
+    /// Returns true if the application in this pane has asked to be
+    /// told about key releases
+    fn wants_key_up_events(&self) -> bool {
+        false
+    }
     fn mouse_event(&self, event: MouseEvent) -> anyhow::Result<()>;
     fn perform_actions(&self, _actions: Vec<termwiz::escape::Action>) {}
     fn is_dead(&self) -> bool;
@@ -370,10 +398,21 @@ pub trait Pane: Downcast {
     }
 
     fn get_current_working_dir(&self) -> Option<Url>;
+    /// Returns the argv that was explicitly requested when this pane
+    /// was spawned, or None if it is running the default program
+    fn get_spawn_argv(&self) -> Option<Vec<String>> {
+        None
+    }
     fn get_foreground_process_name(&self) -> Option<String> {
         None
     }
 
+    /// Returns the state of the connection to the mux server that
+    /// hosts this pane, or None if the pane is not a remote one
+    fn get_connection_status(&self) -> Option<ConnectionStatus> {
+        None
+    }
+
     fn trickle_paste(&self, text: String) -> anyhow::Result<()> {
         if text.len() <= PASTE_CHUNK_SIZE {
             // Send it all now
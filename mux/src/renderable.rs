@@ -77,22 +77,12 @@ pub fn terminal_get_lines(
     lines: Range<StableRowIndex>,
 ) -> (StableRowIndex, Vec<Line>) {
     let reverse = term.get_reverse_video();
-    let screen = term.screen_mut();
-    let phys_range = screen.stable_range(&lines);
-    (
-        screen.phys_to_stable_row_index(phys_range.start),
-        screen
-            .lines
-            .iter_mut()
-            .skip(phys_range.start)
-            .take(phys_range.end - phys_range.start)
-            .map(|line| {
-                let mut cloned = line.clone();
-                cloned.set_reverse(reverse, SEQ_ZERO);
-                cloned
-            })
-            .collect(),
-    )
+    let screen = term.screen();
+    let (first, mut lines) = screen.lines_in_stable_range(&lines);
+    for line in &mut lines {
+        line.set_reverse(reverse, SEQ_ZERO);
+    }
+    (first, lines)
 }
 
 /// Implements Pane::get_dimensions for Terminal
@@ -101,8 +91,8 @@ pub fn terminal_get_dimensions(term: &mut Terminal) -> RenderableDimensions {
     RenderableDimensions {
         cols: screen.physical_cols,
         viewport_rows: screen.physical_rows,
-        scrollback_rows: screen.lines.len(),
+        scrollback_rows: screen.total_lines(),
         physical_top: screen.visible_row_to_stable_row(0),
-        scrollback_top: screen.phys_to_stable_row_index(0),
+        scrollback_top: screen.scrollback_top(),
     }
 }
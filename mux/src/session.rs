@@ -0,0 +1,407 @@
+//! Session snapshots capture the layout of the windows, tabs and panes
+//! in the mux, along with enough information about each pane to respawn
+//! it, so that the layout can be recreated after the mux has been
+//! restarted.
+use crate::domain::{Domain, DomainState};
+use crate::pane::Pane;
+use crate::tab::{SplitDirectionAndSize, Tab};
+use crate::Mux;
+use anyhow::Context;
+use portable_pty::{CommandBuilder, PtySize};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// This type is used directly by the codec, take care to bump
+/// the codec version if you change this
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Default)]
+pub struct SessionSnapshot {
+    pub active_workspace: String,
+    pub windows: Vec<WindowSnapshot>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct WindowSnapshot {
+    pub workspace: String,
+    pub active_tab: usize,
+    pub tabs: Vec<TabSnapshot>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct TabSnapshot {
+    pub title: String,
+    pub size: PtySize,
+    pub active_pane: usize,
+    pub layout: PaneLayout,
+}
+
+/// Mirrors the split tree of a tab
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub enum PaneLayout {
+    Split {
+        left: Box<PaneLayout>,
+        right: Box<PaneLayout>,
+        node: SplitDirectionAndSize,
+    },
+    Pane(PaneSnapshot),
+}
+
+/// The information required to respawn a pane
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct PaneSnapshot {
+    /// The name of the domain that hosted the pane
+    pub domain: String,
+    /// The command that was explicitly requested when the pane was
+    /// spawned, or None if it was running the default program
+    pub argv: Option<Vec<String>>,
+    /// The working directory of the pane at the time of the snapshot
+    pub cwd: Option<String>,
+}
+
+impl PaneLayout {
+    fn from_tree(tree: bintree::Tree<PaneSnapshot, SplitDirectionAndSize>) -> Option<Self> {
+        match tree {
+            bintree::Tree::Empty => None,
+            bintree::Tree::Leaf(pane) => Some(PaneLayout::Pane(pane)),
+            bintree::Tree::Node {
+                left,
+                right,
+                data: Some(node),
+            } => Some(PaneLayout::Split {
+                left: Box::new(Self::from_tree(*left)?),
+                right: Box::new(Self::from_tree(*right)?),
+                node,
+            }),
+            bintree::Tree::Node { data: None, .. } => None,
+        }
+    }
+
+    pub fn to_tree(&self) -> bintree::Tree<(), SplitDirectionAndSize> {
+        match self {
+            PaneLayout::Pane(_) => bintree::Tree::Leaf(()),
+            PaneLayout::Split { left, right, node } => bintree::Tree::Node {
+                left: Box::new(left.to_tree()),
+                right: Box::new(right.to_tree()),
+                data: Some(*node),
+            },
+        }
+    }
+
+    /// Returns the top-left-most pane of this layout, which is the
+    /// pane that occupies the space before it is split up
+    pub fn first_pane(&self) -> &PaneSnapshot {
+        match self {
+            PaneLayout::Pane(pane) => pane,
+            PaneLayout::Split { left, .. } => left.first_pane(),
+        }
+    }
+}
+
+impl PaneSnapshot {
+    fn capture(mux: &Mux, pane: &Rc<dyn Pane>) -> Self {
+        let domain = match mux.get_domain(pane.domain_id()) {
+            Some(domain) => domain.domain_name().to_string(),
+            None => String::new(),
+        };
+        let cwd = pane
+            .get_current_working_dir()
+            .filter(|url| url.scheme() == "file")
+            .and_then(|url| url.to_file_path().ok())
+            .and_then(|path| path.to_str().map(|s| s.to_string()));
+
+        Self {
+            domain,
+            argv: pane.get_spawn_argv(),
+            cwd,
+        }
+    }
+
+    fn command(&self) -> Option<CommandBuilder> {
+        self.argv
+            .as_ref()
+            .map(|argv| CommandBuilder::from_argv(argv.iter().map(Into::into).collect()))
+    }
+
+    /// Resolves the domain that should host this pane.  If the domain
+    /// no longer exists, or is not attached, then the default domain
+    /// is used instead.
+    fn domain(&self, mux: &Mux) -> Arc<dyn Domain> {
+        match mux.get_domain_by_name(&self.domain) {
+            Some(domain) if domain.state() == DomainState::Attached => domain,
+            _ => {
+                log::warn!(
+                    "domain {} is not available; using the default domain",
+                    self.domain
+                );
+                mux.default_domain()
+            }
+        }
+    }
+}
+
+impl TabSnapshot {
+    fn capture(mux: &Mux, tab: &Rc<Tab>) -> Option<Self> {
+        let layout =
+            PaneLayout::from_tree(tab.map_pane_tree(|pane| PaneSnapshot::capture(mux, pane)))?;
+        Some(Self {
+            title: tab.get_title(),
+            size: tab.get_size(),
+            active_pane: tab.get_active_idx(),
+            layout,
+        })
+    }
+}
+
+impl SessionSnapshot {
+    /// Captures the windows, tabs and panes that are present in the mux
+    pub fn capture(mux: &Mux) -> Self {
+        let mut windows = vec![];
+        for window_id in mux.iter_windows() {
+            let window = match mux.get_window(window_id) {
+                Some(window) => window,
+                None => continue,
+            };
+            let tabs: Vec<TabSnapshot> = window
+                .iter()
+                .filter_map(|tab| TabSnapshot::capture(mux, tab))
+                .collect();
+            if tabs.is_empty() {
+                continue;
+            }
+            windows.push(WindowSnapshot {
+                workspace: window.get_workspace().to_string(),
+                active_tab: window.get_active_idx(),
+                tabs,
+            });
+        }
+
+        Self {
+            active_workspace: mux.active_workspace(),
+            windows,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("reading session from {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("parsing session from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating directory {}", parent.display()))?;
+        }
+        let data = serde_json::to_vec_pretty(self)?;
+        std::fs::write(path, data).with_context(|| format!("writing session to {}", path.display()))
+    }
+
+    /// Spawns a fresh set of windows, tabs and panes into the mux
+    /// to match the snapshot.
+    /// The windows are added alongside any pre-existing windows.
+    pub async fn restore(&self) -> anyhow::Result<()> {
+        let mux = Mux::get().ok_or_else(|| anyhow::anyhow!("must be called on main thread"))?;
+
+        for window in &self.windows {
+            let window_builder = mux.new_empty_window(Some(window.workspace.clone()));
+            let window_id = *window_builder;
+
+            for tab in &window.tabs {
+                if let Err(err) = restore_tab(&mux, window_id, tab).await {
+                    log::error!("Failed to restore tab: {:#}", err);
+                }
+            }
+
+            if let Some(mut win) = mux.get_window_mut(window_id) {
+                if window.active_tab < win.len() {
+                    win.save_and_then_set_active(window.active_tab);
+                }
+            }
+        }
+
+        if !self.active_workspace.is_empty()
+            && !mux
+                .iter_windows_in_workspace(&self.active_workspace)
+                .is_empty()
+        {
+            mux.set_active_workspace(&self.active_workspace);
+        }
+
+        Ok(())
+    }
+}
+
+async fn restore_tab(
+    mux: &Rc<Mux>,
+    window_id: crate::window::WindowId,
+    snapshot: &TabSnapshot,
+) -> anyhow::Result<()> {
+    let first = snapshot.layout.first_pane();
+    let tab = first
+        .domain(mux)
+        .spawn(snapshot.size, first.command(), first.cwd.clone(), window_id)
+        .await?;
+    let pane = tab
+        .get_active_pane()
+        .ok_or_else(|| anyhow::anyhow!("newly spawned tab to have a pane"))?;
+
+    // Each entry is a portion of the layout along with the pane that
+    // currently occupies the space that it describes.
+    // Splitting that pane places the new pane to its right or below it,
+    // which matches the shape of the snapshot tree.
+    let mut stack = vec![(&snapshot.layout, pane)];
+    while let Some((layout, pane)) = stack.pop() {
+        if let PaneLayout::Split { left, right, node } = layout {
+            let second = right.first_pane();
+            let new_pane = second
+                .domain(mux)
+                .split_pane(
+                    second.command(),
+                    second.cwd.clone(),
+                    tab.tab_id(),
+                    pane.pane_id(),
+                    node.direction,
+                )
+                .await?;
+            stack.push((&**right, new_pane));
+            stack.push((&**left, pane));
+        }
+    }
+
+    tab.apply_split_sizes(&snapshot.layout.to_tree());
+    if snapshot.active_pane < tab.count_panes() {
+        tab.set_active_idx(snapshot.active_pane);
+    }
+    tab.set_title(&snapshot.title);
+
+    Ok(())
+}
+
+/// Returns the location used for the session when no
+/// explicit path has been specified
+pub fn default_session_path() -> PathBuf {
+    config::DATA_DIR.join("session.json")
+}
+
+/// Saves the layout of the mux to the default session file
+/// if `autosave_session_on_exit` is enabled.
+/// This must be called before the windows are torn down, as there
+/// is nothing left to capture once the mux is empty.
+pub fn maybe_autosave(mux: &Mux) {
+    if !config::configuration().autosave_session_on_exit {
+        return;
+    }
+    let snapshot = SessionSnapshot::capture(mux);
+    if snapshot.is_empty() {
+        return;
+    }
+    let path = default_session_path();
+    match snapshot.save(&path) {
+        Ok(_) => log::info!("saved session to {}", path.display()),
+        Err(err) => log::error!("Failed to save session: {:#}", err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tab::SplitDirection;
+
+    fn size(rows: u16, cols: u16) -> PtySize {
+        PtySize {
+            rows,
+            cols,
+            pixel_width: cols * 10,
+            pixel_height: rows * 20,
+        }
+    }
+
+    fn pane(name: &str) -> PaneSnapshot {
+        PaneSnapshot {
+            domain: "local".to_string(),
+            argv: Some(vec![name.to_string()]),
+            cwd: None,
+        }
+    }
+
+    fn layout() -> PaneLayout {
+        PaneLayout::Split {
+            left: Box::new(PaneLayout::Pane(pane("a"))),
+            right: Box::new(PaneLayout::Split {
+                left: Box::new(PaneLayout::Pane(pane("b"))),
+                right: Box::new(PaneLayout::Pane(pane("c"))),
+                node: SplitDirectionAndSize {
+                    direction: SplitDirection::Vertical,
+                    first: size(11, 39),
+                    second: size(12, 39),
+                },
+            }),
+            node: SplitDirectionAndSize {
+                direction: SplitDirection::Horizontal,
+                first: size(24, 40),
+                second: size(24, 39),
+            },
+        }
+    }
+
+    #[test]
+    fn layout_from_tree() {
+        let tree = bintree::Tree::Node {
+            left: Box::new(bintree::Tree::Leaf(pane("a"))),
+            right: Box::new(bintree::Tree::Node {
+                left: Box::new(bintree::Tree::Leaf(pane("b"))),
+                right: Box::new(bintree::Tree::Leaf(pane("c"))),
+                data: Some(SplitDirectionAndSize {
+                    direction: SplitDirection::Vertical,
+                    first: size(11, 39),
+                    second: size(12, 39),
+                }),
+            }),
+            data: Some(SplitDirectionAndSize {
+                direction: SplitDirection::Horizontal,
+                first: size(24, 40),
+                second: size(24, 39),
+            }),
+        };
+
+        assert_eq!(PaneLayout::from_tree(tree), Some(layout()));
+        assert_eq!(PaneLayout::from_tree(bintree::Tree::Empty), None);
+    }
+
+    #[test]
+    fn first_pane() {
+        let layout = layout();
+        assert_eq!(layout.first_pane(), &pane("a"));
+        match &layout {
+            PaneLayout::Split { right, .. } => assert_eq!(right.first_pane(), &pane("b")),
+            _ => panic!("expected a split"),
+        }
+    }
+
+    #[test]
+    fn round_trip_json() {
+        let snapshot = SessionSnapshot {
+            active_workspace: "default".to_string(),
+            windows: vec![WindowSnapshot {
+                workspace: "default".to_string(),
+                active_tab: 0,
+                tabs: vec![TabSnapshot {
+                    title: "hello".to_string(),
+                    size: size(24, 80),
+                    active_pane: 2,
+                    layout: layout(),
+                }],
+            }],
+        };
+
+        let data = serde_json::to_string(&snapshot).unwrap();
+        let parsed: SessionSnapshot = serde_json::from_str(&data).unwrap();
+        assert_eq!(parsed, snapshot);
+    }
+}
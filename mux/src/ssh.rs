@@ -1,4 +1,4 @@
-use crate::connui::ConnectionUI;
+use crate::connui::{host_verification_changes, is_yes, ConnectionUI};
 use crate::domain::{alloc_domain_id, Domain, DomainId, DomainState};
 use crate::localpane::LocalPane;
 use crate::pane::{alloc_pane_id, Pane, PaneId};
@@ -24,7 +24,9 @@ use termwiz::lineedit::*;
 use termwiz::render::terminfo::TerminfoRenderer;
 use termwiz::surface::Change;
 use termwiz::terminal::{ScreenSize, Terminal, TerminalWaker};
-use wezterm_ssh::{ConfigMap, Session, SessionEvent, SshChildProcess, SshPty};
+use wezterm_ssh::{
+    ConfigMap, Forward, HostKeyStatus, Session, SessionEvent, SshChildProcess, SshPty,
+};
 
 #[derive(Default)]
 struct PasswordPromptHost {
@@ -56,6 +58,21 @@ impl LineEditorHost for PasswordPromptHost {
     }
 }
 
+/// Establishes the port forwards that are configured for the host.
+/// A forward that cannot be established doesn't prevent the session
+/// from being used, so failures are returned as messages that can
+/// be shown to the user.
+fn start_port_forwards(session: &Session, forwards: &[Forward]) -> Vec<String> {
+    let mut failures = vec![];
+    for forward in forwards {
+        if let Err(err) = smol::block_on(session.forward(forward)) {
+            log::error!("{:?}: {:#}", forward, err);
+            failures.push(format!("Port forwarding failed: {:#}", err));
+        }
+    }
+    failures
+}
+
 pub fn ssh_connect_with_ui(
     ssh_config: wezterm_ssh::ConfigMap,
     ui: &mut ConnectionUI,
@@ -76,16 +93,12 @@ pub fn ssh_connect_with_ui(
                     }
                 }
                 SessionEvent::HostVerify(verify) => {
-                    ui.output_str(&format!("{}\n", verify.message));
-                    let ok = if let Ok(line) = ui.input("Enter [y/n]> ") {
-                        match line.as_ref() {
-                            "y" | "Y" | "yes" | "YES" => true,
-                            "n" | "N" | "no" | "NO" | _ => false,
-                        }
-                    } else {
-                        false
-                    };
-                    smol::block_on(verify.answer(ok)).context("send verify response")?;
+                    let ok = ui.verify_host(&verify).unwrap_or(false);
+                    // The session refuses a revoked key without waiting
+                    // for an answer, so there is nobody to send it to
+                    if !matches!(verify.status, HostKeyStatus::Revoked(_)) {
+                        smol::block_on(verify.answer(ok)).context("send verify response")?;
+                    }
                 }
                 SessionEvent::Authenticate(auth) => {
                     if !auth.username.is_empty() {
@@ -117,7 +130,17 @@ pub fn ssh_connect_with_ui(
                 SessionEvent::Error(err) => {
                     anyhow::bail!("Error: {}", err);
                 }
-                SessionEvent::Authenticated => return Ok(session),
+                SessionEvent::Authenticated => {
+                    match Forward::from_config(&ssh_config) {
+                        Ok(forwards) => {
+                            for failure in start_port_forwards(&session, &forwards) {
+                                ui.output_str(&format!("{}\n", failure));
+                            }
+                        }
+                        Err(err) => ui.output_str(&format!("{:#}\n", err)),
+                    }
+                    return Ok(session);
+                }
             }
         }
         bail!("unable to authenticate session");
@@ -137,17 +160,20 @@ pub struct RemoteSshDomain {
     id: DomainId,
     name: String,
     events: RefCell<Option<smol::channel::Receiver<SessionEvent>>>,
+    forwards: Vec<Forward>,
 }
 
 impl RemoteSshDomain {
     pub fn with_ssh_config(name: &str, ssh_config: ConfigMap) -> anyhow::Result<Self> {
         let id = alloc_domain_id();
+        let forwards = Forward::from_config(&ssh_config)?;
         let (session, events) = Session::connect(ssh_config.clone())?;
         Ok(Self {
             id,
             name: format!("SSH to {}", name),
             session,
             events: RefCell::new(Some(events)),
+            forwards,
         })
     }
 
@@ -171,6 +197,7 @@ fn connect_ssh_session(
     size: Arc<Mutex<PtySize>>,
     command_line: Option<String>,
     env: HashMap<String, String>,
+    forwards: Vec<Forward>,
 ) -> anyhow::Result<()> {
     struct StdoutShim<'a> {
         size: Arc<Mutex<PtySize>>,
@@ -339,20 +366,24 @@ fn connect_ssh_session(
                 }
             }
             SessionEvent::HostVerify(verify) => {
-                shim.output_line(&verify.message)?;
-                let mut editor = LineEditor::new(&mut shim);
-                let mut host = PasswordPromptHost::default();
-                host.echo = true;
-                editor.set_prompt("Enter [y/n]> ");
-                let ok = if let Some(line) = editor.read_line(&mut host)? {
-                    match line.as_ref() {
-                        "y" | "Y" | "yes" | "YES" => true,
-                        "n" | "N" | "no" | "NO" | _ => false,
+                let (changes, prompt) = host_verification_changes(&verify);
+                shim.render(&changes)?;
+                let ok = match prompt {
+                    Some(prompt) => {
+                        let mut editor = LineEditor::new(&mut shim);
+                        let mut host = PasswordPromptHost::default();
+                        host.echo = true;
+                        editor.set_prompt(prompt);
+                        match editor.read_line(&mut host)? {
+                            Some(line) => is_yes(&line),
+                            None => false,
+                        }
                     }
-                } else {
-                    false
+                    None => false,
                 };
-                smol::block_on(verify.answer(ok)).context("send verify response")?;
+                if !matches!(verify.status, HostKeyStatus::Revoked(_)) {
+                    smol::block_on(verify.answer(ok)).context("send verify response")?;
+                }
             }
             SessionEvent::Authenticate(auth) => {
                 if !auth.username.is_empty() {
@@ -384,6 +415,10 @@ fn connect_ssh_session(
                 shim.output_line(&format!("Error: {}", err))?;
             }
             SessionEvent::Authenticated => {
+                for failure in start_port_forwards(&session, &forwards) {
+                    shim.output_line(&failure)?;
+                }
+
                 // Our session has been authenticated: we can now
                 // set up the real pty for the pane
                 match smol::block_on(session.request_pty(
@@ -519,6 +554,7 @@ impl Domain for RemoteSshDomain {
             // to perform the blocking (from its perspective) terminal
             // UI to carry out any authentication.
             let session = self.session.clone();
+            let forwards = self.forwards.clone();
             let mut stdout_write = BufWriter::new(stdout_write);
             std::thread::spawn(move || {
                 if let Err(err) = connect_ssh_session(
@@ -533,6 +569,7 @@ impl Domain for RemoteSshDomain {
                     size,
                     command_line,
                     env,
+                    forwards,
                 ) {
                     let _ = write!(stdout_write, "{:#}", err);
                     log::error!("Failed to connect ssh: {:#}", err);
@@ -681,6 +718,10 @@ impl Domain for RemoteSshDomain {
     fn state(&self) -> DomainState {
         DomainState::Attached
     }
+
+    fn sftp(&self) -> Option<wezterm_ssh::Sftp> {
+        Some(self.session.sftp())
+    }
 }
 
 #[derive(Debug)]
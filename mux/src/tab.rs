@@ -25,6 +25,7 @@ pub struct Tab {
     size: RefCell<PtySize>,
     active: RefCell<usize>,
     zoomed: RefCell<Option<Rc<dyn Pane>>>,
+    title: RefCell<String>,
 }
 
 #[derive(Clone)]
@@ -154,14 +155,19 @@ fn pane_tree(
     tree: &Tree,
     tab_id: TabId,
     window_id: WindowId,
+    workspace: &str,
     active: Option<&Rc<dyn Pane>>,
     zoomed: Option<&Rc<dyn Pane>>,
 ) -> PaneNode {
     match tree {
         Tree::Empty => PaneNode::Empty,
         Tree::Node { left, right, data } => PaneNode::Split {
-            left: Box::new(pane_tree(&*left, tab_id, window_id, active, zoomed)),
-            right: Box::new(pane_tree(&*right, tab_id, window_id, active, zoomed)),
+            left: Box::new(pane_tree(
+                &*left, tab_id, window_id, workspace, active, zoomed,
+            )),
+            right: Box::new(pane_tree(
+                &*right, tab_id, window_id, workspace, active, zoomed,
+            )),
             node: data.unwrap(),
         },
         Tree::Leaf(pane) => {
@@ -172,6 +178,7 @@ fn pane_tree(
                 window_id,
                 tab_id,
                 pane_id: pane.pane_id(),
+                workspace: workspace.to_string(),
                 title: pane.get_title(),
                 is_active_pane: is_pane(pane, &active),
                 is_zoomed_pane: is_pane(pane, &zoomed),
@@ -409,6 +416,7 @@ impl Tab {
             size: RefCell::new(*size),
             active: RefCell::new(0),
             zoomed: RefCell::new(None),
+            title: RefCell::new(String::new()),
         }
     }
 
@@ -484,15 +492,100 @@ impl Tab {
             }
         };
 
+        let workspace = match mux.get_window(window_id) {
+            Some(w) => w.get_workspace().to_string(),
+            None => return PaneNode::Empty,
+        };
+
         let zoomed = self.zoomed.borrow();
         let active = self.get_active_pane();
         if let Some(root) = self.pane.borrow().as_ref() {
-            pane_tree(root, tab_id, window_id, active.as_ref(), zoomed.as_ref())
+            pane_tree(
+                root,
+                tab_id,
+                window_id,
+                &workspace,
+                active.as_ref(),
+                zoomed.as_ref(),
+            )
         } else {
             PaneNode::Empty
         }
     }
 
+    /// Returns a copy of the split tree of this tab, with each of
+    /// the panes mapped through the provided function.
+    /// This is used to capture the layout when saving a session.
+    pub fn map_pane_tree<T, F>(&self, f: F) -> bintree::Tree<T, SplitDirectionAndSize>
+    where
+        F: Fn(&Rc<dyn Pane>) -> T,
+    {
+        fn map_tree<T, F>(tree: &Tree, f: &F) -> bintree::Tree<T, SplitDirectionAndSize>
+        where
+            F: Fn(&Rc<dyn Pane>) -> T,
+        {
+            match tree {
+                Tree::Empty => bintree::Tree::Empty,
+                Tree::Leaf(pane) => bintree::Tree::Leaf(f(pane)),
+                Tree::Node { left, right, data } => bintree::Tree::Node {
+                    left: Box::new(map_tree(&*left, f)),
+                    right: Box::new(map_tree(&*right, f)),
+                    data: *data,
+                },
+            }
+        }
+
+        match self.pane.borrow().as_ref() {
+            Some(root) => map_tree(root, &f),
+            None => bintree::Tree::Empty,
+        }
+    }
+
+    /// Replaces the split sizes of this tab with those from `layout`,
+    /// which is expected to have the same shape as the tree of this
+    /// tab, and resizes the panes to match.
+    /// This is used to restore the split ratios when restoring a session;
+    /// portions of `layout` that don't match the shape of the tree are
+    /// ignored.
+    pub fn apply_split_sizes<T>(&self, layout: &bintree::Tree<T, SplitDirectionAndSize>) {
+        fn assign<T>(tree: &mut Tree, layout: &bintree::Tree<T, SplitDirectionAndSize>) {
+            if let (
+                Tree::Node { left, right, data },
+                bintree::Tree::Node {
+                    left: layout_left,
+                    right: layout_right,
+                    data: Some(layout_data),
+                },
+            ) = (tree, layout)
+            {
+                if let Some(data) = data {
+                    if data.direction == layout_data.direction {
+                        *data = *layout_data;
+                    }
+                }
+                assign(&mut *left, &*layout_left);
+                assign(&mut *right, &*layout_right);
+            }
+        }
+
+        if self.zoomed.borrow().is_some() {
+            return;
+        }
+
+        let mut root = self.pane.borrow_mut();
+        if let Some(root) = root.as_mut() {
+            assign(root, layout);
+            if let Tree::Node {
+                data: Some(data), ..
+            } = root
+            {
+                *self.size.borrow_mut() = data.size();
+            }
+            let size = *self.size.borrow();
+            apply_sizes_from_splits(root, &size);
+        }
+    }
+
     /// Returns a count of how many panes are in this tab
     pub fn count_panes(&self) -> usize {
         let mut count = 0;
@@ -702,6 +795,17 @@ impl Tab {
         dividers
     }
 
+    /// Returns the title that was explicitly assigned to this tab.
+    /// An empty string indicates that no title has been set, in which
+    /// case the title of the active pane is typically used instead.
+    pub fn get_title(&self) -> String {
+        self.title.borrow().clone()
+    }
+
+    pub fn set_title(&self, title: &str) {
+        *self.title.borrow_mut() = title.to_string();
+    }
+
     pub fn tab_id(&self) -> TabId {
         self.id
     }
@@ -973,18 +1077,25 @@ impl Tab {
     /// Adjusts the size of the active pane in the specified direction
     /// by the specified amount.
     pub fn adjust_pane_size(&self, direction: PaneDirection, amount: usize) {
+        let active_index = *self.active.borrow();
+        self.adjust_pane_size_at(active_index, direction, amount);
+    }
+
+    /// Adjusts the size of the pane at `pane_index` in the specified
+    /// direction by the specified amount, without changing which pane
+    /// is active.
+    pub fn adjust_pane_size_at(&self, pane_index: usize, direction: PaneDirection, amount: usize) {
         if self.zoomed.borrow().is_some() {
             return;
         }
-        let active_index = *self.active.borrow();
         let mut root = self.pane.borrow_mut();
         let mut cursor = root.take().unwrap().cursor();
         let mut index = 0;
 
-        // Position cursor on the active leaf
+        // Position cursor on the target leaf
         loop {
             if cursor.is_leaf() {
-                if index == active_index {
+                if index == pane_index {
                     // Found it
                     break;
                 }
@@ -1000,7 +1111,7 @@ impl Tab {
             }
         }
 
-        // We are on the active leaf.
+        // We are on the target leaf.
         // Now we go up until we find the parent node that is
         // aligned with the desired direction.
         let split_direction = match direction {
@@ -1046,34 +1157,64 @@ impl Tab {
             }
             self.toggle_zoom();
         }
+
+        if let Some(pane_idx) = self.get_pane_direction(direction) {
+            self.set_active_idx(pane_idx);
+        }
+    }
+
+    /// Activate the pane that is adjacent to the pane at `pane_index`
+    /// in the specified direction.  Unlike `activate_pane_direction`,
+    /// the starting point need not be the active pane.
+    pub fn activate_pane_direction_from(&self, pane_index: usize, direction: PaneDirection) {
+        if self.zoomed.borrow().is_some() {
+            if !configuration().unzoom_on_switch_pane {
+                return;
+            }
+            self.toggle_zoom();
+        }
+
+        let panes = self.iter_panes();
+        if let Some(start) = panes.iter().find(|pane| pane.index == pane_index) {
+            if let Some(pane_idx) = Self::pane_direction_from(&panes, start, direction) {
+                self.set_active_idx(pane_idx);
+            }
+        }
+    }
+
+    /// Returns the index of the pane that is adjacent to the active
+    /// pane in the specified direction.
+    /// In cases where there are multiple adjacent panes in the
+    /// intended direction, we take the pane that has the largest
+    /// edge intersection.
+    pub fn get_pane_direction(&self, direction: PaneDirection) -> Option<usize> {
         let panes = self.iter_panes();
 
         let active = match panes.iter().find(|pane| pane.is_active) {
             Some(p) => p,
             None => {
                 // No active pane somehow...
-                self.set_active_idx(0);
-                return;
+                return Some(0);
             }
         };
 
+        Self::pane_direction_from(&panes, active, direction)
+    }
+
+    fn pane_direction_from(
+        panes: &[PositionedPane],
+        active: &PositionedPane,
+        direction: PaneDirection,
+    ) -> Option<usize> {
         if matches!(direction, PaneDirection::Next | PaneDirection::Prev) {
             let max_pane_id = panes.iter().map(|p| p.index).max().unwrap_or(active.index);
 
-            if direction == PaneDirection::Next {
-                self.set_active_idx(if active.index == max_pane_id {
-                    0
-                } else {
-                    active.index + 1
-                });
-            } else {
-                self.set_active_idx(if active.index == 0 {
-                    max_pane_id
-                } else {
-                    active.index - 1
-                });
-            }
-            return;
+            return Some(match direction {
+                PaneDirection::Next if active.index == max_pane_id => 0,
+                PaneDirection::Next => active.index + 1,
+                _ if active.index == 0 => max_pane_id,
+                _ => active.index - 1,
+            });
         }
 
         let mut best = None;
@@ -1093,7 +1234,7 @@ impl Tab {
             .count()
         }
 
-        for pane in &panes {
+        for pane in panes {
             let score = match direction {
                 PaneDirection::Right => {
                     if pane.left == active.left + active.width + 1 {
@@ -1135,24 +1276,130 @@ impl Tab {
             }
         }
 
-        if let Some((_, target)) = best.take() {
-            self.set_active_idx(target.index);
+        best.map(|(_, target)| target.index)
+    }
+
+    /// Swaps the active pane with the pane at `pane_index`.
+    /// If `keep_focus` is true, the active pane remains active
+    /// in its new position, otherwise the pane that was swapped
+    /// into the active position becomes active.
+    pub fn swap_active_with_index(&self, pane_index: usize, keep_focus: bool) -> Option<()> {
+        self.set_zoomed(false);
+        let active_idx = self.get_active_idx();
+        let mut panes: Vec<Rc<dyn Pane>> = self
+            .iter_panes_ignoring_zoom()
+            .into_iter()
+            .map(|p| p.pane)
+            .collect();
+        if pane_index >= panes.len() || active_idx >= panes.len() {
+            return None;
+        }
+        panes.swap(active_idx, pane_index);
+        self.assign_panes_in_order(panes);
+        if keep_focus {
+            self.set_active_idx(pane_index);
+        }
+        Some(())
+    }
+
+    /// Rotates the panes in the tab clockwise; each pane moves into
+    /// the position of the pane that follows it in the pane tree,
+    /// with the last pane moving into the first position.
+    /// The active pane remains active in its new position.
+    pub fn rotate_clockwise(&self) {
+        self.rotate_panes(|panes| panes.rotate_right(1));
+    }
+
+    /// Rotates the panes in the tab counter-clockwise; each pane moves
+    /// into the position of the pane that precedes it in the pane tree,
+    /// with the first pane moving into the last position.
+    /// The active pane remains active in its new position.
+    pub fn rotate_counter_clockwise(&self) {
+        self.rotate_panes(|panes| panes.rotate_left(1));
+    }
+
+    fn rotate_panes<F: FnOnce(&mut [Rc<dyn Pane>])>(&self, rotate: F) {
+        self.set_zoomed(false);
+        let active = self.get_active_pane();
+        let mut panes: Vec<Rc<dyn Pane>> = self
+            .iter_panes_ignoring_zoom()
+            .into_iter()
+            .map(|p| p.pane)
+            .collect();
+        if panes.len() < 2 {
+            return;
+        }
+        rotate(&mut panes);
+        self.assign_panes_in_order(panes);
+        if let Some(active) = active {
+            self.set_active_pane(&active);
+        }
+    }
+
+    /// Assigns the supplied panes to the leaves of the pane tree, in
+    /// the order they would be visited by a preorder traversal.
+    /// Each pane is resized to fit its new position.
+    /// The caller must supply exactly one pane per leaf.
+    fn assign_panes_in_order(&self, panes: Vec<Rc<dyn Pane>>) {
+        let root_size = *self.size.borrow();
+        let mut root = self.pane.borrow_mut();
+        let mut cursor = root.take().unwrap().cursor();
+        let mut panes = panes.into_iter();
+
+        loop {
+            let pane_size = if let Some((branch, Some(parent))) = cursor.path_to_root().next() {
+                if branch == PathBranch::IsRight {
+                    parent.second
+                } else {
+                    parent.first
+                }
+            } else {
+                root_size
+            };
+
+            if let Some(leaf) = cursor.leaf_mut() {
+                if let Some(pane) = panes.next() {
+                    pane.resize(pane_size).ok();
+                    *leaf = pane;
+                }
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(c) => {
+                    root.replace(c.tree());
+                    break;
+                }
+            }
         }
     }
 
     pub fn prune_dead_panes(&self) -> bool {
-        self.remove_pane_if(|_, pane| pane.is_dead())
+        !self
+            .remove_pane_if(|_, pane| pane.is_dead(), true)
+            .is_empty()
     }
 
     pub fn kill_pane(&self, pane_id: PaneId) -> bool {
-        self.remove_pane_if(|_, pane| pane.pane_id() == pane_id)
+        !self
+            .remove_pane_if(|_, pane| pane.pane_id() == pane_id, true)
+            .is_empty()
     }
 
     pub fn kill_panes_in_domain(&self, domain: DomainId) -> bool {
-        self.remove_pane_if(|_, pane| pane.domain_id() == domain)
+        !self
+            .remove_pane_if(|_, pane| pane.domain_id() == domain, true)
+            .is_empty()
     }
 
-    fn remove_pane_if<F>(&self, f: F) -> bool
+    /// Removes the pane from this tab without killing it, so that
+    /// it can be placed into a different tab.
+    /// Returns the pane if it was part of this tab.
+    pub fn remove_pane(&self, pane_id: PaneId) -> Option<Rc<dyn Pane>> {
+        self.remove_pane_if(|_, pane| pane.pane_id() == pane_id, false)
+            .pop()
+    }
+
+    fn remove_pane_if<F>(&self, f: F, kill: bool) -> Vec<Rc<dyn Pane>>
     where
         F: Fn(usize, &Rc<dyn Pane>) -> bool,
     {
@@ -1193,7 +1440,7 @@ impl Tab {
                         let parent;
                         match cursor.unsplit_leaf() {
                             Ok((c, dead, p)) => {
-                                dead_panes.push(dead.pane_id());
+                                dead_panes.push(dead);
                                 parent = p.unwrap();
                                 cursor = c;
                             }
@@ -1201,7 +1448,7 @@ impl Tab {
                                 // We might be the root, for example
                                 if c.is_top() && c.is_leaf() {
                                     root.replace(Tree::Empty);
-                                    dead_panes.push(pane.pane_id());
+                                    dead_panes.push(pane);
                                 } else {
                                     root.replace(c.tree());
                                 }
@@ -1243,18 +1490,17 @@ impl Tab {
             *self.active.borrow_mut() = active_idx;
         }
 
-        if !dead_panes.is_empty() {
+        if kill && !dead_panes.is_empty() {
+            let dead_pane_ids: Vec<PaneId> = dead_panes.iter().map(|p| p.pane_id()).collect();
             promise::spawn::spawn_into_main_thread(async move {
                 let mux = Mux::get().unwrap();
-                for pane_id in dead_panes.into_iter() {
+                for pane_id in dead_pane_ids.into_iter() {
                     mux.remove_pane(pane_id);
                 }
             })
             .detach();
-            true
-        } else {
-            false
         }
+        dead_panes
     }
 
     pub fn can_close_without_prompting(&self, reason: CloseReason) -> bool {
@@ -1291,7 +1537,6 @@ impl Tab {
             .map(|p| Rc::clone(&p.pane))
     }
 
-    #[allow(unused)]
     pub fn get_active_idx(&self) -> usize {
         *self.active.borrow()
     }
@@ -1410,6 +1655,37 @@ impl Tab {
         pane_index: usize,
         direction: SplitDirection,
         pane: Rc<dyn Pane>,
+    ) -> anyhow::Result<usize> {
+        self.split_and_insert_impl(pane_index, direction, pane, false)
+    }
+
+    /// Split the pane that has pane_index such that the provided Pane
+    /// instance is placed on the side of it indicated by `direction`.
+    /// Returns the resultant index of the newly inserted pane.
+    pub fn split_and_insert_direction(
+        &self,
+        pane_index: usize,
+        direction: PaneDirection,
+        pane: Rc<dyn Pane>,
+    ) -> anyhow::Result<usize> {
+        let (direction, before) = match direction {
+            PaneDirection::Left => (SplitDirection::Horizontal, true),
+            PaneDirection::Right => (SplitDirection::Horizontal, false),
+            PaneDirection::Up => (SplitDirection::Vertical, true),
+            PaneDirection::Down => (SplitDirection::Vertical, false),
+            PaneDirection::Next | PaneDirection::Prev => {
+                anyhow::bail!("{:?} is not a valid split direction", direction)
+            }
+        };
+        self.split_and_insert_impl(pane_index, direction, pane, before)
+    }
+
+    fn split_and_insert_impl(
+        &self,
+        pane_index: usize,
+        direction: SplitDirection,
+        pane: Rc<dyn Pane>,
+        before: bool,
     ) -> anyhow::Result<usize> {
         if self.zoomed.borrow().is_some() {
             anyhow::bail!("cannot split while zoomed");
@@ -1454,10 +1730,20 @@ impl Tab {
 
             let existing_pane = Rc::clone(cursor.leaf_mut().unwrap());
 
-            existing_pane.resize(split_info.first)?;
-            pane.resize(split_info.second.clone())?;
+            let (existing_size, new_size) = if before {
+                (split_info.second, split_info.first)
+            } else {
+                (split_info.first, split_info.second)
+            };
+            existing_pane.resize(existing_size)?;
+            pane.resize(new_size)?;
 
-            match cursor.split_leaf_and_insert_right(pane) {
+            let split_result = if before {
+                cursor.split_leaf_and_insert_left(pane)
+            } else {
+                cursor.split_leaf_and_insert_right(pane)
+            };
+            match split_result {
                 Ok(c) => cursor = c,
                 Err(c) => {
                     root.replace(c.tree());
@@ -1470,14 +1756,15 @@ impl Tab {
             match cursor.assign_node(Some(split_info)) {
                 Err(c) | Ok(c) => root.replace(c.tree()),
             };
-
-            *self.active.borrow_mut() = pane_index + 1;
         }
 
+        let new_index = if before { pane_index } else { pane_index + 1 };
+        *self.active.borrow_mut() = new_index;
+
         log::debug!("split info after split: {:#?}", self.iter_splits());
         log::debug!("pane info after split: {:#?}", self.iter_panes());
 
-        Ok(pane_index + 1)
+        Ok(new_index)
     }
 }
 
@@ -1515,6 +1802,17 @@ impl PaneNode {
         }
     }
 
+    pub fn workspace(&self) -> Option<String> {
+        match self {
+            PaneNode::Empty => None,
+            PaneNode::Split { left, right, .. } => match left.workspace() {
+                Some(res) => Some(res),
+                None => right.workspace(),
+            },
+            PaneNode::Leaf(entry) => Some(entry.workspace.clone()),
+        }
+    }
+
     pub fn window_and_tab_ids(&self) -> Option<(WindowId, TabId)> {
         match self {
             PaneNode::Empty => None,
@@ -1534,6 +1832,7 @@ pub struct PaneEntry {
     pub window_id: WindowId,
     pub tab_id: TabId,
     pub pane_id: PaneId,
+    pub workspace: String,
     pub title: String,
     pub size: PtySize,
     pub working_dir: Option<SerdeUrl>,
@@ -1829,4 +2128,94 @@ mod test {
         assert_eq!(390, panes[2].pixel_width);
         assert_eq!(600, panes[2].pixel_height);
     }
+
+    fn pane_ids(tab: &Tab) -> Vec<PaneId> {
+        tab.iter_panes().iter().map(|p| p.pane.pane_id()).collect()
+    }
+
+    #[test]
+    fn tab_rearrangement() {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 800,
+            pixel_height: 600,
+        };
+
+        let tab = Tab::new(&size);
+        tab.assign_pane(&FakePane::new(1, size));
+        let horz_size = tab
+            .compute_split_size(0, SplitDirection::Horizontal)
+            .unwrap();
+        tab.split_and_insert(
+            0,
+            SplitDirection::Horizontal,
+            FakePane::new(2, horz_size.second),
+        )
+        .unwrap();
+        let vert_size = tab.compute_split_size(1, SplitDirection::Vertical).unwrap();
+        tab.split_and_insert(
+            1,
+            SplitDirection::Vertical,
+            FakePane::new(3, vert_size.second),
+        )
+        .unwrap();
+
+        // 1 is on the left, 2 is top right, 3 is bottom right
+        assert_eq!(pane_ids(&tab), vec![1, 2, 3]);
+        assert_eq!(tab.get_active_idx(), 2);
+        assert_eq!(tab.get_pane_direction(PaneDirection::Up), Some(1));
+        assert_eq!(tab.get_pane_direction(PaneDirection::Left), Some(0));
+        assert_eq!(tab.get_pane_direction(PaneDirection::Right), None);
+        assert_eq!(tab.get_pane_direction(PaneDirection::Next), Some(0));
+
+        // Resizing an inactive pane leaves the focus alone
+        tab.adjust_pane_size_at(0, PaneDirection::Right, 2);
+        assert_eq!(tab.iter_panes()[0].width, 42);
+        assert_eq!(tab.get_active_idx(), 2);
+        tab.adjust_pane_size_at(0, PaneDirection::Left, 2);
+        assert_eq!(tab.iter_panes()[0].width, 40);
+        assert_eq!(tab.get_active_idx(), 2);
+
+        // Swap 3 with 1, keeping the focus on 3
+        tab.swap_active_with_index(0, true).unwrap();
+        assert_eq!(pane_ids(&tab), vec![3, 2, 1]);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+        let panes = tab.iter_panes();
+        assert_eq!(panes[0].width, 40);
+        assert_eq!(panes[0].height, 24);
+
+        // Swap 3 with 2; the focus stays in the left position
+        tab.swap_active_with_index(1, false).unwrap();
+        assert_eq!(pane_ids(&tab), vec![2, 3, 1]);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 2);
+
+        tab.rotate_clockwise();
+        assert_eq!(pane_ids(&tab), vec![1, 2, 3]);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 2);
+
+        tab.rotate_counter_clockwise();
+        assert_eq!(pane_ids(&tab), vec![2, 3, 1]);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 2);
+
+        // Remove 3 without killing it, and join it back to the left of 2
+        let pane = tab.remove_pane(3).unwrap();
+        assert_eq!(pane.pane_id(), 3);
+        assert_eq!(pane_ids(&tab), vec![2, 1]);
+        assert!(tab.remove_pane(3).is_none());
+
+        let idx = tab
+            .split_and_insert_direction(0, PaneDirection::Left, pane)
+            .unwrap();
+        assert_eq!(idx, 0);
+        assert_eq!(pane_ids(&tab), vec![3, 2, 1]);
+        assert_eq!(tab.get_active_pane().unwrap().pane_id(), 3);
+        let panes = tab.iter_panes();
+        assert_eq!(panes[0].left, 0);
+        assert_eq!(panes[1].left, panes[0].width + 1);
+
+        assert!(tab
+            .split_and_insert_direction(0, PaneDirection::Next, FakePane::new(4, size))
+            .is_err());
+    }
 }
@@ -1,13 +1,14 @@
 use crate::domain::{alloc_domain_id, Domain, DomainId, DomainState};
 use crate::pane::{Pane, PaneId};
 use crate::tab::{SplitDirection, Tab, TabId};
+use crate::tmux_commands::{DetachClient, ListAllPanes, NewWindow, SplitPane, TmuxCommand};
 use crate::window::WindowId;
 use crate::Mux;
 use anyhow::anyhow;
 use async_trait::async_trait;
-use portable_pty::{CommandBuilder, PtySize};
-use std::cell::RefCell;
-use std::collections::VecDeque;
+use portable_pty::{CommandBuilder, ExitStatus, PtySize};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
 use std::sync::Arc;
 use tmux_cc::*;
@@ -17,100 +18,19 @@ enum State {
     WaitForInitialGuard,
     Idle,
     WaitingForResponse,
+    Detached,
 }
 
-trait TmuxCommand {
-    fn get_command(&self) -> String;
-    fn process_result(&self, domain_id: DomainId, result: &Guarded) -> anyhow::Result<()>;
-}
-
-struct ListAllPanes;
-impl TmuxCommand for ListAllPanes {
-    fn get_command(&self) -> String {
-        "list-panes -aF '#{session_id} #{window_id} #{pane_id} \
-            #{pane_index} #{cursor_x} #{cursor_y} #{pane_width} #{pane_height} \
-            #{pane_left} #{pane_top}'\n"
-            .to_owned()
-    }
-
-    fn process_result(&self, domain_id: DomainId, result: &Guarded) -> anyhow::Result<()> {
-        #[derive(Debug)]
-        #[allow(dead_code)]
-        struct Item {
-            session_id: TmuxSessionId,
-            window_id: TmuxWindowId,
-            pane_id: TmuxPaneId,
-            pane_index: u64,
-            cursor_x: u64,
-            cursor_y: u64,
-            pane_width: u64,
-            pane_height: u64,
-            pane_left: u64,
-            pane_top: u64,
-        }
-
-        let mut items = vec![];
-
-        for line in result.output.split('\n') {
-            if line.is_empty() {
-                continue;
-            }
-            let mut fields = line.split(' ');
-            let session_id = fields.next().ok_or_else(|| anyhow!("missing session_id"))?;
-            let window_id = fields.next().ok_or_else(|| anyhow!("missing window_id"))?;
-            let pane_id = fields.next().ok_or_else(|| anyhow!("missing pane_id"))?;
-            let pane_index = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_index"))?
-                .parse()?;
-            let cursor_x = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing cursor_x"))?
-                .parse()?;
-            let cursor_y = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing cursor_y"))?
-                .parse()?;
-            let pane_width = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_width"))?
-                .parse()?;
-            let pane_height = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_height"))?
-                .parse()?;
-            let pane_left = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_left"))?
-                .parse()?;
-            let pane_top = fields
-                .next()
-                .ok_or_else(|| anyhow!("missing pane_top"))?
-                .parse()?;
-
-            // These ids all have various sigils such as `$`, `%`, `@`,
-            // so skip those prior to parsing them
-            let session_id = session_id[1..].parse()?;
-            let window_id = window_id[1..].parse()?;
-            let pane_id = pane_id[1..].parse()?;
-
-            items.push(Item {
-                session_id,
-                window_id,
-                pane_id,
-                pane_index,
-                cursor_x,
-                cursor_y,
-                pane_width,
-                pane_height,
-                pane_left,
-                pane_top,
-            });
-        }
-
-        log::error!("panes in domain_id {}: {:?}", domain_id, items);
-        Ok(())
-    }
+/// Tracks a pane that lives in the remote tmux server and the
+/// local pane that mirrors it
+pub(crate) struct TmuxRemotePane {
+    pub local_pane_id: PaneId,
+    /// Output from tmux is sent to the local pane via this channel
+    pub output_write: crossbeam::channel::Sender<Vec<u8>>,
+    /// Used to signal the local pane that the remote pane has gone away
+    pub exit_tx: crossbeam::channel::Sender<ExitStatus>,
+    pub cursor_x: u64,
+    pub cursor_y: u64,
 }
 
 pub(crate) struct TmuxDomainState {
@@ -119,6 +39,15 @@ pub(crate) struct TmuxDomainState {
     parser: RefCell<Parser>,
     state: RefCell<State>,
     cmd_queue: RefCell<VecDeque<Box<dyn TmuxCommand>>>,
+    /// true if a ListAllPanes command without a notifier is queued
+    sync_pending: Cell<bool>,
+    pub(crate) remote_panes: RefCell<HashMap<TmuxPaneId, TmuxRemotePane>>,
+    /// Maps tmux windows to the local tabs that represent them
+    pub(crate) gui_tabs: RefCell<HashMap<TmuxWindowId, TabId>>,
+    /// The local window that holds the tabs for this session
+    pub(crate) gui_window: RefCell<Option<WindowId>>,
+    /// The most recent size that we reported via refresh-client
+    client_size: Cell<Option<(u16, u16)>>,
 }
 
 pub struct TmuxDomain {
@@ -126,40 +55,56 @@ pub struct TmuxDomain {
 }
 
 impl TmuxDomainState {
-    pub fn advance(&self, b: u8) {
+    pub fn advance(self: &Arc<Self>, b: u8) {
         let mut parser = self.parser.borrow_mut();
         if let Some(event) = parser.advance_byte(b) {
             let state = *self.state.borrow();
-            log::error!("tmux: {:?} in state {:?}", event, state);
-            if let Event::Guarded(response) = event {
-                match state {
+            log::trace!("tmux: {:?} in state {:?}", event, state);
+            match event {
+                Event::Guarded(response) => match state {
                     State::WaitForInitialGuard => {
                         *self.state.borrow_mut() = State::Idle;
                     }
                     State::WaitingForResponse => {
                         let cmd = self.cmd_queue.borrow_mut().pop_front().unwrap();
-                        let domain_id = self.domain_id;
                         *self.state.borrow_mut() = State::Idle;
+                        let domain = Arc::clone(self);
                         promise::spawn::spawn(async move {
-                            if let Err(err) = cmd.process_result(domain_id, &response) {
-                                log::error!("error processing result: {}", err);
+                            if let Err(err) = cmd.process_result(&domain, &response) {
+                                log::error!(
+                                    "error processing result of `{}`: {:#}",
+                                    cmd.get_command().trim(),
+                                    err
+                                );
                             }
                         })
                         .detach();
                     }
-                    State::Idle => {}
+                    State::Idle | State::Detached => {}
+                },
+                Event::Output { pane, text } => {
+                    if let Some(remote) = self.remote_panes.borrow().get(&pane) {
+                        remote.output_write.send(text.into_bytes()).ok();
+                    }
+                }
+                Event::WindowAdd { .. }
+                | Event::WindowClose { .. }
+                | Event::WindowPaneChanged { .. }
+                | Event::LayoutChange { .. }
+                | Event::SessionChanged { .. }
+                | Event::SessionWindowChanged { .. } => {
+                    self.queue_sync(None);
+                }
+                Event::Exit { reason } => {
+                    log::info!("tmux exited: {}", reason.as_deref().unwrap_or(""));
                 }
+                _ => {}
             }
         }
         if *self.state.borrow() == State::Idle && !self.cmd_queue.borrow().is_empty() {
-            let domain_id = self.domain_id;
+            let domain = Arc::clone(self);
             promise::spawn::spawn(async move {
-                let mux = Mux::get().expect("to be called on main thread");
-                if let Some(domain) = mux.get_domain(domain_id) {
-                    if let Some(tmux_domain) = domain.downcast_ref::<TmuxDomain>() {
-                        tmux_domain.send_next_command();
-                    }
-                }
+                domain.send_next_command();
             })
             .detach();
         }
@@ -171,7 +116,7 @@ impl TmuxDomainState {
         }
         if let Some(first) = self.cmd_queue.borrow().front() {
             let cmd = first.get_command();
-            log::error!("sending cmd {:?}", cmd);
+            log::trace!("sending cmd {:?}", cmd);
             let mux = Mux::get().expect("to be called on main thread");
             if let Some(pane) = mux.get_pane(self.pane_id) {
                 let mut writer = pane.writer();
@@ -180,6 +125,83 @@ impl TmuxDomainState {
             *self.state.borrow_mut() = State::WaitingForResponse;
         }
     }
+
+    /// Queue up a command to be sent to tmux.
+    /// Commands are sent one at a time, each waiting for the response
+    /// to the prior command.
+    pub(crate) fn queue_command(&self, cmd: Box<dyn TmuxCommand>) {
+        if *self.state.borrow() == State::Detached {
+            return;
+        }
+        self.cmd_queue.borrow_mut().push_back(cmd);
+        self.send_next_command();
+    }
+
+    /// Queue up a ListAllPanes command to re-sync the local model
+    /// with the tmux server.
+    fn queue_sync(&self, notify: Option<smol::channel::Sender<anyhow::Result<()>>>) {
+        if notify.is_none() {
+            if self.sync_pending.get() {
+                return;
+            }
+            self.sync_pending.set(true);
+        }
+        self.queue_command(Box::new(ListAllPanes { notify }));
+    }
+
+    pub(crate) fn sync_completed(&self) {
+        self.sync_pending.set(false);
+    }
+
+    /// Re-sync with the tmux server and wait for that to complete
+    async fn sync_and_wait(&self) -> anyhow::Result<()> {
+        let (tx, rx) = smol::channel::bounded(1);
+        self.queue_sync(Some(tx));
+        rx.recv().await?
+    }
+
+    pub(crate) fn local_pane_id(&self, pane_id: TmuxPaneId) -> Option<PaneId> {
+        self.remote_panes
+            .borrow()
+            .get(&pane_id)
+            .map(|remote| remote.local_pane_id)
+    }
+
+    fn remote_pane_id(&self, local_pane_id: PaneId) -> Option<TmuxPaneId> {
+        self.remote_panes
+            .borrow()
+            .iter()
+            .find(|(_, remote)| remote.local_pane_id == local_pane_id)
+            .map(|(&pane_id, _)| pane_id)
+    }
+
+    pub(crate) fn is_attached(&self) -> bool {
+        *self.state.borrow() != State::Detached
+    }
+
+    /// Records the size of the client; returns true if it differs
+    /// from the size that was previously recorded
+    pub(crate) fn set_client_size(&self, cols: u16, rows: u16) -> bool {
+        let changed = self.client_size.get() != Some((cols, rows));
+        self.client_size.set(Some((cols, rows)));
+        changed
+    }
+
+    /// Ask tmux to detach this client
+    pub(crate) fn detach_client(&self) {
+        self.queue_command(Box::new(DetachClient));
+    }
+
+    /// Called when tmux has left control mode.
+    /// Dropping the remote panes causes the local panes to see EOF.
+    pub(crate) fn detached(&self) {
+        *self.state.borrow_mut() = State::Detached;
+        self.cmd_queue.borrow_mut().clear();
+        for (_, remote) in self.remote_panes.borrow_mut().drain() {
+            remote.exit_tx.try_send(ExitStatus::with_exit_code(0)).ok();
+        }
+        self.gui_tabs.borrow_mut().clear();
+    }
 }
 
 impl TmuxDomain {
@@ -187,19 +209,35 @@ impl TmuxDomain {
         let domain_id = alloc_domain_id();
         let parser = RefCell::new(Parser::new());
         let mut cmd_queue = VecDeque::<Box<dyn TmuxCommand>>::new();
-        cmd_queue.push_back(Box::new(ListAllPanes));
+        cmd_queue.push_back(Box::new(ListAllPanes { notify: None }));
         let inner = Arc::new(TmuxDomainState {
             domain_id,
             pane_id,
             parser,
             state: RefCell::new(State::WaitForInitialGuard),
             cmd_queue: RefCell::new(cmd_queue),
+            sync_pending: Cell::new(true),
+            remote_panes: RefCell::new(HashMap::new()),
+            gui_tabs: RefCell::new(HashMap::new()),
+            gui_window: RefCell::new(None),
+            client_size: Cell::new(None),
         });
         Self { inner }
     }
 
-    fn send_next_command(&self) {
-        self.inner.send_next_command();
+    /// Resolve the state for the tmux domain with the specified id
+    pub(crate) fn get_state(domain_id: DomainId) -> Option<Arc<TmuxDomainState>> {
+        let mux = Mux::get()?;
+        let domain = mux.get_domain(domain_id)?;
+        let tmux = domain.downcast_ref::<TmuxDomain>()?;
+        Some(Arc::clone(&tmux.inner))
+    }
+}
+
+fn command_line(command: Option<CommandBuilder>) -> anyhow::Result<Option<String>> {
+    match command {
+        Some(cmd) if !cmd.is_default_prog() => Ok(Some(cmd.as_unix_command_line()?)),
+        _ => Ok(None),
     }
 }
 
@@ -208,22 +246,61 @@ impl Domain for TmuxDomain {
     async fn spawn(
         &self,
         _size: PtySize,
-        _command: Option<CommandBuilder>,
-        _command_dir: Option<String>,
-        _window: WindowId,
+        command: Option<CommandBuilder>,
+        command_dir: Option<String>,
+        window: WindowId,
     ) -> anyhow::Result<Rc<Tab>> {
-        anyhow::bail!("Spawn not yet implemented for TmuxDomain");
+        if self.inner.gui_window.borrow().is_none() {
+            self.inner.gui_window.borrow_mut().replace(window);
+        }
+
+        let (tx, rx) = smol::channel::bounded(1);
+        self.inner.queue_command(Box::new(NewWindow {
+            command: command_line(command)?,
+            command_dir,
+            notify: tx,
+        }));
+        let window_id = rx.recv().await??;
+        self.inner.sync_and_wait().await?;
+
+        let mux = Mux::get().expect("to be called on main thread");
+        self.inner
+            .gui_tabs
+            .borrow()
+            .get(&window_id)
+            .and_then(|&tab_id| mux.get_tab(tab_id))
+            .ok_or_else(|| anyhow!("tmux window @{} has no corresponding tab", window_id))
     }
 
     async fn split_pane(
         &self,
-        _command: Option<CommandBuilder>,
-        _command_dir: Option<String>,
+        command: Option<CommandBuilder>,
+        command_dir: Option<String>,
         _tab: TabId,
-        _pane_id: PaneId,
-        _direction: SplitDirection,
+        pane_id: PaneId,
+        direction: SplitDirection,
     ) -> anyhow::Result<Rc<dyn Pane>> {
-        anyhow::bail!("split_pane not yet implemented for TmuxDomain");
+        let remote_pane_id = self
+            .inner
+            .remote_pane_id(pane_id)
+            .ok_or_else(|| anyhow!("pane {} is not a tmux pane", pane_id))?;
+
+        let (tx, rx) = smol::channel::bounded(1);
+        self.inner.queue_command(Box::new(SplitPane {
+            pane: remote_pane_id,
+            direction,
+            command: command_line(command)?,
+            command_dir,
+            notify: tx,
+        }));
+        let new_pane_id = rx.recv().await??;
+        self.inner.sync_and_wait().await?;
+
+        let mux = Mux::get().expect("to be called on main thread");
+        self.inner
+            .local_pane_id(new_pane_id)
+            .and_then(|pane_id| mux.get_pane(pane_id))
+            .ok_or_else(|| anyhow!("tmux pane %{} has no corresponding pane", new_pane_id))
     }
 
     fn domain_id(&self) -> DomainId {
@@ -239,10 +316,18 @@ impl Domain for TmuxDomain {
     }
 
     fn detach(&self) -> anyhow::Result<()> {
-        anyhow::bail!("detach not implemented for TmuxDomain");
+        if !self.inner.is_attached() {
+            anyhow::bail!("tmux domain is already detached");
+        }
+        self.inner.detach_client();
+        Ok(())
     }
 
     fn state(&self) -> DomainState {
-        DomainState::Attached
+        if self.inner.is_attached() {
+            DomainState::Attached
+        } else {
+            DomainState::Detached
+        }
     }
 }
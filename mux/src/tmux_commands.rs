@@ -0,0 +1,773 @@
+//! The commands that we send to tmux in control mode, along with
+//! the logic to apply their results to the local mux model.
+use crate::localpane::LocalPane;
+use crate::pane::{alloc_pane_id, Pane, PaneId};
+use crate::tab::{PaneEntry, PaneNode, SplitDirection, SplitDirectionAndSize, Tab};
+use crate::tmux::{TmuxDomainState, TmuxRemotePane};
+use crate::tmux_pty::{TmuxChild, TmuxChildKiller, TmuxPty, TmuxPtyWriter, TmuxReader};
+use crate::Mux;
+use anyhow::{anyhow, Context};
+use portable_pty::{ExitStatus, PtySize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::rc::Rc;
+use std::sync::Arc;
+use tmux_cc::*;
+
+pub(crate) trait TmuxCommand {
+    fn get_command(&self) -> String;
+    fn process_result(&self, domain: &Arc<TmuxDomainState>, result: &Guarded)
+        -> anyhow::Result<()>;
+}
+
+fn check_error(result: &Guarded) -> anyhow::Result<()> {
+    if result.error {
+        anyhow::bail!("tmux reported an error: {}", result.output.trim());
+    }
+    Ok(())
+}
+
+/// Quote a string so that the tmux command parser will treat it
+/// as a single argument
+fn tmux_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\' | '$' | '~') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parses an id with a leading sigil such as `$`, `%`, `@`
+fn parse_sigil_id(field: &str, what: &str) -> anyhow::Result<u64> {
+    field
+        .get(1..)
+        .ok_or_else(|| anyhow!("missing {}", what))?
+        .parse()
+        .with_context(|| format!("parsing {} from {:?}", what, field))
+}
+
+/// Information about a pane, as reported by `list-panes`
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub(crate) struct PaneItem {
+    pub session_id: TmuxSessionId,
+    pub window_id: TmuxWindowId,
+    pub pane_id: TmuxPaneId,
+    pub pane_index: u64,
+    pub cursor_x: u64,
+    pub cursor_y: u64,
+    pub pane_width: u64,
+    pub pane_height: u64,
+    pub pane_left: u64,
+    pub pane_top: u64,
+    pub pane_active: bool,
+    pub window_active: bool,
+    pub window_index: u64,
+    pub title: String,
+}
+
+impl PaneItem {
+    fn size(&self) -> PtySize {
+        PtySize {
+            rows: self.pane_height as u16,
+            cols: self.pane_width as u16,
+            pixel_width: 0,
+            pixel_height: 0,
+        }
+    }
+
+    fn parse(line: &str) -> anyhow::Result<Self> {
+        let mut fields = line.splitn(14, ' ');
+        let mut next = |what: &str| fields.next().ok_or_else(|| anyhow!("missing {}", what));
+
+        let session_id = parse_sigil_id(next("session_id")?, "session_id")?;
+        let window_id = parse_sigil_id(next("window_id")?, "window_id")?;
+        let pane_id = parse_sigil_id(next("pane_id")?, "pane_id")?;
+        let pane_index = next("pane_index")?.parse()?;
+        let cursor_x = next("cursor_x")?.parse()?;
+        let cursor_y = next("cursor_y")?.parse()?;
+        let pane_width = next("pane_width")?.parse()?;
+        let pane_height = next("pane_height")?.parse()?;
+        let pane_left = next("pane_left")?.parse()?;
+        let pane_top = next("pane_top")?.parse()?;
+        let pane_active = next("pane_active")? == "1";
+        let window_active = next("window_active")? == "1";
+        let window_index = next("window_index")?.parse()?;
+        let title = next("pane_title").unwrap_or("").to_string();
+
+        Ok(Self {
+            session_id,
+            window_id,
+            pane_id,
+            pane_index,
+            cursor_x,
+            cursor_y,
+            pane_width,
+            pane_height,
+            pane_left,
+            pane_top,
+            pane_active,
+            window_active,
+            window_index,
+            title,
+        })
+    }
+}
+
+/// Returns the (left, top, right, bottom) bounding box of the panes
+fn bounding_box(panes: &[&PaneItem]) -> (u64, u64, u64, u64) {
+    let left = panes.iter().map(|p| p.pane_left).min().unwrap_or(0);
+    let top = panes.iter().map(|p| p.pane_top).min().unwrap_or(0);
+    let right = panes
+        .iter()
+        .map(|p| p.pane_left + p.pane_width)
+        .max()
+        .unwrap_or(0);
+    let bottom = panes
+        .iter()
+        .map(|p| p.pane_top + p.pane_height)
+        .max()
+        .unwrap_or(0);
+    (left, top, right, bottom)
+}
+
+fn bounding_size(panes: &[&PaneItem]) -> PtySize {
+    let (left, top, right, bottom) = bounding_box(panes);
+    PtySize {
+        rows: (bottom - top) as u16,
+        cols: (right - left) as u16,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// Attempt to partition the panes into two groups separated by a
+/// one cell wide divider.  tmux layouts are built by recursively
+/// splitting panes, so one of the candidate dividers will always
+/// partition the set.
+fn partition<'a>(
+    panes: &[&'a PaneItem],
+    direction: SplitDirection,
+) -> Option<(Vec<&'a PaneItem>, Vec<&'a PaneItem>)> {
+    fn left(p: &PaneItem) -> u64 {
+        p.pane_left
+    }
+    fn right(p: &PaneItem) -> u64 {
+        p.pane_left + p.pane_width
+    }
+    fn top(p: &PaneItem) -> u64 {
+        p.pane_top
+    }
+    fn bottom(p: &PaneItem) -> u64 {
+        p.pane_top + p.pane_height
+    }
+
+    let (start, end): (fn(&PaneItem) -> u64, fn(&PaneItem) -> u64) = match direction {
+        SplitDirection::Horizontal => (left, right),
+        SplitDirection::Vertical => (top, bottom),
+    };
+
+    let mut candidates: Vec<u64> = panes.iter().map(|p| end(*p)).collect();
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    for divider in candidates {
+        let (first, second): (Vec<&PaneItem>, Vec<&PaneItem>) =
+            panes.iter().copied().partition(|p| end(*p) <= divider);
+        if first.is_empty() || second.is_empty() {
+            continue;
+        }
+        if second.iter().all(|p| start(*p) > divider) {
+            return Some((first, second));
+        }
+    }
+    None
+}
+
+/// Build a PaneNode tree from the geometry reported by tmux for the
+/// panes in a single tmux window
+fn build_pane_node(
+    panes: &[&PaneItem],
+    domain: &TmuxDomainState,
+    window_id: crate::WindowId,
+    workspace: &str,
+    tab_id: crate::tab::TabId,
+) -> anyhow::Result<PaneNode> {
+    if panes.len() == 1 {
+        let item = panes[0];
+        let pane_id = domain
+            .local_pane_id(item.pane_id)
+            .ok_or_else(|| anyhow!("no local pane for tmux pane %{}", item.pane_id))?;
+        return Ok(PaneNode::Leaf(PaneEntry {
+            window_id,
+            tab_id,
+            pane_id,
+            workspace: workspace.to_string(),
+            title: item.title.clone(),
+            size: item.size(),
+            working_dir: None,
+            is_active_pane: item.pane_active,
+            is_zoomed_pane: false,
+        }));
+    }
+
+    for &direction in &[SplitDirection::Horizontal, SplitDirection::Vertical] {
+        if let Some((first, second)) = partition(panes, direction) {
+            return Ok(PaneNode::Split {
+                left: Box::new(build_pane_node(
+                    &first, domain, window_id, workspace, tab_id,
+                )?),
+                right: Box::new(build_pane_node(
+                    &second, domain, window_id, workspace, tab_id,
+                )?),
+                node: SplitDirectionAndSize {
+                    direction,
+                    first: bounding_size(&first),
+                    second: bounding_size(&second),
+                },
+            });
+        }
+    }
+
+    anyhow::bail!("unable to infer the tmux layout of {:?}", panes)
+}
+
+impl TmuxDomainState {
+    /// Create a local pane to mirror the specified tmux pane
+    fn create_pane(self: &Arc<Self>, item: &PaneItem) -> anyhow::Result<Rc<dyn Pane>> {
+        let mux = Mux::get().expect("to be called on main thread");
+        let local_pane_id = alloc_pane_id();
+        let size = item.size();
+
+        let (output_tx, output_rx) = crossbeam::channel::unbounded();
+        let (exit_tx, exit_rx) = crossbeam::channel::bounded(1);
+
+        let writer = TmuxPtyWriter {
+            domain_id: self.domain_id,
+            pane_id: item.pane_id,
+        };
+        let pty = TmuxPty {
+            domain_id: self.domain_id,
+            pane_id: item.pane_id,
+            reader: RefCell::new(Some(TmuxReader {
+                rx: output_rx,
+                pending: vec![],
+            })),
+            writer: writer.clone(),
+            size: RefCell::new(size),
+        };
+        let child = TmuxChild {
+            exit_rx,
+            exited: None,
+            killer: TmuxChildKiller {
+                domain_id: self.domain_id,
+                pane_id: item.pane_id,
+            },
+        };
+
+        let terminal = wezterm_term::Terminal::new(
+            crate::pty_size_to_terminal_size(size),
+            std::sync::Arc::new(config::TermConfig::new()),
+            "WezTerm",
+            config::wezterm_version(),
+            Box::new(writer),
+        );
+
+        let pane: Rc<dyn Pane> = Rc::new(LocalPane::new(
+            local_pane_id,
+            terminal,
+            Box::new(child),
+            Box::new(pty),
+            self.domain_id,
+        ));
+
+        self.remote_panes.borrow_mut().insert(
+            item.pane_id,
+            TmuxRemotePane {
+                local_pane_id,
+                output_write: output_tx,
+                exit_tx,
+                cursor_x: item.cursor_x,
+                cursor_y: item.cursor_y,
+            },
+        );
+        mux.add_pane(&pane)?;
+
+        // Fetch whatever is already on the screen in that pane
+        self.queue_command(Box::new(CapturePane { pane: item.pane_id }));
+
+        Ok(pane)
+    }
+
+    /// Reconcile the local windows, tabs and panes with the list of
+    /// panes reported by tmux
+    fn sync_pane_state(self: &Arc<Self>, items: Vec<PaneItem>) -> anyhow::Result<()> {
+        let mux = Mux::get().expect("to be called on main thread");
+
+        // Retire any panes that tmux no longer knows about
+        let live_panes: HashSet<TmuxPaneId> = items.iter().map(|item| item.pane_id).collect();
+        let dead_panes: Vec<PaneId> = {
+            let mut remote_panes = self.remote_panes.borrow_mut();
+            let dead: Vec<TmuxPaneId> = remote_panes
+                .keys()
+                .filter(|id| !live_panes.contains(id))
+                .cloned()
+                .collect();
+            dead.into_iter()
+                .filter_map(|id| remote_panes.remove(&id))
+                .map(|remote| {
+                    remote.exit_tx.try_send(ExitStatus::with_exit_code(0)).ok();
+                    remote.local_pane_id
+                })
+                .collect()
+        };
+
+        // Create local panes for any new tmux panes, and update the
+        // cursor positions for the existing panes
+        for item in &items {
+            let existing = match self.remote_panes.borrow_mut().get_mut(&item.pane_id) {
+                Some(remote) => {
+                    remote.cursor_x = item.cursor_x;
+                    remote.cursor_y = item.cursor_y;
+                    true
+                }
+                None => false,
+            };
+            if !existing {
+                self.create_pane(item)?;
+            }
+        }
+
+        let mut windows: HashMap<TmuxWindowId, Vec<&PaneItem>> = HashMap::new();
+        for item in &items {
+            windows
+                .entry(item.window_id)
+                .or_insert_with(Vec::new)
+                .push(item);
+        }
+        let mut window_ids: Vec<TmuxWindowId> = windows.keys().cloned().collect();
+        window_ids.sort_by_key(|id| windows[id][0].window_index);
+
+        let gui_window_id = match *self.gui_window.borrow() {
+            Some(id) if mux.get_window(id).is_some() => Some(id),
+            _ => None,
+        };
+        // Hold on to the builder until we've populated the window,
+        // so that the gui doesn't see it before it has any tabs
+        let mut window_builder = None;
+        let gui_window_id = match gui_window_id {
+            Some(id) => id,
+            None => {
+                let builder = mux.new_empty_window(None);
+                let window_id = *builder;
+                self.gui_window.borrow_mut().replace(window_id);
+                window_builder.replace(builder);
+                window_id
+            }
+        };
+
+        let workspace = mux
+            .get_window(gui_window_id)
+            .map(|w| w.get_workspace().to_string())
+            .unwrap_or_else(|| mux.active_workspace());
+
+        // Close tabs whose tmux windows have gone away
+        let dead_tabs: Vec<_> = {
+            let mut gui_tabs = self.gui_tabs.borrow_mut();
+            let dead: Vec<TmuxWindowId> = gui_tabs
+                .keys()
+                .filter(|id| !windows.contains_key(id))
+                .cloned()
+                .collect();
+            dead.into_iter()
+                .filter_map(|id| gui_tabs.remove(&id))
+                .collect()
+        };
+
+        let mut active_tab = None;
+        for tmux_window_id in window_ids {
+            let panes = &windows[&tmux_window_id];
+            let root_size = bounding_size(panes);
+
+            let existing_tab = self
+                .gui_tabs
+                .borrow()
+                .get(&tmux_window_id)
+                .and_then(|&tab_id| mux.get_tab(tab_id));
+            let tab = match existing_tab {
+                Some(tab) => tab,
+                None => {
+                    let tab = Rc::new(Tab::new(&root_size));
+                    mux.add_tab_no_panes(&tab);
+                    self.gui_tabs
+                        .borrow_mut()
+                        .insert(tmux_window_id, tab.tab_id());
+                    tab
+                }
+            };
+
+            let root = build_pane_node(panes, self, gui_window_id, &workspace, tab.tab_id())?;
+            tab.sync_with_pane_tree(root_size, root, |entry| {
+                mux.get_pane(entry.pane_id)
+                    .expect("tmux panes are added to the mux prior to syncing")
+            });
+
+            if mux.window_containing_tab(tab.tab_id()).is_none() {
+                mux.add_tab_to_window(&tab, gui_window_id)?;
+            }
+
+            if panes.iter().any(|p| p.window_active) {
+                active_tab.replace(tab.tab_id());
+            }
+        }
+
+        if let Some(tab_id) = active_tab {
+            if let Some(mut window) = mux.get_window_mut(gui_window_id) {
+                if let Some(idx) = window.idx_by_id(tab_id) {
+                    window.save_and_then_set_active(idx);
+                }
+            }
+        }
+
+        drop(window_builder);
+
+        for tab_id in dead_tabs {
+            mux.remove_tab(tab_id);
+        }
+        for pane_id in dead_panes {
+            mux.remove_pane(pane_id);
+        }
+
+        Ok(())
+    }
+}
+
+/// Lists the panes in the attached session and mirrors them as
+/// local windows, tabs and panes
+pub(crate) struct ListAllPanes {
+    pub notify: Option<smol::channel::Sender<anyhow::Result<()>>>,
+}
+
+impl TmuxCommand for ListAllPanes {
+    fn get_command(&self) -> String {
+        "list-panes -s -F '#{session_id} #{window_id} #{pane_id} \
+            #{pane_index} #{cursor_x} #{cursor_y} #{pane_width} #{pane_height} \
+            #{pane_left} #{pane_top} #{pane_active} #{window_active} \
+            #{window_index} #{pane_title}'\n"
+            .to_owned()
+    }
+
+    fn process_result(
+        &self,
+        domain: &Arc<TmuxDomainState>,
+        result: &Guarded,
+    ) -> anyhow::Result<()> {
+        let res = (|| {
+            check_error(result)?;
+            let mut items = vec![];
+            for line in result.output.split('\n') {
+                if line.is_empty() {
+                    continue;
+                }
+                items.push(PaneItem::parse(line)?);
+            }
+            log::debug!("panes in domain_id {}: {:?}", domain.domain_id, items);
+            domain.sync_pane_state(items)
+        })();
+        domain.sync_completed();
+
+        if let Some(notify) = &self.notify {
+            notify
+                .try_send(match &res {
+                    Ok(()) => Ok(()),
+                    Err(err) => Err(anyhow!("{:#}", err)),
+                })
+                .ok();
+        }
+        res
+    }
+}
+
+/// Fetches the current screen contents of a pane
+pub(crate) struct CapturePane {
+    pub pane: TmuxPaneId,
+}
+
+impl TmuxCommand for CapturePane {
+    fn get_command(&self) -> String {
+        format!("capture-pane -p -e -t %{}\n", self.pane)
+    }
+
+    fn process_result(
+        &self,
+        domain: &Arc<TmuxDomainState>,
+        result: &Guarded,
+    ) -> anyhow::Result<()> {
+        check_error(result)?;
+        let remote_panes = domain.remote_panes.borrow();
+        let remote = match remote_panes.get(&self.pane) {
+            Some(remote) => remote,
+            None => return Ok(()),
+        };
+
+        let mut text = "\x1b[H\x1b[2J".to_string();
+        let output = result.output.strip_suffix('\n').unwrap_or(&result.output);
+        text.push_str(&output.replace('\n', "\r\n"));
+        write!(
+            text,
+            "\x1b[{};{}H",
+            remote.cursor_y + 1,
+            remote.cursor_x + 1
+        )?;
+        remote.output_write.send(text.into_bytes()).ok();
+        Ok(())
+    }
+}
+
+/// Sends input to a pane
+pub(crate) struct SendKeys {
+    pub pane: TmuxPaneId,
+    pub keys: Vec<u8>,
+}
+
+impl TmuxCommand for SendKeys {
+    fn get_command(&self) -> String {
+        let mut cmd = format!("send-keys -t %{} -H", self.pane);
+        for b in &self.keys {
+            write!(cmd, " {:02x}", b).ok();
+        }
+        cmd.push('\n');
+        cmd
+    }
+
+    fn process_result(
+        &self,
+        _domain: &Arc<TmuxDomainState>,
+        result: &Guarded,
+    ) -> anyhow::Result<()> {
+        check_error(result)
+    }
+}
+
+pub(crate) struct KillPane {
+    pub pane: TmuxPaneId,
+}
+
+impl TmuxCommand for KillPane {
+    fn get_command(&self) -> String {
+        format!("kill-pane -t %{}\n", self.pane)
+    }
+
+    fn process_result(
+        &self,
+        _domain: &Arc<TmuxDomainState>,
+        result: &Guarded,
+    ) -> anyhow::Result<()> {
+        check_error(result)
+    }
+}
+
+/// Informs tmux of the size of our client area
+pub(crate) struct ResizeClient {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl TmuxCommand for ResizeClient {
+    fn get_command(&self) -> String {
+        format!("refresh-client -C {},{}\n", self.cols, self.rows)
+    }
+
+    fn process_result(
+        &self,
+        _domain: &Arc<TmuxDomainState>,
+        result: &Guarded,
+    ) -> anyhow::Result<()> {
+        check_error(result)
+    }
+}
+
+pub(crate) struct DetachClient;
+
+impl TmuxCommand for DetachClient {
+    fn get_command(&self) -> String {
+        "detach-client\n".to_owned()
+    }
+
+    fn process_result(
+        &self,
+        _domain: &Arc<TmuxDomainState>,
+        result: &Guarded,
+    ) -> anyhow::Result<()> {
+        check_error(result)
+    }
+}
+
+/// Appends the optional working directory and command arguments that
+/// are shared by `new-window` and `split-window`
+fn append_spawn_args(cmd: &mut String, command_dir: &Option<String>, command: &Option<String>) {
+    if let Some(dir) = command_dir {
+        write!(cmd, " -c {}", tmux_quote(dir)).ok();
+    }
+    if let Some(command) = command {
+        write!(cmd, " {}", tmux_quote(command)).ok();
+    }
+}
+
+/// Parses the single id printed by `-P -F '#{...}'`
+fn parse_printed_id(result: &Guarded, what: &str) -> anyhow::Result<u64> {
+    check_error(result)?;
+    parse_sigil_id(result.output.trim(), what)
+}
+
+/// Creates a new tmux window, which we present as a tab
+pub(crate) struct NewWindow {
+    pub command: Option<String>,
+    pub command_dir: Option<String>,
+    pub notify: smol::channel::Sender<anyhow::Result<TmuxWindowId>>,
+}
+
+impl TmuxCommand for NewWindow {
+    fn get_command(&self) -> String {
+        let mut cmd = "new-window -P -F '#{window_id}'".to_string();
+        append_spawn_args(&mut cmd, &self.command_dir, &self.command);
+        cmd.push('\n');
+        cmd
+    }
+
+    fn process_result(
+        &self,
+        _domain: &Arc<TmuxDomainState>,
+        result: &Guarded,
+    ) -> anyhow::Result<()> {
+        let res = parse_printed_id(result, "window_id");
+        let err = res.as_ref().err().map(|err| anyhow!("{:#}", err));
+        self.notify.try_send(res).ok();
+        match err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Splits a tmux pane
+pub(crate) struct SplitPane {
+    pub pane: TmuxPaneId,
+    pub direction: SplitDirection,
+    pub command: Option<String>,
+    pub command_dir: Option<String>,
+    pub notify: smol::channel::Sender<anyhow::Result<TmuxPaneId>>,
+}
+
+impl TmuxCommand for SplitPane {
+    fn get_command(&self) -> String {
+        // tmux and wezterm agree on the naming of the directions:
+        // a horizontal split places the panes side by side.
+        let direction = match self.direction {
+            SplitDirection::Horizontal => "-h",
+            SplitDirection::Vertical => "-v",
+        };
+        let mut cmd = format!(
+            "split-window {} -t %{} -P -F '#{{pane_id}}'",
+            direction, self.pane
+        );
+        append_spawn_args(&mut cmd, &self.command_dir, &self.command);
+        cmd.push('\n');
+        cmd
+    }
+
+    fn process_result(
+        &self,
+        _domain: &Arc<TmuxDomainState>,
+        result: &Guarded,
+    ) -> anyhow::Result<()> {
+        let res = parse_printed_id(result, "pane_id");
+        let err = res.as_ref().err().map(|err| anyhow!("{:#}", err));
+        self.notify.try_send(res).ok();
+        match err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn item(pane_id: TmuxPaneId, left: u64, top: u64, width: u64, height: u64) -> PaneItem {
+        PaneItem {
+            session_id: 0,
+            window_id: 0,
+            pane_id,
+            pane_index: pane_id,
+            cursor_x: 0,
+            cursor_y: 0,
+            pane_width: width,
+            pane_height: height,
+            pane_left: left,
+            pane_top: top,
+            pane_active: false,
+            window_active: false,
+            window_index: 0,
+            title: String::new(),
+        }
+    }
+
+    fn ids(panes: &[&PaneItem]) -> Vec<TmuxPaneId> {
+        panes.iter().map(|p| p.pane_id).collect()
+    }
+
+    #[test]
+    fn parse_list_panes_line() {
+        let item = PaneItem::parse("$1 @2 %3 0 4 5 80 24 0 0 1 1 2 some title here").unwrap();
+        assert_eq!(item.session_id, 1);
+        assert_eq!(item.window_id, 2);
+        assert_eq!(item.pane_id, 3);
+        assert_eq!(item.cursor_x, 4);
+        assert_eq!(item.cursor_y, 5);
+        assert_eq!(item.pane_width, 80);
+        assert_eq!(item.pane_height, 24);
+        assert!(item.pane_active);
+        assert!(item.window_active);
+        assert_eq!(item.window_index, 2);
+        assert_eq!(item.title, "some title here");
+    }
+
+    #[test]
+    fn partition_layout() {
+        // +---+---+
+        // | 0 | 1 |
+        // |   +---+
+        // |   | 2 |
+        // +---+---+
+        let a = item(0, 0, 0, 40, 24);
+        let b = item(1, 41, 0, 39, 12);
+        let c = item(2, 41, 13, 39, 11);
+        let panes = vec![&a, &b, &c];
+
+        assert!(partition(&panes, SplitDirection::Vertical).is_none());
+        let (first, second) = partition(&panes, SplitDirection::Horizontal).unwrap();
+        assert_eq!(ids(&first), vec![0]);
+        assert_eq!(ids(&second), vec![1, 2]);
+
+        let (first, second) = partition(&second, SplitDirection::Vertical).unwrap();
+        assert_eq!(ids(&first), vec![1]);
+        assert_eq!(ids(&second), vec![2]);
+
+        let size = bounding_size(&[&b, &c]);
+        assert_eq!((size.cols, size.rows), (39, 24));
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(tmux_quote("/home/me"), "\"/home/me\"");
+        assert_eq!(tmux_quote("a \"b\" $c"), "\"a \\\"b\\\" \\$c\"");
+    }
+}
@@ -0,0 +1,221 @@
+//! Adapters that allow a pane hosted in a remote tmux server to be
+//! driven by a `LocalPane`.
+//! Output from the tmux `%output` notifications is fed into the pane
+//! via `TmuxReader`, while input written to the pane is relayed back to
+//! tmux as `send-keys` commands by `TmuxPtyWriter`.
+use crate::domain::DomainId;
+use crate::tmux::TmuxDomain;
+use crate::tmux_commands::{KillPane, ResizeClient, SendKeys};
+use crate::Mux;
+use crossbeam::channel::{Receiver, TryRecvError};
+use portable_pty::{Child, ChildKiller, ExitStatus, MasterPty, PtySize};
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use tmux_cc::TmuxPaneId;
+
+/// Receives the output bytes for a tmux pane.
+/// Returns EOF once the corresponding remote pane has been removed.
+pub(crate) struct TmuxReader {
+    pub rx: Receiver<Vec<u8>>,
+    pub pending: Vec<u8>,
+}
+
+impl Read for TmuxReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.rx.recv() {
+                Ok(data) => self.pending = data,
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Ok(len)
+    }
+}
+
+/// Relays data written to a tmux pane to the tmux server
+#[derive(Debug, Clone)]
+pub(crate) struct TmuxPtyWriter {
+    pub domain_id: DomainId,
+    pub pane_id: TmuxPaneId,
+}
+
+impl Write for TmuxPtyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let domain_id = self.domain_id;
+        let pane = self.pane_id;
+        let keys = buf.to_vec();
+        promise::spawn::spawn_into_main_thread(async move {
+            if let Some(tmux) = TmuxDomain::get_state(domain_id) {
+                tmux.queue_command(Box::new(SendKeys { pane, keys }));
+            }
+        })
+        .detach();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) struct TmuxPty {
+    pub domain_id: DomainId,
+    pub pane_id: TmuxPaneId,
+    pub reader: RefCell<Option<TmuxReader>>,
+    pub writer: TmuxPtyWriter,
+    pub size: RefCell<PtySize>,
+}
+
+impl Write for TmuxPty {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl MasterPty for TmuxPty {
+    /// tmux owns the layout of its panes, so rather than resizing
+    /// individual panes we tell tmux how large our client is and let
+    /// it report back the revised layout via `%layout-change`.
+    fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        *self.size.borrow_mut() = size;
+        let domain_id = self.domain_id;
+        let pane_id = self.pane_id;
+        promise::spawn::spawn_into_main_thread(async move {
+            let mux = Mux::get().expect("to be called on main thread");
+            if let Some(tmux) = TmuxDomain::get_state(domain_id) {
+                let local_pane_id = match tmux.local_pane_id(pane_id) {
+                    Some(id) => id,
+                    None => return,
+                };
+                if let Some((_, _, tab_id)) = mux.resolve_pane_id(local_pane_id) {
+                    if let Some(tab) = mux.get_tab(tab_id) {
+                        let size = tab.get_size();
+                        if tmux.set_client_size(size.cols, size.rows) {
+                            tmux.queue_command(Box::new(ResizeClient {
+                                cols: size.cols,
+                                rows: size.rows,
+                            }));
+                        }
+                    }
+                }
+            }
+        })
+        .detach();
+        Ok(())
+    }
+
+    fn get_size(&self) -> anyhow::Result<PtySize> {
+        Ok(*self.size.borrow())
+    }
+
+    fn try_clone_reader(&self) -> anyhow::Result<Box<dyn Read + Send>> {
+        match self.reader.borrow_mut().take() {
+            Some(reader) => Ok(Box::new(reader)),
+            None => anyhow::bail!("reader already taken"),
+        }
+    }
+
+    fn try_clone_writer(&self) -> anyhow::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(self.writer.clone()))
+    }
+
+    #[cfg(unix)]
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        None
+    }
+}
+
+/// Asks the tmux server to kill the pane, provided that the domain
+/// is still attached and the pane is still known to it.
+#[derive(Debug, Clone)]
+pub(crate) struct TmuxChildKiller {
+    pub domain_id: DomainId,
+    pub pane_id: TmuxPaneId,
+}
+
+impl ChildKiller for TmuxChildKiller {
+    fn kill(&mut self) -> std::io::Result<()> {
+        let domain_id = self.domain_id;
+        let pane = self.pane_id;
+        promise::spawn::spawn_into_main_thread(async move {
+            if let Some(tmux) = TmuxDomain::get_state(domain_id) {
+                if tmux.is_attached() && tmux.local_pane_id(pane).is_some() {
+                    tmux.queue_command(Box::new(KillPane { pane }));
+                }
+            }
+        })
+        .detach();
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+/// Represents the process running in a tmux pane.
+/// It is considered to have exited when tmux no longer reports the pane.
+#[derive(Debug)]
+pub(crate) struct TmuxChild {
+    pub exit_rx: Receiver<ExitStatus>,
+    pub exited: Option<ExitStatus>,
+    pub killer: TmuxChildKiller,
+}
+
+impl Child for TmuxChild {
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        if let Some(status) = self.exited.as_ref() {
+            return Ok(Some(status.clone()));
+        }
+        match self.exit_rx.try_recv() {
+            Ok(status) => {
+                self.exited.replace(status.clone());
+                Ok(Some(status))
+            }
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                let status = ExitStatus::with_exit_code(1);
+                self.exited.replace(status.clone());
+                Ok(Some(status))
+            }
+        }
+    }
+
+    fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        if let Some(status) = self.exited.as_ref() {
+            return Ok(status.clone());
+        }
+        let status = self
+            .exit_rx
+            .recv()
+            .unwrap_or_else(|_| ExitStatus::with_exit_code(1));
+        self.exited.replace(status.clone());
+        Ok(status)
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        None
+    }
+
+    #[cfg(windows)]
+    fn as_raw_handle(&self) -> Option<std::os::windows::io::RawHandle> {
+        None
+    }
+}
+
+impl ChildKiller for TmuxChild {
+    fn kill(&mut self) -> std::io::Result<()> {
+        self.killer.kill()
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(self.killer.clone())
+    }
+}
@@ -0,0 +1,525 @@
+//! Copies files between the local machine and the remote host of an
+//! ssh backed pane, using the sftp subsystem of the existing session
+//! that is backing the pane.
+use crate::pane::PaneId;
+use crate::{Mux, MuxNotification};
+use anyhow::{anyhow, Context};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use smol::channel::{bounded, Receiver};
+use smol::io::{AsyncReadExt, AsyncWriteExt};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use wezterm_ssh::{Sftp, Utf8Path, Utf8PathBuf};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferDirection {
+    /// Copy local files to the remote host
+    Upload,
+    /// Copy remote files to the local machine
+    Download,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TransferProgress {
+    /// The directory that the files are being copied into.
+    /// Empty until the destination has been resolved.
+    pub destination: String,
+    /// The source path of the file that is currently being copied
+    pub current: String,
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    /// Set when the transfer completes; holds the list of paths
+    /// that were created in the destination, or the error message
+    pub outcome: Option<Result<Vec<String>, String>>,
+}
+
+/// Tracks a transfer that is running in a background thread
+#[derive(Debug)]
+pub struct FileTransfer {
+    pane_id: PaneId,
+    direction: TransferDirection,
+    progress: Mutex<TransferProgress>,
+    cancelled: AtomicBool,
+    /// Closed when the transfer thread finishes
+    done: Receiver<()>,
+}
+
+impl FileTransfer {
+    pub fn pane_id(&self) -> PaneId {
+        self.pane_id
+    }
+
+    pub fn direction(&self) -> TransferDirection {
+        self.direction
+    }
+
+    /// Returns a snapshot of the current progress
+    pub fn progress(&self) -> TransferProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    /// Requests that the transfer stop after the current chunk
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Waits for the transfer to complete and returns the list of
+    /// paths that were created in the destination
+    pub async fn wait(&self) -> anyhow::Result<Vec<String>> {
+        // The sender is never used; recv returns an error when it
+        // is dropped at the end of the transfer
+        let _ = self.done.recv().await;
+        match self.progress().outcome {
+            Some(Ok(paths)) => Ok(paths),
+            Some(Err(err)) => Err(anyhow!("{}", err)),
+            None => Err(anyhow!("file transfer terminated unexpectedly")),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<TransferProgress> {
+        self.progress.lock().unwrap()
+    }
+
+    fn check_cancelled(&self) -> anyhow::Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            anyhow::bail!("file transfer was cancelled");
+        }
+        Ok(())
+    }
+
+    fn start_file(&self, name: String) {
+        self.lock().current = name;
+    }
+
+    fn add_bytes(&self, amount: usize) {
+        self.lock().bytes_done += amount as u64;
+    }
+
+    fn finish_file(&self) {
+        self.lock().files_done += 1;
+    }
+}
+
+/// Starts copying `paths` in the specified direction over the ssh
+/// session that is backing `pane_id`.
+/// That is either the session of a `wezterm ssh` pane, or the session
+/// that connects an ssh multiplexer domain to its remote mux server.
+///
+/// For uploads, `paths` are local and `destination` is a remote
+/// directory; for downloads it is the other way around.
+/// Relative remote paths are resolved against the current working
+/// directory of the pane, as reported by OSC 7, falling back to the
+/// home directory of the remote user.
+/// For uploads, `destination` defaults to that same directory, but
+/// a download requires an explicit local destination.
+///
+/// Must be called on the main thread.  The transfer runs in a
+/// background thread; `MuxNotification::FileTransferStarted` is
+/// emitted so that the GUI can display its progress.
+pub fn start_transfer(
+    pane_id: PaneId,
+    direction: TransferDirection,
+    paths: Vec<String>,
+    destination: Option<String>,
+) -> anyhow::Result<Arc<FileTransfer>> {
+    let mux = Mux::get().ok_or_else(|| anyhow!("must be called on the main thread"))?;
+    let pane = mux
+        .get_pane(pane_id)
+        .ok_or_else(|| anyhow!("pane id {} is not valid", pane_id))?;
+    let domain = mux
+        .get_domain(pane.domain_id())
+        .ok_or_else(|| anyhow!("domain for pane {} is not valid", pane_id))?;
+    let sftp = domain.sftp().ok_or_else(|| {
+        anyhow!(
+            "pane {} belongs to domain {}, which is not connected via ssh; \
+             file transfers are only possible for panes created by `wezterm ssh` \
+             or hosted by an ssh domain",
+            pane_id,
+            domain.domain_name()
+        )
+    })?;
+
+    if paths.is_empty() {
+        anyhow::bail!("no files were specified");
+    }
+    if direction == TransferDirection::Download && destination.is_none() {
+        anyhow::bail!("a local destination directory is required for downloads");
+    }
+
+    let remote_cwd = pane.get_current_working_dir().and_then(|url| {
+        percent_decode_str(url.path())
+            .decode_utf8()
+            .ok()
+            .map(|path| path.into_owned())
+    });
+
+    let (done_tx, done) = bounded(1);
+    let transfer = Arc::new(FileTransfer {
+        pane_id,
+        direction,
+        progress: Mutex::new(TransferProgress::default()),
+        cancelled: AtomicBool::new(false),
+        done,
+    });
+
+    let worker = Arc::clone(&transfer);
+    std::thread::Builder::new()
+        .name(format!("sftp transfer for pane {}", pane_id))
+        .spawn(move || {
+            let result =
+                smol::block_on(run_transfer(&sftp, &worker, paths, destination, remote_cwd));
+            if let Err(err) = &result {
+                log::error!("file transfer for pane {} failed: {:#}", pane_id, err);
+            }
+            worker.lock().outcome = Some(result.map_err(|err| format!("{:#}", err)));
+            drop(done_tx);
+        })?;
+
+    mux.notify(MuxNotification::FileTransferStarted(Arc::clone(&transfer)));
+    Ok(transfer)
+}
+
+/// The files to be copied, and the directories to be created
+/// before copying them.  The directories are listed parent first.
+struct Plan<S, D> {
+    dirs: Vec<D>,
+    files: Vec<(S, D, u64)>,
+    /// The top level paths that will be created in the destination
+    created: Vec<String>,
+}
+
+impl<S, D> Plan<S, D> {
+    fn new() -> Self {
+        Self {
+            dirs: vec![],
+            files: vec![],
+            created: vec![],
+        }
+    }
+}
+
+async fn run_transfer(
+    sftp: &Sftp,
+    transfer: &FileTransfer,
+    paths: Vec<String>,
+    destination: Option<String>,
+    remote_cwd: Option<String>,
+) -> anyhow::Result<Vec<String>> {
+    let remote_cwd = match remote_cwd {
+        Some(cwd) => Utf8PathBuf::from(cwd),
+        None => sftp
+            .canonicalize(".")
+            .await
+            .context("resolving remote working directory")?,
+    };
+
+    match transfer.direction {
+        TransferDirection::Upload => {
+            let dest_dir = match destination {
+                Some(dest) => remote_cwd.join(dest),
+                None => remote_cwd,
+            };
+            transfer.lock().destination = dest_dir.to_string();
+            let plan = plan_upload(&paths, &dest_dir)?;
+            record_totals(transfer, &plan);
+            upload(sftp, transfer, plan).await
+        }
+        TransferDirection::Download => {
+            let dest_dir = PathBuf::from(destination.unwrap_or_default());
+            transfer.lock().destination = dest_dir.display().to_string();
+            let sources: Vec<Utf8PathBuf> = paths.iter().map(|p| remote_cwd.join(p)).collect();
+            let plan = plan_download(sftp, &sources, &dest_dir).await?;
+            record_totals(transfer, &plan);
+            download(sftp, transfer, plan).await
+        }
+    }
+}
+
+fn record_totals<S, D>(transfer: &FileTransfer, plan: &Plan<S, D>) {
+    let mut progress = transfer.lock();
+    progress.files_total = plan.files.len();
+    progress.bytes_total = plan.files.iter().map(|(_, _, size)| size).sum();
+}
+
+fn plan_upload(
+    paths: &[String],
+    dest_dir: &Utf8Path,
+) -> anyhow::Result<Plan<PathBuf, Utf8PathBuf>> {
+    let mut plan = Plan::new();
+    let mut stack = vec![];
+
+    for path in paths {
+        let path = PathBuf::from(path);
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("cannot determine the file name of {}", path.display()))?;
+        let dest = dest_dir.join(name);
+        plan.created.push(dest.to_string());
+        stack.push((path, dest, true));
+    }
+
+    while let Some((path, dest, top_level)) = stack.pop() {
+        let mut meta = std::fs::symlink_metadata(&path)
+            .with_context(|| format!("reading metadata for {}", path.display()))?;
+        if meta.file_type().is_symlink() {
+            meta = std::fs::metadata(&path)
+                .with_context(|| format!("reading metadata for {}", path.display()))?;
+            // A link to a directory within the tree can form a cycle,
+            // so only those that were explicitly requested are followed
+            if meta.is_dir() && !top_level {
+                log::warn!("not following symlink to directory {}", path.display());
+                continue;
+            }
+        }
+        if meta.is_dir() {
+            for entry in std::fs::read_dir(&path)
+                .with_context(|| format!("reading directory {}", path.display()))?
+            {
+                let entry = entry?;
+                let name = entry.file_name();
+                let name = name
+                    .to_str()
+                    .ok_or_else(|| anyhow!("{} is not valid UTF-8", entry.path().display()))?;
+                stack.push((entry.path(), dest.join(name), false));
+            }
+            plan.dirs.push(dest);
+        } else {
+            plan.files.push((path, dest, meta.len()));
+        }
+    }
+
+    Ok(plan)
+}
+
+async fn plan_download(
+    sftp: &Sftp,
+    paths: &[Utf8PathBuf],
+    dest_dir: &std::path::Path,
+) -> anyhow::Result<Plan<Utf8PathBuf, PathBuf>> {
+    let mut plan = Plan::new();
+    let mut stack = vec![];
+
+    for path in paths {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("cannot determine the file name of {}", path))?;
+        let dest = dest_dir.join(name);
+        plan.created.push(dest.display().to_string());
+        stack.push((path.clone(), dest, true));
+    }
+
+    while let Some((path, dest, top_level)) = stack.pop() {
+        let mut meta = sftp
+            .symlink_metadata(path.as_str())
+            .await
+            .with_context(|| format!("reading metadata for {}", path))?;
+        if meta.is_symlink() {
+            meta = sftp
+                .metadata(path.as_str())
+                .await
+                .with_context(|| format!("reading metadata for {}", path))?;
+            // A link to a directory within the tree can form a cycle,
+            // so only those that were explicitly requested are followed
+            if meta.is_dir() && !top_level {
+                log::warn!("not following symlink to directory {}", path);
+                continue;
+            }
+        }
+        if meta.is_dir() {
+            for (entry, _meta) in sftp
+                .read_dir(path.as_str())
+                .await
+                .with_context(|| format!("reading directory {}", path))?
+            {
+                let name = entry
+                    .file_name()
+                    .ok_or_else(|| anyhow!("cannot determine the file name of {}", entry))?;
+                let child_dest = dest.join(name);
+                stack.push((entry, child_dest, false));
+            }
+            plan.dirs.push(dest);
+        } else {
+            plan.files.push((path, dest, meta.size.unwrap_or(0)));
+        }
+    }
+
+    Ok(plan)
+}
+
+async fn upload(
+    sftp: &Sftp,
+    transfer: &FileTransfer,
+    plan: Plan<PathBuf, Utf8PathBuf>,
+) -> anyhow::Result<Vec<String>> {
+    for dir in &plan.dirs {
+        let exists = match sftp.metadata(dir.as_str()).await {
+            Ok(meta) => meta.is_dir(),
+            Err(_) => false,
+        };
+        if !exists {
+            sftp.create_dir(dir.as_str(), 0o755)
+                .await
+                .with_context(|| format!("creating remote directory {}", dir))?;
+        }
+    }
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    for (src, dest, _size) in &plan.files {
+        transfer.start_file(src.display().to_string());
+        let mut local = std::fs::File::open(src)
+            .with_context(|| format!("opening {} for reading", src.display()))?;
+        let mut remote = sftp
+            .create(dest.as_str())
+            .await
+            .with_context(|| format!("creating remote file {}", dest))?;
+        loop {
+            transfer.check_cancelled()?;
+            let len = local
+                .read(&mut buf)
+                .with_context(|| format!("reading {}", src.display()))?;
+            if len == 0 {
+                break;
+            }
+            remote
+                .write_all(&buf[..len])
+                .await
+                .with_context(|| format!("writing remote file {}", dest))?;
+            transfer.add_bytes(len);
+        }
+        remote
+            .close()
+            .await
+            .with_context(|| format!("closing remote file {}", dest))?;
+        transfer.finish_file();
+    }
+
+    Ok(plan.created)
+}
+
+async fn download(
+    sftp: &Sftp,
+    transfer: &FileTransfer,
+    plan: Plan<Utf8PathBuf, PathBuf>,
+) -> anyhow::Result<Vec<String>> {
+    for dir in &plan.dirs {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("creating directory {}", dir.display()))?;
+    }
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    for (src, dest, _size) in &plan.files {
+        transfer.start_file(src.to_string());
+        let mut remote = sftp
+            .open(src.as_str())
+            .await
+            .with_context(|| format!("opening remote file {}", src))?;
+        let mut local =
+            std::fs::File::create(dest).with_context(|| format!("creating {}", dest.display()))?;
+        loop {
+            transfer.check_cancelled()?;
+            let len = remote
+                .read(&mut buf)
+                .await
+                .with_context(|| format!("reading remote file {}", src))?;
+            if len == 0 {
+                break;
+            }
+            local
+                .write_all(&buf[..len])
+                .with_context(|| format!("writing {}", dest.display()))?;
+            transfer.add_bytes(len);
+        }
+        transfer.finish_file();
+    }
+
+    Ok(plan.created)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn plan_upload_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::write(src.join("a.txt"), "abc").unwrap();
+        std::fs::write(src.join("sub").join("b.txt"), "hello").unwrap();
+        // A link to a file is copied as that file, but a link to a
+        // directory inside the tree is skipped to avoid the cycle
+        std::os::unix::fs::symlink(src.join("a.txt"), src.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(&src, src.join("sub").join("loop")).unwrap();
+
+        let plan = plan_upload(
+            &[src.to_str().unwrap().to_string()],
+            Utf8Path::new("/remote"),
+        )
+        .unwrap();
+
+        assert_eq!(plan.created, vec!["/remote/src".to_string()]);
+        assert_eq!(
+            plan.dirs,
+            vec![
+                Utf8PathBuf::from("/remote/src"),
+                Utf8PathBuf::from("/remote/src/sub")
+            ]
+        );
+
+        let mut files: Vec<(String, u64)> = plan
+            .files
+            .iter()
+            .map(|(_src, dest, size)| (dest.to_string(), *size))
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                ("/remote/src/a.txt".to_string(), 3),
+                ("/remote/src/link.txt".to_string(), 3),
+                ("/remote/src/sub/b.txt".to_string(), 5),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn plan_upload_follows_requested_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        std::fs::create_dir(&target).unwrap();
+        std::fs::write(target.join("c.txt"), "c").unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let plan = plan_upload(
+            &[link.to_str().unwrap().to_string()],
+            Utf8Path::new("/remote"),
+        )
+        .unwrap();
+
+        assert_eq!(plan.dirs, vec![Utf8PathBuf::from("/remote/link")]);
+        assert_eq!(plan.files.len(), 1);
+        assert_eq!(plan.files[0].1, Utf8PathBuf::from("/remote/link/c.txt"));
+    }
+
+    #[test]
+    fn plan_upload_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        assert!(plan_upload(
+            &[missing.to_str().unwrap().to_string()],
+            Utf8Path::new("/remote")
+        )
+        .is_err());
+    }
+}
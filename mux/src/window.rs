@@ -13,19 +13,34 @@ pub struct Window {
     active: usize,
     last_active: Option<TabId>,
     clipboard: Option<Arc<dyn Clipboard>>,
+    workspace: String,
 }
 
 impl Window {
-    pub fn new() -> Self {
+    pub fn new(workspace: String) -> Self {
         Self {
             id: WIN_ID.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed),
             tabs: vec![],
             active: 0,
             last_active: None,
             clipboard: None,
+            workspace,
         }
     }
 
+    /// Returns the name of the workspace to which this window belongs
+    pub fn get_workspace(&self) -> &str {
+        &self.workspace
+    }
+
+    pub fn set_workspace(&mut self, workspace: &str) {
+        if workspace == self.workspace {
+            return;
+        }
+        self.workspace = workspace.to_string();
+        self.invalidate();
+    }
+
     pub fn set_clipboard(&mut self, clipboard: &Arc<dyn Clipboard>) {
         self.clipboard.replace(Arc::clone(clipboard));
     }
@@ -48,7 +63,7 @@ impl Window {
         }
     }
 
-    fn invalidate(&self) {
+    pub fn invalidate(&self) {
         let mux = Mux::get().unwrap();
         mux.notify(MuxNotification::WindowInvalidated(self.id));
     }
@@ -21,6 +21,14 @@ pub trait TerminalConfiguration: std::fmt::Debug {
         3500
     }
 
+    /// Returns the number of additional rows of scrollback that may be
+    /// held in a compressed form on disk once they have scrolled beyond
+    /// the in-memory scrollback defined by `scrollback_size`.
+    /// Zero disables the on-disk scrollback.
+    fn scrollback_disk_size(&self) -> usize {
+        0
+    }
+
     /// Return true if the embedding application wants to use CSI-u encoding
     /// for keys that would otherwise be ambiguous.
     /// <http://www.leonerd.org.uk/hacks/fixterms/>
@@ -85,6 +93,14 @@ pub trait TerminalConfiguration: std::fmt::Debug {
         false
     }
 
+    /// Whether to respond to CSI 20 t and CSI 21 t requests to report
+    /// the icon and window titles.  Since the title can be set by any
+    /// program whose output reaches the terminal, reporting it back
+    /// can be abused to inject input, so this is off by default.
+    fn enable_title_reporting(&self) -> bool {
+        false
+    }
+
     /// The default unicode version to assume.
     /// This affects how the width of certain sequences is interpreted.
     /// At the time of writing, we default to 9 even though the current
@@ -35,6 +35,8 @@ pub use termwiz::surface::line::*;
 pub mod screen;
 pub use crate::screen::*;
 
+mod scrollback;
+
 pub mod selection;
 
 use termwiz::hyperlink::Hyperlink;
@@ -1,6 +1,8 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
 use super::*;
+use crate::scrollback::DiskScrollback;
 use log::debug;
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::sync::Arc;
 use termwiz::surface::SequenceNo;
@@ -9,7 +11,7 @@ use termwiz::surface::SequenceNo;
 /// which includes lines of scrollback text, or the alternate screen
 /// which holds no scrollback.  The intent is to have one instance of
 /// Screen for each of these things.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Screen {
     /// Holds the line data that comprises the screen contents.
     /// This is allocated with capacity for the entire scrollback.
@@ -37,6 +39,14 @@ pub struct Screen {
     pub physical_rows: usize,
     /// Physical, visible width of the screen
     pub physical_cols: usize,
+
+    /// Holds the lines that have been evicted from the top of `lines`
+    /// when the on-disk scrollback is enabled.  The newest line in the
+    /// store is the line immediately above `lines[0]`.
+    disk: Option<DiskScrollback>,
+    /// Set if we failed to create the on-disk store, so that we
+    /// don't keep retrying with every line
+    disk_failed: bool,
 }
 
 fn scrollback_size(config: &Arc<dyn TerminalConfiguration>, allow_scrollback: bool) -> usize {
@@ -73,6 +83,8 @@ impl Screen {
             physical_rows,
             physical_cols,
             stable_row_index_offset: 0,
+            disk: None,
+            disk_failed: false,
         }
     }
 
@@ -80,6 +92,121 @@ impl Screen {
         scrollback_size(&self.config, self.allow_scrollback)
     }
 
+    fn disk_scrollback_size(&self) -> usize {
+        if self.allow_scrollback && !self.disk_failed {
+            self.config.scrollback_disk_size()
+        } else {
+            0
+        }
+    }
+
+    /// Called with a line that is being removed from the top of the
+    /// in-memory scrollback.  Copies it to the on-disk scrollback,
+    /// if that is enabled.
+    fn spill_line(&mut self, line: &Line) {
+        let max_lines = self.disk_scrollback_size();
+        if max_lines == 0 {
+            self.disk = None;
+            return;
+        }
+
+        if self.disk.is_none() {
+            match DiskScrollback::new(max_lines) {
+                Ok(disk) => self.disk = Some(disk),
+                Err(err) => {
+                    log::error!("on-disk scrollback is not available: {:#}", err);
+                    self.disk_failed = true;
+                    return;
+                }
+            }
+        }
+
+        let disk = self.disk.as_mut().unwrap();
+        if let Err(err) = disk
+            .set_max_lines(max_lines)
+            .and_then(|_| disk.push(line.clone()))
+        {
+            // The store must hold a contiguous run of lines, so if we
+            // failed to add this one we have to drop the whole thing
+            log::error!("discarding on-disk scrollback: {:#}", err);
+            self.disk = None;
+        }
+    }
+
+    /// Returns the number of lines of scrollback that are held on disk
+    pub fn disk_scrollback_len(&self) -> usize {
+        self.disk.as_ref().map(DiskScrollback::len).unwrap_or(0)
+    }
+
+    /// Returns the total number of lines, including those on disk
+    pub fn total_lines(&self) -> usize {
+        self.lines.len() + self.disk_scrollback_len()
+    }
+
+    /// Returns the StableRowIndex of the oldest line that we remember,
+    /// including those held on disk
+    pub fn scrollback_top(&self) -> StableRowIndex {
+        self.phys_to_stable_row_index(0) - self.disk_scrollback_len() as StableRowIndex
+    }
+
+    /// Returns copies of the lines in the specified range, along with
+    /// the StableRowIndex of the first of them.
+    /// Lines that have been spilled to disk are paged back in.
+    /// As with `stable_range`, a range that extends beyond the ends of
+    /// the scrollback is clamped.
+    pub fn lines_in_stable_range(
+        &self,
+        range: &Range<StableRowIndex>,
+    ) -> (StableRowIndex, Vec<Line>) {
+        let mem_top = self.phys_to_stable_row_index(0);
+        let disk = match self.disk.as_ref() {
+            Some(disk) if !disk.is_empty() && range.start < mem_top => disk,
+            _ => {
+                let phys = self.stable_range(range);
+                return (
+                    self.phys_to_stable_row_index(phys.start),
+                    self.lines
+                        .iter()
+                        .skip(phys.start)
+                        .take(phys.end - phys.start)
+                        .cloned()
+                        .collect(),
+                );
+            }
+        };
+
+        let top = self.scrollback_top();
+        let len = (range.end - range.start).max(0);
+        let start = range.start.max(top);
+        let end = (start + len).min(mem_top + self.lines.len() as StableRowIndex);
+
+        let mut lines =
+            match disk.get_range((start - top) as usize..(end.min(mem_top) - top) as usize) {
+                Ok(lines) => lines,
+                Err(err) => {
+                    log::error!("reading on-disk scrollback: {:#}", err);
+                    vec![]
+                }
+            };
+        if end > mem_top {
+            lines.extend(self.lines.iter().take((end - mem_top) as usize).cloned());
+        }
+        (start, lines)
+    }
+
+    /// Iterates over the entire history, including the lines that are
+    /// held on disk, yielding each line with its StableRowIndex
+    pub fn iter_stable_lines(&self) -> impl Iterator<Item = (StableRowIndex, Cow<'_, Line>)> + '_ {
+        let top = self.scrollback_top();
+        self.disk
+            .iter()
+            .flat_map(|disk| disk.iter())
+            .map(Cow::Owned)
+            .chain(self.lines.iter().map(Cow::Borrowed))
+            .enumerate()
+            .map(move |(idx, line)| (top + idx as StableRowIndex, line))
+    }
+
     fn rewrap_lines(
         &mut self,
         physical_cols: usize,
@@ -601,6 +728,9 @@ impl Screen {
         let (to_remove, to_add) = {
             for _ in 0..to_move {
                 let mut line = self.lines.remove(remove_idx).unwrap();
+                if remove_idx == 0 {
+                    self.spill_line(&line);
+                }
                 // Make the line like a new one of the appropriate width
                 line.resize_and_clear(self.physical_cols, seqno, blank_attr.clone());
                 line.update_last_change_seqno(seqno);
@@ -617,7 +747,11 @@ impl Screen {
 
         // Perform the removal
         for _ in 0..to_remove {
-            self.lines.remove(remove_idx);
+            if let Some(line) = self.lines.remove(remove_idx) {
+                if remove_idx == 0 {
+                    self.spill_line(&line);
+                }
+            }
         }
 
         if remove_idx == 0 {
@@ -648,6 +782,7 @@ impl Screen {
     }
 
     pub fn erase_scrollback(&mut self) {
+        self.disk = None;
         let len = self.lines.len();
         let to_clear = len - self.physical_rows;
         for _ in 0..to_clear {
@@ -0,0 +1,300 @@
+//! Storage for scrollback lines that have been evicted from the
+//! in-memory portion of the scrollback.
+//! Lines are accumulated into blocks which are serialized, compressed
+//! and appended to an anonymous temporary file.  The file is removed
+//! by the operating system when the store is dropped.
+use crate::Line;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// The number of lines that are compressed together
+const LINES_PER_BLOCK: usize = 256;
+
+/// Once this many bytes at the start of the file are occupied by
+/// blocks that have been discarded, we consider compacting the file
+const COMPACT_THRESH: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug)]
+struct Block {
+    /// The sequence number of the first line in this block
+    first: usize,
+    num_lines: usize,
+    /// Location of the compressed data in the file
+    offset: u64,
+    len: usize,
+}
+
+impl Block {
+    fn contains(&self, seq: usize) -> bool {
+        seq >= self.first && seq < self.first + self.num_lines
+    }
+}
+
+/// Holds lines in a compressed form on disk.
+/// Each line is identified by a sequence number which counts the
+/// number of lines that have ever been pushed into the store.
+/// The oldest lines are discarded once the store exceeds its
+/// maximum size.
+#[derive(Debug)]
+pub struct DiskScrollback {
+    file: File,
+    /// The size of the file; new blocks are written at this offset
+    file_len: u64,
+    blocks: VecDeque<Block>,
+    /// Lines that have yet to be written out to a block
+    pending: Vec<Line>,
+    /// Sequence number of the oldest retained line
+    first: usize,
+    /// Sequence number that will be assigned to the next line
+    next: usize,
+    max_lines: usize,
+    /// The most recently decompressed block, keyed by the sequence
+    /// number of its first line.  Scrolling and searching tend to
+    /// touch the same block repeatedly.
+    cache: RefCell<Option<(usize, Vec<Line>)>>,
+}
+
+impl DiskScrollback {
+    pub fn new(max_lines: usize) -> anyhow::Result<Self> {
+        let file = tempfile::tempfile().context("creating scrollback file")?;
+        Ok(Self {
+            file,
+            file_len: 0,
+            blocks: VecDeque::new(),
+            pending: vec![],
+            first: 0,
+            next: 0,
+            max_lines,
+            cache: RefCell::new(None),
+        })
+    }
+
+    /// Returns the number of lines held in the store
+    pub fn len(&self) -> usize {
+        self.next - self.first
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn set_max_lines(&mut self, max_lines: usize) -> anyhow::Result<()> {
+        self.max_lines = max_lines;
+        self.trim()
+    }
+
+    /// Appends a line to the store, making it the newest line.
+    /// If the store is full, the oldest line is discarded.
+    pub fn push(&mut self, line: Line) -> anyhow::Result<()> {
+        self.pending.push(line);
+        self.next += 1;
+        if self.pending.len() >= LINES_PER_BLOCK {
+            self.flush_pending()?;
+        }
+        self.trim()
+    }
+
+    /// Returns a copy of the line at `idx`, where 0 is the oldest line
+    pub fn get(&self, idx: usize) -> anyhow::Result<Option<Line>> {
+        if idx >= self.len() {
+            return Ok(None);
+        }
+        let seq = self.first + idx;
+        let pending_first = self.next - self.pending.len();
+        if seq >= pending_first {
+            return Ok(Some(self.pending[seq - pending_first].clone()));
+        }
+
+        // The blocks are ordered by their first line, so find the
+        // first one that ends after seq
+        let block_idx = self
+            .blocks
+            .partition_point(|block| block.first + block.num_lines <= seq);
+        let block = match self.blocks.get(block_idx) {
+            Some(block) if block.contains(seq) => block,
+            _ => return Ok(None),
+        };
+        let mut cache = self.cache.borrow_mut();
+        match cache.as_ref() {
+            Some((first, _)) if *first == block.first => {}
+            _ => {
+                cache.replace((block.first, self.load_block(block)?));
+            }
+        }
+        let (_, lines) = cache.as_ref().unwrap();
+        Ok(lines.get(seq - block.first).cloned())
+    }
+
+    /// Returns copies of the lines in `range`, where index 0 is the
+    /// oldest line.  The range is clamped to the lines that are present.
+    pub fn get_range(&self, range: std::ops::Range<usize>) -> anyhow::Result<Vec<Line>> {
+        let mut lines = vec![];
+        for idx in range.start..range.end.min(self.len()) {
+            if let Some(line) = self.get(idx)? {
+                lines.push(line);
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Iterates over the lines in the store, from oldest to newest.
+    /// Lines that cannot be read back are logged and skipped.
+    pub fn iter(&self) -> impl Iterator<Item = Line> + '_ {
+        (0..self.len()).filter_map(move |idx| match self.get(idx) {
+            Ok(line) => line,
+            Err(err) => {
+                log::error!("reading scrollback line {}: {:#}", idx, err);
+                None
+            }
+        })
+    }
+
+    fn flush_pending(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut data = vec![];
+        let mut encode = varbincode::Serializer::new(&mut data);
+        self.pending
+            .serialize(&mut encode)
+            .context("serializing scrollback")?;
+        let compressed = miniz_oxide::deflate::compress_to_vec(&data, 6);
+
+        self.file.seek(SeekFrom::Start(self.file_len))?;
+        self.file
+            .write_all(&compressed)
+            .context("writing scrollback")?;
+
+        let num_lines = self.pending.len();
+        self.blocks.push_back(Block {
+            first: self.next - num_lines,
+            num_lines,
+            offset: self.file_len,
+            len: compressed.len(),
+        });
+        self.file_len += compressed.len() as u64;
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn load_block(&self, block: &Block) -> anyhow::Result<Vec<Line>> {
+        let mut compressed = vec![0u8; block.len];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(block.offset))?;
+        file.read_exact(&mut compressed)
+            .context("reading scrollback")?;
+        let data = miniz_oxide::inflate::decompress_to_vec(&compressed)
+            .map_err(|err| anyhow::anyhow!("decompressing scrollback: {:?}", err))?;
+        let mut decode = varbincode::Deserializer::new(data.as_slice());
+        Vec::<Line>::deserialize(&mut decode).context("deserializing scrollback")
+    }
+
+    /// Discards the oldest lines until we fit within max_lines
+    fn trim(&mut self) -> anyhow::Result<()> {
+        if self.len() > self.max_lines {
+            self.first = self.next - self.max_lines;
+        }
+
+        while let Some(block) = self.blocks.front() {
+            if block.first + block.num_lines > self.first {
+                break;
+            }
+            self.blocks.pop_front();
+        }
+
+        let pending_first = self.next - self.pending.len();
+        if self.first > pending_first {
+            self.pending.drain(..self.first - pending_first);
+        }
+
+        self.maybe_compact()
+    }
+
+    /// The blocks are appended to the file and the discarded blocks
+    /// leave a hole at the start.  When that hole makes up the bulk
+    /// of the file, move the live blocks down to the start of the file
+    /// so that the size of the file remains proportional to the number
+    /// of lines that we retain.
+    fn maybe_compact(&mut self) -> anyhow::Result<()> {
+        let hole = match self.blocks.front() {
+            Some(block) => block.offset,
+            None => self.file_len,
+        };
+        if hole < COMPACT_THRESH || hole < self.file_len / 2 {
+            return Ok(());
+        }
+
+        let mut data = vec![0u8; (self.file_len - hole) as usize];
+        self.file.seek(SeekFrom::Start(hole))?;
+        self.file.read_exact(&mut data)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&data)?;
+        self.file.set_len(data.len() as u64)?;
+        self.file_len = data.len() as u64;
+        for block in &mut self.blocks {
+            block.offset -= hole;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn line(n: usize) -> Line {
+        Line::from_text(&format!("line {}", n), &Default::default())
+    }
+
+    #[test]
+    fn push_and_get() {
+        let mut store = DiskScrollback::new(10_000).unwrap();
+        for n in 0..1000 {
+            store.push(line(n)).unwrap();
+        }
+        assert_eq!(store.len(), 1000);
+        assert_eq!(store.get(0).unwrap(), Some(line(0)));
+        assert_eq!(store.get(500).unwrap(), Some(line(500)));
+        assert_eq!(store.get(999).unwrap(), Some(line(999)));
+        assert_eq!(store.get(1000).unwrap(), None);
+
+        let range = store.get_range(254..258).unwrap();
+        assert_eq!(range, vec![line(254), line(255), line(256), line(257)]);
+
+        let all: Vec<Line> = store.iter().collect();
+        assert_eq!(all.len(), 1000);
+        assert_eq!(all[123], line(123));
+    }
+
+    #[test]
+    fn discards_oldest() {
+        let mut store = DiskScrollback::new(300).unwrap();
+        for n in 0..1000 {
+            store.push(line(n)).unwrap();
+        }
+        assert_eq!(store.len(), 300);
+        assert_eq!(store.get(0).unwrap(), Some(line(700)));
+        assert_eq!(store.get(299).unwrap(), Some(line(999)));
+
+        store.set_max_lines(10).unwrap();
+        assert_eq!(store.len(), 10);
+        assert_eq!(store.get(0).unwrap(), Some(line(990)));
+    }
+
+    #[test]
+    fn small_capacity() {
+        let mut store = DiskScrollback::new(3).unwrap();
+        for n in 0..5 {
+            store.push(line(n)).unwrap();
+        }
+        assert_eq!(
+            store.get_range(0..10).unwrap(),
+            vec![line(2), line(3), line(4)]
+        );
+    }
+}
@@ -10,8 +10,20 @@ use termwiz::surface::TextureCoordinate;
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PlacementInfo {
     pub first_row: StableRowIndex,
+    pub first_col: usize,
     pub rows: usize,
     pub cols: usize,
+    pub z_index: i32,
+}
+
+impl PlacementInfo {
+    pub fn intersects_row(&self, row: StableRowIndex) -> bool {
+        row >= self.first_row && row < self.first_row + self.rows as StableRowIndex
+    }
+
+    pub fn intersects_col(&self, col: usize) -> bool {
+        col >= self.first_col && col < self.first_col + self.cols
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -195,8 +207,10 @@ impl TerminalState {
 
         Ok(PlacementInfo {
             first_row,
+            first_col: cursor_x,
             rows: height_in_cells,
             cols: width_in_cells,
+            z_index: params.z_index,
         })
     }
 
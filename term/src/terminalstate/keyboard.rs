@@ -1,23 +1,143 @@
 use crate::input::*;
 use crate::TerminalState;
-use termwiz::input::KeyCodeEncodeModes;
+use std::io::Write;
+use termwiz::escape::csi::{Keyboard, KittyKeyboardFlags, KittyKeyboardMode};
+use termwiz::escape::CSI;
+use termwiz::input::{KeyCodeEncodeModes, KeyboardEventType};
+
+/// Bounds the number of entries in each kitty keyboard flags stack;
+/// when full, the oldest entry is evicted to make room.
+const MAX_KITTY_KEYBOARD_STACK_DEPTH: usize = 16;
+
+/// Pressed keys are tracked without regard to shift state, so that
+/// releasing `a` after pressing `A` is matched up
+fn normalize_pressed_key(key: KeyCode) -> KeyCode {
+    match key {
+        KeyCode::Char(c) => {
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(lower), None) => KeyCode::Char(lower),
+                _ => KeyCode::Char(c),
+            }
+        }
+        key => key,
+    }
+}
 
 impl TerminalState {
+    /// Returns the kitty keyboard protocol flags that are in effect
+    /// for the active screen
+    pub fn kitty_keyboard_flags(&self) -> KittyKeyboardFlags {
+        self.screen.kitty_keyboard_flags()
+    }
+
+    fn key_code_encode_modes(&self) -> KeyCodeEncodeModes {
+        KeyCodeEncodeModes {
+            enable_csi_u_key_encoding: self.config.enable_csi_u_key_encoding(),
+            newline_mode: self.newline_mode,
+            application_cursor_keys: self.application_cursor_keys,
+            kitty_keyboard: self.kitty_keyboard_flags(),
+        }
+    }
+
+    pub(crate) fn perform_csi_keyboard(&mut self, keyboard: Keyboard) {
+        match keyboard {
+            Keyboard::SetKittyState { flags, mode } => {
+                let current = self.kitty_keyboard_flags();
+                let flags = match mode {
+                    KittyKeyboardMode::AssignAll => flags,
+                    KittyKeyboardMode::SetSpecified => current | flags,
+                    KittyKeyboardMode::ClearSpecified => current - flags,
+                };
+                let stack = self.screen.kitty_keyboard_stack();
+                match stack.last_mut() {
+                    Some(top) => *top = flags,
+                    None => stack.push(flags),
+                }
+                if !flags.contains(KittyKeyboardFlags::REPORT_EVENT_TYPES) {
+                    self.pressed_keys.clear();
+                }
+            }
+            Keyboard::PushKittyState(flags) => {
+                let stack = self.screen.kitty_keyboard_stack();
+                if stack.len() >= MAX_KITTY_KEYBOARD_STACK_DEPTH {
+                    stack.remove(0);
+                }
+                stack.push(flags);
+            }
+            Keyboard::PopKittyState(n) => {
+                let stack = self.screen.kitty_keyboard_stack();
+                let len = stack.len().saturating_sub(n as usize);
+                stack.truncate(len);
+                // The application that saw the presses may no longer
+                // be the one that will see the releases
+                self.pressed_keys.clear();
+            }
+            Keyboard::QueryKittySupport => {
+                let flags = self.kitty_keyboard_flags();
+                let response = CSI::Keyboard(Keyboard::ReportKittyState(flags));
+                write!(self.writer, "{}", response).ok();
+                self.writer.flush().ok();
+            }
+            Keyboard::ReportKittyState(_) => {
+                log::warn!("unhandled {:?}", keyboard);
+            }
+        }
+    }
+
     /// Processes a key_down event generated by the gui/render layer
     /// that is embedding the Terminal.  This method translates the
     /// keycode into a sequence of bytes to send to the slave end
     /// of the pty via the `Write`-able object provided by the caller.
     pub fn key_down(&mut self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
-        let to_send = key.encode(
-            mods,
-            KeyCodeEncodeModes {
-                enable_csi_u_key_encoding: self.config.enable_csi_u_key_encoding(),
-                newline_mode: self.newline_mode,
-                application_cursor_keys: self.application_cursor_keys,
-            },
-        )?;
-
-        log::trace!("sending {:?}, {:?}", to_send, key);
+        // Pressed keys are only tracked while the application has asked
+        // for event types, as that is the only time that we report
+        // repeats and releases
+        let event = if !self.wants_key_up_events()
+            || self.pressed_keys.insert(normalize_pressed_key(key))
+        {
+            KeyboardEventType::Press
+        } else {
+            KeyboardEventType::Repeat
+        };
+        let modes = self.key_code_encode_modes();
+        let to_send = if modes.kitty_keyboard.is_empty() {
+            key.encode(mods, modes)?
+        } else {
+            key.encode_kitty(mods, modes, event)?
+        };
+
+        log::trace!("sending {:?}, {:?} {:?}", to_send, key, event);
+        self.writer.write_all(to_send.as_bytes())?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Returns true if the application has asked to be told about
+    /// key releases via the kitty keyboard protocol
+    pub fn wants_key_up_events(&self) -> bool {
+        self.kitty_keyboard_flags()
+            .contains(KittyKeyboardFlags::REPORT_EVENT_TYPES)
+    }
+
+    /// Processes a key_up event generated by the gui/render layer.
+    /// Key releases are only reported to the application when it
+    /// has requested them via the kitty keyboard protocol.
+    pub fn key_up(&mut self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+        if !self.pressed_keys.remove(&normalize_pressed_key(key)) {
+            return Ok(());
+        }
+        let modes = self.key_code_encode_modes();
+        if modes.kitty_keyboard.is_empty() {
+            return Ok(());
+        }
+        let to_send = key.encode_kitty(mods, modes, KeyboardEventType::Release)?;
+        if to_send.is_empty() {
+            return Ok(());
+        }
+
+        log::trace!("sending {:?}, {:?} release", to_send, key);
         self.writer.write_all(to_send.as_bytes())?;
         self.writer.flush()?;
 
@@ -10,11 +10,11 @@ use std::sync::Arc;
 use std::time::Duration;
 use termwiz::escape::apc::KittyImageData;
 use termwiz::escape::apc::{
-    KittyFrameCompositionMode, KittyImage, KittyImageCompression, KittyImageDelete,
-    KittyImageFormat, KittyImageFrame, KittyImageFrameCompose, KittyImagePlacement,
-    KittyImageTransmit, KittyImageVerbosity,
+    KittyAnimationState, KittyFrameCompositionMode, KittyImage, KittyImageAnimation,
+    KittyImageCompression, KittyImageDelete, KittyImageFormat, KittyImageFrame,
+    KittyImageFrameCompose, KittyImagePlacement, KittyImageTransmit, KittyImageVerbosity,
 };
-use termwiz::image::ImageDataType;
+use termwiz::image::{AnimationState, ImageDataType};
 use termwiz::surface::change::ImageData;
 
 #[derive(Debug, Default)]
@@ -34,6 +34,14 @@ impl KittyImageState {
         }
     }
 
+    /// Frees the data for image_id if no placements refer to it
+    fn remove_data_if_unreferenced(&mut self, image_id: u32) {
+        if !self.placements.keys().any(|(id, _)| *id == image_id) {
+            self.remove_data_for_id(image_id);
+            self.number_to_id.retain(|_, id| *id != image_id);
+        }
+    }
+
     fn record_id_to_data(&mut self, image_id: u32, data: Arc<ImageData>) {
         self.remove_data_for_id(image_id);
         self.prune_unreferenced();
@@ -74,21 +82,30 @@ impl TerminalState {
         placement: KittyImagePlacement,
         verbosity: KittyImageVerbosity,
     ) -> anyhow::Result<()> {
-        let image_id = match image_id {
-            Some(id) => id,
-            None => *self
-                .kitty_img
-                .number_to_id
-                .get(
-                    &image_number
-                        .ok_or_else(|| anyhow::anyhow!("no image_id or image_number specified!"))?,
-                )
-                .ok_or_else(|| {
-                    anyhow::anyhow!(
-                        "image_number has no matching image id {:?} in number_to_id",
-                        image_number
-                    )
-                })?,
+        let resolved_id = image_id
+            .or_else(|| image_number.and_then(|no| self.kitty_img.number_to_id.get(&no).copied()));
+        let found = resolved_id.and_then(|id| {
+            self.kitty_img
+                .id_to_data
+                .get(&id)
+                .map(|img| (id, Arc::clone(img)))
+        });
+        let (image_id, img) = match found {
+            Some(found) => found,
+            None => {
+                self.kitty_send_response(
+                    verbosity,
+                    false,
+                    image_id,
+                    image_number,
+                    "ENOENT".to_string(),
+                );
+                anyhow::bail!(
+                    "no matching image for image_id {:?} image_number {:?}",
+                    image_id,
+                    image_number
+                );
+            }
         };
 
         log::trace!(
@@ -99,13 +116,6 @@ impl TerminalState {
             verbosity
         );
         self.kitty_remove_placement(image_id, placement.placement_id);
-        let img = Arc::clone(self.kitty_img.id_to_data.get(&image_id).ok_or_else(|| {
-            anyhow::anyhow!(
-                "no matching image id {} in id_to_data for image_number {:?}",
-                image_id,
-                image_number
-            )
-        })?);
 
         let (image_width, image_height) = match &*img.data() {
             ImageDataType::EncodedFile(data) => {
@@ -240,38 +250,18 @@ impl TerminalState {
                 verbosity,
             } => {
                 self.kitty_img_place(image_id, image_number, placement, verbosity)?;
-            }
-            KittyImage::Delete {
-                what:
-                    KittyImageDelete::ByImageId {
+                if image_id.is_some() || image_number.is_some() {
+                    self.kitty_send_response(
+                        verbosity,
+                        true,
                         image_id,
-                        placement_id,
-                        delete,
-                    },
-                verbosity,
-            } => {
-                log::trace!(
-                    "remove a placement: image_id {} placement_id {:?} delete {} verb {:?}",
-                    image_id,
-                    placement_id,
-                    delete,
-                    verbosity
-                );
-
-                self.kitty_remove_placement(image_id, placement_id);
-
-                if delete {
-                    self.kitty_img.remove_data_for_id(image_id);
+                        image_number,
+                        "OK".to_string(),
+                    );
                 }
             }
-            KittyImage::Delete {
-                what: KittyImageDelete::All { delete },
-                verbosity: _,
-            } => {
-                self.kitty_remove_all_placements(delete);
-            }
             KittyImage::Delete { what, verbosity } => {
-                log::warn!("unhandled KittyImage::Delete {:?} {:?}", what, verbosity);
+                self.kitty_img_delete(what, verbosity)?;
             }
             KittyImage::TransmitFrame {
                 transmit,
@@ -287,6 +277,17 @@ impl TerminalState {
                     log::error!("Error {:#} while handling KittyImage::ComposeFrame", err);
                 }
             }
+            KittyImage::ControlAnimation {
+                animation,
+                verbosity,
+            } => {
+                if let Err(err) = self.kitty_animation_control(animation, verbosity) {
+                    log::error!(
+                        "Error {:#} while handling KittyImage::ControlAnimation",
+                        err
+                    );
+                }
+            }
         };
 
         Ok(())
@@ -351,6 +352,265 @@ impl TerminalState {
         }
     }
 
+    fn kitty_img_delete(
+        &mut self,
+        what: KittyImageDelete,
+        verbosity: KittyImageVerbosity,
+    ) -> anyhow::Result<()> {
+        log::trace!("delete {:?} verb {:?}", what, verbosity);
+        match what {
+            KittyImageDelete::All { delete } => {
+                self.kitty_remove_all_placements(delete);
+            }
+            KittyImageDelete::ByImageId {
+                image_id,
+                placement_id,
+                delete,
+            } => {
+                self.kitty_delete_image(Some(image_id), placement_id, delete);
+            }
+            KittyImageDelete::ByImageNumber {
+                image_number,
+                placement_id,
+                delete,
+            } => {
+                let image_id = self.kitty_img.number_to_id.get(&image_number).copied();
+                self.kitty_delete_image(image_id, placement_id, delete);
+            }
+            KittyImageDelete::AtCursorPosition { delete } => {
+                let col = self.cursor.x;
+                let row = self.screen().visible_row_to_stable_row(self.cursor.y);
+                self.kitty_remove_placements_matching(delete, |_, info| {
+                    info.intersects_col(col) && info.intersects_row(row)
+                });
+            }
+            KittyImageDelete::DeleteAt { x, y, delete } => {
+                let col = (x as usize).saturating_sub(1);
+                let row = self.kitty_cell_row(y);
+                self.kitty_remove_placements_matching(delete, |_, info| {
+                    info.intersects_col(col) && info.intersects_row(row)
+                });
+            }
+            KittyImageDelete::DeleteAtZ { x, y, z, delete } => {
+                let col = (x as usize).saturating_sub(1);
+                let row = self.kitty_cell_row(y);
+                self.kitty_remove_placements_matching(delete, |_, info| {
+                    info.intersects_col(col) && info.intersects_row(row) && info.z_index == z
+                });
+            }
+            KittyImageDelete::DeleteColumn { x, delete } => {
+                let col = (x as usize).saturating_sub(1);
+                self.kitty_remove_placements_matching(delete, |_, info| info.intersects_col(col));
+            }
+            KittyImageDelete::DeleteRow { y, delete } => {
+                let row = self.kitty_cell_row(y);
+                self.kitty_remove_placements_matching(delete, |_, info| info.intersects_row(row));
+            }
+            KittyImageDelete::DeleteZ { z, delete } => {
+                self.kitty_remove_placements_matching(delete, |_, info| info.z_index == z);
+            }
+            KittyImageDelete::AnimationFrames {
+                image_id,
+                image_number,
+                frame_number,
+                delete: _,
+            } => {
+                self.kitty_frame_delete(image_id, image_number, frame_number)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts the 1-based screen row used by the delete commands
+    /// to a stable row index
+    fn kitty_cell_row(&self, y: u32) -> StableRowIndex {
+        self.screen()
+            .visible_row_to_stable_row((y as i64).saturating_sub(1))
+    }
+
+    /// Removes the placements of the image with the specified id,
+    /// optionally only the placement with placement_id.
+    /// Like kitty, we don't reply to delete commands, even when
+    /// there is no such image.
+    fn kitty_delete_image(
+        &mut self,
+        image_id: Option<u32>,
+        placement_id: Option<u32>,
+        delete: bool,
+    ) {
+        let image_id = match image_id {
+            Some(id) => id,
+            None => return,
+        };
+
+        self.kitty_remove_placement(image_id, placement_id);
+        if delete {
+            self.kitty_img.remove_data_if_unreferenced(image_id);
+        }
+    }
+
+    /// Removes the placements for which `matches` returns true.
+    /// If `delete` is true, the data for images that are left without
+    /// any placements is also freed.
+    fn kitty_remove_placements_matching<F>(&mut self, delete: bool, matches: F)
+    where
+        F: Fn(u32, &PlacementInfo) -> bool,
+    {
+        let to_remove: Vec<(u32, Option<u32>)> = self
+            .kitty_img
+            .placements
+            .iter()
+            .filter(|((image_id, _), info)| matches(*image_id, info))
+            .map(|(key, _)| *key)
+            .collect();
+
+        for &(image_id, placement_id) in &to_remove {
+            if let Some(info) = self.kitty_img.placements.remove(&(image_id, placement_id)) {
+                self.kitty_remove_placement_from_model(image_id, placement_id, info);
+            }
+        }
+
+        if delete {
+            for (image_id, _) in to_remove {
+                self.kitty_img.remove_data_if_unreferenced(image_id);
+            }
+        }
+    }
+
+    /// Deletes a frame of an animation.  Errors are logged rather
+    /// than reported to the application, as kitty doesn't reply
+    /// to delete commands.
+    fn kitty_frame_delete(
+        &mut self,
+        image_id: Option<u32>,
+        image_number: Option<u32>,
+        frame_number: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let resolved_id = match (image_id, image_number) {
+            (Some(id), None) => Some(id),
+            (None, Some(no)) => self.kitty_img.number_to_id.get(&no).copied(),
+            _ => anyhow::bail!("frame deletion requires exactly one of i= or I="),
+        };
+        let img = match resolved_id.and_then(|id| self.kitty_img.id_to_data.get(&id)) {
+            Some(img) => Arc::clone(img),
+            None => {
+                anyhow::bail!(
+                    "no matching image for image_id {:?} image_number {:?}",
+                    image_id,
+                    image_number
+                );
+            }
+        };
+
+        let mut data = img.data();
+        if let ImageDataType::AnimRgba8 {
+            width,
+            height,
+            frames,
+            durations,
+            hashes,
+        } = &mut *data
+        {
+            // A missing or zero frame number refers to the first frame;
+            // a frame number beyond the end doesn't refer to anything,
+            // so there is nothing to delete
+            let idx = frame_number.unwrap_or(1).max(1) as usize - 1;
+            if idx < frames.len() {
+                frames.remove(idx);
+                durations.remove(idx);
+                hashes.remove(idx);
+            }
+
+            if frames.len() == 1 {
+                let (width, height) = (*width, *height);
+                let data_frame = frames.remove(0);
+                let hash = hashes.remove(0);
+                *data = ImageDataType::Rgba8 {
+                    width,
+                    height,
+                    data: data_frame,
+                    hash,
+                };
+            }
+        }
+        drop(data);
+
+        Ok(())
+    }
+
+    fn kitty_animation_control(
+        &mut self,
+        animation: KittyImageAnimation,
+        verbosity: KittyImageVerbosity,
+    ) -> anyhow::Result<()> {
+        let image_id = animation.image_id;
+        let image_number = animation.image_number;
+        let resolved_id = image_id
+            .or_else(|| image_number.and_then(|no| self.kitty_img.number_to_id.get(&no).copied()));
+        let img = match resolved_id.and_then(|id| self.kitty_img.id_to_data.get(&id)) {
+            Some(img) => Arc::clone(img),
+            None => {
+                self.kitty_send_response(
+                    verbosity,
+                    false,
+                    image_id,
+                    image_number,
+                    "ENOENT".to_string(),
+                );
+                anyhow::bail!(
+                    "no matching image for image_id {:?} image_number {:?}",
+                    image_id,
+                    image_number
+                );
+            }
+        };
+
+        let mut data = img.data();
+        let num_frames = match &mut *data {
+            ImageDataType::AnimRgba8 {
+                frames, durations, ..
+            } => {
+                if let (Some(frame_no), Some(gap)) = (animation.frame_number, animation.duration_ms)
+                {
+                    // A zero gap is ignored; negative gaps make the
+                    // frame gapless, which we model as zero duration
+                    if gap != 0 {
+                        if let Some(duration) = durations.get_mut(frame_no as usize - 1) {
+                            *duration = Duration::from_millis(gap.max(0) as u64);
+                        }
+                    }
+                }
+                frames.len()
+            }
+            _ => 1,
+        };
+        drop(data);
+
+        let mut anim = img.animation();
+        if let Some(state) = animation.state {
+            anim.state = match state {
+                KittyAnimationState::Stop => AnimationState::Stopped,
+                KittyAnimationState::Loading => AnimationState::Loading,
+                KittyAnimationState::Loop => AnimationState::Running,
+            };
+        }
+        if let Some(loops) = animation.loops {
+            anim.loops = match loops {
+                1 => None,
+                n => Some(n - 1),
+            };
+        }
+        if let Some(frame_no) = animation.current_frame {
+            anim.set_current_frame((frame_no as usize).min(num_frames) - 1);
+        }
+        drop(anim);
+
+        if image_id.is_some() || image_number.is_some() {
+            self.kitty_send_response(verbosity, true, image_id, image_number, "OK".to_string());
+        }
+        Ok(())
+    }
+
     fn kitty_send_response(
         &mut self,
         verbosity: KittyImageVerbosity,
@@ -555,7 +815,21 @@ impl TerminalState {
             }
         }
 
-        let (image_id, image_number, img) = self.kitty_img_transmit_inner(transmit)?;
+        let requested_id = transmit.image_id;
+        let requested_number = transmit.image_number;
+        let (image_id, image_number, img) = match self.kitty_img_transmit_inner(transmit) {
+            Ok(result) => result,
+            Err(err) => {
+                self.kitty_send_response(
+                    verbosity,
+                    false,
+                    requested_id,
+                    requested_number,
+                    "EINVAL".to_string(),
+                );
+                return Err(err);
+            }
+        };
 
         let img = match img.decode() {
             ImageDataType::Rgba8 {
@@ -576,8 +850,8 @@ impl TerminalState {
             (background_pixel & 0xff) as u8,
         ]);
 
-        let anim = match self.kitty_img.id_to_data.get(&image_id) {
-            Some(anim) => anim,
+        let anim_data = match self.kitty_img.id_to_data.get(&image_id) {
+            Some(anim) => Arc::clone(anim),
             None => {
                 self.kitty_send_response(
                     verbosity,
@@ -594,7 +868,7 @@ impl TerminalState {
             }
         };
 
-        let mut anim = anim.data();
+        let mut anim = anim_data.data();
         let x = frame.x.unwrap_or(0);
         let y = frame.y.unwrap_or(0);
         let frame_gap = Duration::from_millis(match frame.duration_ms {
@@ -667,6 +941,12 @@ impl TerminalState {
                             durations,
                             hashes,
                         };
+
+                        // Animations are not played until the client
+                        // starts them via a=a, so hold on the root frame
+                        let mut animation = anim_data.animation();
+                        animation.state = AnimationState::Stopped;
+                        animation.set_current_frame(0);
                     }
                     Some(n) => anyhow::bail!(
                         "attempted to edit frame {} but there is only a single frame",
@@ -748,7 +1028,7 @@ impl TerminalState {
         log::trace!("transmit {:?}", transmit);
         let (id, no) = match (transmit.image_id, transmit.image_number) {
             (Some(_), Some(_)) => {
-                // TODO: send an EINVAL error back here
+                // The caller reports this to the application as EINVAL
                 anyhow::bail!("cannot use both i= and I= in the same request");
             }
             (None, None) => {
@@ -824,13 +1104,27 @@ impl TerminalState {
         transmit: KittyImageTransmit,
         verbosity: KittyImageVerbosity,
     ) -> anyhow::Result<u32> {
-        let (image_id, image_number, img) = self.kitty_img_transmit_inner(transmit)?;
+        let requested_id = transmit.image_id;
+        let requested_number = transmit.image_number;
+        let (image_id, image_number, img) = match self.kitty_img_transmit_inner(transmit) {
+            Ok(result) => result,
+            Err(err) => {
+                self.kitty_send_response(
+                    verbosity,
+                    false,
+                    requested_id,
+                    requested_number,
+                    "EINVAL".to_string(),
+                );
+                return Err(err);
+            }
+        };
         self.kitty_img.max_image_id = self.kitty_img.max_image_id.max(image_id);
 
         let img = self.raw_image_to_image_data(img);
         self.kitty_img.record_id_to_data(image_id, img);
 
-        if image_number.is_some() {
+        if requested_id.is_some() || image_number.is_some() {
             self.kitty_send_response(
                 verbosity,
                 true,
@@ -850,7 +1144,10 @@ impl TerminalState {
             let mut data = vec![];
             let mut trans;
             let place;
-            let final_verbosity = img.verbosity();
+            // icat only sends q= with the first chunk, but other clients
+            // repeat it on each chunk, so honor whichever is quieter
+            let final_verbosity =
+                quietest_verbosity(self.kitty_img.accumulator[0].verbosity(), img.verbosity());
 
             self.kitty_img.accumulator.push(img);
 
@@ -914,6 +1211,15 @@ impl TerminalState {
     }
 }
 
+fn quietest_verbosity(a: KittyImageVerbosity, b: KittyImageVerbosity) -> KittyImageVerbosity {
+    use KittyImageVerbosity::*;
+    match (a, b) {
+        (Quiet, _) | (_, Quiet) => Quiet,
+        (OnlyErrors, _) | (_, OnlyErrors) => OnlyErrors,
+        (Verbose, Verbose) => Verbose,
+    }
+}
+
 /// Make a copy of the source region.
 /// Ideally we wouldn't need this, but Rust's mutability rules
 /// make it very awkward to mutably reference a frame while
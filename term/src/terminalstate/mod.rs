@@ -5,15 +5,15 @@ use super::*;
 use crate::color::{ColorPalette, RgbColor};
 use log::debug;
 use num_traits::ToPrimitive;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::mpsc::{channel, Sender};
 use std::sync::Arc;
 use terminfo::{Database, Value};
 use termwiz::cell::UnicodeVersion;
 use termwiz::escape::csi::{
     Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay,
-    EraseInLine, Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode, Window, XtSmGraphics,
-    XtSmGraphicsAction, XtSmGraphicsItem, XtSmGraphicsStatus,
+    EraseInLine, KittyKeyboardFlags, Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode,
+    Window, XtSmGraphics, XtSmGraphicsAction, XtSmGraphicsItem, XtSmGraphicsStatus,
 };
 use termwiz::escape::{OneBased, OperatingSystemCommand, CSI};
 use termwiz::image::ImageData;
@@ -133,6 +133,13 @@ struct ScreenOrAlt {
     alt_screen_is_active: bool,
     saved_cursor: Option<SavedCursor>,
     alt_saved_cursor: Option<SavedCursor>,
+    /// The kitty keyboard protocol flags stack for each screen;
+    /// the top of the stack holds the flags that are in effect
+    kitty_keyboard_stack: Vec<KittyKeyboardFlags>,
+    alt_kitty_keyboard_stack: Vec<KittyKeyboardFlags>,
+    /// The XTWINOPS title stack for each screen
+    title_stack: Vec<SavedTitle>,
+    alt_title_stack: Vec<SavedTitle>,
 }
 
 impl Deref for ScreenOrAlt {
@@ -172,6 +179,10 @@ impl ScreenOrAlt {
             alt_screen_is_active: false,
             saved_cursor: None,
             alt_saved_cursor: None,
+            kitty_keyboard_stack: vec![],
+            alt_kitty_keyboard_stack: vec![],
+            title_stack: vec![],
+            alt_title_stack: vec![],
         }
     }
 
@@ -229,6 +240,81 @@ impl ScreenOrAlt {
             &mut self.saved_cursor
         }
     }
+
+    pub fn kitty_keyboard_stack(&mut self) -> &mut Vec<KittyKeyboardFlags> {
+        if self.alt_screen_is_active {
+            &mut self.alt_kitty_keyboard_stack
+        } else {
+            &mut self.kitty_keyboard_stack
+        }
+    }
+
+    pub fn kitty_keyboard_flags(&self) -> KittyKeyboardFlags {
+        let stack = if self.alt_screen_is_active {
+            &self.alt_kitty_keyboard_stack
+        } else {
+            &self.kitty_keyboard_stack
+        };
+        stack.last().copied().unwrap_or(KittyKeyboardFlags::NONE)
+    }
+
+    pub fn has_saved_cursor(&self) -> bool {
+        if self.alt_screen_is_active {
+            self.alt_saved_cursor.is_some()
+        } else {
+            self.saved_cursor.is_some()
+        }
+    }
+
+    /// Discard the keyboard flags and title stacks of both screens
+    pub fn clear_stacks(&mut self) {
+        self.kitty_keyboard_stack.clear();
+        self.alt_kitty_keyboard_stack.clear();
+        self.title_stack.clear();
+        self.alt_title_stack.clear();
+    }
+
+    fn title_stack(&mut self) -> &mut Vec<SavedTitle> {
+        if self.alt_screen_is_active {
+            &mut self.alt_title_stack
+        } else {
+            &mut self.title_stack
+        }
+    }
+}
+
+/// An entry on the title stack maintained by XTWINOPS 22 and 23.
+/// Each entry may hold either or both of the titles, depending
+/// on which of them were pushed.
+#[derive(Debug, Clone, Default)]
+struct SavedTitle {
+    title: Option<String>,
+    /// The outer Option indicates whether the icon title was pushed
+    icon_title: Option<Option<String>>,
+}
+
+/// xterm limits the depth of its title stack to 10 entries
+const MAX_TITLE_STACK_DEPTH: usize = 10;
+
+/// The mode status values reported in response to DECRQM
+/// <https://vt100.net/docs/vt510-rm/DECRPM.html>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModeSetting {
+    NotRecognized = 0,
+    Set = 1,
+    Reset = 2,
+    PermanentlySet = 3,
+    PermanentlyReset = 4,
+}
+
+impl From<bool> for ModeSetting {
+    fn from(enabled: bool) -> Self {
+        if enabled {
+            Self::Set
+        } else {
+            Self::Reset
+        }
+    }
 }
 
 /// Manages the state for the terminal
@@ -348,6 +434,13 @@ pub struct TerminalState {
     unicode_version: UnicodeVersion,
     unicode_version_stack: Vec<UnicodeVersionStackEntry>,
 
+    /// Keys that are currently held down, used to report repeat
+    /// and release events for the kitty keyboard protocol
+    pressed_keys: HashSet<KeyCode>,
+
+    /// DEC private modes saved by XTSAVE, keyed by mode number
+    saved_dec_modes: HashMap<u16, bool>,
+
     /// On Windows, the ConPTY layer emits an OSC sequence to
     /// set the title shortly after it starts up.
     /// We don't want that, so we use this flag to remember
@@ -490,6 +583,8 @@ impl TerminalState {
             seqno: 0,
             unicode_version,
             unicode_version_stack: vec![],
+            pressed_keys: HashSet::new(),
+            saved_dec_modes: HashMap::new(),
             suppress_initial_title_change: false,
         }
     }
@@ -645,6 +740,9 @@ impl TerminalState {
 
     /// Advise the terminal about a change in its focus state
     pub fn focus_changed(&mut self, focused: bool) {
+        // We won't see releases for keys that are let go while
+        // we are not focused, so forget about them
+        self.pressed_keys.clear();
         if !focused {
             // notify app of release of buttons
             let buttons = self.current_mouse_buttons.clone();
@@ -1045,26 +1143,40 @@ impl TerminalState {
         match dev {
             Device::DeviceAttributes(a) => log::warn!("unhandled: {:?}", a),
             Device::SoftReset => {
-                // TODO: see https://vt100.net/docs/vt510-rm/DECSTR.html
-                self.pen = CellAttributes::default();
+                // Resets the state listed in
+                // https://vt100.net/docs/vt510-rm/DECSTR.html
+                // The cursor position and the other modes are unchanged.
+
+                // DECTCEM: cursor enabled
+                self.cursor_visible = true;
+                // IRM: replace
                 self.insert = false;
+                // DECOM: absolute
                 self.dec_origin_mode = false;
-                // Note that xterm deviates from the documented DECSTR
-                // setting for dec_auto_wrap, so we do too
+                // DECAWM: Note that xterm deviates from the documented
+                // DECSTR setting for dec_auto_wrap, so we do too
                 self.dec_auto_wrap = true;
-                self.application_cursor_keys = false;
+                // DECNKM: numeric
                 self.application_keypad = false;
+                // DECCKM: normal
+                self.application_cursor_keys = false;
+                // DECSTBM and DECSLRM: full screen
                 self.top_and_bottom_margins = 0..self.screen().physical_rows as i64;
                 self.left_and_right_margins = 0..self.screen().physical_cols;
-                self.left_and_right_margin_mode = false;
-                self.screen.activate_alt_screen(self.seqno);
-                self.screen.saved_cursor().take();
-                self.screen.activate_primary_screen(self.seqno);
-                self.screen.saved_cursor().take();
-                self.kitty_remove_all_placements(true);
+                // All character sets: default, with G0 invoked into GL
+                self.g0_charset = CharSet::Ascii;
+                self.g1_charset = CharSet::DecLineDrawing;
+                self.shift_out = false;
+                // SGR and DECSCA: normal rendition
+                self.pen = CellAttributes::default();
+                // DECSC: home position, for both screens
+                self.screen.saved_cursor.take();
+                self.screen.alt_saved_cursor.take();
 
+                // Beyond the vt510 set, xterm also resets reverse
+                // wraparound, and we discard kitty image placements
                 self.reverse_wraparound_mode = false;
-                self.reverse_video_mode = false;
+                self.kitty_remove_all_placements(true);
             }
             Device::RequestPrimaryDeviceAttributes => {
                 let mut ident = "\x1b[?65".to_string(); // Vt500
@@ -1149,37 +1261,93 @@ impl TerminalState {
         }
     }
 
-    fn decqrm_response(&mut self, mode: Mode, mut recognized: bool, enabled: bool) {
+    fn decqrm_response(&mut self, mode: Mode, mut setting: ModeSetting) {
         let (is_dec, number) = match &mode {
             Mode::QueryDecPrivateMode(DecPrivateMode::Code(code)) => (true, code.to_u16().unwrap()),
             Mode::QueryDecPrivateMode(DecPrivateMode::Unspecified(code)) => {
-                recognized = false;
+                setting = ModeSetting::NotRecognized;
                 (true, *code)
             }
             Mode::QueryMode(TerminalMode::Code(code)) => (false, code.to_u16().unwrap()),
             Mode::QueryMode(TerminalMode::Unspecified(code)) => {
-                recognized = false;
+                setting = ModeSetting::NotRecognized;
                 (false, *code)
             }
             _ => unreachable!(),
         };
 
         let prefix = if is_dec { "?" } else { "" };
+        let status = setting as u8;
 
-        let status = if recognized {
-            if enabled {
-                1 // set
-            } else {
-                2 // reset
-            }
-        } else {
-            0
-        };
-
-        log::trace!("{:?} -> recognized={} status={}", mode, recognized, status);
+        log::trace!("{:?} -> setting={:?} status={}", mode, setting, status);
         write!(self.writer, "\x1b[{}{};{}$y", prefix, number, status).ok();
     }
 
+    /// Returns the current setting of a DEC private mode that is
+    /// tracked by the terminal state, or None for modes that have
+    /// no state of their own.  This is the set of modes that can
+    /// be saved and restored via XTSAVE and XTRESTORE.
+    fn dec_private_mode(&self, code: &DecPrivateModeCode) -> Option<bool> {
+        Some(match code {
+            DecPrivateModeCode::ApplicationCursorKeys => self.application_cursor_keys,
+            DecPrivateModeCode::DecAnsiMode => self.dec_ansi_mode,
+            DecPrivateModeCode::ReverseVideo => self.reverse_video_mode,
+            DecPrivateModeCode::OriginMode => self.dec_origin_mode,
+            DecPrivateModeCode::AutoWrap => self.dec_auto_wrap,
+            DecPrivateModeCode::ShowCursor => self.cursor_visible,
+            DecPrivateModeCode::ReverseWraparound => self.reverse_wraparound_mode,
+            DecPrivateModeCode::LeftRightMarginMode => self.left_and_right_margin_mode,
+            DecPrivateModeCode::SixelScrolling => self.sixel_scrolling,
+            DecPrivateModeCode::MouseTracking => self.mouse_tracking,
+            DecPrivateModeCode::ButtonEventMouse => self.button_event_mouse,
+            DecPrivateModeCode::AnyEventMouse => self.any_event_mouse,
+            DecPrivateModeCode::FocusTracking => self.focus_tracking,
+            DecPrivateModeCode::SGRMouse => self.sgr_mouse,
+            DecPrivateModeCode::EnableAlternateScreen
+            | DecPrivateModeCode::OptEnableAlternateScreen
+            | DecPrivateModeCode::ClearAndEnableAlternateScreen => {
+                self.screen.is_alt_screen_active()
+            }
+            DecPrivateModeCode::BracketedPaste => self.bracketed_paste,
+            DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic => {
+                self.use_private_color_registers_for_each_graphic
+            }
+            DecPrivateModeCode::SixelScrollsRight => self.sixel_scrolls_right,
+            DecPrivateModeCode::Select132Columns
+            | DecPrivateModeCode::SmoothScroll
+            | DecPrivateModeCode::AutoRepeat
+            | DecPrivateModeCode::StartBlinkingCursor
+            | DecPrivateModeCode::HighlightMouseTracking
+            | DecPrivateModeCode::SaveCursor
+            | DecPrivateModeCode::SynchronizedOutput => return None,
+        })
+    }
+
+    /// Computes the DECRQM response for a DEC private mode
+    fn dec_private_mode_setting(&self, code: &DecPrivateModeCode) -> ModeSetting {
+        if let Some(enabled) = self.dec_private_mode(code) {
+            return enabled.into();
+        }
+        match code {
+            // We leave key repeat to the GUI layer prefs
+            DecPrivateModeCode::AutoRepeat => ModeSetting::PermanentlySet,
+            // We don't support 132 column mode, always output at our
+            // "best" rate, and don't implement highlight tracking
+            DecPrivateModeCode::Select132Columns
+            | DecPrivateModeCode::SmoothScroll
+            | DecPrivateModeCode::HighlightMouseTracking => ModeSetting::PermanentlyReset,
+            // Like xterm, report whether the cursor has been saved
+            DecPrivateModeCode::SaveCursor => self.screen.has_saved_cursor().into(),
+            // Synchronized output is handled in wezterm's mux; if we get
+            // here, then it isn't enabled.  The blinking state of the
+            // cursor is configured by the user, so we don't track it.
+            DecPrivateModeCode::SynchronizedOutput | DecPrivateModeCode::StartBlinkingCursor => {
+                ModeSetting::Reset
+            }
+            _ => ModeSetting::NotRecognized,
+        }
+    }
+
     fn perform_csi_mode(&mut self, mode: Mode) {
         match mode {
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
@@ -1188,11 +1356,6 @@ impl TerminalState {
             | Mode::ResetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::StartBlinkingCursor,
             )) => {}
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::StartBlinkingCursor,
-            )) => {
-                self.decqrm_response(mode, true, false);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoRepeat))
             | Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoRepeat)) => {
@@ -1211,12 +1374,6 @@ impl TerminalState {
                 self.reverse_wraparound_mode = false;
             }
 
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ReverseWraparound,
-            )) => {
-                self.decqrm_response(mode, true, self.reverse_wraparound_mode);
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::LeftRightMarginMode,
             )) => {
@@ -1230,12 +1387,6 @@ impl TerminalState {
                 self.left_and_right_margins = 0..self.screen().physical_cols;
             }
 
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::LeftRightMarginMode,
-            )) => {
-                self.decqrm_response(mode, true, self.left_and_right_margin_mode);
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SaveCursor)) => {
                 self.dec_save_cursor();
             }
@@ -1252,10 +1403,6 @@ impl TerminalState {
                 self.dec_auto_wrap = false;
             }
 
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AutoWrap)) => {
-                self.decqrm_response(mode, true, self.dec_auto_wrap);
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::OriginMode)) => {
                 self.dec_origin_mode = true;
                 self.set_cursor_pos(&Position::Absolute(0), &Position::Absolute(0));
@@ -1266,10 +1413,6 @@ impl TerminalState {
                 self.set_cursor_pos(&Position::Absolute(0), &Position::Absolute(0));
             }
 
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::OriginMode)) => {
-                self.decqrm_response(mode, true, self.dec_origin_mode);
-            }
-
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic,
             )) => {
@@ -1280,15 +1423,6 @@ impl TerminalState {
             )) => {
                 self.use_private_color_registers_for_each_graphic = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic,
-            )) => {
-                self.decqrm_response(
-                    mode,
-                    true,
-                    self.use_private_color_registers_for_each_graphic,
-                );
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::SynchronizedOutput,
@@ -1300,13 +1434,6 @@ impl TerminalState {
             )) => {
                 // This is handled in wezterm's mux
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::SynchronizedOutput,
-            )) => {
-                // This is handled in wezterm's mux; if we get here, then it isn't enabled,
-                // so we always report false
-                self.decqrm_response(mode, true, false);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SmoothScroll))
             | Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SmoothScroll)) => {
@@ -1347,7 +1474,7 @@ impl TerminalState {
                 self.insert = false;
             }
             Mode::QueryMode(TerminalMode::Code(TerminalModeCode::Insert)) => {
-                self.decqrm_response(mode, true, self.insert);
+                self.decqrm_response(mode, self.insert.into());
             }
 
             Mode::SetMode(TerminalMode::Code(TerminalModeCode::AutomaticNewline)) => {
@@ -1357,7 +1484,7 @@ impl TerminalState {
                 self.newline_mode = false;
             }
             Mode::QueryMode(TerminalMode::Code(TerminalModeCode::AutomaticNewline)) => {
-                self.decqrm_response(mode, true, self.newline_mode);
+                self.decqrm_response(mode, self.newline_mode.into());
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::BracketedPaste)) => {
@@ -1366,9 +1493,6 @@ impl TerminalState {
             Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::BracketedPaste)) => {
                 self.bracketed_paste = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::BracketedPaste)) => {
-                self.decqrm_response(mode, true, self.bracketed_paste);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::OptEnableAlternateScreen,
@@ -1410,11 +1534,6 @@ impl TerminalState {
             )) => {
                 self.application_cursor_keys = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ApplicationCursorKeys,
-            )) => {
-                self.decqrm_response(mode, true, self.application_cursor_keys);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SixelScrolling)) => {
                 self.sixel_scrolling = true;
@@ -1422,9 +1541,6 @@ impl TerminalState {
             Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SixelScrolling)) => {
                 self.sixel_scrolling = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SixelScrolling)) => {
-                self.decqrm_response(mode, true, self.sixel_scrolling);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::DecAnsiMode)) => {
                 self.dec_ansi_mode = true;
@@ -1432,9 +1548,6 @@ impl TerminalState {
             Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::DecAnsiMode)) => {
                 self.dec_ansi_mode = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::DecAnsiMode)) => {
-                self.decqrm_response(mode, true, self.dec_ansi_mode);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
                 self.cursor_visible = true;
@@ -1442,15 +1555,15 @@ impl TerminalState {
             Mode::ResetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
                 self.cursor_visible = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::ShowCursor)) => {
-                self.decqrm_response(mode, true, self.cursor_visible);
-            }
             Mode::SetMode(TerminalMode::Code(TerminalModeCode::ShowCursor)) => {
                 self.cursor_visible = true;
             }
             Mode::ResetMode(TerminalMode::Code(TerminalModeCode::ShowCursor)) => {
                 self.cursor_visible = false;
             }
+            Mode::QueryMode(TerminalMode::Code(TerminalModeCode::ShowCursor)) => {
+                self.decqrm_response(mode, self.cursor_visible.into());
+            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::MouseTracking)) => {
                 self.mouse_tracking = true;
@@ -1460,9 +1573,6 @@ impl TerminalState {
                 self.mouse_tracking = false;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::MouseTracking)) => {
-                self.decqrm_response(mode, true, self.mouse_tracking);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::HighlightMouseTracking,
@@ -1481,11 +1591,6 @@ impl TerminalState {
                 self.button_event_mouse = false;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::ButtonEventMouse,
-            )) => {
-                self.decqrm_response(mode, true, self.button_event_mouse);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
                 self.any_event_mouse = true;
@@ -1495,9 +1600,6 @@ impl TerminalState {
                 self.any_event_mouse = false;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::AnyEventMouse)) => {
-                self.decqrm_response(mode, true, self.any_event_mouse);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
                 self.focus_tracking = true;
@@ -1507,9 +1609,6 @@ impl TerminalState {
                 self.focus_tracking = false;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::FocusTracking)) => {
-                self.decqrm_response(mode, true, self.focus_tracking);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRMouse)) => {
                 self.sgr_mouse = true;
@@ -1519,9 +1618,6 @@ impl TerminalState {
                 self.sgr_mouse = false;
                 self.last_mouse_move.take();
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRMouse)) => {
-                self.decqrm_response(mode, true, self.sgr_mouse);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::SixelScrollsRight,
@@ -1533,11 +1629,6 @@ impl TerminalState {
             )) => {
                 self.sixel_scrolls_right = false;
             }
-            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
-                DecPrivateModeCode::SixelScrollsRight,
-            )) => {
-                self.decqrm_response(mode, true, self.sixel_scrolls_right);
-            }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::ClearAndEnableAlternateScreen,
@@ -1558,9 +1649,44 @@ impl TerminalState {
                     self.dec_restore_cursor();
                 }
             }
-            Mode::SaveDecPrivateMode(DecPrivateMode::Code(n))
-            | Mode::RestoreDecPrivateMode(DecPrivateMode::Code(n)) => {
-                log::warn!("save/restore dec mode {:?} unimplemented", n)
+            Mode::SaveDecPrivateMode(DecPrivateMode::Code(code)) => {
+                match self.dec_private_mode(&code) {
+                    Some(enabled) => {
+                        self.saved_dec_modes.insert(code.to_u16().unwrap(), enabled);
+                    }
+                    None => log::debug!("XTSAVE: dec mode {:?} has no state to save", code),
+                }
+            }
+            Mode::RestoreDecPrivateMode(DecPrivateMode::Code(code)) => {
+                let saved = self.saved_dec_modes.get(&code.to_u16().unwrap()).copied();
+                if let Some(enabled) = saved {
+                    // Restoring a mode shouldn't repeat the side effects
+                    // of setting it, such as homing the cursor or saving
+                    // and clearing the screen, so those modes restore
+                    // just the state that they track
+                    let code = match code {
+                        DecPrivateModeCode::OriginMode => {
+                            self.dec_origin_mode = enabled;
+                            return;
+                        }
+                        DecPrivateModeCode::OptEnableAlternateScreen
+                        | DecPrivateModeCode::ClearAndEnableAlternateScreen => {
+                            DecPrivateModeCode::EnableAlternateScreen
+                        }
+                        code => code,
+                    };
+                    let mode = DecPrivateMode::Code(code);
+                    self.perform_csi_mode(if enabled {
+                        Mode::SetDecPrivateMode(mode)
+                    } else {
+                        Mode::ResetDecPrivateMode(mode)
+                    });
+                }
+            }
+
+            Mode::QueryDecPrivateMode(DecPrivateMode::Code(ref code)) => {
+                let setting = self.dec_private_mode_setting(code);
+                self.decqrm_response(mode, setting);
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Unspecified(_))
@@ -1579,7 +1705,7 @@ impl TerminalState {
             }
 
             Mode::QueryDecPrivateMode(_) | Mode::QueryMode(_) => {
-                self.decqrm_response(mode, false, false);
+                self.decqrm_response(mode, ModeSetting::NotRecognized);
             }
         }
     }
@@ -1629,6 +1755,47 @@ impl TerminalState {
         checksum
     }
 
+    fn push_title(&mut self, window: bool, icon: bool) {
+        let saved = SavedTitle {
+            title: if window {
+                Some(self.title.clone())
+            } else {
+                None
+            },
+            icon_title: if icon {
+                Some(self.icon_title.clone())
+            } else {
+                None
+            },
+        };
+        let stack = self.screen.title_stack();
+        if stack.len() >= MAX_TITLE_STACK_DEPTH {
+            // Make room by discarding the oldest entry
+            stack.remove(0);
+        }
+        stack.push(saved);
+    }
+
+    fn pop_title(&mut self, window: bool, icon: bool) {
+        let saved = match self.screen.title_stack().pop() {
+            Some(saved) => saved,
+            None => return,
+        };
+        if window {
+            if let Some(title) = saved.title {
+                self.title = title;
+            }
+        }
+        if icon {
+            if let Some(icon_title) = saved.icon_title {
+                self.icon_title = icon_title;
+            }
+        }
+        if let Some(handler) = self.alert_handler.as_mut() {
+            handler.alert(Alert::TitleMaybeChanged);
+        }
+    }
+
     fn perform_csi_window(&mut self, window: Window) {
         match window {
             Window::ReportTextAreaSizeCells => {
@@ -1663,13 +1830,31 @@ impl TerminalState {
             }
 
             Window::ReportWindowTitle => {
-                write!(
-                    self.writer,
-                    "{}",
-                    OperatingSystemCommand::SetWindowTitleSun(self.title.clone())
-                )
-                .ok();
-                self.writer.flush().ok();
+                // Reporting the title allows an application to read back
+                // content that may have been injected by someone else,
+                // so this is only permitted when explicitly enabled
+                if self.config.enable_title_reporting() {
+                    write!(
+                        self.writer,
+                        "{}",
+                        OperatingSystemCommand::SetWindowTitleSun(self.title.clone())
+                    )
+                    .ok();
+                    self.writer.flush().ok();
+                }
+            }
+
+            Window::ReportIconLabel => {
+                if self.config.enable_title_reporting() {
+                    let icon_title = self.icon_title.as_ref().unwrap_or(&self.title).clone();
+                    write!(
+                        self.writer,
+                        "{}",
+                        OperatingSystemCommand::SetIconNameSun(icon_title)
+                    )
+                    .ok();
+                    self.writer.flush().ok();
+                }
             }
 
             Window::ChecksumRectangularArea {
@@ -1694,12 +1879,12 @@ impl TerminalState {
                 // up to the user!
             }
             Window::Iconify | Window::DeIconify => {}
-            Window::PopIconAndWindowTitle
-            | Window::PopWindowTitle
-            | Window::PopIconTitle
-            | Window::PushIconAndWindowTitle
-            | Window::PushIconTitle
-            | Window::PushWindowTitle => {}
+            Window::PushIconAndWindowTitle => self.push_title(true, true),
+            Window::PushIconTitle => self.push_title(false, true),
+            Window::PushWindowTitle => self.push_title(true, false),
+            Window::PopIconAndWindowTitle => self.pop_title(true, true),
+            Window::PopIconTitle => self.pop_title(false, true),
+            Window::PopWindowTitle => self.pop_title(true, false),
 
             _ => log::warn!("unhandled Window CSI {:?}", window),
         }
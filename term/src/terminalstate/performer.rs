@@ -404,6 +404,7 @@ impl<'a> Performer<'a> {
             CSI::Device(dev) => self.state.perform_device(*dev),
             CSI::Mouse(mouse) => error!("mouse report sent by app? {:?}", mouse),
             CSI::Window(window) => self.state.perform_csi_window(window),
+            CSI::Keyboard(keyboard) => self.state.perform_csi_keyboard(keyboard),
             CSI::Unspecified(unspec) => {
                 log::warn!("unknown unspecified CSI: {:?}", format!("{}", unspec))
             }
@@ -530,8 +531,11 @@ impl<'a> Performer<'a> {
                 self.unicode_version = UnicodeVersion(self.config.unicode_version());
                 self.unicode_version_stack.clear();
                 self.suppress_initial_title_change = false;
+                self.pressed_keys.clear();
+                self.saved_dec_modes.clear();
 
                 self.screen.activate_primary_screen(seqno);
+                self.screen.clear_stacks();
                 self.erase_in_display(EraseInDisplay::EraseScrollback);
                 self.erase_in_display(EraseInDisplay::EraseDisplay);
                 if let Some(handler) = self.alert_handler.as_mut() {
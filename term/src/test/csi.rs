@@ -222,3 +222,273 @@ fn test_ed_erase_scrollback() {
     term.print("b");
     assert_all_contents(&term, file!(), line!(), &["111", "222", "ab "]);
 }
+
+#[test]
+fn test_kitty_keyboard_stack() {
+    use termwiz::escape::csi::KittyKeyboardFlags;
+
+    let mut term = TestTerm::new(3, 3, 0);
+    term.print("\x1b[?u");
+    assert_eq!(term.take_output(), "\x1b[?0u");
+
+    term.print("\x1b[>1u");
+    term.print("\x1b[>3u");
+    assert_eq!(
+        term.kitty_keyboard_flags(),
+        KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES | KittyKeyboardFlags::REPORT_EVENT_TYPES
+    );
+    term.print("\x1b[=2;3u");
+    term.print("\x1b[?u");
+    assert_eq!(term.take_output(), "\x1b[?1u");
+
+    term.print("\x1b[=8;2u");
+    assert_eq!(
+        term.kitty_keyboard_flags(),
+        KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES
+            | KittyKeyboardFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+    );
+
+    // The alternate screen has its own stack
+    term.print("\x1b[?1049h");
+    assert_eq!(term.kitty_keyboard_flags(), KittyKeyboardFlags::NONE);
+    term.print("\x1b[>16u");
+    term.print("\x1b[?1049l");
+    term.print("\x1b[<u");
+    assert_eq!(
+        term.kitty_keyboard_flags(),
+        KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES
+    );
+    term.print("\x1b[<5u");
+    assert_eq!(term.kitty_keyboard_flags(), KittyKeyboardFlags::NONE);
+
+    term.print("\x1b[?1049h");
+    assert_eq!(
+        term.kitty_keyboard_flags(),
+        KittyKeyboardFlags::REPORT_ASSOCIATED_TEXT
+    );
+    // RIS clears both stacks
+    term.print("\x1bc");
+    term.print("\x1b[?1049h");
+    assert_eq!(term.kitty_keyboard_flags(), KittyKeyboardFlags::NONE);
+}
+
+#[test]
+fn test_kitty_keyboard_events() {
+    let mut term = TestTerm::new(3, 3, 0);
+
+    // Releases are not reported until requested
+    term.key_down(KeyCode::Char('a'), KeyModifiers::NONE)
+        .unwrap();
+    term.key_up(KeyCode::Char('a'), KeyModifiers::NONE).unwrap();
+    assert_eq!(term.take_output(), "a");
+
+    term.print("\x1b[>3u");
+    term.key_down(KeyCode::Char('a'), KeyModifiers::CTRL)
+        .unwrap();
+    term.key_down(KeyCode::Char('a'), KeyModifiers::CTRL)
+        .unwrap();
+    term.key_up(KeyCode::Char('a'), KeyModifiers::CTRL).unwrap();
+    assert_eq!(term.take_output(), "\x1b[97;5u\x1b[97;5:2u\x1b[97;5:3u");
+
+    // A release without a matching press is not reported
+    term.key_up(KeyCode::Char('b'), KeyModifiers::NONE).unwrap();
+    term.key_down(KeyCode::Escape, KeyModifiers::NONE).unwrap();
+    term.key_up(KeyCode::Escape, KeyModifiers::NONE).unwrap();
+    assert_eq!(term.take_output(), "\x1b[27u\x1b[27;1:3u");
+
+    // Enter keeps its legacy encoding and has no release event
+    term.key_down(KeyCode::Enter, KeyModifiers::NONE).unwrap();
+    term.key_up(KeyCode::Enter, KeyModifiers::NONE).unwrap();
+    assert_eq!(term.take_output(), "\r");
+
+    term.print("\x1b[=31u");
+    term.key_down(KeyCode::Char('A'), KeyModifiers::SHIFT)
+        .unwrap();
+    term.key_up(KeyCode::Char('a'), KeyModifiers::SHIFT)
+        .unwrap();
+    assert_eq!(term.take_output(), "\x1b[97:65;2;65u\x1b[97:65;2:3u");
+
+    // Popping the flags forgets about keys that are still held
+    term.print("\x1b[=3u");
+    term.key_down(KeyCode::Char('x'), KeyModifiers::CTRL)
+        .unwrap();
+    term.print("\x1b[<u\x1b[>3u");
+    term.key_up(KeyCode::Char('x'), KeyModifiers::CTRL).unwrap();
+    assert_eq!(term.take_output(), "\x1b[120;5u");
+
+    // as does losing the focus
+    term.key_down(KeyCode::Char('x'), KeyModifiers::CTRL)
+        .unwrap();
+    term.focus_changed(false);
+    term.focus_changed(true);
+    term.key_down(KeyCode::Char('x'), KeyModifiers::CTRL)
+        .unwrap();
+    assert_eq!(term.take_output(), "\x1b[120;5u\x1b[120;5u");
+
+    // Keys pressed before the protocol was enabled are not tracked
+    term.print("\x1b[<u");
+    term.key_down(KeyCode::Char('y'), KeyModifiers::CTRL)
+        .unwrap();
+    term.print("\x1b[>3u");
+    term.key_up(KeyCode::Char('y'), KeyModifiers::CTRL).unwrap();
+    term.key_down(KeyCode::Char('y'), KeyModifiers::CTRL)
+        .unwrap();
+    assert_eq!(term.take_output(), "\x19\x1b[121;5u");
+}
+
+#[test]
+fn test_title_stack() {
+    let mut term = TestTerm::with_title_reporting(3, 10);
+    term.print("\x1b]2;shell\x1b\\");
+
+    // An editor saves both titles on entry
+    term.print("\x1b[22;0t");
+    term.print("\x1b]2;vim\x1b\\");
+    assert_eq!(term.get_title(), "vim");
+
+    // and a nested program saves just the window title
+    term.print("\x1b[22;2t");
+    term.print("\x1b]2;:help\x1b\\");
+    term.print("\x1b[21t");
+    assert_eq!(term.take_output(), "\x1b]l:help\x1b\\");
+
+    term.print("\x1b[23;2t");
+    assert_eq!(term.get_title(), "vim");
+    term.print("\x1b[23;0t");
+    assert_eq!(term.get_title(), "shell");
+
+    // Popping an empty stack leaves the title alone;
+    // an omitted parameter is treated as 0
+    term.print("\x1b[23t");
+    assert_eq!(term.get_title(), "shell");
+
+    // Only the icon title is restored when only it was pushed
+    term.print("\x1b]1;tab\x1b\\");
+    term.print("\x1b[22;1t");
+    term.print("\x1b]1;other\x1b\\");
+    term.print("\x1b]2;window\x1b\\");
+    term.print("\x1b[23;0t");
+    assert_eq!(term.get_title(), "tab");
+    term.print("\x1b[20t\x1b[21t");
+    assert_eq!(term.take_output(), "\x1b]Ltab\x1b\\\x1b]lwindow\x1b\\");
+}
+
+#[test]
+fn test_title_reporting_disabled() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("\x1b]1;tab\x1b\\\x1b]2;window\x1b\\");
+    term.print("\x1b[20t\x1b[21t");
+    assert_eq!(term.take_output(), "");
+}
+
+#[test]
+fn test_title_stack_per_screen_and_bounded() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("\x1b]2;primary\x1b\\\x1b[22;2t");
+
+    // The alternate screen has its own stack
+    term.print("\x1b[?1049h");
+    term.print("\x1b]2;alt\x1b\\\x1b[23;2t");
+    assert_eq!(term.get_title(), "alt");
+    term.print("\x1b[?1049l");
+    term.print("\x1b[23;2t");
+    assert_eq!(term.get_title(), "primary");
+
+    // The oldest entries are discarded when the stack is full
+    for i in 0..12 {
+        term.print(format!("\x1b]2;{}\x1b\\\x1b[22;2t", i));
+    }
+    for _ in 0..12 {
+        term.print("\x1b[23;2t");
+    }
+    assert_eq!(term.get_title(), "2");
+}
+
+#[test]
+fn test_decrqm() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("\x1b[?1000$p\x1b[?2004$p");
+    assert_eq!(term.take_output(), "\x1b[?1000;2$y\x1b[?2004;2$y");
+
+    term.print("\x1b[?1000h\x1b[?1049h");
+    term.print("\x1b[?1000$p\x1b[?1049$p\x1b[?47$p");
+    assert_eq!(
+        term.take_output(),
+        "\x1b[?1000;1$y\x1b[?1049;1$y\x1b[?47;1$y"
+    );
+
+    // Modes that cannot be changed report their permanent setting
+    term.print("\x1b[?3$p\x1b[?8$p\x1b[?9999$p");
+    assert_eq!(term.take_output(), "\x1b[?3;4$y\x1b[?8;3$y\x1b[?9999;0$y");
+
+    term.print("\x1b[4h\x1b[4$p\x1b[25$p\x1b[2$p");
+    assert_eq!(term.take_output(), "\x1b[4;1$y\x1b[25;1$y\x1b[2;0$y");
+}
+
+#[test]
+fn test_xtsave_xtrestore() {
+    let mut term = TestTerm::new(3, 10, 0);
+    term.print("\x1b[?2004h\x1b[?1002h\x1b[?1006h");
+    term.print("\x1b[?2004;1002;1006;1000s");
+
+    // An application changes the modes, then restores them on exit
+    term.print("\x1b[?2004l\x1b[?1002l\x1b[?1006l\x1b[?1000h");
+    term.print("\x1b[?2004;1002;1006;1000r");
+    term.print("\x1b[?2004$p\x1b[?1002$p\x1b[?1006$p\x1b[?1000$p");
+    assert_eq!(
+        term.take_output(),
+        "\x1b[?2004;1$y\x1b[?1002;1$y\x1b[?1006;1$y\x1b[?1000;2$y"
+    );
+
+    // Restoring a mode that was never saved leaves it alone
+    term.print("\x1b[?25l\x1b[?25r\x1b[?25$p");
+    assert_eq!(term.take_output(), "\x1b[?25;2$y");
+
+    // Restoring origin mode doesn't home the cursor
+    term.print("\x1b[?6s\x1b[?6h");
+    term.cup(3, 1);
+    term.print("\x1b[?6r");
+    term.assert_cursor_pos(3, 1, None, None);
+    term.print("\x1b[?6$p");
+    assert_eq!(term.take_output(), "\x1b[?6;2$y");
+
+    // and restoring the alternate screen doesn't clear it
+    term.print("\x1b[?1049h\x1b[Halt\x1b[?1049s\x1b[?1049l");
+    term.print("\x1b[?1049r\x1b[?1049$p");
+    assert_eq!(term.take_output(), "\x1b[?1049;1$y");
+    assert_visible_contents(
+        &term,
+        file!(),
+        line!(),
+        &["alt       ", "          ", "          "],
+    );
+}
+
+#[test]
+fn test_decstr() {
+    let mut term = TestTerm::new(5, 10, 0);
+    term.print("\x1b[?1049h");
+    term.print("\x1b[4h\x1b[?6h\x1b[?1h\x1b[?25l\x1b[?5h\x1b[?2004h");
+    term.print("\x1b[2;4r\x1b[1;31m\x0e");
+    term.print("\x1b[!p");
+
+    term.print("\x1b[4$p\x1b[?6$p\x1b[?1$p\x1b[?25$p");
+    assert_eq!(
+        term.take_output(),
+        "\x1b[4;2$y\x1b[?6;2$y\x1b[?1;2$y\x1b[?25;1$y"
+    );
+
+    // Modes that DECSTR doesn't mention are left alone,
+    // including the active screen
+    term.print("\x1b[?5$p\x1b[?2004$p\x1b[?1049$p");
+    assert_eq!(
+        term.take_output(),
+        "\x1b[?5;1$y\x1b[?2004;1$y\x1b[?1049;1$y"
+    );
+
+    // The pen and character set are the defaults
+    term.print("\x1b[Hq");
+    let cell = term.screen().get_cell(0, 0).unwrap().clone();
+    assert_eq!(cell.str(), "q");
+    assert_eq!(*cell.attrs(), CellAttributes::default());
+}
@@ -0,0 +1,361 @@
+//! Tests for the kitty graphics protocol.
+//! The fixtures follow the shape of the sequences that
+//! `kitty +kitten icat` sends: a support query followed by
+//! direct (`t=d`) transmissions, chunked in the same way as
+//! its `write_chunked` function.
+use super::*;
+use termwiz::image::{AnimationState, ImageAnimation, ImageDataType};
+
+/// The query that icat uses to detect support for the protocol
+const ICAT_QUERY: &str = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+
+/// What `icat --clear` sends to remove all of the images
+const ICAT_CLEAR: &str = "\x1b_Ga=d\x1b\\";
+
+/// A 16x32 pixel red PNG, which covers 2x2 cells in a TestTerm
+const PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAABAAAAAgCAYAAAAbifjMAAAAIklEQVR42mP4z8DwnxLMMGrAqAGjBow\
+                   aMGrAqAGjBgw3AwC68Pwu7IetYQAAAABJRU5ErkJggg==";
+
+impl TestTerm {
+    fn kitty(&mut self, control: &str) {
+        self.print(format!("\x1b_G{}\x1b\\", control));
+    }
+
+    /// Transmits `data` the way that icat does: the base64 payload is
+    /// split into chunks of 4096 bytes, the control keys are only sent
+    /// with the first chunk, and the continuation chunks carry nothing
+    /// but `m`.  A payload that fits in one chunk is sent without `m`.
+    fn kitty_transmit(&mut self, keys: &str, data: &[u8]) {
+        let payload = base64::encode(data);
+        let chunks: Vec<&str> = payload
+            .as_bytes()
+            .chunks(4096)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap())
+            .collect();
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let more = idx + 1 < chunks.len();
+            let control = match (idx, more) {
+                (0, false) => keys.to_string(),
+                (0, true) => format!("{},m=1", keys),
+                (_, true) => "m=1".to_string(),
+                (_, false) => "m=0".to_string(),
+            };
+            self.kitty(&format!("{};{}", control, chunk));
+        }
+    }
+
+    fn kitty_transmit_png(&mut self, keys: &str, quiet: u8) {
+        let png = base64::decode(PNG).unwrap();
+        self.kitty_transmit(&format!("{},q={},f=100,t=d", keys, quiet), &png);
+    }
+
+    fn has_placement(&self, x: usize, y: VisibleRowIndex, image_id: u32) -> bool {
+        self.screen()
+            .get_cell(x, y)
+            .and_then(|cell| cell.attrs().images())
+            .map(|images| {
+                images
+                    .iter()
+                    .any(|img| img.matches_placement(image_id, None))
+            })
+            .unwrap_or(false)
+    }
+}
+
+#[test]
+fn test_kitty_query() {
+    let mut term = TestTerm::new(5, 10, 0);
+    term.print(ICAT_QUERY);
+    assert_eq!(term.take_output(), "\x1b_Gi=31;OK\x1b\\");
+}
+
+#[test]
+fn test_kitty_transmit_responses() {
+    let mut term = TestTerm::new(5, 10, 0);
+
+    term.kitty_transmit_png("a=T,i=1", 0);
+    assert_eq!(term.take_output(), "\x1b_Gi=1;OK\x1b\\");
+    assert!(term.has_placement(0, 0, 1));
+    assert!(term.has_placement(1, 1, 1));
+    assert!(!term.has_placement(2, 1, 1));
+
+    // q=1 suppresses OK, q=2 suppresses everything
+    term.kitty_transmit_png("a=t,i=2", 1);
+    term.kitty("a=p,i=7,q=2");
+    assert_eq!(term.take_output(), "");
+
+    term.kitty("a=p,i=7,q=1");
+    assert_eq!(term.take_output(), "\x1b_Gi=7;ENOENT\x1b\\");
+
+    term.kitty("a=p,i=2,C=1");
+    assert_eq!(term.take_output(), "\x1b_Gi=2;OK\x1b\\");
+
+    // i= and I= are mutually exclusive
+    term.kitty_transmit_png("a=t,i=3,I=4", 0);
+    assert_eq!(term.take_output(), "\x1b_GI=4,i=3;EINVAL\x1b\\");
+}
+
+#[test]
+fn test_kitty_chunked_transmit() {
+    let mut term = TestTerm::new(5, 10, 0);
+
+    // 32x32 pixels of noise, which doesn't compress well, so that
+    // the payload needs more than one chunk
+    let mut seed = 0x2545_f491u32;
+    let pixels: Vec<u8> = (0..32 * 32 * 4)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed >> 24) as u8
+        })
+        .collect();
+    // Like icat, compress raw pixel data
+    let data = miniz_oxide::deflate::compress_to_vec_zlib(&pixels, 6);
+    assert!(base64::encode(&data).len() > 4096);
+
+    term.kitty_transmit("a=T,f=32,s=32,v=32,i=5,o=z,t=d", &data);
+    assert_eq!(term.take_output(), "\x1b_Gi=5;OK\x1b\\");
+    assert!(term.has_placement(3, 1, 5));
+    assert!(!term.has_placement(4, 1, 5));
+
+    let images = term
+        .screen()
+        .get_cell(0, 0)
+        .unwrap()
+        .attrs()
+        .images()
+        .unwrap();
+    match &*images[0].image_data().data() {
+        ImageDataType::Rgba8 {
+            width,
+            height,
+            data,
+            ..
+        } => {
+            assert_eq!((*width, *height), (32, 32));
+            assert!(data == &pixels);
+        }
+        _ => panic!("expected rgba data"),
+    }
+
+    // The quiet level of the first chunk applies to the whole image
+    term.print("\x1b[H");
+    term.kitty_transmit("a=T,q=2,f=32,s=32,v=32,i=6,o=z,t=d", &data);
+    assert_eq!(term.take_output(), "");
+    assert!(term.has_placement(0, 0, 6));
+}
+
+#[test]
+fn test_kitty_clear() {
+    let mut term = TestTerm::new(5, 10, 0);
+    term.kitty_transmit_png("a=T,i=1", 2);
+    assert!(term.has_placement(0, 0, 1));
+
+    term.print(ICAT_CLEAR);
+    assert_eq!(term.take_output(), "");
+    assert!(!term.has_placement(0, 0, 1));
+
+    // The data is kept, as d=a is lower case
+    term.print("\x1b[H");
+    term.kitty("a=p,i=1,q=1");
+    assert_eq!(term.take_output(), "");
+    assert!(term.has_placement(0, 0, 1));
+}
+
+#[test]
+fn test_kitty_delete_by_id() {
+    let mut term = TestTerm::new(5, 10, 0);
+
+    term.kitty_transmit_png("a=T,i=1", 2);
+    term.kitty_transmit_png("a=T,I=5", 2);
+    assert_eq!(term.take_output(), "");
+    assert!(term.has_placement(0, 0, 1));
+
+    // Delete commands never produce a reply
+    term.kitty("a=d,d=i,i=1");
+    assert_eq!(term.take_output(), "");
+    assert!(!term.has_placement(0, 0, 1));
+
+    // The data was kept, so the image can be placed again
+    term.print("\x1b[H");
+    term.kitty("a=p,i=1,q=1");
+    assert_eq!(term.take_output(), "");
+    assert!(term.has_placement(0, 0, 1));
+
+    term.kitty("a=d,d=I,i=1,q=1");
+    term.kitty("a=p,i=1,q=1");
+    assert_eq!(term.take_output(), "\x1b_Gi=1;ENOENT\x1b\\");
+
+    term.kitty("a=d,d=i,i=9");
+    assert_eq!(term.take_output(), "");
+
+    term.kitty("a=d,d=N,I=5");
+    term.kitty("a=p,I=5,q=1");
+    assert_eq!(term.take_output(), "\x1b_GI=5;ENOENT\x1b\\");
+    term.kitty("a=d,d=n,I=5");
+    assert_eq!(term.take_output(), "");
+}
+
+#[test]
+fn test_kitty_delete_by_position() {
+    let mut term = TestTerm::new(5, 10, 0);
+
+    term.kitty_transmit_png("a=t,i=1", 2);
+    term.kitty_transmit_png("a=t,i=2", 2);
+    term.kitty_transmit_png("a=t,i=3", 2);
+
+    let place_all = |term: &mut TestTerm| {
+        // 1 covers cols 0-1 rows 0-1, 2 covers cols 4-5 rows 0-1
+        // and 3 covers cols 0-1 rows 3-4
+        term.print("\x1b[1;1H");
+        term.kitty("a=p,i=1,C=1,q=2");
+        term.print("\x1b[1;5H");
+        term.kitty("a=p,i=2,z=-1,C=1,q=2");
+        term.print("\x1b[4;1H");
+        term.kitty("a=p,i=3,z=5,C=1,q=2");
+    };
+
+    place_all(&mut term);
+    term.kitty("a=d,d=x,x=5");
+    assert!(term.has_placement(0, 0, 1));
+    assert!(!term.has_placement(4, 0, 2));
+    assert!(term.has_placement(0, 3, 3));
+
+    place_all(&mut term);
+    term.kitty("a=d,d=y,y=5");
+    assert!(term.has_placement(0, 0, 1));
+    assert!(term.has_placement(4, 0, 2));
+    assert!(!term.has_placement(0, 3, 3));
+
+    place_all(&mut term);
+    term.kitty("a=d,d=z,z=-1");
+    assert!(term.has_placement(0, 0, 1));
+    assert!(!term.has_placement(4, 0, 2));
+    assert!(term.has_placement(0, 3, 3));
+
+    place_all(&mut term);
+    term.kitty("a=d,d=p,x=2,y=2");
+    assert!(!term.has_placement(0, 0, 1));
+    assert!(term.has_placement(4, 0, 2));
+
+    place_all(&mut term);
+    term.kitty("a=d,d=q,x=5,y=1,z=0");
+    assert!(term.has_placement(4, 0, 2));
+    term.kitty("a=d,d=q,x=5,y=1,z=-1");
+    assert!(!term.has_placement(4, 0, 2));
+
+    place_all(&mut term);
+    term.print("\x1b[5;2H");
+    term.kitty("a=d,d=c");
+    assert!(term.has_placement(0, 0, 1));
+    assert!(term.has_placement(4, 0, 2));
+    assert!(!term.has_placement(0, 3, 3));
+
+    // Upper case variants also free the data of images that are
+    // no longer placed anywhere
+    place_all(&mut term);
+    term.kitty("a=d,d=Y,y=1");
+    assert!(term.has_placement(0, 3, 3));
+    term.kitty("a=p,i=1,q=1");
+    term.kitty("a=p,i=2,q=1");
+    assert_eq!(
+        term.take_output(),
+        "\x1b_Gi=1;ENOENT\x1b\\\x1b_Gi=2;ENOENT\x1b\\"
+    );
+}
+
+#[test]
+fn test_kitty_delete_frames() {
+    let mut term = TestTerm::new(5, 10, 0);
+
+    // A 1x1 red image with a second green frame
+    term.kitty("a=t,i=1,f=32,s=1,v=1,q=2;/wAA/w==");
+    term.kitty("a=f,i=1,f=32,s=1,v=1,q=2;AP8A/w==");
+    term.kitty("a=p,i=1,C=1,q=2");
+
+    let frame_count = |term: &TestTerm| {
+        let images = term
+            .screen()
+            .get_cell(0, 0)
+            .unwrap()
+            .attrs()
+            .images()
+            .unwrap();
+        let data = images[0].image_data().data();
+        match &*data {
+            ImageDataType::AnimRgba8 { frames, .. } => frames.len(),
+            ImageDataType::Rgba8 { .. } => 1,
+            ImageDataType::EncodedFile(_) => 0,
+        }
+    };
+    assert_eq!(frame_count(&term), 2);
+
+    // Out of range frame numbers are ignored
+    term.kitty("a=d,d=f,i=1,r=3");
+    assert_eq!(frame_count(&term), 2);
+
+    term.kitty("a=d,d=f,i=1,r=1");
+    assert_eq!(frame_count(&term), 1);
+
+    // Errors are not reported either
+    term.kitty("a=d,d=f");
+    term.kitty("a=d,d=f,I=3");
+    assert_eq!(term.take_output(), "");
+}
+
+#[test]
+fn test_kitty_animation_control() {
+    let mut term = TestTerm::new(5, 10, 0);
+
+    // A 1x1 red image with two more frames
+    term.kitty("a=t,i=1,f=32,s=1,v=1,q=2;/wAA/w==");
+    term.kitty("a=f,i=1,f=32,s=1,v=1,q=2;AP8A/w==");
+    term.kitty("a=f,i=1,f=32,s=1,v=1,Z=100,q=2;AAD//w==");
+    term.kitty("a=p,i=1,C=1,q=2");
+
+    let animation = |term: &TestTerm| -> (ImageAnimation, Vec<u128>) {
+        let images = term
+            .screen()
+            .get_cell(0, 0)
+            .unwrap()
+            .attrs()
+            .images()
+            .unwrap();
+        let durations = match &*images[0].image_data().data() {
+            ImageDataType::AnimRgba8 { durations, .. } => {
+                durations.iter().map(|d| d.as_millis()).collect()
+            }
+            _ => vec![],
+        };
+        let anim = *images[0].image_data().animation();
+        (anim, durations)
+    };
+
+    // Playback doesn't start until requested
+    let (anim, durations) = animation(&term);
+    assert_eq!(anim.state, AnimationState::Stopped);
+    assert_eq!(anim.current_frame, 0);
+    assert_eq!(durations, vec![0, 40, 100]);
+
+    term.kitty("a=a,i=1,s=3,v=3,c=2,r=1,z=250");
+    assert_eq!(term.take_output(), "\x1b_Gi=1;OK\x1b\\");
+    let (anim, durations) = animation(&term);
+    assert_eq!(anim.state, AnimationState::Running);
+    assert_eq!(anim.loops, Some(2));
+    assert_eq!(anim.current_frame, 1);
+    assert_eq!(durations, vec![250, 40, 100]);
+
+    // v=1 loops forever; negative gaps are gapless
+    term.kitty("a=a,i=1,s=2,v=1,r=3,z=-1,q=2");
+    let (anim, durations) = animation(&term);
+    assert_eq!(anim.state, AnimationState::Loading);
+    assert_eq!(anim.loops, None);
+    assert_eq!(durations, vec![250, 40, 0]);
+
+    term.kitty("a=a,i=1,s=1,q=2");
+    assert_eq!(animation(&term).0.state, AnimationState::Stopped);
+
+    term.kitty("a=a,i=2,s=3");
+    assert_eq!(term.take_output(), "\x1b_Gi=2;ENOENT\x1b\\");
+}
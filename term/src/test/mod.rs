@@ -6,11 +6,13 @@ mod c0;
 use bitflags::bitflags;
 mod c1;
 mod csi;
+mod kitty;
 // mod selection; FIXME: port to render layer
 use crate::color::ColorPalette;
 use pretty_assertions::assert_eq;
 use std::cell::RefCell;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use termwiz::escape::csi::{Edit, EraseInDisplay, EraseInLine};
 use termwiz::escape::{OneBased, OperatingSystemCommand, CSI};
 use termwiz::surface::{CursorShape, CursorVisibility, SequenceNo, SEQ_ZERO};
@@ -39,31 +41,97 @@ impl Clipboard for LocalClip {
     }
 }
 
+/// Collects the data that the terminal sends back to the application
+#[derive(Clone, Default)]
+struct LocalWriter {
+    buf: Arc<Mutex<Vec<u8>>>,
+}
+
+impl std::io::Write for LocalWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 struct TestTerm {
     term: Terminal,
+    output: LocalWriter,
 }
 
 #[derive(Debug)]
 struct TestTermConfig {
     scrollback: usize,
+    disk_scrollback: usize,
+    title_reporting: bool,
 }
 impl TerminalConfiguration for TestTermConfig {
     fn scrollback_size(&self) -> usize {
         self.scrollback
     }
 
+    fn scrollback_disk_size(&self) -> usize {
+        self.disk_scrollback
+    }
+
     fn color_palette(&self) -> ColorPalette {
         ColorPalette::default()
     }
+
+    fn enable_kitty_graphics(&self) -> bool {
+        true
+    }
+
+    fn enable_title_reporting(&self) -> bool {
+        self.title_reporting
+    }
 }
 
 impl TestTerm {
     fn new(height: usize, width: usize, scrollback: usize) -> Self {
+        Self::with_disk_scrollback(height, width, scrollback, 0)
+    }
+
+    fn with_disk_scrollback(
+        height: usize,
+        width: usize,
+        scrollback: usize,
+        disk_scrollback: usize,
+    ) -> Self {
+        Self::with_config(
+            height,
+            width,
+            TestTermConfig {
+                scrollback,
+                disk_scrollback,
+                title_reporting: false,
+            },
+        )
+    }
+
+    fn with_title_reporting(height: usize, width: usize) -> Self {
+        Self::with_config(
+            height,
+            width,
+            TestTermConfig {
+                scrollback: 0,
+                disk_scrollback: 0,
+                title_reporting: true,
+            },
+        )
+    }
+
+    fn with_config(height: usize, width: usize, config: TestTermConfig) -> Self {
         let _ = pretty_env_logger::formatted_builder()
             .is_test(true)
             .filter_level(log::LevelFilter::Trace)
             .try_init();
 
+        let output = LocalWriter::default();
         let mut term = Terminal::new(
             TerminalSize {
                 physical_rows: height,
@@ -71,15 +139,15 @@ impl TestTerm {
                 pixel_width: width * 8,
                 pixel_height: height * 16,
             },
-            Arc::new(TestTermConfig { scrollback }),
+            Arc::new(config),
             "WezTerm",
             "O_o",
-            Box::new(Vec::new()),
+            Box::new(output.clone()),
         );
         let clip: Arc<dyn Clipboard> = Arc::new(LocalClip::new());
         term.set_clipboard(&clip);
 
-        let mut term = Self { term };
+        let mut term = Self { term, output };
 
         term.set_auto_wrap(true);
 
@@ -144,6 +212,29 @@ impl TestTerm {
         self.print("\x1b]8;;\x1b\\");
     }
 
+    /// Returns the data that the terminal has sent to the application
+    /// since the last call.  The terminal writes from a separate thread,
+    /// so a status report is requested and its response is used to know
+    /// that everything sent before it has arrived.
+    fn take_output(&mut self) -> String {
+        const MARKER: &str = "\x1b[0n";
+        self.print("\x1b[5n");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            {
+                let mut buf = self.output.buf.lock().unwrap();
+                if buf.ends_with(MARKER.as_bytes()) {
+                    let len = buf.len() - MARKER.len();
+                    let result = String::from_utf8_lossy(&buf[..len]).to_string();
+                    buf.clear();
+                    return result;
+                }
+            }
+            assert!(Instant::now() < deadline, "timed out waiting for output");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
     fn soft_reset(&mut self) {
         self.print(CSI);
         self.print("!p");
@@ -1118,3 +1209,79 @@ fn test_hyperlinks() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+#[test]
+fn disk_scrollback() {
+    let mut term = TestTerm::with_disk_scrollback(3, 10, 5, 1000);
+    for n in 0..600 {
+        term.print(format!("{}\r\n", n));
+    }
+
+    fn text(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| line.as_str().trim_end().to_string())
+            .collect()
+    }
+
+    let screen = term.screen();
+    // 600 numbered lines plus the blank line holding the cursor,
+    // of which 3 + 5 remain in memory
+    assert_eq!(screen.lines.len(), 8);
+    assert_eq!(screen.disk_scrollback_len(), 593);
+    assert_eq!(screen.total_lines(), 601);
+    assert_eq!(screen.scrollback_top(), 0);
+
+    let (first, lines) = screen.lines_in_stable_range(&(0..3));
+    assert_eq!(first, 0);
+    assert_eq!(text(&lines), vec!["0", "1", "2"]);
+
+    // Spanning the disk and memory
+    let (first, lines) = screen.lines_in_stable_range(&(590..596));
+    assert_eq!(first, 590);
+    assert_eq!(text(&lines), vec!["590", "591", "592", "593", "594", "595"]);
+
+    // Entirely in memory
+    let (first, lines) = screen.lines_in_stable_range(&(597..600));
+    assert_eq!(first, 597);
+    assert_eq!(text(&lines), vec!["597", "598", "599"]);
+
+    let all: Vec<(StableRowIndex, String)> = screen
+        .iter_stable_lines()
+        .map(|(idx, line)| (idx, line.as_str().trim_end().to_string()))
+        .collect();
+    assert_eq!(all.len(), 601);
+    assert_eq!(all[0], (0, "0".to_string()));
+    assert_eq!(all[300], (300, "300".to_string()));
+    assert_eq!(all[599], (599, "599".to_string()));
+
+    term.erase_in_display(EraseInDisplay::EraseScrollback);
+    let screen = term.screen();
+    assert_eq!(screen.disk_scrollback_len(), 0);
+    assert_eq!(screen.total_lines(), 3);
+    assert_eq!(screen.scrollback_top(), 598);
+}
+
+#[test]
+fn disk_scrollback_is_bounded() {
+    let mut term = TestTerm::with_disk_scrollback(3, 10, 5, 100);
+    for n in 0..600 {
+        term.print(format!("{}\r\n", n));
+    }
+
+    let screen = term.screen();
+    assert_eq!(screen.disk_scrollback_len(), 100);
+    assert_eq!(screen.total_lines(), 108);
+    assert_eq!(screen.scrollback_top(), 493);
+
+    // Asking for lines that have been discarded yields the oldest lines
+    let (first, lines) = screen.lines_in_stable_range(&(0..2));
+    assert_eq!(first, 493);
+    assert_eq!(
+        lines
+            .iter()
+            .map(|line| line.as_str().trim_end().to_string())
+            .collect::<Vec<_>>(),
+        vec!["493", "494"]
+    );
+}
@@ -505,8 +505,14 @@ pub enum KittyImageDelete {
     AtCursorPosition { delete: bool },
 
     /// d='f' or d='F'
-    /// Delete animation frames
-    AnimationFrames { delete: bool },
+    /// Delete the animation frame specified by frame_number
+    /// from the image specified by image_id or image_number.
+    AnimationFrames {
+        image_id: Option<u32>,
+        image_number: Option<u32>,
+        frame_number: Option<u32>,
+        delete: bool,
+    },
 
     /// d='p' or d='P'
     /// Delete all placements that intersect the specified
@@ -557,7 +563,12 @@ impl KittyImageDelete {
                 delete,
             }),
             'c' | 'C' => Some(Self::AtCursorPosition { delete }),
-            'f' | 'F' => Some(Self::AnimationFrames { delete }),
+            'f' | 'F' => Some(Self::AnimationFrames {
+                image_id: geti(keys, "i"),
+                image_number: geti(keys, "I"),
+                frame_number: geti(keys, "r"),
+                delete,
+            }),
             'p' | 'P' => Some(Self::DeleteAt {
                 x: geti(keys, "x")?,
                 y: geti(keys, "y")?,
@@ -619,8 +630,22 @@ impl KittyImageDelete {
             Self::AtCursorPosition { delete } => {
                 keys.insert("d", d('c', delete));
             }
-            Self::AnimationFrames { delete } => {
+            Self::AnimationFrames {
+                image_id,
+                image_number,
+                frame_number,
+                delete,
+            } => {
                 keys.insert("d", d('f', delete));
+                if let Some(i) = image_id {
+                    keys.insert("i", i.to_string());
+                }
+                if let Some(no) = image_number {
+                    keys.insert("I", no.to_string());
+                }
+                if let Some(r) = frame_number {
+                    keys.insert("r", r.to_string());
+                }
             }
             Self::DeleteAt { x, y, delete } => {
                 keys.insert("d", d('p', delete));
@@ -628,7 +653,7 @@ impl KittyImageDelete {
                 keys.insert("y", y.to_string());
             }
             Self::DeleteAtZ { x, y, z, delete } => {
-                keys.insert("d", d('p', delete));
+                keys.insert("d", d('q', delete));
                 keys.insert("x", x.to_string());
                 keys.insert("y", y.to_string());
                 keys.insert("z", z.to_string());
@@ -826,6 +851,102 @@ impl KittyImageFrame {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KittyAnimationState {
+    /// s=1
+    Stop,
+    /// Run the animation, but wait for new frames
+    /// to arrive when the last frame is reached.
+    /// s=2
+    Loading,
+    /// Run the animation, looping
+    /// s=3
+    Loop,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KittyImageAnimation {
+    /// i=...
+    pub image_id: Option<u32>,
+    /// I=...
+    pub image_number: Option<u32>,
+
+    /// Change the playback state
+    /// s=...
+    pub state: Option<KittyAnimationState>,
+
+    /// 1-based number of the frame whose gap should be
+    /// changed to `duration_ms`.
+    /// r=...
+    pub frame_number: Option<u32>,
+
+    /// Gap in milliseconds of the frame specified by `frame_number`
+    /// from the next one.  Negative values make the frame gapless.
+    /// z=...
+    pub duration_ms: Option<i32>,
+
+    /// 1-based number of the frame which should be made
+    /// the current frame.
+    /// c=...
+    pub current_frame: Option<u32>,
+
+    /// The number of loops to play.
+    /// 1 means loop forever, larger numbers mean loop
+    /// that number - 1 times.  0 is ignored.
+    /// v=...
+    pub loops: Option<u32>,
+}
+
+impl KittyImageAnimation {
+    fn from_keys(keys: &BTreeMap<&str, &str>) -> Option<Self> {
+        Some(Self {
+            image_id: geti(keys, "i"),
+            image_number: geti(keys, "I"),
+            state: match geti(keys, "s") {
+                None => None,
+                Some(1) => Some(KittyAnimationState::Stop),
+                Some(2) => Some(KittyAnimationState::Loading),
+                Some(3) => Some(KittyAnimationState::Loop),
+                _ => return None,
+            },
+            frame_number: match geti(keys, "r") {
+                None | Some(0) => None,
+                n => n,
+            },
+            duration_ms: geti(keys, "z"),
+            current_frame: match geti(keys, "c") {
+                None | Some(0) => None,
+                n => n,
+            },
+            loops: match geti(keys, "v") {
+                None | Some(0) => None,
+                n => n,
+            },
+        })
+    }
+
+    fn to_keys(&self, keys: &mut BTreeMap<&'static str, String>) {
+        set(keys, "i", &self.image_id);
+        set(keys, "I", &self.image_number);
+        match &self.state {
+            None => {}
+            Some(KittyAnimationState::Stop) => {
+                keys.insert("s", "1".to_string());
+            }
+            Some(KittyAnimationState::Loading) => {
+                keys.insert("s", "2".to_string());
+            }
+            Some(KittyAnimationState::Loop) => {
+                keys.insert("s", "3".to_string());
+            }
+        }
+        set(keys, "r", &self.frame_number);
+        set(keys, "z", &self.duration_ms);
+        set(keys, "c", &self.current_frame);
+        set(keys, "v", &self.loops);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum KittyImage {
     /// a='t'
@@ -864,6 +985,11 @@ pub enum KittyImage {
         frame: KittyImageFrameCompose,
         verbosity: KittyImageVerbosity,
     },
+    /// a='a'
+    ControlAnimation {
+        animation: KittyImageAnimation,
+        verbosity: KittyImageVerbosity,
+    },
 }
 
 impl KittyImage {
@@ -876,6 +1002,7 @@ impl KittyImage {
             Self::Delete { verbosity, .. } => *verbosity,
             Self::TransmitFrame { verbosity, .. } => *verbosity,
             Self::ComposeFrame { verbosity, .. } => *verbosity,
+            Self::ControlAnimation { verbosity, .. } => *verbosity,
         }
     }
 
@@ -929,6 +1056,10 @@ impl KittyImage {
                 frame: KittyImageFrameCompose::from_keys(&keys)?,
                 verbosity,
             }),
+            "a" => Some(Self::ControlAnimation {
+                animation: KittyImageAnimation::from_keys(&keys)?,
+                verbosity,
+            }),
             _ => None,
         }
     }
@@ -993,6 +1124,14 @@ impl KittyImage {
                 frame.to_keys(keys);
                 verbosity.to_keys(keys);
             }
+            Self::ControlAnimation {
+                animation,
+                verbosity,
+            } => {
+                keys.insert("a", "a".to_string());
+                animation.to_keys(keys);
+                verbosity.to_keys(keys);
+            }
         }
     }
 }
@@ -1087,4 +1226,66 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn kitty_delete() {
+        for (apc, what) in [
+            (
+                "Ga=d,d=F,i=3,r=2",
+                KittyImageDelete::AnimationFrames {
+                    image_id: Some(3),
+                    image_number: None,
+                    frame_number: Some(2),
+                    delete: true,
+                },
+            ),
+            (
+                "Ga=d,d=q,x=4,y=2,z=-1",
+                KittyImageDelete::DeleteAtZ {
+                    x: 4,
+                    y: 2,
+                    z: -1,
+                    delete: false,
+                },
+            ),
+            (
+                "Ga=d,d=Y,y=7",
+                KittyImageDelete::DeleteRow { y: 7, delete: true },
+            ),
+        ] {
+            let img = KittyImage::parse_apc(apc.as_bytes()).unwrap();
+            assert_eq!(
+                img,
+                KittyImage::Delete {
+                    what,
+                    verbosity: KittyImageVerbosity::Verbose,
+                }
+            );
+            assert_eq!(format!("{}", img), format!("\x1b_{}", apc));
+        }
+    }
+
+    #[test]
+    fn kitty_animation() {
+        let apc = "Ga=a,c=2,i=3,r=4,s=3,v=1,z=-1";
+        let img = KittyImage::parse_apc(apc.as_bytes()).unwrap();
+        assert_eq!(
+            img,
+            KittyImage::ControlAnimation {
+                animation: KittyImageAnimation {
+                    image_id: Some(3),
+                    image_number: None,
+                    state: Some(KittyAnimationState::Loop),
+                    frame_number: Some(4),
+                    duration_ms: Some(-1),
+                    current_frame: Some(2),
+                    loops: Some(1),
+                },
+                verbosity: KittyImageVerbosity::Verbose,
+            }
+        );
+        assert_eq!(format!("{}", img), format!("\x1b_{}", apc));
+
+        assert_eq!(KittyImage::parse_apc("Ga=a,i=1,s=4".as_bytes()), None);
+    }
 }
@@ -2,8 +2,10 @@ use super::OneBased;
 use crate::cell::{Blink, Intensity, Underline};
 use crate::color::{AnsiColor, ColorSpec, RgbColor};
 use crate::input::{Modifiers, MouseButtons};
+use bitflags::bitflags;
 use num_derive::*;
 use num_traits::{FromPrimitive, ToPrimitive};
+use std::convert::TryFrom;
 use std::fmt::{Display, Error as FmtError, Formatter};
 
 pub use vtparse::CsiParam;
@@ -27,6 +29,8 @@ pub enum CSI {
 
     Window(Window),
 
+    Keyboard(Keyboard),
+
     /// Unknown or unspecified; should be rare and is rather
     /// large, so it is boxed and kept outside of the enum
     /// body to help reduce space usage in the common cases.
@@ -69,6 +73,7 @@ impl Display for CSI {
             CSI::Mouse(mouse) => mouse.fmt(f)?,
             CSI::Device(dev) => dev.fmt(f)?,
             CSI::Window(window) => window.fmt(f)?,
+            CSI::Keyboard(k) => k.fmt(f)?,
         };
         Ok(())
     }
@@ -631,6 +636,63 @@ impl Display for Mode {
     }
 }
 
+bitflags! {
+    /// The progressive enhancement flags of the kitty keyboard protocol.
+    /// <https://sw.kovidgoyal.net/kitty/keyboard-protocol/>
+    #[derive(Default)]
+    pub struct KittyKeyboardFlags: u16 {
+        const NONE = 0;
+        const DISAMBIGUATE_ESCAPE_CODES = 1;
+        const REPORT_EVENT_TYPES = 2;
+        const REPORT_ALTERNATE_KEYS = 4;
+        const REPORT_ALL_KEYS_AS_ESCAPE_CODES = 8;
+        const REPORT_ASSOCIATED_TEXT = 16;
+    }
+}
+
+/// How `CSI = flags ; mode u` combines flags with the current state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum KittyKeyboardMode {
+    AssignAll = 1,
+    SetSpecified = 2,
+    ClearSpecified = 3,
+}
+
+/// Sequences that negotiate the kitty keyboard protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Keyboard {
+    /// `CSI = flags ; mode u`
+    SetKittyState {
+        flags: KittyKeyboardFlags,
+        mode: KittyKeyboardMode,
+    },
+    /// `CSI > flags u`
+    PushKittyState(KittyKeyboardFlags),
+    /// `CSI < number u`
+    PopKittyState(u32),
+    /// `CSI ? u`
+    QueryKittySupport,
+    /// `CSI ? flags u`; the response to QueryKittySupport
+    ReportKittyState(KittyKeyboardFlags),
+}
+
+impl Display for Keyboard {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            Self::SetKittyState { flags, mode } => write!(
+                f,
+                "={};{}u",
+                flags.bits(),
+                mode.to_u8().ok_or_else(|| FmtError)?
+            ),
+            Self::PushKittyState(flags) => write!(f, ">{}u", flags.bits()),
+            Self::PopKittyState(n) => write!(f, "<{}u", n),
+            Self::QueryKittySupport => write!(f, "?u"),
+            Self::ReportKittyState(flags) => write!(f, "?{}u", flags.bits()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DecPrivateMode {
     Code(DecPrivateModeCode),
@@ -1563,6 +1625,11 @@ impl<'a> CSIParser<'a> {
 
             ('p', [CsiParam::P(b'!')]) => Ok(CSI::Device(Box::new(Device::SoftReset))),
 
+            ('u', [CsiParam::P(b'='), ..])
+            | ('u', [CsiParam::P(b'>'), ..])
+            | ('u', [CsiParam::P(b'<'), ..])
+            | ('u', [CsiParam::P(b'?'), ..]) => self.kitty_keyboard(params),
+
             _ => match self.control {
                 'c' => self
                     .req_primary_device_attributes(params)
@@ -1757,6 +1824,39 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    fn kitty_keyboard(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        fn flags(p: &CsiParam) -> Result<KittyKeyboardFlags, ()> {
+            let value = p.as_integer().ok_or(())?;
+            let value = u16::try_from(value).map_err(|_| ())?;
+            Ok(KittyKeyboardFlags::from_bits_truncate(value))
+        }
+
+        let keyboard = match params {
+            [CsiParam::P(b'?')] => Keyboard::QueryKittySupport,
+            [CsiParam::P(b'?'), f] => Keyboard::ReportKittyState(flags(f)?),
+            [CsiParam::P(b'>')] => Keyboard::PushKittyState(KittyKeyboardFlags::NONE),
+            [CsiParam::P(b'>'), f] => Keyboard::PushKittyState(flags(f)?),
+            [CsiParam::P(b'<')] => Keyboard::PopKittyState(1),
+            [CsiParam::P(b'<'), n] => {
+                let n = n.as_integer().ok_or(())?;
+                Keyboard::PopKittyState(u32::try_from(n).map_err(|_| ())?)
+            }
+            [CsiParam::P(b'='), f] => Keyboard::SetKittyState {
+                flags: flags(f)?,
+                mode: KittyKeyboardMode::AssignAll,
+            },
+            [CsiParam::P(b'='), f, CsiParam::P(b';'), m] => Keyboard::SetKittyState {
+                flags: flags(f)?,
+                mode: m
+                    .as_integer()
+                    .and_then(KittyKeyboardMode::from_i64)
+                    .ok_or(())?,
+            },
+            _ => return Err(()),
+        };
+        Ok(self.advance_by(params.len(), params, CSI::Keyboard(keyboard)))
+    }
+
     fn decslrm(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
         match params {
             [] => {
@@ -2083,13 +2183,13 @@ impl<'a> CSIParser<'a> {
             20 => Ok(Window::ReportIconLabel),
             21 => Ok(Window::ReportWindowTitle),
             22 => match arg1 {
-                Some(0) => Ok(Window::PushIconAndWindowTitle),
+                None | Some(0) => Ok(Window::PushIconAndWindowTitle),
                 Some(1) => Ok(Window::PushIconTitle),
                 Some(2) => Ok(Window::PushWindowTitle),
                 _ => Err(()),
             },
             23 => match arg1 {
-                Some(0) => Ok(Window::PopIconAndWindowTitle),
+                None | Some(0) => Ok(Window::PopIconAndWindowTitle),
                 Some(1) => Ok(Window::PopIconTitle),
                 Some(2) => Ok(Window::PopWindowTitle),
                 _ => Err(()),
@@ -2690,6 +2790,62 @@ mod test {
         assert_eq!(res, vec![CSI::Device(Box::new(Device::SoftReset))],);
     }
 
+    #[test]
+    fn kitty_keyboard() {
+        let res: Vec<_> = CSI::parse(&[CsiParam::P(b'?')], false, 'u').collect();
+        assert_eq!(encode(&res), "\x1b[?u");
+        assert_eq!(res, vec![CSI::Keyboard(Keyboard::QueryKittySupport)]);
+
+        let res: Vec<_> =
+            CSI::parse(&[CsiParam::P(b'>'), CsiParam::Integer(3)], false, 'u').collect();
+        assert_eq!(encode(&res), "\x1b[>3u");
+        assert_eq!(
+            res,
+            vec![CSI::Keyboard(Keyboard::PushKittyState(
+                KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | KittyKeyboardFlags::REPORT_EVENT_TYPES
+            ))]
+        );
+
+        let res: Vec<_> = CSI::parse(&[CsiParam::P(b'<')], false, 'u').collect();
+        assert_eq!(encode(&res), "\x1b[<1u");
+        assert_eq!(res, vec![CSI::Keyboard(Keyboard::PopKittyState(1))]);
+
+        let res: Vec<_> = CSI::parse(
+            &[
+                CsiParam::P(b'='),
+                CsiParam::Integer(8),
+                CsiParam::P(b';'),
+                CsiParam::Integer(3),
+            ],
+            false,
+            'u',
+        )
+        .collect();
+        assert_eq!(encode(&res), "\x1b[=8;3u");
+        assert_eq!(
+            res,
+            vec![CSI::Keyboard(Keyboard::SetKittyState {
+                flags: KittyKeyboardFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES,
+                mode: KittyKeyboardMode::ClearSpecified,
+            })]
+        );
+
+        let res: Vec<_> =
+            CSI::parse(&[CsiParam::P(b'?'), CsiParam::Integer(1)], false, 'u').collect();
+        assert_eq!(encode(&res), "\x1b[?1u");
+        assert_eq!(
+            res,
+            vec![CSI::Keyboard(Keyboard::ReportKittyState(
+                KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES
+            ))]
+        );
+
+        // Plain CSI u is still SCORC
+        let res: Vec<_> = CSI::parse(&[], false, 'u').collect();
+        assert_eq!(res, vec![CSI::Cursor(Cursor::RestoreCursor)]);
+    }
+
     #[test]
     fn device_attr() {
         let res: Vec<_> = CSI::parse(
@@ -371,6 +371,57 @@ impl ImageDataType {
     }
 }
 
+/// Describes how the frames of an animated image should be played back
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationState {
+    /// Hold on the frame that is currently being displayed
+    Stopped,
+    /// Play through the frames, but wait on the last frame
+    /// for more frames to be added rather than looping
+    Loading,
+    /// Play through the frames, looping back to the start
+    Running,
+}
+
+/// Playback controls for an animated image.
+/// The frame durations are part of the `ImageDataType::AnimRgba8` data;
+/// this tracks the rest of the animation state so that it can be
+/// manipulated by the terminal model and honored by the renderer.
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageAnimation {
+    pub state: AnimationState,
+    /// The number of times to play through the frames before
+    /// holding on the last frame.  `None` means loop forever.
+    pub loops: Option<u32>,
+    /// The 0-based index of the frame that playback should
+    /// (re)start from when `generation` changes.
+    pub current_frame: usize,
+    /// Incremented each time `current_frame` is explicitly set,
+    /// so that the renderer knows to jump to that frame.
+    pub generation: usize,
+}
+
+impl Default for ImageAnimation {
+    fn default() -> Self {
+        Self {
+            state: AnimationState::Running,
+            loops: None,
+            current_frame: 0,
+            generation: 0,
+        }
+    }
+}
+
+impl ImageAnimation {
+    /// Request that playback continue from the specified 0-based frame
+    pub fn set_current_frame(&mut self, frame: usize) {
+        self.current_frame = frame;
+        self.generation += 1;
+    }
+}
+
 static IMAGE_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
 
 #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
@@ -378,6 +429,7 @@ static IMAGE_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicU
 pub struct ImageData {
     id: usize,
     data: Mutex<ImageDataType>,
+    animation: Mutex<ImageAnimation>,
 }
 
 impl Eq for ImageData {}
@@ -398,6 +450,7 @@ impl ImageData {
         Self {
             id,
             data: Mutex::new(data),
+            animation: Mutex::new(ImageAnimation::default()),
         }
     }
 
@@ -414,6 +467,10 @@ impl ImageData {
         self.data.lock().unwrap()
     }
 
+    pub fn animation(&self) -> MutexGuard<ImageAnimation> {
+        self.animation.lock().unwrap()
+    }
+
     pub fn id(&self) -> usize {
         self.id
     }
@@ -2,7 +2,7 @@
 //! input received from a terminal.
 use crate::bail;
 use crate::error::Result;
-use crate::escape::csi::MouseReport;
+use crate::escape::csi::{KittyKeyboardFlags, MouseReport};
 use crate::escape::parser::Parser;
 use crate::escape::{Action, CSI};
 use crate::keymap::{Found, KeyMap};
@@ -22,6 +22,17 @@ pub struct KeyCodeEncodeModes {
     pub enable_csi_u_key_encoding: bool,
     pub application_cursor_keys: bool,
     pub newline_mode: bool,
+    /// The active kitty keyboard protocol flags; when non-empty, keys
+    /// are encoded as described by the kitty keyboard protocol.
+    pub kitty_keyboard: KittyKeyboardFlags,
+}
+
+/// The kind of key event, as reported by the kitty keyboard protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardEventType {
+    Press,
+    Repeat,
+    Release,
 }
 
 #[cfg(windows)]
@@ -237,6 +248,10 @@ impl KeyCode {
     pub fn encode(&self, mods: Modifiers, modes: KeyCodeEncodeModes) -> Result<String> {
         use KeyCode::*;
 
+        if !modes.kitty_keyboard.is_empty() {
+            return self.encode_kitty(mods, modes, KeyboardEventType::Press);
+        }
+
         let key = self.normalize_shift_to_upper_case(mods);
         // Normalize the modifier state for Char's that are uppercase; remove
         // the SHIFT modifier so that reduce ambiguity below
@@ -468,6 +483,281 @@ impl KeyCode {
 
         Ok(buf)
     }
+
+    /// Returns the byte sequence that represents this KeyCode, Modifier
+    /// and event type combination according to the kitty keyboard protocol
+    /// flags in `modes`.
+    /// <https://sw.kovidgoyal.net/kitty/keyboard-protocol/>
+    pub fn encode_kitty(
+        &self,
+        mods: Modifiers,
+        modes: KeyCodeEncodeModes,
+        event: KeyboardEventType,
+    ) -> Result<String> {
+        use KeyCode::*;
+
+        let flags = modes.kitty_keyboard;
+        let report_all = flags.contains(KittyKeyboardFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES);
+        let mut buf = String::new();
+
+        let event = match event {
+            // Without event type reporting, repeats look like presses
+            // and releases are not reported at all
+            KeyboardEventType::Repeat
+                if !flags.contains(KittyKeyboardFlags::REPORT_EVENT_TYPES) =>
+            {
+                KeyboardEventType::Press
+            }
+            KeyboardEventType::Release
+                if !flags.contains(KittyKeyboardFlags::REPORT_EVENT_TYPES) =>
+            {
+                return Ok(buf);
+            }
+            event => event,
+        };
+
+        let legacy = || {
+            self.encode(
+                mods,
+                KeyCodeEncodeModes {
+                    kitty_keyboard: KittyKeyboardFlags::NONE,
+                    ..modes
+                },
+            )
+        };
+
+        let key = match *self {
+            Char('\x7f') => Delete,
+            Char('\x08') => Backspace,
+            c => c,
+        };
+
+        match key {
+            Char(c) => {
+                // The key code is always the un-shifted key
+                let base = single_char(c.to_lowercase()).unwrap_or(c);
+                let text = if mods.contains(Modifiers::SHIFT) {
+                    single_char(c.to_uppercase()).unwrap_or(c)
+                } else {
+                    c
+                };
+                let shifted = if mods.contains(Modifiers::SHIFT) && text != base {
+                    Some(text)
+                } else {
+                    None
+                };
+                let produces_text = (mods - Modifiers::SHIFT).is_empty();
+
+                if produces_text && !report_all && event != KeyboardEventType::Release {
+                    buf.push(text);
+                } else {
+                    let text = if produces_text
+                        && report_all
+                        && flags.contains(KittyKeyboardFlags::REPORT_ASSOCIATED_TEXT)
+                        && event != KeyboardEventType::Release
+                    {
+                        Some(text)
+                    } else {
+                        None
+                    };
+                    kitty_csi_u(&mut buf, base as u32, shifted, mods, event, text, flags)?;
+                }
+            }
+
+            Enter | Tab | Backspace | Escape => {
+                let code = match key {
+                    Enter => 13,
+                    Tab => 9,
+                    Backspace => 127,
+                    Escape => 27,
+                    _ => unreachable!(),
+                };
+                // Unmodified Enter, Tab and Backspace keep their legacy
+                // encoding so that a shell remains usable if an application
+                // crashes without restoring the keyboard mode
+                if key != Escape && mods.is_empty() && !report_all {
+                    if event != KeyboardEventType::Release {
+                        return legacy();
+                    }
+                } else {
+                    kitty_csi_u(&mut buf, code, None, mods, event, None, flags)?;
+                }
+            }
+
+            Home
+            | End
+            | UpArrow
+            | DownArrow
+            | RightArrow
+            | LeftArrow
+            | ApplicationUpArrow
+            | ApplicationDownArrow
+            | ApplicationRightArrow
+            | ApplicationLeftArrow
+            | Function(1..=2)
+            | Function(4) => {
+                if mods.is_empty() && event == KeyboardEventType::Press {
+                    return legacy();
+                }
+                let c = match key {
+                    UpArrow | ApplicationUpArrow => 'A',
+                    DownArrow | ApplicationDownArrow => 'B',
+                    RightArrow | ApplicationRightArrow => 'C',
+                    LeftArrow | ApplicationLeftArrow => 'D',
+                    Home => 'H',
+                    End => 'F',
+                    Function(1) => 'P',
+                    Function(2) => 'Q',
+                    Function(4) => 'S',
+                    _ => unreachable!(),
+                };
+                write!(buf, "{}1;{}{}", CSI, kitty_modifier_field(mods, event), c)?;
+            }
+
+            PageUp | PageDown | Insert | Delete | Function(3) | Function(5..=12) => {
+                if mods.is_empty() && event == KeyboardEventType::Press && key != Function(3) {
+                    return legacy();
+                }
+                let n = match key {
+                    Insert => 2,
+                    Delete => 3,
+                    PageUp => 5,
+                    PageDown => 6,
+                    Function(3) => 13,
+                    Function(5) => 15,
+                    Function(6) => 17,
+                    Function(7) => 18,
+                    Function(8) => 19,
+                    Function(9) => 20,
+                    Function(10) => 21,
+                    Function(11) => 23,
+                    Function(12) => 24,
+                    _ => unreachable!(),
+                };
+                let field = kitty_modifier_field(mods, event);
+                if field.is_empty() {
+                    write!(buf, "{}{}~", CSI, n)?;
+                } else {
+                    write!(buf, "{}{};{}~", CSI, n, field)?;
+                }
+            }
+
+            _ => {
+                let code = match kitty_functional_key_code(key) {
+                    Some(code) => code,
+                    None => return Ok(buf),
+                };
+                // Modifier and lock keys are only reported on their own
+                // when all keys are being reported
+                if (key.is_modifier() || matches!(key, CapsLock | NumLock | ScrollLock))
+                    && !report_all
+                {
+                    return Ok(buf);
+                }
+                kitty_csi_u(&mut buf, code, None, mods, event, None, flags)?;
+            }
+        }
+
+        Ok(buf)
+    }
+}
+
+/// Returns the result of a case conversion if it is a single char;
+/// some chars, such as `ß`, expand to several when upper cased
+fn single_char(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Returns the kitty protocol code for keys that have no legacy encoding.
+/// These live in the unicode private use area.
+fn kitty_functional_key_code(key: KeyCode) -> Option<u32> {
+    use KeyCode::*;
+    Some(match key {
+        CapsLock => 57358,
+        ScrollLock => 57359,
+        NumLock => 57360,
+        PrintScreen => 57361,
+        Pause => 57362,
+        Menu | LeftMenu | RightMenu | Applications => 57363,
+        Function(n @ 13..=35) => 57376 + (n as u32 - 13),
+        Numpad0 => 57399,
+        Numpad1 => 57400,
+        Numpad2 => 57401,
+        Numpad3 => 57402,
+        Numpad4 => 57403,
+        Numpad5 => 57404,
+        Numpad6 => 57405,
+        Numpad7 => 57406,
+        Numpad8 => 57407,
+        Numpad9 => 57408,
+        Decimal => 57409,
+        Divide => 57410,
+        Multiply => 57411,
+        Subtract => 57412,
+        Add => 57413,
+        Separator => 57416,
+        MediaPlayPause => 57430,
+        MediaStop => 57432,
+        MediaNextTrack => 57435,
+        MediaPrevTrack => 57436,
+        VolumeDown => 57438,
+        VolumeUp => 57439,
+        VolumeMute => 57440,
+        Shift | LeftShift => 57441,
+        Control | LeftControl => 57442,
+        Alt | LeftAlt => 57443,
+        Super | LeftWindows => 57444,
+        Hyper => 57445,
+        Meta => 57446,
+        RightShift => 57447,
+        RightControl => 57448,
+        RightAlt => 57449,
+        RightWindows => 57450,
+        _ => return None,
+    })
+}
+
+/// Returns the `modifiers:event-type` field of a kitty protocol sequence,
+/// or an empty string if it can be omitted.
+fn kitty_modifier_field(mods: Modifiers, event: KeyboardEventType) -> String {
+    let mut number = encode_modifiers(mods);
+    if mods.contains(Modifiers::SUPER) {
+        number |= 8;
+    }
+    match event {
+        KeyboardEventType::Press if number == 0 => String::new(),
+        KeyboardEventType::Press => format!("{}", 1 + number),
+        KeyboardEventType::Repeat => format!("{}:2", 1 + number),
+        KeyboardEventType::Release => format!("{}:3", 1 + number),
+    }
+}
+
+fn kitty_csi_u(
+    buf: &mut String,
+    code: u32,
+    shifted: Option<char>,
+    mods: Modifiers,
+    event: KeyboardEventType,
+    text: Option<char>,
+    flags: KittyKeyboardFlags,
+) -> Result<()> {
+    write!(buf, "{}{}", CSI, code)?;
+    if let Some(shifted) = shifted {
+        if flags.contains(KittyKeyboardFlags::REPORT_ALTERNATE_KEYS) {
+            write!(buf, ":{}", shifted as u32)?;
+        }
+    }
+    let field = kitty_modifier_field(mods, event);
+    if let Some(text) = text {
+        write!(buf, ";{};{}", field, text as u32)?;
+    } else if !field.is_empty() {
+        write!(buf, ";{}", field)?;
+    }
+    buf.push('u');
+    Ok(())
 }
 
 fn encode_modifiers(mods: Modifiers) -> u8 {
@@ -1502,6 +1792,7 @@ mod test {
             enable_csi_u_key_encoding: false,
             newline_mode: false,
             application_cursor_keys: false,
+            kitty_keyboard: KittyKeyboardFlags::NONE,
         };
 
         assert_eq!(
@@ -1541,4 +1832,184 @@ mod test {
             "\x1bOP".to_string()
         );
     }
+
+    #[test]
+    fn encode_kitty_keyboard() {
+        let mode = KeyCodeEncodeModes {
+            enable_csi_u_key_encoding: false,
+            newline_mode: false,
+            application_cursor_keys: false,
+            kitty_keyboard: KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES,
+        };
+
+        assert_eq!(
+            KeyCode::Char('a').encode(Modifiers::NONE, mode).unwrap(),
+            "a"
+        );
+        assert_eq!(
+            KeyCode::Char('A').encode(Modifiers::SHIFT, mode).unwrap(),
+            "A"
+        );
+        assert_eq!(
+            KeyCode::Char('a').encode(Modifiers::CTRL, mode).unwrap(),
+            "\x1b[97;5u"
+        );
+        assert_eq!(
+            KeyCode::Char('i').encode(Modifiers::CTRL, mode).unwrap(),
+            "\x1b[105;5u"
+        );
+        assert_eq!(
+            KeyCode::Char('A')
+                .encode(Modifiers::CTRL | Modifiers::SHIFT, mode)
+                .unwrap(),
+            "\x1b[97;6u"
+        );
+        // The key code is the lower case form of non-ASCII keys too,
+        // unless case mapping would change the number of chars
+        assert_eq!(
+            KeyCode::Char('Ä')
+                .encode(Modifiers::CTRL | Modifiers::SHIFT, mode)
+                .unwrap(),
+            "\x1b[228;6u"
+        );
+        assert_eq!(
+            KeyCode::Char('ß')
+                .encode(Modifiers::CTRL | Modifiers::SHIFT, mode)
+                .unwrap(),
+            "\x1b[223;6u"
+        );
+        assert_eq!(
+            KeyCode::Char('a').encode(Modifiers::ALT, mode).unwrap(),
+            "\x1b[97;3u"
+        );
+        assert_eq!(
+            KeyCode::Char('a').encode(Modifiers::SUPER, mode).unwrap(),
+            "\x1b[97;9u"
+        );
+        assert_eq!(
+            KeyCode::Escape.encode(Modifiers::NONE, mode).unwrap(),
+            "\x1b[27u"
+        );
+        assert_eq!(KeyCode::Enter.encode(Modifiers::NONE, mode).unwrap(), "\r");
+        assert_eq!(KeyCode::Tab.encode(Modifiers::NONE, mode).unwrap(), "\t");
+        assert_eq!(
+            KeyCode::Backspace.encode(Modifiers::NONE, mode).unwrap(),
+            "\x7f"
+        );
+        assert_eq!(
+            KeyCode::Enter.encode(Modifiers::SHIFT, mode).unwrap(),
+            "\x1b[13;2u"
+        );
+        assert_eq!(
+            KeyCode::UpArrow.encode(Modifiers::NONE, mode).unwrap(),
+            "\x1b[A"
+        );
+        assert_eq!(
+            KeyCode::UpArrow.encode(Modifiers::CTRL, mode).unwrap(),
+            "\x1b[1;5A"
+        );
+        assert_eq!(
+            KeyCode::Function(3).encode(Modifiers::NONE, mode).unwrap(),
+            "\x1b[13~"
+        );
+        assert_eq!(
+            KeyCode::Function(3).encode(Modifiers::SHIFT, mode).unwrap(),
+            "\x1b[13;2~"
+        );
+        assert_eq!(
+            KeyCode::Numpad5.encode(Modifiers::NONE, mode).unwrap(),
+            "\x1b[57404u"
+        );
+        // Modifier keys are only reported when reporting all keys
+        assert_eq!(
+            KeyCode::LeftShift.encode(Modifiers::NONE, mode).unwrap(),
+            ""
+        );
+        assert_eq!(
+            KeyCode::Char('a')
+                .encode_kitty(Modifiers::CTRL, mode, KeyboardEventType::Repeat)
+                .unwrap(),
+            "\x1b[97;5u"
+        );
+        // Release events are only reported when event types are requested
+        assert_eq!(
+            KeyCode::Char('a')
+                .encode_kitty(Modifiers::NONE, mode, KeyboardEventType::Release)
+                .unwrap(),
+            ""
+        );
+
+        let mode = KeyCodeEncodeModes {
+            kitty_keyboard: KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES
+                | KittyKeyboardFlags::REPORT_EVENT_TYPES,
+            ..mode
+        };
+        assert_eq!(
+            KeyCode::Char('a')
+                .encode_kitty(Modifiers::NONE, mode, KeyboardEventType::Repeat)
+                .unwrap(),
+            "a"
+        );
+        assert_eq!(
+            KeyCode::Char('a')
+                .encode_kitty(Modifiers::NONE, mode, KeyboardEventType::Release)
+                .unwrap(),
+            "\x1b[97;1:3u"
+        );
+        assert_eq!(
+            KeyCode::Char('a')
+                .encode_kitty(Modifiers::CTRL, mode, KeyboardEventType::Repeat)
+                .unwrap(),
+            "\x1b[97;5:2u"
+        );
+        assert_eq!(
+            KeyCode::Enter
+                .encode_kitty(Modifiers::NONE, mode, KeyboardEventType::Release)
+                .unwrap(),
+            ""
+        );
+        assert_eq!(
+            KeyCode::LeftArrow
+                .encode_kitty(Modifiers::NONE, mode, KeyboardEventType::Release)
+                .unwrap(),
+            "\x1b[1;1:3D"
+        );
+        assert_eq!(
+            KeyCode::PageUp
+                .encode_kitty(Modifiers::NONE, mode, KeyboardEventType::Release)
+                .unwrap(),
+            "\x1b[5;1:3~"
+        );
+
+        let mode = KeyCodeEncodeModes {
+            kitty_keyboard: KittyKeyboardFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                | KittyKeyboardFlags::REPORT_ALTERNATE_KEYS
+                | KittyKeyboardFlags::REPORT_ASSOCIATED_TEXT,
+            ..mode
+        };
+        assert_eq!(
+            KeyCode::Char('a').encode(Modifiers::NONE, mode).unwrap(),
+            "\x1b[97;;97u"
+        );
+        assert_eq!(
+            KeyCode::Char('A').encode(Modifiers::SHIFT, mode).unwrap(),
+            "\x1b[97:65;2;65u"
+        );
+        assert_eq!(
+            KeyCode::Char('a').encode(Modifiers::CTRL, mode).unwrap(),
+            "\x1b[97;5u"
+        );
+        assert_eq!(
+            KeyCode::Enter.encode(Modifiers::NONE, mode).unwrap(),
+            "\x1b[13u"
+        );
+        assert_eq!(
+            KeyCode::Tab.encode(Modifiers::NONE, mode).unwrap(),
+            "\x1b[9u"
+        );
+        assert_eq!(
+            KeyCode::LeftShift.encode(Modifiers::SHIFT, mode).unwrap(),
+            "\x1b[57441;2u"
+        );
+    }
 }
@@ -10,7 +10,7 @@ use filedescriptor::FileDescriptor;
 use futures::FutureExt;
 use mux::connui::ConnectionUI;
 use mux::domain::{alloc_domain_id, DomainId};
-use mux::pane::PaneId;
+use mux::pane::{ConnectionStatus, PaneId};
 use mux::ssh::ssh_connect_with_ui;
 use mux::Mux;
 use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
@@ -25,9 +25,11 @@ use std::marker::Unpin;
 use std::net::TcpStream;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use wezterm_ssh::Sftp;
 
 enum ReaderMessage {
     SendPdu {
@@ -35,14 +37,44 @@ enum ReaderMessage {
         promise: Sender<anyhow::Result<Pdu>>,
     },
     Readable,
+    Heartbeat,
+}
+
+/// How many consecutive heartbeat intervals may elapse without
+/// a response before a reconnectable connection is considered dead
+const MISSED_HEARTBEAT_LIMIT: u32 = 3;
+
+/// Tracks the health of the connection as observed by the client thread
+#[derive(Debug)]
+struct ConnectionState {
+    connected: bool,
+    rtt: Option<Duration>,
+    last_recv: Instant,
+    reconnects: usize,
+    /// The compression negotiated with the server
+    compression: Compression,
+}
+
+impl ConnectionState {
+    fn new() -> Self {
+        Self {
+            connected: true,
+            rtt: None,
+            last_recv: Instant::now(),
+            reconnects: 0,
+            compression: Compression::default(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Client {
     sender: Sender<ReaderMessage>,
     local_domain_id: DomainId,
+    state: Arc<Mutex<ConnectionState>>,
     pub is_reconnectable: bool,
     pub is_local: bool,
+    sftp: Arc<Mutex<Option<Sftp>>>,
 }
 
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
@@ -123,6 +155,20 @@ async fn process_unilateral_inner_async(
         .downcast_ref::<ClientDomain>()
         .ok_or_else(|| anyhow!("domain {} is not a ClientDomain instance", local_domain_id))?;
 
+    // Let the server know that we've received the changes, so that it
+    // can continue to send us output.  We do this even for panes that
+    // we don't know about, so that the server doesn't hold up their
+    // output on our account.
+    if let Pdu::GetPaneRenderChangesResponse(delta) = &decoded.pdu {
+        let inner = ClientDomain::get_client_inner_for_domain(local_domain_id)?;
+        let ack = AckPaneRenderChanges {
+            pane_id,
+            seqno: delta.seqno,
+        };
+        promise::spawn::spawn(async move { inner.client.ack_pane_render_changes(ack).await })
+            .detach();
+    }
+
     // If we get a push for a pane that we don't yet know about,
     // it means that some other client has manipulated the mux
     // topology; we need to re-sync.
@@ -180,17 +226,40 @@ fn client_thread(
     reconnectable: &mut Reconnectable,
     local_domain_id: DomainId,
     rx: &mut Receiver<ReaderMessage>,
+    state: &Mutex<ConnectionState>,
 ) -> anyhow::Result<()> {
-    block_on(client_thread_async(reconnectable, local_domain_id, rx))
+    block_on(client_thread_async(
+        reconnectable,
+        local_domain_id,
+        rx,
+        state,
+    ))
 }
 
 async fn client_thread_async(
     reconnectable: &mut Reconnectable,
     local_domain_id: DomainId,
     rx: &mut Receiver<ReaderMessage>,
+    state: &Mutex<ConnectionState>,
 ) -> anyhow::Result<()> {
     let mut next_serial = 1u64;
 
+    let heartbeat_interval = match configuration().mux_heartbeat_interval {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    };
+    // Only a connection that we can re-establish is worth tearing down
+    // when it stops responding; for the others we merely measure
+    // the round trip time.
+    let heartbeat_timeout = if reconnectable.reconnectable() {
+        heartbeat_interval.map(|interval| interval * MISSED_HEARTBEAT_LIMIT)
+    } else {
+        None
+    };
+    let mut next_heartbeat = heartbeat_interval.map(|interval| Instant::now() + interval);
+    // The serial and send time of the heartbeat that is awaiting a Pong
+    let mut pending_ping: Option<(u64, Instant)> = None;
+
     struct Promises {
         map: HashMap<u64, Sender<anyhow::Result<Pdu>>>,
     }
@@ -214,27 +283,76 @@ async fn client_thread_async(
     };
 
     let mut stream = reconnectable.take_stream().unwrap();
+    {
+        let mut state = state.lock().unwrap();
+        state.connected = true;
+        state.last_recv = Instant::now();
+    }
 
     loop {
         let rx_msg = rx.recv();
         let wait_for_read = stream
             .wait_for_readable()
             .map(|_| Ok(ReaderMessage::Readable));
+        let heartbeat = async {
+            match next_heartbeat {
+                Some(when) => {
+                    smol::Timer::at(when).await;
+                    Ok(ReaderMessage::Heartbeat)
+                }
+                None => futures::future::pending().await,
+            }
+        };
 
-        match smol::future::or(rx_msg, wait_for_read).await {
+        match smol::future::or(smol::future::or(rx_msg, wait_for_read), heartbeat).await {
             Ok(ReaderMessage::SendPdu { pdu, promise }) => {
                 let serial = next_serial;
                 next_serial += 1;
                 promises.map.insert(serial, promise);
 
-                pdu.encode_async(&mut stream, serial)
-                    .await
-                    .context("encoding a PDU to send to the server")?;
-                stream.flush().await.context("flushing PDU to server")?;
+                let compression = state.lock().unwrap().compression;
+                send_with_timeout(&mut stream, pdu, serial, compression, heartbeat_timeout).await?;
+            }
+            Ok(ReaderMessage::Heartbeat) => {
+                let now = Instant::now();
+                next_heartbeat = heartbeat_interval.map(|interval| now + interval);
+                match pending_ping {
+                    Some((_, sent)) => {
+                        if let Some(timeout) = heartbeat_timeout {
+                            if now.duration_since(sent) >= timeout {
+                                let reason = format!(
+                                    "no response from server for {:?}",
+                                    now.duration_since(sent)
+                                );
+                                promises.fail_all(&reason);
+                                return Err(std::io::Error::new(
+                                    std::io::ErrorKind::TimedOut,
+                                    reason,
+                                )
+                                .into());
+                            }
+                        }
+                    }
+                    None => {
+                        let serial = next_serial;
+                        next_serial += 1;
+                        pending_ping.replace((serial, now));
+                        let compression = state.lock().unwrap().compression;
+                        send_with_timeout(
+                            &mut stream,
+                            Pdu::Ping(Ping {}),
+                            serial,
+                            compression,
+                            heartbeat_timeout,
+                        )
+                        .await?;
+                    }
+                }
             }
             Ok(ReaderMessage::Readable) => match Pdu::decode_async(&mut stream).await {
                 Ok(decoded) => {
                     log::trace!("decoded serial {}", decoded.serial);
+                    state.lock().unwrap().last_recv = Instant::now();
                     if decoded.serial == 0 {
                         process_unilateral(local_domain_id, decoded)
                             .context("processing unilateral PDU from server")
@@ -242,6 +360,10 @@ async fn client_thread_async(
                                 log::error!("process_unilateral: {:?}", e);
                                 e
                             })?;
+                    } else if pending_ping.map(|(serial, _)| serial) == Some(decoded.serial) {
+                        if let Some((_, sent)) = pending_ping.take() {
+                            state.lock().unwrap().rtt.replace(sent.elapsed());
+                        }
                     } else if let Some(promise) = promises.map.remove(&decoded.serial) {
                         if promise.try_send(Ok(decoded.pdu)).is_err() {
                             return Err(NotReconnectableError::ClientWasDestroyed.into());
@@ -267,6 +389,40 @@ async fn client_thread_async(
     }
 }
 
+/// Sends a PDU to the server.  When a timeout is specified, a write
+/// that stalls for longer than that (which is what happens when the
+/// peer silently goes away) is reported as an error so that the
+/// connection can be re-established.
+async fn send_with_timeout(
+    stream: &mut Box<dyn AsyncReadAndWrite>,
+    pdu: Pdu,
+    serial: u64,
+    compression: Compression,
+    timeout: Option<Duration>,
+) -> anyhow::Result<()> {
+    let send = async {
+        pdu.encode_async_with_compression(&mut *stream, serial, compression)
+            .await
+            .context("encoding a PDU to send to the server")?;
+        stream.flush().await.context("flushing PDU to server")?;
+        Ok::<(), anyhow::Error>(())
+    };
+
+    match timeout {
+        Some(timeout) => {
+            let expire = async {
+                smol::Timer::after(timeout).await;
+                Err(anyhow::Error::new(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("timed out after {:?} sending PDU to server", timeout),
+                )))
+            };
+            smol::future::or(send, expire).await
+        }
+        None => send.await,
+    }
+}
+
 pub fn unix_connect_with_retry(
     target: &UnixTarget,
     just_spawned: bool,
@@ -369,6 +525,9 @@ struct Reconnectable {
     config: ClientDomainConfig,
     stream: Option<Box<dyn AsyncReadAndWrite>>,
     tls_creds: Option<GetTlsCredsResponse>,
+    /// An sftp client for the ssh session that carries the stream,
+    /// if this is an ssh connection.  Shared with the Client.
+    sftp: Arc<Mutex<Option<Sftp>>>,
 }
 
 struct SshStream {
@@ -417,6 +576,7 @@ impl Reconnectable {
             config,
             stream,
             tls_creds: None,
+            sftp: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -506,6 +666,22 @@ impl Reconnectable {
             }
         };
 
+        // Overrides are applied prior to resolving the config for the
+        // host, so that tokens such as %h and %p in ProxyCommand
+        // reflect them
+        for (k, v) in &ssh_dom.ssh_option {
+            ssh_config.set_option(k, v);
+        }
+        if let Some(username) = &ssh_dom.username {
+            ssh_config.set_option("user", username);
+        }
+        if let Some(port) = port {
+            ssh_config.set_option("port", port.to_string());
+        }
+        if ssh_dom.no_agent_auth {
+            ssh_config.set_option("identitiesonly", "yes");
+        }
+
         let mut ssh_config = ssh_config.for_host(&remote_host_name);
         ssh_config.insert(
             "wezterm_ssh_backend".to_string(),
@@ -518,19 +694,6 @@ impl Reconnectable {
             }
             .to_string(),
         );
-        for (k, v) in &ssh_dom.ssh_option {
-            ssh_config.insert(k.to_string(), v.to_string());
-        }
-
-        if let Some(username) = &ssh_dom.username {
-            ssh_config.insert("user".to_string(), username.to_string());
-        }
-        if let Some(port) = port {
-            ssh_config.insert("port".to_string(), port.to_string());
-        }
-        if ssh_dom.no_agent_auth {
-            ssh_config.insert("identitiesonly".to_string(), "yes".to_string());
-        }
 
         let sess = ssh_connect_with_ui(ssh_config, ui)?;
         let proxy_bin = Self::wezterm_bin_path(&ssh_dom.remote_wezterm_path);
@@ -573,6 +736,7 @@ impl Reconnectable {
             stdout: exec.stdout,
         })?);
         self.stream.replace(stream);
+        self.sftp.lock().unwrap().replace(sess.sftp());
         Ok(())
     }
 
@@ -705,13 +869,13 @@ impl Reconnectable {
                     .ok_or_else(|| anyhow::anyhow!("no host component somehow"))?;
                 let port = fields.next();
 
-                let mut ssh_config = ssh_config.for_host(host);
                 if let Some(username) = &ssh_params.username {
-                    ssh_config.insert("user".to_string(), username.to_string());
+                    ssh_config.set_option("user", username);
                 }
                 if let Some(port) = port {
-                    ssh_config.insert("port".to_string(), port.to_string());
+                    ssh_config.set_option("port", port);
                 }
+                let ssh_config = ssh_config.for_host(host);
 
                 let sess = ssh_connect_with_ui(ssh_config, ui)?;
 
@@ -878,7 +1042,10 @@ impl Client {
     fn new(local_domain_id: DomainId, mut reconnectable: Reconnectable) -> Self {
         let is_reconnectable = reconnectable.reconnectable();
         let is_local = reconnectable.is_local();
+        let sftp = Arc::clone(&reconnectable.sftp);
         let (sender, mut receiver) = unbounded();
+        let state = Arc::new(Mutex::new(ConnectionState::new()));
+        let thread_state = Arc::clone(&state);
 
         thread::spawn(move || {
             const BASE_INTERVAL: Duration = Duration::from_secs(1);
@@ -886,7 +1053,14 @@ impl Client {
 
             let mut backoff = BASE_INTERVAL;
             loop {
-                if let Err(e) = client_thread(&mut reconnectable, local_domain_id, &mut receiver) {
+                let result = client_thread(
+                    &mut reconnectable,
+                    local_domain_id,
+                    &mut receiver,
+                    &thread_state,
+                );
+                thread_state.lock().unwrap().connected = false;
+                if let Err(e) = result {
                     if !reconnectable.reconnectable() {
                         log::debug!("client thread ended: {}", e);
                         break;
@@ -919,6 +1093,7 @@ impl Client {
                         match reconnectable.connect(initial, &mut ui, no_auto_start) {
                             Ok(_) => {
                                 backoff = BASE_INTERVAL;
+                                thread_state.lock().unwrap().reconnects += 1;
                                 log::error!("Reconnected!");
                                 promise::spawn::spawn_into_main_thread(async move {
                                     ClientDomain::reattach(local_domain_id, ui).await.ok();
@@ -965,8 +1140,41 @@ impl Client {
         Self {
             sender,
             local_domain_id,
+            state,
             is_reconnectable,
             is_local,
+            sftp,
+        }
+    }
+
+    /// Asks the server to use the configured compression when sending
+    /// to us, and uses whatever it settles on in the other direction
+    pub async fn negotiate_compression(&self) -> anyhow::Result<()> {
+        let requested = Compression::from_config(&configuration());
+        let response = self
+            .set_compression(SetCompression {
+                compression: requested,
+            })
+            .await?;
+        log::trace!(
+            "requested {:?} compression; server will use {:?}",
+            requested,
+            response.compression
+        );
+        self.state.lock().unwrap().compression = response.compression;
+        Ok(())
+    }
+
+    /// Returns the health of the connection to the server as most
+    /// recently observed by the heartbeat
+    pub fn connection_status(&self) -> ConnectionStatus {
+        let state = self.state.lock().unwrap();
+        ConnectionStatus {
+            connected: state.connected,
+            rtt_ms: state.rtt.map(|rtt| rtt.as_millis() as u64),
+            since_last_response_ms: state.last_recv.elapsed().as_millis() as u64,
+            is_tardy: false,
+            reconnects: state.reconnects,
         }
     }
 
@@ -1072,6 +1280,12 @@ impl Client {
         Ok(Self::new(local_domain_id, reconnectable))
     }
 
+    /// Returns an sftp client for the ssh session that carries this
+    /// connection, if it was made via ssh
+    pub fn sftp(&self) -> Option<Sftp> {
+        self.sftp.lock().unwrap().clone()
+    }
+
     pub async fn send_pdu(&self, pdu: Pdu) -> anyhow::Result<Pdu> {
         let (promise, rx) = bounded(1);
         self.sender
@@ -1105,4 +1319,22 @@ impl Client {
         SearchScrollbackResponse
     );
     rpc!(kill_pane, KillPane, UnitResponse);
+    rpc!(set_focused_pane, SetFocusedPane, UnitResponse);
+    rpc!(activate_pane_direction, ActivatePaneDirection, UnitResponse);
+    rpc!(adjust_pane_size, AdjustPaneSize, UnitResponse);
+    rpc!(
+        get_dimensions,
+        GetPaneRenderableDimensions,
+        GetPaneRenderableDimensionsResponse
+    );
+    rpc!(
+        get_session_snapshot,
+        GetSessionSnapshot,
+        GetSessionSnapshotResponse
+    );
+    rpc!(restore_session, RestoreSession, UnitResponse);
+    rpc!(transfer_files, TransferFiles, TransferFilesResponse);
+    rpc!(set_compression, SetCompression, SetCompressionResponse);
+    rpc!(ack_pane_render_changes, AckPaneRenderChanges, UnitResponse);
+    rpc!(key_up, SendKeyUp, UnitResponse);
 }
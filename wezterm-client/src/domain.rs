@@ -224,12 +224,28 @@ impl ClientDomain {
     /// more tabs at the time that a disconnect was detected, and
     /// it's also possible that another client connected and adjusted
     /// the set of tabs since we were connected, so we need to re-sync.
+    /// The panes that survived the disconnect then catch up on any
+    /// output that they missed while we were away.
     pub async fn reattach(domain_id: DomainId, ui: ConnectionUI) -> anyhow::Result<()> {
         let inner = Self::get_client_inner_for_domain(domain_id)?;
 
+        // The server doesn't remember what we negotiated
+        // on the prior connection
+        inner.client.negotiate_compression().await?;
+
         let panes = inner.client.list_panes().await?;
         Self::process_pane_list(inner, panes)?;
 
+        let mux = Mux::get().unwrap();
+        for pane in mux.iter_panes() {
+            if pane.domain_id() != domain_id {
+                continue;
+            }
+            if let Some(pane) = pane.downcast_ref::<ClientPane>() {
+                pane.reconnected();
+            }
+        }
+
         ui.close();
         Ok(())
     }
@@ -252,6 +268,7 @@ impl ClientDomain {
                 None => continue,
             };
 
+            let workspace = tabroot.workspace();
             if let Some((remote_window_id, remote_tab_id)) = tabroot.window_and_tab_ids() {
                 let tab;
 
@@ -321,7 +338,10 @@ impl ClientDomain {
                         window.push(&tab);
                     }
                 } else {
-                    let local_window_id = mux.new_empty_window();
+                    // Place the window in the workspace that the server
+                    // remembered for it, so that reattaching restores
+                    // the same set of windows into each workspace
+                    let local_window_id = mux.new_empty_window(workspace);
                     inner.record_remote_to_local_window_mapping(remote_window_id, *local_window_id);
                     mux.add_tab_to_window(&tab, *local_window_id)?;
                 }
@@ -377,6 +397,11 @@ impl Domain for ClientDomain {
         let inner = self
             .inner()
             .ok_or_else(|| anyhow!("domain is not attached"))?;
+        let workspace = Mux::get()
+            .unwrap()
+            .get_window(window)
+            .map(|w| w.get_workspace().to_string())
+            .ok_or_else(|| anyhow!("no such window {}", window))?;
         let result = inner
             .client
             .spawn(Spawn {
@@ -385,6 +410,7 @@ impl Domain for ClientDomain {
                 size,
                 command,
                 command_dir,
+                workspace,
             })
             .await?;
 
@@ -500,6 +526,7 @@ impl Domain for ClientDomain {
 
                 ui.output_str("Checking server version\n");
                 client.verify_version_compat(&ui).await?;
+                client.negotiate_compression().await?;
 
                 ui.output_str("Version check OK!  Requesting pane list...\n");
                 let panes = client.list_panes().await?;
@@ -551,4 +578,8 @@ impl Domain for ClientDomain {
             DomainState::Detached
         }
     }
+
+    fn sftp(&self) -> Option<wezterm_ssh::Sftp> {
+        self.inner()?.client.sftp()
+    }
 }
@@ -6,7 +6,9 @@ use async_trait::async_trait;
 use codec::*;
 use config::configuration;
 use mux::domain::DomainId;
-use mux::pane::{alloc_pane_id, CloseReason, Pane, PaneId, Pattern, SearchResult};
+use mux::pane::{
+    alloc_pane_id, CloseReason, ConnectionStatus, Pane, PaneId, Pattern, SearchResult,
+};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::TabId;
 use mux::{Mux, MuxNotification};
@@ -35,6 +37,7 @@ pub struct ClientPane {
     mouse: Rc<RefCell<MouseState>>,
     clipboard: RefCell<Option<Arc<dyn Clipboard>>>,
     mouse_grabbed: RefCell<bool>,
+    wants_key_up_events: RefCell<bool>,
     ignore_next_kill: RefCell<bool>,
 }
 
@@ -91,6 +94,7 @@ impl ClientPane {
             palette: RefCell::new(palette),
             clipboard: RefCell::new(None),
             mouse_grabbed: RefCell::new(false),
+            wants_key_up_events: RefCell::new(false),
             ignore_next_kill: RefCell::new(false),
         }
     }
@@ -99,6 +103,7 @@ impl ClientPane {
         match pdu {
             Pdu::GetPaneRenderChangesResponse(delta) => {
                 *self.mouse_grabbed.borrow_mut() = delta.mouse_grabbed;
+                *self.wants_key_up_events.borrow_mut() = delta.wants_key_up_events;
                 self.renderable
                     .borrow()
                     .inner
@@ -150,6 +155,13 @@ impl ClientPane {
         Ok(())
     }
 
+    /// Called after the connection to the server has been re-established
+    pub fn reconnected(&self) {
+        self.renderable.borrow().inner.borrow_mut().reconnected();
+        let mux = Mux::get().unwrap();
+        mux.notify(MuxNotification::PaneOutput(self.local_pane_id));
+    }
+
     pub fn remote_pane_id(&self) -> TabId {
         self.remote_pane_id
     }
@@ -344,6 +356,30 @@ impl Pane for ClientPane {
         Ok(())
     }
 
+    fn key_up(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+        // Avoid a round trip for every release when the remote
+        // application isn't going to see it
+        if !self.wants_key_up_events() {
+            return Ok(());
+        }
+        let client = Arc::clone(&self.client);
+        let remote_pane_id = self.remote_pane_id;
+        promise::spawn::spawn(async move {
+            client
+                .client
+                .key_up(SendKeyUp {
+                    pane_id: remote_pane_id,
+                    event: KeyEvent {
+                        key,
+                        modifiers: mods,
+                    },
+                })
+                .await
+        })
+        .detach();
+        Ok(())
+    }
+
     fn kill(&self) {
         let mut ignore = self.ignore_next_kill.borrow_mut();
         if *ignore {
@@ -397,11 +433,19 @@ impl Pane for ClientPane {
         *self.mouse_grabbed.borrow()
     }
 
+    fn wants_key_up_events(&self) -> bool {
+        *self.wants_key_up_events.borrow()
+    }
+
     fn is_alt_screen_active(&self) -> bool {
         // FIXME: retrieve this from the remote
         false
     }
 
+    fn get_connection_status(&self) -> Option<ConnectionStatus> {
+        Some(self.renderable.borrow().get_connection_status())
+    }
+
     fn get_current_working_dir(&self) -> Option<Url> {
         self.renderable.borrow().inner.borrow().working_dir.clone()
     }
@@ -4,6 +4,7 @@ use anyhow::anyhow;
 use codec::*;
 use config::{configuration, ConfigHandle};
 use lru::LruCache;
+use mux::pane::ConnectionStatus;
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::TabId;
 use mux::Mux;
@@ -297,6 +298,19 @@ impl RenderableInner {
         self.cursor_position.y += lines.len().saturating_sub(1) as StableRowIndex;
     }
 
+    /// Called after the connection to the server has been re-established.
+    /// Our cached lines remain valid as of `self.seqno`, which we send
+    /// along with the next poll so that the server can tell us about just
+    /// the lines that changed while we were disconnected.
+    /// We reset the poll interval so that happens promptly.
+    pub fn reconnected(&mut self) {
+        let now = Instant::now();
+        self.dead = false;
+        self.poll_interval = BASE_POLL_INTERVAL;
+        self.last_recv_time = now;
+        self.last_send_time = now;
+    }
+
     pub fn update_last_send(&mut self) {
         self.last_send_time = Instant::now();
         self.poll_interval = BASE_POLL_INTERVAL;
@@ -572,12 +586,14 @@ impl RenderableInner {
         self.poll_in_progress.store(true, Ordering::SeqCst);
         let remote_pane_id = self.remote_pane_id;
         let local_pane_id = self.local_pane_id;
+        let seqno = self.seqno;
         let client = Arc::clone(&self.client);
         promise::spawn::spawn(async move {
             let alive = match client
                 .client
                 .get_tab_render_changes(GetPaneRenderChanges {
                     pane_id: remote_pane_id,
+                    seqno,
                 })
                 .await
             {
@@ -683,6 +699,13 @@ impl RenderableState {
         self.inner.borrow().seqno
     }
 
+    pub fn get_connection_status(&self) -> ConnectionStatus {
+        let inner = self.inner.borrow();
+        let mut status = inner.client.client.connection_status();
+        status.is_tardy = inner.is_tardy();
+        status
+    }
+
     pub fn get_changed_since(
         &self,
         lines: Range<StableRowIndex>,
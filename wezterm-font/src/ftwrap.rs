@@ -1,12 +1,14 @@
 //! Higher level freetype bindings
 
 use crate::locator::{FontDataHandle, FontDataSource};
-use crate::parser::ParsedFont;
+use crate::parser::{ParsedFont, VariationAxis};
 use anyhow::{anyhow, Context};
 use config::{configuration, FreeTypeLoadFlags, FreeTypeLoadTarget};
 pub use freetype::*;
 use memmap2::{Mmap, MmapOptions};
+use ordered_float::NotNan;
 use rangeset::RangeSet;
+use std::collections::BTreeMap;
 use std::convert::TryInto;
 use std::ffi::CStr;
 use std::fs::File;
@@ -43,6 +45,16 @@ fn ft_result<T>(err: FT_Error, t: T) -> anyhow::Result<T> {
     }
 }
 
+fn fixed_to_f64(f: FT_Fixed) -> f64 {
+    f as f64 / 65536.0
+}
+
+/// Convert an OpenType tag into its string form, eg: `wght`
+fn tag_to_string(tag: FT_ULong) -> String {
+    let bytes = (tag as u32).to_be_bytes();
+    String::from_utf8_lossy(&bytes).trim_end().to_string()
+}
+
 fn render_mode_to_load_target(render_mode: FT_Render_Mode) -> u32 {
     // enable FT_LOAD_TARGET bits.  There are no flags defined
     // for these in the bindings so we do some bit magic for
@@ -191,6 +203,108 @@ impl Face {
         }
     }
 
+    /// Returns the variation axes of a variable font, or an
+    /// empty list if the font is not variable
+    pub fn variation_axes(&self) -> Vec<VariationAxis> {
+        unsafe {
+            if ((*self.face).face_flags as u32) & FT_FACE_FLAG_MULTIPLE_MASTERS == 0 {
+                return vec![];
+            }
+
+            let mut mm = std::ptr::null_mut();
+            if !succeeded(FT_Get_MM_Var(self.face, &mut mm)) {
+                return vec![];
+            }
+
+            let axes = std::slice::from_raw_parts((*mm).axis, (*mm).num_axis as usize);
+            let res = axes
+                .iter()
+                .map(|axis| VariationAxis {
+                    tag: tag_to_string(axis.tag),
+                    name: if axis.name.is_null() {
+                        String::new()
+                    } else {
+                        CStr::from_ptr(axis.name).to_string_lossy().to_string()
+                    },
+                    minimum: fixed_to_f64(axis.minimum),
+                    default: fixed_to_f64(axis.def),
+                    maximum: fixed_to_f64(axis.maximum),
+                })
+                .collect();
+
+            FT_Done_MM_Var(self.lib, mm);
+            res
+        }
+    }
+
+    /// Apply explicit values to the variation axes of a variable font.
+    /// Axes that are not mentioned keep their current value, which
+    /// reflects the selected named instance, if any.
+    /// Values are clamped to the range supported by the axis.
+    pub fn set_variations(
+        &mut self,
+        variations: &BTreeMap<String, NotNan<f64>>,
+    ) -> anyhow::Result<()> {
+        if variations.is_empty() {
+            return Ok(());
+        }
+
+        unsafe {
+            if ((*self.face).face_flags as u32) & FT_FACE_FLAG_MULTIPLE_MASTERS == 0 {
+                log::warn!(
+                    "{:?} is not a variable font; ignoring variations {:?}",
+                    self.source,
+                    variations
+                );
+                return Ok(());
+            }
+
+            let mut mm = std::ptr::null_mut();
+            ft_result(FT_Get_MM_Var(self.face, &mut mm), ()).context("FT_Get_MM_Var")?;
+
+            let axes = std::slice::from_raw_parts((*mm).axis, (*mm).num_axis as usize);
+            let mut coords: Vec<FT_Fixed> = vec![0; axes.len()];
+            let result = ft_result(
+                FT_Get_Var_Design_Coordinates(
+                    self.face,
+                    coords.len() as FT_UInt,
+                    coords.as_mut_ptr(),
+                ),
+                (),
+            )
+            .context("FT_Get_Var_Design_Coordinates")
+            .and_then(|_| {
+                for (tag, value) in variations {
+                    match axes.iter().position(|axis| tag_to_string(axis.tag) == *tag) {
+                        Some(idx) => {
+                            let axis = &axes[idx];
+                            let value = value
+                                .into_inner()
+                                .max(fixed_to_f64(axis.minimum))
+                                .min(fixed_to_f64(axis.maximum));
+                            coords[idx] = (value * 65536.0).round() as FT_Fixed;
+                        }
+                        None => {
+                            log::warn!("{:?} has no variation axis named {}", self.source, tag);
+                        }
+                    }
+                }
+                ft_result(
+                    FT_Set_Var_Design_Coordinates(
+                        self.face,
+                        coords.len() as FT_UInt,
+                        coords.as_mut_ptr(),
+                    ),
+                    (),
+                )
+                .context("FT_Set_Var_Design_Coordinates")
+            });
+
+            FT_Done_MM_Var(self.lib, mm);
+            result
+        }
+    }
+
     pub fn get_sfnt_names(&self) -> Vec<String> {
         let num_names = unsafe { FT_Get_Sfnt_Name_Count(self.face) };
 
@@ -36,6 +36,22 @@ pub fn feature_from_string(s: &str) -> Result<hb_feature_t, Error> {
     }
 }
 
+pub fn variation_from_string(s: &str) -> Result<hb_variation_t, Error> {
+    unsafe {
+        let mut variation = mem::zeroed();
+        ensure!(
+            hb_variation_from_string(
+                s.as_ptr() as *const c_char,
+                s.len() as i32,
+                &mut variation as *mut _,
+            ) != 0,
+            "failed to create variation from {}",
+            s
+        );
+        Ok(variation)
+    }
+}
+
 pub struct Font {
     font: *mut hb_font_t,
 }
@@ -72,6 +88,12 @@ impl Font {
         }
     }
 
+    pub fn set_variations(&mut self, variations: &[hb_variation_t]) {
+        unsafe {
+            hb_font_set_variations(self.font, variations.as_ptr(), variations.len() as u32);
+        }
+    }
+
     /// Perform shaping.  On entry, Buffer holds the text to shape.
     /// Once done, Buffer holds the output glyph and position info
     pub fn shape(&mut self, buf: &mut Buffer, features: &[hb_feature_t]) {
@@ -179,6 +179,7 @@ fn build_fallback_list_impl() -> anyhow::Result<Vec<ParsedFont>> {
         freetype_load_target: None,
         freetype_render_target: None,
         freetype_load_flags: None,
+        variations: None,
     };
     if let Ok(descriptors) = descriptor_from_attr(&symbols) {
         for descriptor in descriptors.iter() {
@@ -2,10 +2,34 @@ use crate::locator::{FontDataHandle, FontDataSource, FontOrigin};
 use crate::shaper::GlyphInfo;
 use config::{FontAttributes, FreeTypeLoadFlags, FreeTypeLoadTarget};
 pub use config::{FontStretch, FontWeight};
+use ordered_float::NotNan;
 use rangeset::RangeSet;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::sync::Mutex;
 
+/// Describes a variation axis supported by a variable font
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariationAxis {
+    /// The OpenType axis tag, eg: `wght`
+    pub tag: String,
+    /// The human readable name of the axis, eg: `Weight`
+    pub name: String,
+    pub minimum: f64,
+    pub default: f64,
+    pub maximum: f64,
+}
+
+impl std::fmt::Display for VariationAxis {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{} ({}) {}-{} default={}",
+            self.tag, self.name, self.minimum, self.maximum, self.default
+        )
+    }
+}
+
 #[derive(Debug)]
 pub enum MaybeShaped {
     Resolved(GlyphInfo),
@@ -26,11 +50,13 @@ pub struct ParsedFont {
     pub synthesize_dim: bool,
     pub assume_emoji_presentation: bool,
     pub pixel_sizes: Vec<u16>,
+    variation_axes: Vec<VariationAxis>,
 
     pub harfbuzz_features: Option<Vec<String>>,
     pub freetype_load_target: Option<FreeTypeLoadTarget>,
     pub freetype_render_target: Option<FreeTypeLoadTarget>,
     pub freetype_load_flags: Option<FreeTypeLoadFlags>,
+    pub variations: Option<BTreeMap<String, NotNan<f64>>>,
 }
 
 impl std::fmt::Debug for ParsedFont {
@@ -47,6 +73,8 @@ impl std::fmt::Debug for ParsedFont {
             .field("synthesize_dim", &self.synthesize_dim)
             .field("assume_emoji_presentation", &self.assume_emoji_presentation)
             .field("pixel_sizes", &self.pixel_sizes)
+            .field("variation_axes", &self.variation_axes)
+            .field("variations", &self.variations)
             .finish()
     }
 }
@@ -66,10 +94,12 @@ impl Clone for ParsedFont {
             cap_height: self.cap_height.clone(),
             coverage: Mutex::new(self.coverage.lock().unwrap().clone()),
             pixel_sizes: self.pixel_sizes.clone(),
+            variation_axes: self.variation_axes.clone(),
             harfbuzz_features: self.harfbuzz_features.clone(),
             freetype_load_target: self.freetype_load_target,
             freetype_render_target: self.freetype_render_target,
             freetype_load_flags: self.freetype_load_flags,
+            variations: self.variations.clone(),
         }
     }
 }
@@ -175,6 +205,9 @@ impl ParsedFont {
             if !p.pixel_sizes.is_empty() {
                 code.push_str(&format!("  -- Pixel sizes: {:?}\n", p.pixel_sizes));
             }
+            for axis in &p.variation_axes {
+                code.push_str(&format!("  -- Variation axis: {}\n", axis));
+            }
 
             if p.weight == FontWeight::REGULAR
                 && p.stretch == FontStretch::Normal
@@ -183,6 +216,7 @@ impl ParsedFont {
                 && p.freetype_load_target.is_none()
                 && p.freetype_load_flags.is_none()
                 && p.harfbuzz_features.is_none()
+                && p.variations.is_none()
             {
                 code.push_str(&format!("  \"{}\",\n", p.names.family));
             } else {
@@ -217,6 +251,16 @@ impl ParsedFont {
                     }
                     code.push('}');
                 }
+                if let Some(variations) = &p.variations {
+                    code.push_str(", variations={");
+                    for (idx, (tag, value)) in variations.iter().enumerate() {
+                        if idx > 0 {
+                            code.push_str(", ");
+                        }
+                        code.push_str(&format!("{}={}", tag, value));
+                    }
+                    code.push('}');
+                }
                 code.push_str("},\n")
             }
             code.push_str("\n");
@@ -232,6 +276,7 @@ impl ParsedFont {
         let stretch = FontStretch::from_opentype_stretch(width);
         let cap_height = face.cap_height();
         let pixel_sizes = face.pixel_sizes();
+        let variation_axes = face.variation_axes();
         let has_color = unsafe {
             (((*face.face).face_flags as u32) & (crate::ftwrap::FT_FACE_FLAG_COLOR as u32)) != 0
         };
@@ -259,10 +304,12 @@ impl ParsedFont {
             coverage: Mutex::new(RangeSet::new()),
             cap_height,
             pixel_sizes,
+            variation_axes,
             harfbuzz_features: None,
             freetype_render_target: None,
             freetype_load_target: None,
             freetype_load_flags: None,
+            variations: None,
         })
     }
 
@@ -292,6 +339,12 @@ impl ParsedFont {
         &self.names
     }
 
+    /// Returns the variation axes supported by this font.
+    /// The list is empty if this is not a variable font.
+    pub fn variation_axes(&self) -> &[VariationAxis] {
+        &self.variation_axes
+    }
+
     pub fn weight(&self) -> FontWeight {
         self.weight
     }
@@ -499,6 +552,7 @@ impl ParsedFont {
         self.freetype_render_target = attr.freetype_render_target;
         self.freetype_load_target = attr.freetype_load_target;
         self.freetype_load_flags = attr.freetype_load_flags;
+        self.variations = attr.variations.clone();
 
         self.synthesize_italic = !self.italic && attr.italic;
         self.synthesize_bold = attr.weight >= FontWeight::BOLD
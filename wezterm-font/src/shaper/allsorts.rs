@@ -0,0 +1,752 @@
+//! A font shaper built on the pure-rust allsorts crate.
+//! It implements the same fallback and clustering strategy as
+//! the harfbuzz shaper so that the two can be swapped and compared.
+use crate::locator::FontDataSource;
+use crate::parser::ParsedFont;
+use crate::shaper::{
+    make_question_string, FallbackIdx, FontMetrics, FontShaper, GlyphInfo, NoMoreFallbacksError,
+};
+use crate::units::*;
+use allsorts::binary::read::ReadScope;
+use allsorts::font::{Font, MatchingPresentation};
+use allsorts::font_data::{DynamicFontTableProvider, FontData};
+use allsorts::gpos::{Info, Placement};
+use allsorts::gsub::{FeatureMask, Features};
+use allsorts::post::PostTable;
+use allsorts::tables::{FontTableProvider, HeadTable, HheaTable};
+use allsorts::tag;
+use anyhow::anyhow;
+use config::ConfigHandle;
+use log::error;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+use termwiz::cell::Presentation;
+use unicode_script::{Script, UnicodeScript};
+
+/// Convert a feature or script name such as `liga` or `cyrl`
+/// into its OpenType tag
+fn opentype_tag(name: &str) -> Option<u32> {
+    let bytes = name.as_bytes();
+    if bytes.is_empty() || bytes.len() > 4 || !name.is_ascii() {
+        return None;
+    }
+    let mut tag = [b' '; 4];
+    tag[..bytes.len()].copy_from_slice(bytes);
+    Some(u32::from_be_bytes(tag))
+}
+
+/// Convert a script into its OpenType script tag.
+/// The OpenType tags are mostly the lowercase ISO 15924 codes,
+/// but a handful of them differ.
+fn script_tag(script: Script) -> u32 {
+    let name = match script {
+        Script::Hiragana | Script::Katakana => "kana",
+        Script::Lao => "lao",
+        Script::Nko => "nko",
+        Script::Vai => "vai",
+        Script::Yi => "yi",
+        _ => script.short_name(),
+    };
+    opentype_tag(&name.to_ascii_lowercase()).unwrap_or(tag::LATN)
+}
+
+/// Split the text into runs of the same script.
+/// Characters that are common to several scripts, such as spaces,
+/// punctuation and combining marks, are attached to the run that
+/// they appear in rather than starting a new one.
+fn script_runs(s: &str) -> Vec<(u32, &str)> {
+    let mut runs = vec![];
+    let mut start = 0;
+    let mut current = None;
+    for (idx, c) in s.char_indices() {
+        let script = match c.script() {
+            Script::Common | Script::Inherited | Script::Unknown => continue,
+            script => script_tag(script),
+        };
+        match current {
+            None => current = Some(script),
+            Some(tag) if tag != script => {
+                runs.push((tag, &s[start..idx]));
+                start = idx;
+                current = Some(script);
+            }
+            Some(_) => {}
+        }
+    }
+    if start < s.len() || runs.is_empty() {
+        runs.push((current.unwrap_or(tag::LATN), &s[start..]));
+    }
+    runs
+}
+
+/// Computes the GSUB feature mask from harfbuzz style feature strings.
+/// We accept `name`, `+name`, `-name`, `name=0` and `name=1`.
+/// Features that are not represented in the allsorts feature mask
+/// are ignored.
+fn compute_feature_mask(features: &[String]) -> FeatureMask {
+    let mut mask = FeatureMask::default();
+    for feature in features {
+        let (name, enable) = if let Some(name) = feature.strip_prefix('-') {
+            (name, false)
+        } else if let Some(name) = feature.strip_prefix('+') {
+            (name, true)
+        } else if let Some((name, value)) = feature.split_once('=') {
+            (name, value.trim() != "0")
+        } else {
+            (feature.as_str(), true)
+        };
+
+        let bit = match opentype_tag(name.trim()) {
+            Some(tag) => FeatureMask::from_tag(tag),
+            None => {
+                log::warn!("invalid font feature {}", feature);
+                continue;
+            }
+        };
+        if bit.is_empty() {
+            log::debug!("allsorts shaper doesn't support font feature {}", feature);
+            continue;
+        }
+        if enable {
+            mask.insert(bit);
+        } else {
+            mask.remove(bit);
+        }
+    }
+    mask
+}
+
+/// A shaped glyph, positioned in font units
+#[derive(Debug)]
+struct ShapedGlyph {
+    glyph_index: u16,
+    first_char: Option<char>,
+    chars_len: usize,
+    is_dup: bool,
+    x_advance: i32,
+    x_offset: i32,
+    y_offset: i32,
+}
+
+struct AllsortsFont {
+    /// Borrows from `_data`, so it must be declared ahead of it
+    /// in order to be dropped first
+    font: RefCell<Font<DynamicFontTableProvider<'static>>>,
+    units_per_em: f64,
+    /// Line height in font units
+    height: f64,
+    descender: f64,
+    cell_width: f64,
+    underline_position: f64,
+    underline_thickness: f64,
+    is_scaled: bool,
+    presentation: Presentation,
+    features: Features,
+    _data: Cow<'static, [u8]>,
+}
+
+impl AllsortsFont {
+    fn load(handle: &ParsedFont, features: &[String]) -> anyhow::Result<Self> {
+        let data: Cow<'static, [u8]> = match &handle.handle.source {
+            FontDataSource::BuiltIn { data, .. } => Cow::Borrowed(*data),
+            source => Cow::Owned(source.load_data()?.into_owned()),
+        };
+        // The parsed tables borrow from the font data.  The data is either
+        // static or lives on the heap, so its address is stable when we move
+        // it into Self below, and `font` is dropped before `_data`.
+        let bytes: &'static [u8] = unsafe { std::mem::transmute::<&[u8], &'static [u8]>(&data) };
+
+        let font_data = ReadScope::new(bytes).read::<FontData<'static>>()?;
+        let provider = font_data.table_provider(handle.handle.index as usize)?;
+
+        let head = ReadScope::new(&provider.read_table_data(tag::HEAD)?).read::<HeadTable>()?;
+        let hhea = ReadScope::new(&provider.read_table_data(tag::HHEA)?).read::<HheaTable>()?;
+        let (underline_position, underline_thickness) = if provider.has_table(tag::POST) {
+            let post_data = provider.read_table_data(tag::POST)?;
+            let post = ReadScope::new(&post_data).read::<PostTable>()?;
+            (
+                f64::from(post.header.underline_position),
+                f64::from(post.header.underline_thickness),
+            )
+        } else {
+            (0., 0.)
+        };
+        let is_scaled = provider.has_table(tag::GLYF) || provider.has_table(tag::CFF);
+
+        let mut font =
+            Font::new(provider)?.ok_or_else(|| anyhow!("font has no usable cmap subtable"))?;
+
+        let height =
+            f64::from(hhea.ascender) - f64::from(hhea.descender) + f64::from(hhea.line_gap);
+
+        // Use the widest printable ASCII glyph for the cell width,
+        // the same way that we do for freetype
+        let ascii: String = (32u8..128).map(char::from).collect();
+        let glyphs = font.map_glyphs(&ascii, tag::LATN, MatchingPresentation::NotRequired);
+        let mut cell_width = glyphs
+            .iter()
+            .filter(|g| g.glyph_index != 0)
+            .filter_map(|g| font.horizontal_advance(g.glyph_index))
+            .max()
+            .unwrap_or(0);
+        if cell_width == 0 {
+            // Most likely a symbol font with no latin glyphs
+            cell_width = (1..8)
+                .filter_map(|g| font.horizontal_advance(g))
+                .max()
+                .unwrap_or(0);
+        }
+        let cell_width = if cell_width == 0 {
+            log::error!("Couldn't find any glyphs for metrics, so guessing width == height");
+            height
+        } else {
+            f64::from(cell_width)
+        };
+
+        Ok(Self {
+            font: RefCell::new(font),
+            units_per_em: f64::from(head.units_per_em),
+            height,
+            descender: f64::from(hhea.descender),
+            cell_width,
+            underline_position,
+            underline_thickness,
+            is_scaled,
+            presentation: if handle.assume_emoji_presentation {
+                Presentation::Emoji
+            } else {
+                Presentation::Text
+            },
+            features: Features::Mask(compute_feature_mask(features)),
+            _data: data,
+        })
+    }
+
+    /// Returns the number of pixels per font unit
+    fn pixel_scale(&self, size: f64, dpi: u32) -> f64 {
+        size * dpi as f64 / 72.0 / self.units_per_em
+    }
+
+    /// Apply GSUB and GPOS to the text, one script run at a time
+    fn shape(&self, s: &str) -> Vec<ShapedGlyph> {
+        let mut font = self.font.borrow_mut();
+        let mut infos: Vec<Info> = vec![];
+        for (script, run) in script_runs(s) {
+            let glyphs = font.map_glyphs(run, script, MatchingPresentation::NotRequired);
+            match font.shape(glyphs, script, None, &self.features, true) {
+                Ok(run_infos) => infos.extend(run_infos),
+                Err((err, run_infos)) => {
+                    log::debug!("error while shaping {:?}: {}", run, err);
+                    infos.extend(run_infos);
+                }
+            }
+        }
+
+        infos
+            .into_iter()
+            .map(|info| {
+                let advance = font
+                    .horizontal_advance(info.glyph.glyph_index)
+                    .map(i32::from)
+                    .unwrap_or(0);
+                let (x_offset, y_offset) = match info.placement {
+                    Placement::Distance(dx, dy) => (dx, dy),
+                    _ => (0, 0),
+                };
+                ShapedGlyph {
+                    glyph_index: info.glyph.glyph_index,
+                    first_char: info.glyph.unicodes.first().copied(),
+                    chars_len: info.glyph.unicodes.iter().map(|c| c.len_utf8()).sum(),
+                    is_dup: info.glyph.multi_subst_dup,
+                    x_advance: advance + i32::from(info.kerning),
+                    x_offset,
+                    y_offset,
+                }
+            })
+            .collect()
+    }
+
+    fn metrics(&self, size: f64, dpi: u32) -> FontMetrics {
+        let scale = self.pixel_scale(size, dpi);
+        FontMetrics {
+            cell_width: PixelLength::new(self.cell_width * scale),
+            cell_height: PixelLength::new(self.height * scale),
+            descender: PixelLength::new(self.descender * scale),
+            underline_thickness: PixelLength::new(self.underline_thickness * scale),
+            underline_position: PixelLength::new(self.underline_position * scale),
+            cap_height_ratio: None,
+            cap_height: None,
+            is_scaled: self.is_scaled,
+            presentation: self.presentation,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Cluster {
+    start: usize,
+    len: usize,
+    glyphs: Vec<ShapedGlyph>,
+}
+
+impl Cluster {
+    fn is_incomplete(&self) -> bool {
+        self.glyphs.iter().any(|g| g.glyph_index == 0)
+    }
+}
+
+enum FontSlot {
+    NotLoaded,
+    Loaded(Rc<AllsortsFont>),
+    Failed,
+}
+
+pub struct AllsortsShaper {
+    handles: Vec<ParsedFont>,
+    fonts: Vec<RefCell<FontSlot>>,
+    features: Vec<String>,
+}
+
+impl AllsortsShaper {
+    pub fn new(config: &ConfigHandle, handles: &[ParsedFont]) -> anyhow::Result<Self> {
+        let handles = handles.to_vec();
+        let mut fonts = vec![];
+        for _ in 0..handles.len() {
+            fonts.push(RefCell::new(FontSlot::NotLoaded));
+        }
+        Ok(Self {
+            handles,
+            fonts,
+            features: config.harfbuzz_features.clone(),
+        })
+    }
+
+    /// Returns the parsed font for the fallback slot.
+    /// Fonts that fail to parse are logged once and then
+    /// reported as errors on subsequent calls.
+    fn load_fallback(&self, font_idx: FallbackIdx) -> anyhow::Result<Option<Rc<AllsortsFont>>> {
+        let slot = match self.fonts.get(font_idx) {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        let mut slot = slot.borrow_mut();
+        match &*slot {
+            FontSlot::Loaded(font) => return Ok(Some(Rc::clone(font))),
+            FontSlot::Failed => anyhow::bail!("font idx {} failed to load", font_idx),
+            FontSlot::NotLoaded => {}
+        }
+
+        let handle = &self.handles[font_idx];
+        log::trace!("shaper wants {} {:?}", font_idx, handle);
+        let features = handle.harfbuzz_features.as_ref().unwrap_or(&self.features);
+        match AllsortsFont::load(handle, features) {
+            Ok(font) => {
+                let font = Rc::new(font);
+                *slot = FontSlot::Loaded(Rc::clone(&font));
+                Ok(Some(font))
+            }
+            Err(err) => {
+                log::warn!("allsorts failed to parse {:?}: {:#}", handle, err);
+                *slot = FontSlot::Failed;
+                Err(err)
+            }
+        }
+    }
+
+    fn do_shape(
+        &self,
+        mut font_idx: FallbackIdx,
+        s: &str,
+        font_size: f64,
+        dpi: u32,
+        no_glyphs: &mut Vec<char>,
+        presentation: Option<Presentation>,
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        let initial_font_idx = font_idx;
+
+        let font = loop {
+            match self.load_fallback(font_idx) {
+                Ok(Some(font)) => {
+                    // Ignore presentation if we've reached the last resort font
+                    if font_idx + 1 < self.fonts.len() {
+                        if let Some(p) = presentation {
+                            if font.presentation != p {
+                                font_idx += 1;
+                                continue;
+                            }
+                        }
+                    }
+                    break font;
+                }
+                Ok(None) => {
+                    for c in s.chars() {
+                        no_glyphs.push(c);
+                    }
+                    return Err(NoMoreFallbacksError {
+                        text: s.to_string(),
+                    }
+                    .into());
+                }
+                Err(_) => {
+                    // Treat a font that we can't parse as though it
+                    // has no glyphs at all
+                    font_idx += 1;
+                }
+            }
+        };
+
+        if font_idx > 0 && font_idx + 1 == self.fonts.len() {
+            // We are the last resort font, so each codepoint is considered
+            // to be worthy of a fallback lookup
+            for c in s.chars() {
+                no_glyphs.push(c);
+            }
+
+            if presentation.is_some() {
+                // See the harfbuzz shaper for the rationale
+                return self.do_shape(initial_font_idx, s, font_size, dpi, no_glyphs, None);
+            }
+        }
+
+        let scale = font.pixel_scale(font_size, dpi);
+        let shaped = font.shape(s);
+
+        // Allsorts tells us which codepoints produced each glyph,
+        // but not where they are in the input text, and it may
+        // elide some codepoints (eg: variation selectors) entirely.
+        // Find the start of each glyph in the text and then group
+        // glyphs into clusters in the same way as the harfbuzz shaper:
+        // ligatures take the place of multiple characters, zero-width
+        // glyphs (typically combining marks) join the preceding
+        // cluster and runs of unresolved codepoints are kept together
+        // so that they can shape together in a fallback font.
+        let mut starts = Vec::with_capacity(shaped.len());
+        let mut pos = 0;
+        for glyph in &shaped {
+            let start = match glyph.first_char {
+                Some(c) if !glyph.is_dup => s[pos..].find(c).map(|idx| idx + pos).unwrap_or(pos),
+                _ => starts.last().copied().unwrap_or(pos),
+            };
+            starts.push(start);
+            pos = pos.max(start + glyph.chars_len).min(s.len());
+        }
+
+        let mut clusters: Vec<Cluster> = Vec::with_capacity(shaped.len());
+        for (idx, glyph) in shaped.into_iter().enumerate() {
+            let start = starts[idx];
+            let next_start = starts[idx + 1..]
+                .iter()
+                .copied()
+                .find(|&next| next > start)
+                .unwrap_or(s.len());
+            let len = next_start - start;
+
+            if let Some(cluster) = clusters.last_mut() {
+                let joins = cluster.start == start
+                    || (glyph.glyph_index != 0 && glyph.x_advance == 0 && !cluster.is_incomplete())
+                    || (glyph.glyph_index == 0 && cluster.is_incomplete());
+                if joins {
+                    cluster.len = next_start.max(cluster.start + cluster.len) - cluster.start;
+                    cluster.glyphs.push(glyph);
+                    continue;
+                }
+            }
+            clusters.push(Cluster {
+                start,
+                len,
+                glyphs: vec![glyph],
+            });
+        }
+
+        let mut result = Vec::with_capacity(clusters.len());
+        for cluster in clusters {
+            let substr = &s[cluster.start..cluster.start + cluster.len];
+
+            if cluster.is_incomplete() {
+                // One or more entries didn't have a corresponding glyph,
+                // so try a fallback
+                let mut shape = match self.do_shape(
+                    font_idx + 1,
+                    substr,
+                    font_size,
+                    dpi,
+                    no_glyphs,
+                    presentation,
+                ) {
+                    Ok(shape) => Ok(shape),
+                    Err(e) => {
+                        error!("{:?} for {:?}", e, substr);
+                        self.do_shape(
+                            0,
+                            &make_question_string(substr),
+                            font_size,
+                            dpi,
+                            no_glyphs,
+                            presentation,
+                        )
+                    }
+                }?;
+
+                // Fixup the cluster member to match our current offset
+                for info in &mut shape {
+                    info.cluster += cluster.start as u32;
+                }
+                result.append(&mut shape);
+                continue;
+            }
+
+            let mut text = substr;
+            for glyph in &cluster.glyphs {
+                if glyph.x_advance == 0 {
+                    continue;
+                }
+                result.push(GlyphInfo {
+                    #[cfg(debug_assertions)]
+                    text: text.into(),
+                    is_space: text == " ",
+                    cluster: cluster.start as u32,
+                    font_idx,
+                    glyph_pos: u32::from(glyph.glyph_index),
+                    x_advance: PixelLength::new(f64::from(glyph.x_advance) * scale),
+                    y_advance: PixelLength::new(0.),
+                    x_offset: PixelLength::new(f64::from(glyph.x_offset) * scale),
+                    y_offset: PixelLength::new(f64::from(glyph.y_offset) * scale),
+                });
+                // Only the first glyph of a multi-glyph cluster
+                // is associated with the text
+                text = "";
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+impl FontShaper for AllsortsShaper {
+    fn shape(
+        &self,
+        text: &str,
+        size: f64,
+        dpi: u32,
+        no_glyphs: &mut Vec<char>,
+        presentation: Option<Presentation>,
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        log::trace!("shape byte_len={} `{}`", text.len(), text.escape_debug());
+        let start = std::time::Instant::now();
+        let result = self.do_shape(0, text, size, dpi, no_glyphs, presentation);
+        metrics::histogram!("shape.allsorts", start.elapsed());
+        result
+    }
+
+    fn metrics_for_idx(&self, font_idx: usize, size: f64, dpi: u32) -> anyhow::Result<FontMetrics> {
+        let font = self
+            .load_fallback(font_idx)?
+            .ok_or_else(|| anyhow!("unable to load font idx {}!?", font_idx))?;
+        Ok(font.metrics(size, dpi))
+    }
+
+    fn metrics(&self, size: f64, dpi: u32) -> anyhow::Result<FontMetrics> {
+        // Skip fallback slots whose metrics are implausible for the
+        // requested size; see the harfbuzz shaper for the rationale.
+        let theoretical_height = size * dpi as f64 / 72.0;
+        for font_idx in 0..self.fonts.len() {
+            let font = match self.load_fallback(font_idx) {
+                Ok(Some(font)) => font,
+                _ => continue,
+            };
+            let metrics = font.metrics(size, dpi);
+            let diff = (theoretical_height - metrics.cell_height.get()).abs();
+            if diff / theoretical_height < 2.0 {
+                return Ok(metrics);
+            }
+        }
+        anyhow::bail!("no fonts available for collecting metrics!?");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::shaper::harfbuzz::HarfbuzzShaper;
+    use crate::FontDatabase;
+    use config::{FontAttributes, FontWeight};
+
+    fn attrs(family: &str, weight: FontWeight, italic: bool) -> FontAttributes {
+        FontAttributes {
+            family: family.into(),
+            stretch: Default::default(),
+            weight,
+            is_fallback: false,
+            is_synthetic: false,
+            italic,
+            freetype_load_flags: None,
+            variations: None,
+            freetype_load_target: None,
+            freetype_render_target: None,
+            harfbuzz_features: None,
+        }
+    }
+
+    /// Shape `text` with both shapers and verify that allsorts selects
+    /// the same glyphs for the same clusters as harfbuzz.
+    /// Harfbuzz positions glyphs using hinted freetype metrics, so we
+    /// allow a pixel of slop in the positioning unless `check_advances`
+    /// is false, which is the case for bitmap fonts where freetype
+    /// reports the metrics of the bitmap strike.
+    fn assert_conforms(handle: &ParsedFont, text: &str, check_advances: bool) {
+        let config = config::configuration();
+        let handles = [handle.clone()];
+        let harfbuzz = HarfbuzzShaper::new(&config, &handles).unwrap();
+        let allsorts = AllsortsShaper::new(&config, &handles).unwrap();
+
+        let mut hb_no_glyphs = vec![];
+        let expected = harfbuzz
+            .shape(text, 10., 72, &mut hb_no_glyphs, None)
+            .unwrap();
+        let mut no_glyphs = vec![];
+        let actual = allsorts.shape(text, 10., 72, &mut no_glyphs, None).unwrap();
+
+        let context = format!(
+            "font={} text={:?}\nharfbuzz: {:#?}\nallsorts: {:#?}",
+            handle.names().full_name,
+            text,
+            expected,
+            actual
+        );
+        assert_eq!(hb_no_glyphs, no_glyphs, "{}", context);
+        assert_eq!(expected.len(), actual.len(), "{}", context);
+
+        for (hb, ours) in expected.iter().zip(actual.iter()) {
+            #[cfg(debug_assertions)]
+            assert_eq!(hb.text, ours.text, "{}", context);
+            assert_eq!(hb.cluster, ours.cluster, "{}", context);
+            assert_eq!(hb.font_idx, ours.font_idx, "{}", context);
+            assert_eq!(hb.glyph_pos, ours.glyph_pos, "{}", context);
+            assert_eq!(hb.is_space, ours.is_space, "{}", context);
+            if check_advances {
+                for (a, b) in &[
+                    (hb.x_advance, ours.x_advance),
+                    (hb.x_offset, ours.x_offset),
+                    (hb.y_offset, ours.y_offset),
+                ] {
+                    assert!((a.get() - b.get()).abs() <= 1.0, "{}", context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn conformance() {
+        let _ = pretty_env_logger::formatted_builder()
+            .is_test(true)
+            .filter_level(log::LevelFilter::Trace)
+            .try_init();
+
+        let db = FontDatabase::with_built_in().unwrap();
+        let resolve = |attr: FontAttributes| db.resolve(&attr, 14).unwrap().clone();
+
+        let text_samples = [
+            "abc",
+            "x x",
+            "Hello, World!",
+            "<",
+            "<-",
+            "<--",
+            "->",
+            "=>",
+            "!=",
+            "===",
+            "<!--",
+            "www",
+            "0xFF",
+            "fi fl",
+            "ÀÉÎÕÜ ąčęšž",
+            "|>",
+            "/* */",
+            "Привет, мир",
+            "αβγ δ",
+            "abc где",
+        ];
+
+        for &weight in &[
+            FontWeight::THIN,
+            FontWeight::EXTRALIGHT,
+            FontWeight::LIGHT,
+            FontWeight::REGULAR,
+            FontWeight::MEDIUM,
+            FontWeight::BOLD,
+            FontWeight::EXTRABOLD,
+        ] {
+            for &italic in &[false, true] {
+                let handle = resolve(attrs("JetBrains Mono", weight, italic));
+                for text in &text_samples {
+                    assert_conforms(&handle, text, true);
+                }
+            }
+        }
+
+        let powerline = resolve(attrs("Powerline Extra Symbols", FontWeight::REGULAR, false));
+        assert_conforms(&powerline, "\u{e0b4}\u{e0b6}\u{e0c0}\u{e0c2}", true);
+
+        let emoji = resolve(attrs("Noto Color Emoji", FontWeight::REGULAR, false));
+        assert_conforms(&emoji, "\u{1f600}\u{1f389}", false);
+    }
+
+    #[test]
+    fn fallback() {
+        let db = FontDatabase::with_built_in().unwrap();
+        let jb = db
+            .resolve(&attrs("JetBrains Mono", FontWeight::REGULAR, false), 14)
+            .unwrap()
+            .clone();
+        let emoji = db
+            .resolve(&attrs("Noto Color Emoji", FontWeight::REGULAR, false), 14)
+            .unwrap()
+            .clone();
+
+        let config = config::configuration();
+        let shaper = AllsortsShaper::new(&config, &[jb, emoji]).unwrap();
+        let mut no_glyphs = vec![];
+        let info = shaper
+            .shape("a\u{1f600}b", 10., 72, &mut no_glyphs, None)
+            .unwrap();
+
+        assert_eq!(no_glyphs, vec!['\u{1f600}']);
+        assert_eq!(
+            info.iter()
+                .map(|g| (g.cluster, g.font_idx))
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (1, 1), (5, 0)]
+        );
+    }
+
+    #[test]
+    fn script_run_split() {
+        assert_eq!(script_runs(""), vec![(tag::LATN, "")]);
+        assert_eq!(script_runs("1 + 2"), vec![(tag::LATN, "1 + 2")]);
+        assert_eq!(
+            script_runs("abc где, xy"),
+            vec![
+                (tag::LATN, "abc "),
+                (script_tag(Script::Cyrillic), "где, "),
+                (tag::LATN, "xy")
+            ]
+        );
+        assert_eq!(script_tag(Script::Cyrillic), opentype_tag("cyrl").unwrap());
+        assert_eq!(script_tag(Script::Katakana), opentype_tag("kana").unwrap());
+        assert_eq!(script_tag(Script::Lao), opentype_tag("lao").unwrap());
+    }
+
+    #[test]
+    fn feature_mask() {
+        let mask = compute_feature_mask(&["calt=0".to_string(), "-liga".to_string()]);
+        assert!(!mask.contains(FeatureMask::CALT));
+        assert!(!mask.contains(FeatureMask::LIGA));
+        assert!(mask.contains(FeatureMask::CLIG));
+
+        let mask = compute_feature_mask(&["smcp".to_string(), "bogus_feature".to_string()]);
+        assert!(mask.contains(FeatureMask::SMCP));
+        assert!(mask.contains(FeatureMask::LIGA));
+    }
+}
@@ -1,7 +1,9 @@
 use crate::ftwrap;
 use crate::hbwrap as harfbuzz;
 use crate::parser::ParsedFont;
-use crate::shaper::{FallbackIdx, FontMetrics, FontShaper, GlyphInfo};
+use crate::shaper::{
+    make_question_string, FallbackIdx, FontMetrics, FontShaper, GlyphInfo, NoMoreFallbacksError,
+};
 use crate::units::*;
 use anyhow::{anyhow, Context};
 use config::ConfigHandle;
@@ -10,7 +12,6 @@ use ordered_float::NotNan;
 use std::cell::{RefCell, RefMut};
 use std::collections::HashMap;
 use termwiz::cell::Presentation;
-use thiserror::Error;
 use unicode_segmentation::UnicodeSegmentation;
 
 #[derive(Clone, Debug)]
@@ -64,39 +65,6 @@ pub struct HarfbuzzShaper {
     lang: harfbuzz::hb_language_t,
 }
 
-#[derive(Error, Debug)]
-#[error("No more fallbacks while shaping {}", .text.escape_unicode())]
-struct NoMoreFallbacksError {
-    text: String,
-}
-
-/// Make a string holding a set of unicode replacement
-/// characters equal to the number of graphemes in the
-/// original string.  That isn't perfect, but it should
-/// be good enough to indicate that something isn't right.
-fn make_question_string(s: &str) -> String {
-    let len = s.graphemes(true).count();
-    let mut result = String::new();
-    let c = if !is_question_string(s) {
-        std::char::REPLACEMENT_CHARACTER
-    } else {
-        '?'
-    };
-    for _ in 0..len {
-        result.push(c);
-    }
-    result
-}
-
-fn is_question_string(s: &str) -> bool {
-    for c in s.chars() {
-        if c != std::char::REPLACEMENT_CHARACTER {
-            return false;
-        }
-    }
-    true
-}
-
 impl HarfbuzzShaper {
     pub fn new(config: &ConfigHandle, handles: &[ParsedFont]) -> anyhow::Result<Self> {
         let lib = ftwrap::Library::new()?;
@@ -135,8 +103,20 @@ impl HarfbuzzShaper {
                 if opt_pair.is_none() {
                     let handle = &self.handles[font_idx];
                     log::trace!("shaper wants {} {:?}", font_idx, handle);
-                    let face = self.lib.face_from_locator(&handle.handle)?;
+                    let mut face = self.lib.face_from_locator(&handle.handle)?;
+                    if let Some(variations) = &handle.variations {
+                        face.set_variations(variations)?;
+                    }
                     let mut font = harfbuzz::Font::new(face.face);
+                    if let Some(variations) = &handle.variations {
+                        let variations: Vec<harfbuzz::hb_variation_t> = variations
+                            .iter()
+                            .filter_map(|(tag, value)| {
+                                harfbuzz::variation_from_string(&format!("{}={}", tag, value)).ok()
+                            })
+                            .collect();
+                        font.set_variations(&variations);
+                    }
                     let (load_flags, _) = ftwrap::compute_load_flags_from_config(
                         handle.freetype_load_flags,
                         handle.freetype_load_target,
@@ -579,6 +559,7 @@ mod test {
                     is_synthetic: false,
                     italic: false,
                     freetype_load_flags: None,
+                    variations: None,
                     freetype_load_target: None,
                     freetype_render_target: None,
                     harfbuzz_features: None,
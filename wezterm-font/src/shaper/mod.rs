@@ -1,7 +1,10 @@
 use crate::parser::ParsedFont;
 use crate::units::PixelLength;
 use termwiz::cell::Presentation;
+use thiserror::Error;
+use unicode_segmentation::UnicodeSegmentation;
 
+pub mod allsorts;
 pub mod harfbuzz;
 
 /// Holds information about a shaped glyph
@@ -27,6 +30,39 @@ pub struct GlyphInfo {
     pub y_offset: PixelLength,
 }
 
+#[derive(Error, Debug)]
+#[error("No more fallbacks while shaping {}", .text.escape_unicode())]
+pub(crate) struct NoMoreFallbacksError {
+    pub text: String,
+}
+
+/// Make a string holding a set of unicode replacement
+/// characters equal to the number of graphemes in the
+/// original string.  That isn't perfect, but it should
+/// be good enough to indicate that something isn't right.
+pub(crate) fn make_question_string(s: &str) -> String {
+    let len = s.graphemes(true).count();
+    let mut result = String::new();
+    let c = if !is_question_string(s) {
+        std::char::REPLACEMENT_CHARACTER
+    } else {
+        '?'
+    };
+    for _ in 0..len {
+        result.push(c);
+    }
+    result
+}
+
+fn is_question_string(s: &str) -> bool {
+    for c in s.chars() {
+        if c != std::char::REPLACEMENT_CHARACTER {
+            return false;
+        }
+    }
+    true
+}
+
 /// Represents a numbered index in the fallback sequence for a `NamedFont`.
 /// 0 is the first, best match.  If a glyph isn't present then we will
 /// want to search for a fallback in later indices.
@@ -92,7 +128,7 @@ pub fn new_shaper(
             Ok(Box::new(harfbuzz::HarfbuzzShaper::new(config, handles)?))
         }
         FontShaperSelection::Allsorts => {
-            anyhow::bail!("The incomplete Allsorts shaper has been removed");
+            Ok(Box::new(allsorts::AllsortsShaper::new(config, handles)?))
         }
     }
 }
@@ -0,0 +1,349 @@
+//! Describes the actions that can be performed in a window, so that
+//! they can be discovered and launched via the command palette.
+use config::keyassignment::{
+    BroadcastScope, ClipboardCopyDestination, ClipboardPasteSource, InputMap, KeyAssignment,
+    PaneDirection, Pattern, RotationDirection, ScrollbackEraseMode, SpawnCommand, SpawnTabDomain,
+};
+use window::{KeyCode, Modifiers};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandDef {
+    /// Human readable description of the action
+    pub brief: String,
+    /// The key combinations that trigger the action
+    pub keys: Vec<(KeyCode, Modifiers)>,
+    pub action: KeyAssignment,
+}
+
+impl CommandDef {
+    /// Builds the list of commands to show in the palette.
+    /// This is made up of the built-in actions, the actions that are
+    /// bound in the key map and the action callbacks that were defined
+    /// by the configuration.
+    pub fn compute(input_map: &InputMap, user_callbacks: Vec<String>) -> Vec<CommandDef> {
+        let mut actions = default_actions();
+        for action in input_map.key_assignments() {
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        }
+        for name in user_callbacks {
+            let action = KeyAssignment::EmitEvent(name);
+            if !actions.contains(&action) {
+                actions.push(action);
+            }
+        }
+
+        let mut commands: Vec<CommandDef> = actions
+            .into_iter()
+            .filter_map(|action| {
+                let brief = describe(&action)?;
+                Some(CommandDef {
+                    brief,
+                    keys: input_map.locate_key_bindings(&action),
+                    action,
+                })
+            })
+            .collect();
+        commands.sort_by(|a, b| a.brief.cmp(&b.brief));
+        commands
+    }
+
+    /// Returns a human readable representation of the key bindings,
+    /// such as `CTRL+SHIFT+P`
+    pub fn key_label(&self) -> String {
+        self.keys
+            .iter()
+            .map(|(key, mods)| format_key(key, *mods))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn format_key(key: &KeyCode, mods: Modifiers) -> String {
+    let mut parts = vec![];
+    for (flag, label) in &[
+        (Modifiers::LEADER, "LEADER"),
+        (Modifiers::SUPER, "SUPER"),
+        (Modifiers::CTRL, "CTRL"),
+        (Modifiers::ALT, "ALT"),
+        (Modifiers::SHIFT, "SHIFT"),
+    ] {
+        if mods.contains(*flag) {
+            parts.push(label.to_string());
+        }
+    }
+    parts.push(match key {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char('\t') => "Tab".to_string(),
+        KeyCode::Char('\r') | KeyCode::Char('\n') => "Enter".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        key => format!("{:?}", key),
+    });
+    parts.join("+")
+}
+
+/// The set of actions that are always listed in the palette,
+/// regardless of whether they are bound to keys.
+/// Actions that require parameters that cannot be reasonably
+/// defaulted are only listed when they appear in the key map.
+fn default_actions() -> Vec<KeyAssignment> {
+    use KeyAssignment::*;
+    vec![
+        SpawnTab(SpawnTabDomain::CurrentPaneDomain),
+        SpawnWindow,
+        ToggleFullScreen,
+        CopyTo(ClipboardCopyDestination::Clipboard),
+        PasteFrom(ClipboardPasteSource::Clipboard),
+        PasteFrom(ClipboardPasteSource::PrimarySelection),
+        ActivateTabRelative(-1),
+        ActivateTabRelative(1),
+        IncreaseFontSize,
+        DecreaseFontSize,
+        ResetFontSize,
+        ResetFontAndWindowSize,
+        ActivateLastTab,
+        Hide,
+        Show,
+        CloseCurrentTab { confirm: true },
+        ReloadConfiguration,
+        MoveTabRelative(-1),
+        MoveTabRelative(1),
+        ScrollByPage(-1),
+        ScrollByPage(1),
+        ScrollToPrompt(-1),
+        ScrollToPrompt(1),
+        ScrollToTop,
+        ScrollToBottom,
+        ShowTabNavigator,
+        ShowDebugOverlay,
+        HideApplication,
+        QuitApplication,
+        SplitHorizontal(SpawnCommand {
+            domain: SpawnTabDomain::CurrentPaneDomain,
+            ..Default::default()
+        }),
+        SplitVertical(SpawnCommand {
+            domain: SpawnTabDomain::CurrentPaneDomain,
+            ..Default::default()
+        }),
+        ShowLauncher,
+        ClearScrollback(ScrollbackEraseMode::ScrollbackOnly),
+        ClearScrollback(ScrollbackEraseMode::ScrollbackAndViewport),
+        Search(Pattern::CaseSensitiveString("".into())),
+        ActivateCopyMode,
+        ActivatePaneDirection(PaneDirection::Left),
+        ActivatePaneDirection(PaneDirection::Right),
+        ActivatePaneDirection(PaneDirection::Up),
+        ActivatePaneDirection(PaneDirection::Down),
+        ActivatePaneDirection(PaneDirection::Next),
+        ActivatePaneDirection(PaneDirection::Prev),
+        TogglePaneZoomState,
+        CloseCurrentPane { confirm: true },
+        QuickSelect,
+        RotatePanes(RotationDirection::Clockwise),
+        RotatePanes(RotationDirection::CounterClockwise),
+        MovePaneToNewTab,
+        MovePaneToNewWindow,
+        SwitchToWorkspace {
+            name: None,
+            spawn: None,
+        },
+        SwitchWorkspaceRelative(1),
+        SwitchWorkspaceRelative(-1),
+        ToggleBroadcastInput {
+            scope: BroadcastScope::Tab,
+        },
+        ToggleBroadcastInput {
+            scope: BroadcastScope::Window,
+        },
+        ToggleBroadcastInput {
+            scope: BroadcastScope::Marked,
+        },
+        TogglePaneBroadcastMark,
+    ]
+}
+
+fn direction_label(direction: PaneDirection) -> &'static str {
+    match direction {
+        PaneDirection::Up => "up",
+        PaneDirection::Down => "down",
+        PaneDirection::Left => "left",
+        PaneDirection::Right => "right",
+        PaneDirection::Next => "next",
+        PaneDirection::Prev => "previous",
+    }
+}
+
+fn destination_label(dest: ClipboardCopyDestination) -> &'static str {
+    match dest {
+        ClipboardCopyDestination::Clipboard => "the clipboard",
+        ClipboardCopyDestination::PrimarySelection => "the primary selection",
+        ClipboardCopyDestination::ClipboardAndPrimarySelection => {
+            "the clipboard and primary selection"
+        }
+    }
+}
+
+fn spawn_label(spawn: &SpawnCommand) -> String {
+    if let Some(label) = &spawn.label {
+        return label.to_string();
+    }
+    match &spawn.args {
+        Some(args) => args.join(" "),
+        None => "the default program".to_string(),
+    }
+}
+
+fn relative(n: isize, singular: &str) -> String {
+    match n {
+        1 => format!("next {}", singular),
+        -1 => format!("previous {}", singular),
+        n if n > 0 => format!("{} {}s forwards", n, singular),
+        n => format!("{} {}s backwards", -n, singular),
+    }
+}
+
+/// Returns a human readable description of `action`, or None if
+/// the action doesn't make sense to launch from the palette.
+pub fn describe(action: &KeyAssignment) -> Option<String> {
+    use KeyAssignment::*;
+    Some(match action {
+        SpawnTab(SpawnTabDomain::DefaultDomain) => "New tab in the default domain".to_string(),
+        SpawnTab(SpawnTabDomain::CurrentPaneDomain) => "New tab".to_string(),
+        SpawnTab(SpawnTabDomain::DomainName(name)) => format!("New tab in domain `{}`", name),
+        SpawnWindow => "New window".to_string(),
+        ToggleFullScreen => "Toggle full screen mode".to_string(),
+        Copy => "Copy the selection to the clipboard".to_string(),
+        CopyTo(dest) => format!("Copy the selection to {}", destination_label(*dest)),
+        Paste => "Paste from the clipboard".to_string(),
+        PastePrimarySelection => "Paste from the primary selection".to_string(),
+        PasteFrom(ClipboardPasteSource::Clipboard) => "Paste from the clipboard".to_string(),
+        PasteFrom(ClipboardPasteSource::PrimarySelection) => {
+            "Paste from the primary selection".to_string()
+        }
+        ActivateTabRelative(n) => format!("Activate the {}", relative(*n, "tab")),
+        ActivateTabRelativeNoWrap(n) => format!(
+            "Activate the {}, without wrapping around",
+            relative(*n, "tab")
+        ),
+        IncreaseFontSize => "Increase the font size".to_string(),
+        DecreaseFontSize => "Decrease the font size".to_string(),
+        ResetFontSize => "Reset the font size".to_string(),
+        ResetFontAndWindowSize => "Reset the font and window size".to_string(),
+        ActivateTab(n) if *n < 0 => format!("Activate tab {} from the right", -n),
+        ActivateTab(n) => format!("Activate tab {}", n + 1),
+        ActivateLastTab => "Activate the previously active tab".to_string(),
+        SendString(s) => format!("Send the string {:?} to the terminal", s),
+        SendKey(key) => format!("Send the key {:?} to the terminal", key),
+        Nop | DisableDefaultAssignment => return None,
+        Hide => "Hide (minimize) the window".to_string(),
+        Show => "Show the window".to_string(),
+        CloseCurrentTab { .. } => "Close the current tab".to_string(),
+        ReloadConfiguration => "Reload the configuration".to_string(),
+        MoveTabRelative(n) => format!("Move the tab to the {} position", relative(*n, "tab")),
+        MoveTab(n) => format!("Move the tab to position {}", n + 1),
+        ScrollByPage(n) if *n < 0 => format!("Scroll up {} page(s)", -n),
+        ScrollByPage(n) => format!("Scroll down {} page(s)", n),
+        ScrollByLine(n) if *n < 0 => format!("Scroll up {} line(s)", -n),
+        ScrollByLine(n) => format!("Scroll down {} line(s)", n),
+        ScrollToPrompt(n) if *n < 0 => "Scroll to the previous prompt".to_string(),
+        ScrollToPrompt(_) => "Scroll to the next prompt".to_string(),
+        ScrollToTop => "Scroll to the top of the scrollback".to_string(),
+        ScrollToBottom => "Scroll to the bottom".to_string(),
+        ShowTabNavigator => "Show the tab navigator".to_string(),
+        ShowDebugOverlay => "Show the debug overlay".to_string(),
+        HideApplication => "Hide the application".to_string(),
+        QuitApplication => "Quit wezterm".to_string(),
+        SpawnCommandInNewTab(spawn) => format!("Run {} in a new tab", spawn_label(spawn)),
+        SpawnCommandInNewWindow(spawn) => {
+            format!("Run {} in a new window", spawn_label(spawn))
+        }
+        SplitHorizontal(spawn) if spawn.args.is_none() && spawn.label.is_none() => {
+            "Split the pane horizontally".to_string()
+        }
+        SplitHorizontal(spawn) => {
+            format!("Split the pane horizontally and run {}", spawn_label(spawn))
+        }
+        SplitVertical(spawn) if spawn.args.is_none() && spawn.label.is_none() => {
+            "Split the pane vertically".to_string()
+        }
+        SplitVertical(spawn) => {
+            format!("Split the pane vertically and run {}", spawn_label(spawn))
+        }
+        ShowLauncher => "Show the launcher".to_string(),
+        ClearScrollback(ScrollbackEraseMode::ScrollbackOnly) => "Clear the scrollback".to_string(),
+        ClearScrollback(ScrollbackEraseMode::ScrollbackAndViewport) => {
+            "Clear the scrollback and the viewport".to_string()
+        }
+        Search(pattern) if pattern.is_empty() => "Search the scrollback".to_string(),
+        Search(pattern) => format!("Search the scrollback for {:?}", &**pattern),
+        ActivateCopyMode => "Activate copy mode".to_string(),
+        // These act on the mouse position and are not useful
+        // to launch from the keyboard
+        SelectTextAtMouseCursor(_)
+        | ExtendSelectionToMouseCursor(_)
+        | OpenLinkAtMouseCursor
+        | CompleteSelection(_)
+        | CompleteSelectionOrOpenLinkAtMouseCursor(_)
+        | StartWindowDrag => return None,
+        AdjustPaneSize(direction, amount) => format!(
+            "Resize the pane {} by {} cell(s)",
+            direction_label(*direction),
+            amount
+        ),
+        ActivatePaneDirection(direction) => {
+            format!("Activate the {} pane", direction_label(*direction))
+        }
+        TogglePaneZoomState => "Toggle the zoom state of the pane".to_string(),
+        CloseCurrentPane { .. } => "Close the current pane".to_string(),
+        EmitEvent(name) => match name.strip_prefix("user-defined-") {
+            Some(idx) => format!("Run user defined action callback {}", idx),
+            None => format!("Emit the `{}` event", name),
+        },
+        QuickSelect => "Enter quick select mode".to_string(),
+        QuickSelectArgs(args) if !args.label.is_empty() => {
+            format!("Enter quick select mode to {}", args.label)
+        }
+        QuickSelectArgs(_) => "Enter quick select mode with custom patterns".to_string(),
+        Multiple(actions) => {
+            let actions: Vec<String> = actions.iter().filter_map(describe).collect();
+            if actions.is_empty() {
+                return None;
+            }
+            actions.join(", then ")
+        }
+        SwapActivePaneDirection { direction, .. } => format!(
+            "Swap the pane with the {} pane",
+            direction_label(*direction)
+        ),
+        RotatePanes(RotationDirection::Clockwise) => "Rotate the panes clockwise".to_string(),
+        RotatePanes(RotationDirection::CounterClockwise) => {
+            "Rotate the panes counter-clockwise".to_string()
+        }
+        MovePaneToNewTab => "Move the pane to a new tab".to_string(),
+        MovePaneToNewWindow => "Move the pane to a new window".to_string(),
+        JoinPaneIntoTab { tab, direction } => format!(
+            "Move the pane into tab {}, placing it {}",
+            tab + 1,
+            direction_label(*direction)
+        ),
+        ActivateKeyTable { name, .. } => format!("Activate the `{}` key table", name),
+        PopKeyTable => "Deactivate the current key table".to_string(),
+        SwitchToWorkspace {
+            name: Some(name), ..
+        } => format!("Switch to workspace `{}`", name),
+        SwitchToWorkspace { name: None, .. } => "Create a new workspace".to_string(),
+        SwitchWorkspaceRelative(n) => format!("Switch to the {}", relative(*n, "workspace")),
+        ActivateCommandPalette => return None,
+        ToggleBroadcastInput { scope } => format!(
+            "Toggle broadcasting input to {}",
+            match scope {
+                BroadcastScope::Tab => "all panes in the tab",
+                BroadcastScope::Window => "all panes in the window",
+                BroadcastScope::Marked => "the marked panes",
+            }
+        ),
+        TogglePaneBroadcastMark => "Mark or unmark the pane for broadcast input".to_string(),
+    })
+}
@@ -2,14 +2,18 @@ use crate::TermWindow;
 use ::window::*;
 use anyhow::Error;
 pub use config::FrontEndSelection;
+use mux::window::WindowId as MuxWindowId;
 use mux::{Mux, MuxNotification};
 use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::rc::Rc;
 use wezterm_term::Alert;
 use wezterm_toast_notification::*;
 
 pub struct GuiFrontEnd {
     connection: Rc<Connection>,
+    /// The mux windows for which we have created a TermWindow
+    known_windows: RefCell<BTreeSet<MuxWindowId>>,
 }
 
 impl Drop for GuiFrontEnd {
@@ -21,28 +25,44 @@ impl Drop for GuiFrontEnd {
 impl GuiFrontEnd {
     pub fn try_new() -> anyhow::Result<Rc<GuiFrontEnd>> {
         let connection = Connection::init()?;
-        let front_end = Rc::new(GuiFrontEnd { connection });
+        let front_end = Rc::new(GuiFrontEnd {
+            connection,
+            known_windows: RefCell::new(BTreeSet::new()),
+        });
         let mux = Mux::get().expect("mux started and running on main thread");
         let fe = Rc::downgrade(&front_end);
         mux.subscribe(move |n| {
-            if let Some(_fe) = fe.upgrade() {
+            if let Some(fe) = fe.upgrade() {
                 match n {
                     MuxNotification::WindowCreated(mux_window_id) => {
+                        // We can't change the active workspace from inside
+                        // this callback, so defer the work
+                        let fe = Rc::downgrade(&fe);
                         promise::spawn::spawn(async move {
-                            if let Err(err) = TermWindow::new_window(mux_window_id).await {
-                                log::error!("Failed to create window: {:#}", err);
-                                let mux = Mux::get().expect("subscribe to trigger on main thread");
-                                mux.kill_window(mux_window_id);
+                            if let Some(fe) = fe.upgrade() {
+                                fe.window_created(mux_window_id);
+                            }
+                        })
+                        .detach();
+                    }
+                    MuxNotification::ActiveWorkspaceChanged(_) => {
+                        fe.reconcile_workspace();
+                    }
+                    MuxNotification::WindowRemoved(mux_window_id) => {
+                        fe.known_windows.borrow_mut().remove(&mux_window_id);
+                        let fe = Rc::downgrade(&fe);
+                        promise::spawn::spawn(async move {
+                            if let Some(fe) = fe.upgrade() {
+                                fe.switch_away_from_empty_workspace();
                             }
-                            anyhow::Result::<()>::Ok(())
                         })
                         .detach();
                     }
-                    MuxNotification::WindowRemoved(_) => {}
                     MuxNotification::PaneRemoved(_) => {}
                     MuxNotification::WindowInvalidated(_) => {}
                     MuxNotification::PaneOutput(_) => {}
                     MuxNotification::PaneAdded(_) => {}
+                    MuxNotification::FileTransferStarted(_) => {}
                     MuxNotification::Alert {
                         pane_id: _,
                         alert:
@@ -88,6 +108,74 @@ impl GuiFrontEnd {
         Ok(front_end)
     }
 
+    /// Called when a new mux window has been created.
+    /// Windows are only shown when they belong to the active workspace.
+    /// If the active workspace has no windows, then we switch to the
+    /// workspace of the new window; that is the typical situation when
+    /// attaching to a mux server whose windows belong to some other
+    /// workspace.
+    fn window_created(&self, mux_window_id: MuxWindowId) {
+        let mux = Mux::get().expect("to be called on main thread");
+        let workspace = match mux.get_window(mux_window_id) {
+            Some(window) => window.get_workspace().to_string(),
+            None => return,
+        };
+        let active_workspace = mux.active_workspace();
+        if workspace != active_workspace
+            && mux.iter_windows_in_workspace(&active_workspace).is_empty()
+        {
+            mux.set_active_workspace(&workspace);
+        }
+        self.reconcile_workspace();
+    }
+
+    /// If the active workspace no longer has any windows, but some
+    /// other workspace does, then switch to that other workspace so
+    /// that we don't leave the user without any visible windows.
+    fn switch_away_from_empty_workspace(&self) {
+        let mux = Mux::get().expect("to be called on main thread");
+        let active_workspace = mux.active_workspace();
+        if !mux.iter_windows_in_workspace(&active_workspace).is_empty() {
+            return;
+        }
+        if let Some(workspace) = mux
+            .iter_workspaces()
+            .into_iter()
+            .find(|w| !mux.iter_windows_in_workspace(w).is_empty())
+        {
+            mux.set_active_workspace(&workspace);
+        }
+    }
+
+    /// Ensures that there is a TermWindow for each of the mux windows
+    /// in the active workspace.  TermWindows for the windows in other
+    /// workspaces close themselves when the active workspace changes.
+    fn reconcile_workspace(&self) {
+        let mux = Mux::get().expect("to be called on main thread");
+        let workspace = mux.active_workspace();
+        let mut known_windows = self.known_windows.borrow_mut();
+
+        known_windows.retain(|&mux_window_id| match mux.get_window(mux_window_id) {
+            Some(window) => window.get_workspace() == workspace,
+            None => false,
+        });
+
+        for mux_window_id in mux.iter_windows_in_workspace(&workspace) {
+            if !known_windows.insert(mux_window_id) {
+                continue;
+            }
+            promise::spawn::spawn(async move {
+                if let Err(err) = TermWindow::new_window(mux_window_id).await {
+                    log::error!("Failed to create window: {:#}", err);
+                    let mux = Mux::get().expect("to be called on main thread");
+                    mux.kill_window(mux_window_id);
+                }
+                anyhow::Result::<()>::Ok(())
+            })
+            .detach();
+        }
+    }
+
     pub fn run_forever(&self) -> anyhow::Result<()> {
         self.connection.run_message_loop()
     }
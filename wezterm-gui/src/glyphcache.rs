@@ -19,9 +19,9 @@ use std::collections::HashMap;
 use std::convert::TryInto;
 use std::rc::Rc;
 use std::sync::{Arc, MutexGuard};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use termwiz::color::RgbColor;
-use termwiz::image::{ImageData, ImageDataType};
+use termwiz::image::{AnimationState, ImageAnimation, ImageData, ImageDataType};
 use termwiz::surface::CursorShape;
 use wezterm_font::units::*;
 use wezterm_font::{FontConfiguration, GlyphInfo, LoadedFont};
@@ -210,6 +210,11 @@ pub struct DecodedImage {
     frame_start: Instant,
     current_frame: usize,
     image: Arc<ImageData>,
+    /// The ImageAnimation generation that current_frame was synced with
+    generation: usize,
+    /// The playback state observed by the most recent render
+    state: AnimationState,
+    loops_completed: u32,
 }
 
 impl DecodedImage {
@@ -220,34 +225,86 @@ impl DecodedImage {
             frame_start: Instant::now(),
             current_frame: 0,
             image: Arc::new(image),
+            generation: 0,
+            state: AnimationState::Running,
+            loops_completed: 0,
         }
     }
 
     fn load(image_data: &Arc<ImageData>) -> Self {
-        match &*image_data.data() {
+        let anim = *image_data.animation();
+        let current_frame = match &*image_data.data() {
             ImageDataType::EncodedFile(_) => {
                 log::warn!("Unexpected ImageDataType::EncodedFile; either file is unreadable or we missed a .decode call somewhere");
-                Self::placeholder()
+                return Self::placeholder();
             }
             ImageDataType::AnimRgba8 { durations, .. } => {
-                let current_frame = if durations.len() > 1 && durations[0].as_millis() == 0 {
+                if anim.generation != 0 {
+                    // The model explicitly selected a frame
+                    anim.current_frame.min(durations.len() - 1)
+                } else if durations.len() > 1 && durations[0].as_millis() == 0 {
                     // Skip possible 0-duration root frame
                     1
                 } else {
                     0
-                };
-                Self {
-                    frame_start: Instant::now(),
-                    current_frame,
-                    image: Arc::clone(image_data),
                 }
             }
+            _ => 0,
+        };
+        Self {
+            frame_start: Instant::now(),
+            current_frame,
+            image: Arc::clone(image_data),
+            generation: anim.generation,
+            state: anim.state,
+            loops_completed: 0,
+        }
+    }
 
-            _ => Self {
-                frame_start: Instant::now(),
-                current_frame: 0,
-                image: Arc::clone(image_data),
-            },
+    /// Reconcile our playback position with changes made to the
+    /// animation state by the terminal model since the last render
+    fn sync_animation(&mut self, anim: &ImageAnimation, num_frames: usize) {
+        if self.generation != anim.generation {
+            self.generation = anim.generation;
+            self.current_frame = anim.current_frame;
+            self.frame_start = Instant::now();
+            self.loops_completed = 0;
+        }
+        if self.state != anim.state {
+            if self.state == AnimationState::Stopped {
+                // Time the current frame from when we resumed,
+                // rather than from when we stopped
+                self.frame_start = Instant::now();
+                self.loops_completed = 0;
+            }
+            self.state = anim.state;
+        }
+        // Frames may have been deleted since the last render
+        self.current_frame = self.current_frame.min(num_frames.saturating_sub(1));
+    }
+
+    /// Returns the index of the frame that follows the current frame,
+    /// or None if playback should hold on the current frame
+    fn next_frame(&mut self, anim: &ImageAnimation, durations: &[Duration]) -> Option<usize> {
+        let next = self.current_frame + 1;
+        if next < durations.len() {
+            return Some(next);
+        }
+        if anim.state == AnimationState::Loading {
+            // Wait for more frames to be added
+            return None;
+        }
+        if let Some(loops) = anim.loops {
+            if self.loops_completed + 1 >= loops {
+                return None;
+            }
+        }
+        self.loops_completed += 1;
+        // Skip potential 0-duration root frame
+        if durations[0].as_millis() == 0 && durations.len() > 1 {
+            Some(1)
+        } else {
+            Some(0)
         }
     }
 }
@@ -589,8 +646,10 @@ impl<T: Texture2d> GlyphCache<T> {
         decoded: &mut DecodedImage,
         padding: Option<usize>,
     ) -> anyhow::Result<(Sprite<T>, Option<Instant>)> {
+        let anim = *decoded.image.animation();
+        let image = Arc::clone(&decoded.image);
         let mut handle = DecodedImageHandle {
-            h: decoded.image.data(),
+            h: image.data(),
             current_frame: decoded.current_frame,
         };
         match &*handle.h {
@@ -609,26 +668,24 @@ impl<T: Texture2d> GlyphCache<T> {
                 durations,
                 ..
             } => {
+                decoded.sync_animation(&anim, frames.len());
+                handle.current_frame = decoded.current_frame;
+
                 let mut next = None;
-                if frames.len() > 1 {
+                if frames.len() > 1 && anim.state != AnimationState::Stopped {
                     let now = Instant::now();
-                    let mut next_due = decoded.frame_start + durations[decoded.current_frame];
+                    let next_due = decoded.frame_start + durations[decoded.current_frame];
                     if now >= next_due {
-                        // Advance to next frame
-                        decoded.current_frame += 1;
-                        if decoded.current_frame >= frames.len() {
-                            decoded.current_frame = 0;
-                            // Skip potential 0-duration root frame
-                            if durations[0].as_millis() == 0 && frames.len() > 1 {
-                                decoded.current_frame += 1;
-                            }
+                        if let Some(frame) = decoded.next_frame(&anim, durations) {
+                            // Advance to next frame
+                            decoded.current_frame = frame;
+                            decoded.frame_start = now;
+                            handle.current_frame = frame;
+                            next.replace(now + durations[frame]);
                         }
-                        decoded.frame_start = now;
-                        next_due = decoded.frame_start + durations[decoded.current_frame];
-                        handle.current_frame = decoded.current_frame;
+                    } else {
+                        next.replace(next_due);
                     }
-
-                    next.replace(next_due);
                 }
 
                 let hash = hashes[decoded.current_frame];
@@ -641,10 +698,7 @@ impl<T: Texture2d> GlyphCache<T> {
 
                 frame_cache.insert(hash, sprite.clone());
 
-                return Ok((
-                    sprite,
-                    Some(decoded.frame_start + durations[decoded.current_frame]),
-                ));
+                return Ok((sprite, next));
             }
             ImageDataType::EncodedFile(_) => unreachable!(),
         }
@@ -22,6 +22,7 @@ use wezterm_ssh::*;
 use wezterm_toast_notification::*;
 
 mod cache;
+mod commands;
 mod customglyph;
 mod download;
 mod frontend;
@@ -113,6 +114,19 @@ async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
         .ok_or_else(|| anyhow::anyhow!("no host component somehow"))?;
     let port = fields.next();
 
+    // Overrides are applied prior to resolving the config for the
+    // host, so that tokens such as %h and %p in ProxyCommand
+    // reflect them
+    if let Some(username) = &opts.user_at_host_and_port.username {
+        ssh_config.set_option("user", username);
+    }
+    if let Some(port) = port {
+        ssh_config.set_option("port", port);
+    }
+    for (k, v) in &opts.config_override {
+        ssh_config.set_option(k, v);
+    }
+
     let mut ssh_config = ssh_config.for_host(host);
     ssh_config.insert(
         "wezterm_ssh_backend".to_string(),
@@ -126,16 +140,6 @@ async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
         ssh_config.insert("wezterm_ssh_verbose".to_string(), "true".to_string());
     }
 
-    if let Some(username) = &opts.user_at_host_and_port.username {
-        ssh_config.insert("user".to_string(), username.to_string());
-    }
-    if let Some(port) = port {
-        ssh_config.insert("port".to_string(), port.to_string());
-    }
-    for (k, v) in opts.config_override {
-        ssh_config.insert(k.to_lowercase().to_string(), v);
-    }
-
     let _gui = front_end().unwrap();
 
     let cmd = if !opts.prog.is_empty() {
@@ -159,7 +163,7 @@ async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
     let local_domain: Arc<dyn Domain> = Arc::new(LocalDomain::new("local")?);
     mux.add_domain(&local_domain);
 
-    let window_id = mux.new_empty_window();
+    let window_id = mux.new_empty_window(None);
     let _tab = domain
         .spawn(config.initial_size(), cmd, None, *window_id)
         .await?;
@@ -177,6 +181,10 @@ fn run_ssh(opts: SshCommand) -> anyhow::Result<()> {
 
     let gui = crate::frontend::try_new()?;
 
+    // Allow `wezterm cli` to reach our panes; in particular, this
+    // is needed for `wezterm cli upload` and `wezterm cli download`
+    start_gui_mux_listener();
+
     // Initiate an ssh connection; since that is a blocking process with
     // callbacks, we have to run it in another thread
     promise::spawn::spawn(async {
@@ -206,7 +214,7 @@ fn run_serial(config: config::ConfigHandle, opts: &SerialCommand) -> anyhow::Res
     block_on(domain.attach())?; // FIXME: blocking
 
     {
-        let window_id = mux.new_empty_window();
+        let window_id = mux.new_empty_window(None);
         // FIXME: blocking
         let _tab = block_on(domain.spawn(config.initial_size(), None, None, *window_id))?;
     }
@@ -297,7 +305,7 @@ async fn spawn_tab_in_default_domain_if_mux_is_empty(
     }
 
     let config = config::configuration();
-    let window_id = mux.new_empty_window();
+    let window_id = mux.new_empty_window(None);
     let _tab = domain
         .spawn(config.initial_size(), cmd, None, *window_id)
         .await?;
@@ -328,19 +336,33 @@ async fn update_mux_domains(config: &ConfigHandle, do_auto_connect: bool) -> any
     Ok(())
 }
 
+async fn trigger_gui_startup(lua: Option<Rc<mlua::Lua>>) -> anyhow::Result<()> {
+    if let Some(lua) = lua {
+        let args = lua.pack_multi(())?;
+        config::lua::emit_event(&lua, ("gui-startup".to_string(), args)).await?;
+    }
+    Ok(())
+}
+
 async fn async_run_terminal_gui(
     cmd: Option<CommandBuilder>,
     do_auto_connect: bool,
 ) -> anyhow::Result<()> {
     update_mux_domains(&config::configuration(), do_auto_connect).await?;
-    spawn_tab_in_default_domain_if_mux_is_empty(cmd).await
-}
 
-fn run_terminal_gui(opts: StartCommand) -> anyhow::Result<()> {
-    if let Some(cls) = opts.class.as_ref() {
-        crate::set_window_class(cls);
+    // Give the config a chance to set up windows via the wezterm.mux
+    // module; if it does so then we won't spawn the default window
+    if let Err(err) = config::with_lua_config_on_main_thread(trigger_gui_startup).await {
+        log::error!("while processing gui-startup event: {:#}", err);
     }
 
+    spawn_tab_in_default_domain_if_mux_is_empty(cmd).await
+}
+
+/// Starts a mux listener on a socket that is specific to this gui
+/// process, so that `wezterm cli` can manipulate its panes, and
+/// exports its path to the panes that we spawn
+fn start_gui_mux_listener() {
     let unix_socket_path =
         config::RUNTIME_DIR.join(format!("gui-sock-{}", unsafe { libc::getpid() }));
     std::env::set_var("WEZTERM_UNIX_SOCKET", unix_socket_path.clone());
@@ -355,6 +377,14 @@ fn run_terminal_gui(opts: StartCommand) -> anyhow::Result<()> {
             listener.run();
         });
     }
+}
+
+fn run_terminal_gui(opts: StartCommand) -> anyhow::Result<()> {
+    if let Some(cls) = opts.class.as_ref() {
+        crate::set_window_class(cls);
+    }
+
+    start_gui_mux_listener();
 
     let run = move || -> anyhow::Result<()> {
         let need_builder = !opts.prog.is_empty() || opts.cwd.is_some();
@@ -413,7 +443,9 @@ fn run_terminal_gui(opts: StartCommand) -> anyhow::Result<()> {
         });
 
         maybe_show_configuration_error_window();
-        gui.run_forever()
+        let res = gui.run_forever();
+        maybe_autosave_session();
+        res
     };
 
     let res = run();
@@ -423,6 +455,16 @@ fn run_terminal_gui(opts: StartCommand) -> anyhow::Result<()> {
     res
 }
 
+/// Saves the layout of the mux to the default session file
+/// if `autosave_session_on_exit` is enabled.
+/// Closing the last window saves the session before the mux is emptied;
+/// this catches the case where the gui quits with windows still open.
+fn maybe_autosave_session() {
+    if let Some(mux) = Mux::get() {
+        mux::session::maybe_autosave(&mux);
+    }
+}
+
 fn fatal_toast_notification(title: &str, message: &str) {
     persistent_toast_notification(title, message);
     // We need a short delay otherwise the notification
@@ -469,6 +511,12 @@ fn maybe_show_configuration_error_window() {
     }
 }
 
+fn print_variation_axes(font: &wezterm_font::parser::ParsedFont) {
+    for axis in font.variation_axes() {
+        println!("    -- Variation axis: {}", axis);
+    }
+}
+
 pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyhow::Result<()> {
     use wezterm_font::parser::ParsedFont;
 
@@ -575,6 +623,7 @@ pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyho
         );
         for font in font_dirs {
             println!("{} -- {}", font.lua_name(), font.handle.diagnostic_string());
+            print_variation_axes(&font);
         }
 
         match font_config.list_system_fonts() {
@@ -596,6 +645,7 @@ pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyho
                         font.handle.diagnostic_string(),
                         pixel_sizes
                     );
+                    print_variation_axes(&font);
                 }
             }
             Err(err) => log::error!("Unable to list system fonts: {}", err),
@@ -647,6 +697,7 @@ fn run() -> anyhow::Result<()> {
     stats::Stats::init()?;
     let _saver = umask::UmaskSaver::new();
 
+    config::assign_lua_factory(scripting::make_lua_context);
     config::common_init(
         opts.config_file.as_ref(),
         &opts.config_override,
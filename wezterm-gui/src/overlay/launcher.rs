@@ -35,6 +35,10 @@ enum Entry {
         label: String,
         domain: DomainId,
     },
+    SwitchWorkspace {
+        label: String,
+        workspace: String,
+    },
 }
 
 impl Entry {
@@ -42,6 +46,7 @@ impl Entry {
         match self {
             Entry::Spawn { label, .. } => label,
             Entry::Attach { label, .. } => label,
+            Entry::SwitchWorkspace { label, .. } => label,
         }
     }
 }
@@ -105,6 +110,8 @@ pub fn launcher(
     mut term: TermWizTerminal,
     mux_window_id: WindowId,
     domains: Vec<(DomainId, String, DomainState, String)>,
+    workspaces: Vec<String>,
+    active_workspace: String,
     clipboard: ClipboardHelper,
     size: PtySize,
     term_config: Arc<TermConfig>,
@@ -165,6 +172,16 @@ pub fn launcher(
         entries.push(entry);
     }
 
+    for workspace in workspaces {
+        if workspace == active_workspace {
+            continue;
+        }
+        entries.push(Entry::SwitchWorkspace {
+            label: format!("Switch to workspace `{}`", workspace),
+            workspace,
+        });
+    }
+
     fn render(
         active_idx: usize,
         entries: &[Entry],
@@ -236,6 +253,13 @@ pub fn launcher(
                 })
                 .detach();
             }
+            Entry::SwitchWorkspace { workspace, .. } => {
+                promise::spawn::spawn_into_main_thread(async move {
+                    let mux = Mux::get().unwrap();
+                    mux.set_active_workspace(&workspace);
+                })
+                .detach();
+            }
         }
     }
 
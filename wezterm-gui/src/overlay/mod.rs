@@ -10,9 +10,11 @@ mod confirm_close_pane;
 mod copy;
 mod debug;
 mod launcher;
+mod palette;
 mod quickselect;
 mod search;
 mod tabnavigator;
+mod transfer;
 
 pub use confirm_close_pane::confirm_close_pane;
 pub use confirm_close_pane::confirm_close_tab;
@@ -21,9 +23,11 @@ pub use confirm_close_pane::confirm_quit_program;
 pub use copy::CopyOverlay;
 pub use debug::show_debug_overlay;
 pub use launcher::launcher;
+pub use palette::command_palette;
 pub use quickselect::QuickSelectOverlay;
 pub use search::SearchOverlay;
 pub use tabnavigator::tab_navigator;
+pub use transfer::transfer_progress;
 
 pub fn start_overlay<T, F>(
     term_window: &TermWindow,
@@ -0,0 +1,362 @@
+use crate::commands::CommandDef;
+use config::keyassignment::KeyAssignment;
+use mux::tab::TabId;
+use mux::termwiztermtab::TermWizTerminal;
+use termwiz::cell::{AttributeChange, CellAttributes, Intensity};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, CursorVisibility, Position};
+use termwiz::terminal::Terminal;
+
+/// The number of rows that precede the list of commands
+const HEADER_ROWS: usize = 2;
+
+/// Computes a fuzzy match score for `query` against `text`.
+/// Each character of the query must appear in the text, in order,
+/// but not necessarily adjacent to each other.
+/// Runs of consecutive characters and matches at the start of words
+/// score more highly.
+/// Returns None if the text doesn't match.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut text_idx = 0;
+    let mut prior_match: Option<usize> = None;
+
+    for q in query.chars().flat_map(char::to_lowercase) {
+        if q.is_whitespace() {
+            continue;
+        }
+        let found = text[text_idx..].iter().position(|&c| c == q)? + text_idx;
+
+        score += 1;
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        match prior_match {
+            Some(prior) if prior + 1 == found => score += 5,
+            Some(prior) => score -= (found - prior - 1).min(3) as i64,
+            None => score -= found.min(3) as i64,
+        }
+
+        prior_match = Some(found);
+        text_idx = found + 1;
+    }
+
+    Some(score)
+}
+
+/// Returns the indices of the commands that match `query`,
+/// ordered from best to worst match
+fn filter_commands(commands: &[CommandDef], query: &str) -> Vec<usize> {
+    let mut matches: Vec<(i64, usize)> = commands
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, cmd)| {
+            let score = fuzzy_score(query, &cmd.brief)
+                .or_else(|| fuzzy_score(query, &cmd.key_label()).map(|s| s - 100))?;
+            Some((score, idx))
+        })
+        .collect();
+    // Sort by descending score; the sort is stable so equal scores
+    // retain the alphabetical order of the commands
+    matches.sort_by(|a, b| b.0.cmp(&a.0));
+    matches.into_iter().map(|(_, idx)| idx).collect()
+}
+
+struct State {
+    commands: Vec<CommandDef>,
+    query: String,
+    matches: Vec<usize>,
+    selected: usize,
+    top_row: usize,
+}
+
+impl State {
+    fn new(commands: Vec<CommandDef>) -> Self {
+        let matches = filter_commands(&commands, "");
+        Self {
+            commands,
+            query: String::new(),
+            matches,
+            selected: 0,
+            top_row: 0,
+        }
+    }
+
+    fn update_filter(&mut self) {
+        self.matches = filter_commands(&self.commands, &self.query);
+        self.selected = 0;
+        self.top_row = 0;
+    }
+
+    fn move_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    fn move_down(&mut self) {
+        self.selected = (self.selected + 1).min(self.matches.len().saturating_sub(1));
+    }
+
+    fn selected_action(&self) -> Option<KeyAssignment> {
+        self.matches
+            .get(self.selected)
+            .map(|&idx| self.commands[idx].action.clone())
+    }
+
+    fn render(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let size = term.get_screen_size()?;
+        let max_rows = size.rows.saturating_sub(HEADER_ROWS).max(1);
+
+        if self.selected < self.top_row {
+            self.top_row = self.selected;
+        } else if self.selected >= self.top_row + max_rows {
+            self.top_row = self.selected + 1 - max_rows;
+        }
+
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(
+                "Type to filter, select a command and press Enter to run it.  Press Escape to cancel\r\n"
+                    .to_string(),
+            ),
+            AttributeChange::Intensity(Intensity::Bold).into(),
+            Change::Text(format!("> {}\r\n", self.query)),
+            Change::AllAttributes(CellAttributes::default()),
+        ];
+
+        for (row, &cmd_idx) in self
+            .matches
+            .iter()
+            .enumerate()
+            .skip(self.top_row)
+            .take(max_rows)
+        {
+            let cmd = &self.commands[cmd_idx];
+            if row == self.selected {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+
+            let keys = cmd.key_label();
+            let brief: String = cmd
+                .brief
+                .chars()
+                .take(size.cols.saturating_sub(1))
+                .collect();
+            let brief_len = brief.chars().count() + 1;
+            let pad = size
+                .cols
+                .saturating_sub(brief_len + keys.chars().count() + 1);
+            let mut line = format!(" {}", brief);
+            if !keys.is_empty() && pad > 0 {
+                line.push_str(&" ".repeat(pad));
+                line.push_str(&keys);
+            }
+            changes.push(Change::Text(line));
+
+            if row == self.selected {
+                changes.push(AttributeChange::Reverse(false).into());
+            }
+            changes.push(Change::Text("\r\n".to_string()));
+        }
+
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(2 + self.query.chars().count()),
+            y: Position::Absolute(1),
+        });
+
+        term.render(&changes)?;
+        term.flush()
+    }
+}
+
+/// Shows the command palette overlay.
+/// Returns the action that was selected by the user, or None if
+/// the palette was cancelled.
+pub fn command_palette(
+    _tab_id: TabId,
+    mut term: TermWizTerminal,
+    commands: Vec<CommandDef>,
+) -> anyhow::Result<Option<KeyAssignment>> {
+    let mut state = State::new(commands);
+
+    term.set_raw_mode()?;
+    term.render(&[
+        Change::Title("Command Palette".to_string()),
+        Change::CursorVisibility(CursorVisibility::Visible),
+    ])?;
+    state.render(&mut term)?;
+
+    while let Ok(Some(event)) = term.poll_input(None) {
+        match event {
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::UpArrow,
+                ..
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('P'),
+                modifiers: Modifiers::CTRL,
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('p'),
+                modifiers: Modifiers::CTRL,
+            }) => {
+                state.move_up();
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::DownArrow,
+                ..
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('N'),
+                modifiers: Modifiers::CTRL,
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('n'),
+                modifiers: Modifiers::CTRL,
+            }) => {
+                state.move_down();
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }) => {
+                return Ok(None);
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Backspace,
+                ..
+            }) => {
+                if state.query.pop().is_some() {
+                    state.update_filter();
+                }
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('U'),
+                modifiers: Modifiers::CTRL,
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('u'),
+                modifiers: Modifiers::CTRL,
+            }) => {
+                state.query.clear();
+                state.update_filter();
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Enter,
+                ..
+            }) => {
+                return Ok(state.selected_action());
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::NONE,
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::Char(c),
+                modifiers: Modifiers::SHIFT,
+            }) => {
+                state.query.push(c);
+                state.update_filter();
+            }
+            InputEvent::Paste(text) => {
+                state.query.push_str(&text);
+                state.update_filter();
+            }
+            InputEvent::Mouse(MouseEvent {
+                y, mouse_buttons, ..
+            }) => {
+                let y = y as usize;
+                if y >= HEADER_ROWS && y - HEADER_ROWS + state.top_row < state.matches.len() {
+                    state.selected = y - HEADER_ROWS + state.top_row;
+                    if mouse_buttons == MouseButtons::LEFT {
+                        return Ok(state.selected_action());
+                    }
+                }
+                if mouse_buttons != MouseButtons::NONE && mouse_buttons != MouseButtons::LEFT {
+                    // Treat any other mouse button as cancel
+                    return Ok(None);
+                }
+            }
+            _ => {}
+        }
+        state.render(&mut term)?;
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command(brief: &str, action: KeyAssignment) -> CommandDef {
+        CommandDef {
+            brief: brief.to_string(),
+            keys: vec![],
+            action,
+        }
+    }
+
+    #[test]
+    fn score() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert!(fuzzy_score("spl", "Split the pane").is_some());
+        assert!(fuzzy_score("stp", "Split the pane").is_some());
+        assert!(fuzzy_score("SPLIT", "split the pane").is_some());
+        assert_eq!(fuzzy_score("pls", "Split the pane"), None);
+        assert_eq!(fuzzy_score("splitx", "Split the pane"), None);
+
+        // Contiguous and word-start matches rank higher
+        assert!(fuzzy_score("new", "New tab") > fuzzy_score("new", "Run a new tab"));
+        assert!(fuzzy_score("tab", "New tab") > fuzzy_score("tab", "Toggle the tab bar"));
+    }
+
+    #[test]
+    fn filter() {
+        let commands = vec![
+            command("Activate copy mode", KeyAssignment::ActivateCopyMode),
+            command("New tab", KeyAssignment::SpawnWindow),
+            command("New window", KeyAssignment::SpawnWindow),
+            command("Show the launcher", KeyAssignment::ShowLauncher),
+        ];
+
+        assert_eq!(filter_commands(&commands, ""), vec![0, 1, 2, 3]);
+        assert_eq!(filter_commands(&commands, "win"), vec![2]);
+        assert_eq!(filter_commands(&commands, "new"), vec![1, 2]);
+        assert_eq!(filter_commands(&commands, "launch"), vec![3]);
+        assert_eq!(filter_commands(&commands, "zzz"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn selection() {
+        let mut state = State::new(vec![
+            command("Activate copy mode", KeyAssignment::ActivateCopyMode),
+            command("Show the launcher", KeyAssignment::ShowLauncher),
+        ]);
+        assert_eq!(
+            state.selected_action(),
+            Some(KeyAssignment::ActivateCopyMode)
+        );
+        state.move_down();
+        state.move_down();
+        assert_eq!(state.selected_action(), Some(KeyAssignment::ShowLauncher));
+        state.move_up();
+        assert_eq!(
+            state.selected_action(),
+            Some(KeyAssignment::ActivateCopyMode)
+        );
+
+        state.query = "launch".to_string();
+        state.update_filter();
+        assert_eq!(state.selected_action(), Some(KeyAssignment::ShowLauncher));
+
+        state.query = "nothing matches this".to_string();
+        state.update_filter();
+        assert_eq!(state.selected_action(), None);
+    }
+}
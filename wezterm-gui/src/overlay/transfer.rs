@@ -0,0 +1,161 @@
+use mux::termwiztermtab::TermWizTerminal;
+use mux::transfer::{FileTransfer, TransferDirection, TransferProgress};
+use std::sync::Arc;
+use std::time::Duration;
+use termwiz::cell::{AttributeChange, Intensity};
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent};
+use termwiz::surface::{Change, CursorVisibility, Position};
+use termwiz::terminal::Terminal;
+
+/// How often the progress display is refreshed
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a successful result remains visible before
+/// the overlay dismisses itself
+const LINGER_DURATION: Duration = Duration::from_secs(2);
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit + 1 < UNITS.len() {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn progress_bar(width: usize, done: u64, total: u64) -> String {
+    let filled = if total == 0 {
+        width
+    } else {
+        ((done.min(total) as f64 / total as f64) * width as f64) as usize
+    };
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(width - filled))
+}
+
+fn render(
+    term: &mut TermWizTerminal,
+    direction: TransferDirection,
+    progress: &TransferProgress,
+) -> termwiz::Result<()> {
+    let size = term.get_screen_size()?;
+    let width = size.cols.saturating_sub(4).max(10);
+
+    let verb = match direction {
+        TransferDirection::Upload => "Uploading",
+        TransferDirection::Download => "Downloading",
+    };
+
+    let mut lines = vec![];
+    let status = match &progress.outcome {
+        None => format!(
+            "{} {}/{} files to {}",
+            verb, progress.files_done, progress.files_total, progress.destination
+        ),
+        Some(Ok(_)) => format!(
+            "Transferred {} files to {}",
+            progress.files_total, progress.destination
+        ),
+        Some(Err(_)) => format!("{} failed", verb),
+    };
+    lines.push((status, true));
+
+    match &progress.outcome {
+        None => {
+            lines.push((progress.current.clone(), false));
+            lines.push((
+                format!(
+                    "{} {} of {}",
+                    progress_bar(
+                        width.saturating_sub(30).max(10),
+                        progress.bytes_done,
+                        progress.bytes_total
+                    ),
+                    format_bytes(progress.bytes_done),
+                    format_bytes(progress.bytes_total)
+                ),
+                false,
+            ));
+            lines.push((String::new(), false));
+            lines.push(("Press ESC to cancel".to_string(), false));
+        }
+        Some(Ok(_)) => {
+            lines.push((format_bytes(progress.bytes_done), false));
+        }
+        Some(Err(err)) => {
+            for line in textwrap::fill(err, width).lines() {
+                lines.push((line.to_string(), false));
+            }
+            lines.push((String::new(), false));
+            lines.push(("Press any key to close".to_string(), false));
+        }
+    }
+
+    let mut changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorVisibility(CursorVisibility::Hidden),
+    ];
+    for (y, (line, bold)) in lines.into_iter().enumerate() {
+        changes.push(Change::CursorPosition {
+            x: Position::Absolute(2),
+            y: Position::Absolute(y + 1),
+        });
+        if bold {
+            changes.push(AttributeChange::Intensity(Intensity::Bold).into());
+        }
+        changes.push(Change::Text(line));
+        if bold {
+            changes.push(AttributeChange::Intensity(Intensity::Normal).into());
+        }
+    }
+
+    term.render(&changes)?;
+    term.flush()
+}
+
+/// Displays the progress of a file transfer.
+/// Pressing ESC while the transfer is running cancels it.
+/// Once it has completed successfully, the overlay dismisses itself
+/// after a brief delay; errors remain visible until a key is pressed.
+pub fn transfer_progress(
+    mut term: TermWizTerminal,
+    transfer: Arc<FileTransfer>,
+) -> anyhow::Result<()> {
+    term.set_raw_mode()?;
+
+    loop {
+        let progress = transfer.progress();
+        render(&mut term, transfer.direction(), &progress)?;
+
+        match &progress.outcome {
+            Some(Ok(_)) => {
+                // Wait for the linger period, but allow a key press
+                // to dismiss the overlay sooner
+                term.poll_input(Some(LINGER_DURATION))?;
+                return Ok(());
+            }
+            Some(Err(_)) => {
+                while let Ok(Some(event)) = term.poll_input(None) {
+                    if let InputEvent::Key(_) = event {
+                        return Ok(());
+                    }
+                }
+                return Ok(());
+            }
+            None => {
+                if let Some(InputEvent::Key(KeyEvent {
+                    key: KeyCode::Escape,
+                    ..
+                })) = term.poll_input(Some(REFRESH_INTERVAL))?
+                {
+                    transfer.cancel();
+                }
+            }
+        }
+    }
+}
@@ -7,6 +7,7 @@ use config::keyassignment::KeyAssignment;
 use luahelper::*;
 use mlua::{UserData, UserDataMethods};
 use mux::window::WindowId as MuxWindowId;
+use mux::Mux;
 use serde::*;
 use wezterm_toast_notification::ToastNotification;
 use window::{Connection, ConnectionOps, WindowOps, WindowState};
@@ -115,6 +116,16 @@ impl UserData for GuiWin {
 
             Ok((*config).clone())
         });
+        methods.add_async_method("active_key_table", |_, this, _: ()| async move {
+            let (tx, rx) = smol::channel::bounded(1);
+            this.window.notify(TermWindowNotif::GetActiveKeyTable(tx));
+            let name = rx
+                .recv()
+                .await
+                .map_err(|e| anyhow::anyhow!("{:#}", e))
+                .map_err(luaerr)?;
+            Ok(name)
+        });
         methods.add_async_method("get_config_overrides", |_, this, _: ()| async move {
             let (tx, rx) = smol::channel::bounded(1);
             this.window.notify(TermWindowNotif::GetConfigOverrides(tx));
@@ -132,5 +143,14 @@ impl UserData for GuiWin {
                 .notify(TermWindowNotif::SetConfigOverrides(value.0));
             Ok(())
         });
+        methods.add_method("get_connection_status", |_, this, _: ()| {
+            let mux = Mux::get()
+                .ok_or_else(|| anyhow::anyhow!("must be called on main thread"))
+                .map_err(luaerr)?;
+            Ok(mux
+                .get_active_tab_for_window(this.mux_window_id)
+                .and_then(|tab| tab.get_active_pane())
+                .and_then(|pane| pane.get_connection_status()))
+        });
     }
 }
@@ -1,6 +1,18 @@
+use mlua::Lua;
+use std::path::Path;
+
 pub mod guiwin;
+pub mod mux;
 pub mod pane;
 
 fn luaerr(err: anyhow::Error) -> mlua::Error {
     mlua::Error::external(err)
 }
+
+/// Extends the lua context built by the config crate with the
+/// modules that are only available in the GUI
+pub fn make_lua_context(config_file: &Path) -> anyhow::Result<Lua> {
+    let lua = config::lua::make_lua_context(config_file)?;
+    mux::register(&lua)?;
+    Ok(lua)
+}
@@ -0,0 +1,367 @@
+//! The `wezterm.mux` module exposes the windows, tabs and panes
+//! that are managed by the Mux to lua code
+use super::luaerr;
+use super::pane::PaneObject;
+use anyhow::anyhow;
+use config::keyassignment::SpawnTabDomain;
+use luahelper::impl_lua_conversion;
+use mlua::{Lua, Table, UserData, UserDataMethods};
+use mux::domain::{Domain, DomainState};
+use mux::pane::{Pane, PaneId};
+use mux::tab::{Tab, TabId};
+use mux::window::{Window, WindowId};
+use mux::Mux;
+use portable_pty::CommandBuilder;
+use serde::*;
+use std::cell::{Ref, RefMut};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+pub fn get_mux() -> mlua::Result<Rc<Mux>> {
+    Mux::get()
+        .ok_or_else(|| anyhow!("must be called on main thread"))
+        .map_err(luaerr)
+}
+
+/// Registers the `mux` module as `wezterm.mux`
+pub fn register(lua: &Lua) -> anyhow::Result<()> {
+    let mux_mod = lua.create_table()?;
+
+    mux_mod.set(
+        "all_windows",
+        lua.create_function(|_, _: ()| {
+            let mux = get_mux()?;
+            Ok(mux
+                .iter_windows()
+                .into_iter()
+                .map(MuxWindow)
+                .collect::<Vec<MuxWindow>>())
+        })?,
+    )?;
+
+    mux_mod.set(
+        "get_window",
+        lua.create_function(|_, window_id: WindowId| {
+            let mux = get_mux()?;
+            let window = MuxWindow(window_id);
+            let _resolved = window.resolve(&mux)?;
+            Ok(window)
+        })?,
+    )?;
+
+    mux_mod.set(
+        "get_tab",
+        lua.create_function(|_, tab_id: TabId| {
+            let mux = get_mux()?;
+            let tab = MuxTab(tab_id);
+            let _resolved = tab.resolve(&mux)?;
+            Ok(tab)
+        })?,
+    )?;
+
+    mux_mod.set(
+        "get_pane",
+        lua.create_function(|_, pane_id: PaneId| {
+            let pane = PaneObject { pane: pane_id };
+            let _resolved = pane.pane()?;
+            Ok(pane)
+        })?,
+    )?;
+
+    mux_mod.set(
+        "spawn_window",
+        lua.create_async_function(|_, spawn: Option<SpawnArgs>| async move {
+            spawn_window(spawn.unwrap_or_default()).await
+        })?,
+    )?;
+
+    mux_mod.set(
+        "get_active_workspace",
+        lua.create_function(|_, _: ()| {
+            let mux = get_mux()?;
+            Ok(mux.active_workspace())
+        })?,
+    )?;
+
+    mux_mod.set(
+        "set_active_workspace",
+        lua.create_function(|_, workspace: String| {
+            let mux = get_mux()?;
+            let workspaces = mux.iter_workspaces();
+            if workspaces.contains(&workspace) {
+                mux.set_active_workspace(&workspace);
+                Ok(())
+            } else {
+                Err(luaerr(anyhow!(
+                    "{:?} is not an existing workspace; known workspaces are {:?}",
+                    workspace,
+                    workspaces
+                )))
+            }
+        })?,
+    )?;
+
+    mux_mod.set(
+        "get_workspace_names",
+        lua.create_function(|_, _: ()| {
+            let mux = get_mux()?;
+            Ok(mux.iter_workspaces())
+        })?,
+    )?;
+
+    let package: Table = lua.globals().get("package")?;
+    let loaded: Table = package.get("loaded")?;
+    let wezterm_mod: Table = loaded.get("wezterm")?;
+    wezterm_mod.set("mux", mux_mod)?;
+
+    Ok(())
+}
+
+/// Describes where and what to spawn; this is the lua facing
+/// equivalent of `SpawnCommand`
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SpawnArgs {
+    #[serde(default)]
+    pub domain: SpawnTabDomain,
+    pub args: Option<Vec<String>>,
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub set_environment_variables: HashMap<String, String>,
+    /// The workspace for a newly created window; defaults to
+    /// the active workspace
+    pub workspace: Option<String>,
+}
+impl_lua_conversion!(SpawnArgs);
+
+impl SpawnArgs {
+    pub fn to_command_builder(&self) -> (Option<CommandBuilder>, Option<String>) {
+        build_command(&self.args, &self.cwd, &self.set_environment_variables)
+    }
+}
+
+/// Returns the command builder (if `args` were specified) and
+/// the directory in which the domain should spawn the command
+pub fn build_command(
+    args: &Option<Vec<String>>,
+    cwd: &Option<String>,
+    set_environment_variables: &HashMap<String, String>,
+) -> (Option<CommandBuilder>, Option<String>) {
+    let cmd_builder = args.as_ref().map(|args| {
+        let mut builder = CommandBuilder::from_argv(args.iter().map(Into::into).collect());
+        for (k, v) in set_environment_variables.iter() {
+            builder.env(k, v);
+        }
+        if let Some(cwd) = cwd.as_ref() {
+            builder.cwd(cwd);
+        }
+        builder
+    });
+
+    (cmd_builder, cwd.clone())
+}
+
+/// Resolves a `SpawnTabDomain` to a domain that is able to spawn.
+/// `CurrentPaneDomain` refers to the domain of `current_pane` if
+/// there is one, otherwise the default domain.
+pub fn resolve_spawn_domain(
+    mux: &Rc<Mux>,
+    domain: &SpawnTabDomain,
+    current_pane: Option<&Rc<dyn Pane>>,
+) -> mlua::Result<Arc<dyn Domain>> {
+    let domain = match (domain, current_pane) {
+        (SpawnTabDomain::DefaultDomain, _) | (SpawnTabDomain::CurrentPaneDomain, None) => {
+            mux.default_domain()
+        }
+        (SpawnTabDomain::CurrentPaneDomain, Some(pane)) => mux
+            .get_domain(pane.domain_id())
+            .ok_or_else(|| anyhow!("pane {} has unresolvable domain id", pane.pane_id()))
+            .map_err(luaerr)?,
+        (SpawnTabDomain::DomainName(name), _) => mux
+            .get_domain_by_name(name)
+            .ok_or_else(|| anyhow!("domain name {} is invalid", name))
+            .map_err(luaerr)?,
+    };
+
+    if domain.state() == DomainState::Detached {
+        return Err(luaerr(anyhow!(
+            "Cannot spawn into Detached domain {}",
+            domain.domain_name()
+        )));
+    }
+
+    Ok(domain)
+}
+
+/// Spawns a new window; `CurrentPaneDomain` is treated as the default
+/// domain because there is no current pane to speak of
+async fn spawn_window(spawn: SpawnArgs) -> mlua::Result<(MuxTab, PaneObject, MuxWindow)> {
+    let mux = get_mux()?;
+    let domain = resolve_spawn_domain(&mux, &spawn.domain, None)?;
+    let (cmd_builder, cwd) = spawn.to_command_builder();
+    let size = config::configuration().initial_size();
+
+    let window_id = mux.new_empty_window(spawn.workspace.clone());
+    let tab = domain
+        .spawn(size, cmd_builder, cwd, *window_id)
+        .await
+        .map_err(luaerr)?;
+    let pane = tab
+        .get_active_pane()
+        .ok_or_else(|| anyhow!("newly spawned tab to have a pane"))
+        .map_err(luaerr)?;
+
+    Ok((
+        MuxTab(tab.tab_id()),
+        PaneObject::new(&pane),
+        MuxWindow(*window_id),
+    ))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MuxWindow(pub WindowId);
+
+impl MuxWindow {
+    pub fn resolve<'a>(&self, mux: &'a Rc<Mux>) -> mlua::Result<Ref<'a, Window>> {
+        mux.get_window(self.0)
+            .ok_or_else(|| anyhow!("window id {} not found in mux", self.0))
+            .map_err(luaerr)
+    }
+
+    pub fn resolve_mut<'a>(&self, mux: &'a Rc<Mux>) -> mlua::Result<RefMut<'a, Window>> {
+        mux.get_window_mut(self.0)
+            .ok_or_else(|| anyhow!("window id {} not found in mux", self.0))
+            .map_err(luaerr)
+    }
+}
+
+impl UserData for MuxWindow {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("window_id", |_, this, _: ()| Ok(this.0));
+        methods.add_method("get_workspace", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let window = this.resolve(&mux)?;
+            Ok(window.get_workspace().to_string())
+        });
+        methods.add_method("tabs", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let window = this.resolve(&mux)?;
+            Ok(window
+                .iter()
+                .map(|tab| MuxTab(tab.tab_id()))
+                .collect::<Vec<MuxTab>>())
+        });
+        methods.add_method("active_tab", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let window = this.resolve(&mux)?;
+            Ok(window.get_active().map(|tab| MuxTab(tab.tab_id())))
+        });
+        methods.add_method("active_pane", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let window = this.resolve(&mux)?;
+            Ok(window
+                .get_active()
+                .and_then(|tab| tab.get_active_pane())
+                .map(|pane| PaneObject::new(&pane)))
+        });
+        methods.add_async_method(
+            "spawn_tab",
+            |_, this, spawn: Option<SpawnArgs>| async move {
+                let spawn = spawn.unwrap_or_default();
+                let mux = get_mux()?;
+                let current_pane = mux
+                    .get_active_tab_for_window(this.0)
+                    .and_then(|tab| tab.get_active_pane());
+                let domain = resolve_spawn_domain(&mux, &spawn.domain, current_pane.as_ref())?;
+                let (cmd_builder, cwd) = spawn.to_command_builder();
+                let size = match mux.get_active_tab_for_window(this.0) {
+                    Some(tab) => tab.get_size(),
+                    None => config::configuration().initial_size(),
+                };
+
+                let tab = domain
+                    .spawn(size, cmd_builder, cwd, this.0)
+                    .await
+                    .map_err(luaerr)?;
+                let pane = tab
+                    .get_active_pane()
+                    .ok_or_else(|| anyhow!("newly spawned tab to have a pane"))
+                    .map_err(luaerr)?;
+
+                {
+                    let mut window = this.resolve_mut(&mux)?;
+                    if let Some(idx) = window.idx_by_id(tab.tab_id()) {
+                        window.save_and_then_set_active(idx);
+                    }
+                }
+
+                Ok((MuxTab(tab.tab_id()), PaneObject::new(&pane), this))
+            },
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MuxTab(pub TabId);
+
+impl MuxTab {
+    pub fn resolve(&self, mux: &Rc<Mux>) -> mlua::Result<Rc<Tab>> {
+        mux.get_tab(self.0)
+            .ok_or_else(|| anyhow!("tab id {} not found in mux", self.0))
+            .map_err(luaerr)
+    }
+}
+
+impl UserData for MuxTab {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("tab_id", |_, this, _: ()| Ok(this.0));
+        methods.add_method("window", |_, this, _: ()| {
+            let mux = get_mux()?;
+            Ok(mux.window_containing_tab(this.0).map(MuxWindow))
+        });
+        methods.add_method("panes", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let tab = this.resolve(&mux)?;
+            Ok(tab
+                .iter_panes()
+                .into_iter()
+                .map(|pos| PaneObject::new(&pos.pane))
+                .collect::<Vec<PaneObject>>())
+        });
+        methods.add_method("active_pane", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let tab = this.resolve(&mux)?;
+            Ok(tab.get_active_pane().map(|pane| PaneObject::new(&pane)))
+        });
+        methods.add_method("get_title", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let tab = this.resolve(&mux)?;
+            Ok(tab.get_title())
+        });
+        methods.add_method("set_title", |_, this, title: String| {
+            let mux = get_mux()?;
+            let tab = this.resolve(&mux)?;
+            tab.set_title(&title);
+            if let Some(window_id) = mux.window_containing_tab(this.0) {
+                if let Some(window) = mux.get_window(window_id) {
+                    window.invalidate();
+                }
+            }
+            Ok(())
+        });
+        methods.add_method("activate", |_, this, _: ()| {
+            let mux = get_mux()?;
+            let window_id = mux
+                .window_containing_tab(this.0)
+                .ok_or_else(|| anyhow!("tab {} is not in any window", this.0))
+                .map_err(luaerr)?;
+            let mut window = MuxWindow(window_id).resolve_mut(&mux)?;
+            let idx = window
+                .idx_by_id(this.0)
+                .ok_or_else(|| anyhow!("tab {} is not in window {}", this.0, window_id))
+                .map_err(luaerr)?;
+            window.save_and_then_set_active(idx);
+            Ok(())
+        });
+    }
+}
@@ -1,9 +1,15 @@
 //! PaneObject represents a Mux Pane instance in lua code
 use super::luaerr;
+use super::mux::{build_command, get_mux, resolve_spawn_domain, MuxTab, MuxWindow};
 use anyhow::anyhow;
+use config::keyassignment::{PaneDirection, SpawnTabDomain};
+use luahelper::impl_lua_conversion;
 use mlua::{UserData, UserDataMethods};
 use mux::pane::{Pane, PaneId};
+use mux::tab::{SplitDirection, Tab};
 use mux::Mux;
+use serde::*;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 #[derive(Clone)]
@@ -54,6 +60,30 @@ impl UserData for PaneObject {
         methods.add_method("get_user_vars", |_, this, _: ()| {
             Ok(this.pane()?.copy_user_vars())
         });
+        methods.add_method("get_connection_status", |_, this, _: ()| {
+            Ok(this.pane()?.get_connection_status())
+        });
+        methods.add_method("tab", |_, this, _: ()| {
+            let mux = get_mux()?;
+            Ok(mux
+                .resolve_pane_id(this.pane)
+                .map(|(_domain_id, _window_id, tab_id)| MuxTab(tab_id)))
+        });
+        methods.add_method("window", |_, this, _: ()| {
+            let mux = get_mux()?;
+            Ok(mux
+                .resolve_pane_id(this.pane)
+                .map(|(_domain_id, window_id, _tab_id)| MuxWindow(window_id)))
+        });
+        methods.add_method("activate", |_, this, _: ()| {
+            let mux = get_mux()?;
+            mux.focus_pane_and_containing_tab(this.pane)
+                .map_err(luaerr)?;
+            Ok(())
+        });
+        methods.add_async_method("split", |_, this, split: Option<SplitPane>| async move {
+            split.unwrap_or_default().run(this.pane()?).await
+        });
 
         // When called with no arguments, returns the lines from the
         // viewport as plain text (no escape sequences).
@@ -82,3 +112,120 @@ impl UserData for PaneObject {
         });
     }
 }
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+enum SplitPlacement {
+    Right,
+    Bottom,
+}
+
+impl Default for SplitPlacement {
+    fn default() -> Self {
+        Self::Right
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct SplitPane {
+    #[serde(default)]
+    direction: SplitPlacement,
+    /// When less than 1.0, the fraction of the available space
+    /// to allocate to the new pane, otherwise the number of cells
+    size: Option<f32>,
+    #[serde(default)]
+    domain: SpawnTabDomain,
+    args: Option<Vec<String>>,
+    cwd: Option<String>,
+    #[serde(default)]
+    set_environment_variables: HashMap<String, String>,
+}
+impl_lua_conversion!(SplitPane);
+
+impl SplitPane {
+    async fn run(self, pane: Rc<dyn Pane>) -> mlua::Result<PaneObject> {
+        let mux = get_mux()?;
+        let (_domain_id, _window_id, tab_id) = mux
+            .resolve_pane_id(pane.pane_id())
+            .ok_or_else(|| anyhow!("pane {} is not in any tab", pane.pane_id()))
+            .map_err(luaerr)?;
+        let tab = mux
+            .get_tab(tab_id)
+            .ok_or_else(|| anyhow!("tab {} not found in mux", tab_id))
+            .map_err(luaerr)?;
+        let direction = match self.direction {
+            SplitPlacement::Right => SplitDirection::Horizontal,
+            SplitPlacement::Bottom => SplitDirection::Vertical,
+        };
+        let available = tab
+            .iter_panes()
+            .into_iter()
+            .find(|p| p.pane.pane_id() == pane.pane_id())
+            .map(|pos| match direction {
+                SplitDirection::Horizontal => pos.width,
+                SplitDirection::Vertical => pos.height,
+            })
+            .unwrap_or(0);
+
+        let domain = resolve_spawn_domain(&mux, &self.domain, Some(&pane))?;
+        let (cmd_builder, cwd) =
+            build_command(&self.args, &self.cwd, &self.set_environment_variables);
+
+        let new_pane = domain
+            .split_pane(cmd_builder, cwd, tab_id, pane.pane_id(), direction)
+            .await
+            .map_err(luaerr)?;
+
+        if let Some(size) = self.size {
+            apply_split_size(&tab, &new_pane, direction, available, size);
+        }
+
+        Ok(PaneObject::new(&new_pane))
+    }
+}
+
+/// Moves the split that was just created to hold `new_pane` so that
+/// the new pane occupies the requested size.
+/// This is only possible when the split was made in the local tab
+/// structure, which is reflected by the new pane being active.
+fn apply_split_size(
+    tab: &Rc<Tab>,
+    new_pane: &Rc<dyn Pane>,
+    direction: SplitDirection,
+    available: usize,
+    size: f32,
+) {
+    match tab.get_active_pane() {
+        Some(active) if active.pane_id() == new_pane.pane_id() => {}
+        _ => return,
+    }
+    let current = match tab
+        .iter_panes()
+        .into_iter()
+        .find(|p| p.pane.pane_id() == new_pane.pane_id())
+    {
+        Some(pos) => match direction {
+            SplitDirection::Horizontal => pos.width,
+            SplitDirection::Vertical => pos.height,
+        },
+        None => return,
+    };
+
+    let target = if size < 1.0 {
+        (available as f32 * size) as usize
+    } else {
+        size as usize
+    };
+    let target = target.max(1).min(available.saturating_sub(2));
+
+    // The new pane is the second child of the split, so growing it
+    // means moving the split towards the left/top
+    let (grow, shrink) = match direction {
+        SplitDirection::Horizontal => (PaneDirection::Left, PaneDirection::Right),
+        SplitDirection::Vertical => (PaneDirection::Up, PaneDirection::Down),
+    };
+    if target > current {
+        tab.adjust_pane_size(grow, target - current);
+    } else if target < current {
+        tab.adjust_pane_size(shrink, current - target);
+    }
+}
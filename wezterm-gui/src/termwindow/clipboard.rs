@@ -84,6 +84,9 @@ impl TermWindow {
                         let mux = Mux::get().unwrap();
                         mux.get_pane(pane_id)
                     }) {
+                        for target in myself.broadcast_targets(&pane) {
+                            target.trickle_paste(clip.clone()).ok();
+                        }
                         pane.trickle_paste(clip).ok();
                     }
                 })));
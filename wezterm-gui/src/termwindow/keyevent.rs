@@ -1,4 +1,11 @@
 use ::window::{KeyCode, KeyEvent, Modifiers, WindowOps};
+use config::keyassignment::{BroadcastScope, KeyAssignment};
+use mux::pane::{Pane, PaneId};
+use mux::tab::PositionedPane;
+use mux::Mux;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 pub fn window_mods_to_termwiz_mods(modifiers: ::window::Modifiers) -> termwiz::input::Modifiers {
     let mut result = termwiz::input::Modifiers::NONE;
@@ -33,9 +40,141 @@ pub enum Key {
     None,
 }
 
+#[derive(Debug, Clone)]
+struct KeyTableStateEntry {
+    name: String,
+    /// If Some, the table is automatically popped after this instant
+    expiration: Option<Instant>,
+    /// If true, the table is popped after the next key press
+    one_shot: bool,
+}
+
+/// Tracks the stack of key tables that have been activated via
+/// ActivateKeyTable.  The tables in the stack are consulted from the
+/// top down before the main key assignments.
+#[derive(Debug, Default)]
+pub struct KeyTableState {
+    stack: Vec<KeyTableStateEntry>,
+}
+
+impl KeyTableState {
+    /// Pushes the named table onto the stack.
+    /// Returns the instant at which it will expire, if it has a timeout.
+    pub fn activate(
+        &mut self,
+        name: &str,
+        timeout_milliseconds: Option<u64>,
+        one_shot: bool,
+    ) -> Option<Instant> {
+        let expiration = timeout_milliseconds.map(|ms| Instant::now() + Duration::from_millis(ms));
+        self.stack.push(KeyTableStateEntry {
+            name: name.to_string(),
+            expiration,
+            one_shot,
+        });
+        expiration
+    }
+
+    pub fn pop(&mut self) {
+        self.stack.pop();
+    }
+
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    /// Removes any tables whose timeout has elapsed.
+    /// Returns true if the stack was changed as a result.
+    fn process_expiration(&mut self) -> bool {
+        let now = Instant::now();
+        let len = self.stack.len();
+        self.stack
+            .retain(|entry| entry.expiration.map(|exp| exp > now).unwrap_or(true));
+        len != self.stack.len()
+    }
+
+    /// Returns the name of the currently active key table, if any
+    pub fn current_table(&mut self) -> Option<&str> {
+        self.process_expiration();
+        self.stack.last().map(|entry| entry.name.as_str())
+    }
+
+    /// Called when a key is pressed; returns the names of the tables
+    /// that should be used to resolve the key, from the top of the stack
+    /// down, popping the top table if it was a one-shot activation.
+    fn tables_for_key_press(&mut self, key_is_modifier: bool) -> Vec<String> {
+        self.process_expiration();
+        let tables = self
+            .stack
+            .iter()
+            .rev()
+            .map(|entry| entry.name.clone())
+            .collect();
+        if let Some(entry) = self.stack.last() {
+            if entry.one_shot && !key_is_modifier {
+                self.stack.pop();
+            }
+        }
+        tables
+    }
+}
+
+/// Tracks whether the input that is sent to the active pane is also
+/// sent to other panes; see ToggleBroadcastInput and
+/// TogglePaneBroadcastMark.
+#[derive(Debug, Default)]
+pub struct BroadcastState {
+    scope: Option<BroadcastScope>,
+    marked: HashSet<PaneId>,
+}
+
+impl BroadcastState {
+    /// Enables broadcasting to `scope`, or disables broadcasting if
+    /// it was already enabled for that scope
+    pub fn toggle_scope(&mut self, scope: BroadcastScope) {
+        if self.scope == Some(scope) {
+            self.scope = None;
+        } else {
+            self.scope = Some(scope);
+        }
+    }
+
+    pub fn toggle_mark(&mut self, pane_id: PaneId) {
+        if !self.marked.remove(&pane_id) {
+            self.marked.insert(pane_id);
+        }
+    }
+
+    /// Forgets the mark on a pane that has been removed from the mux
+    pub fn pane_removed(&mut self, pane_id: PaneId) {
+        self.marked.remove(&pane_id);
+    }
+
+    /// Returns the ids of the panes, other than `source`, that should
+    /// receive a copy of its input.  `panes_in_scope` is called to
+    /// enumerate the panes for the tab and window scopes.
+    pub fn target_ids<F>(&self, source: PaneId, panes_in_scope: F) -> Vec<PaneId>
+    where
+        F: FnOnce(BroadcastScope) -> Vec<PaneId>,
+    {
+        let mut ids = match self.scope {
+            None => return vec![],
+            Some(BroadcastScope::Marked) => {
+                let mut ids: Vec<PaneId> = self.marked.iter().copied().collect();
+                ids.sort_unstable();
+                ids
+            }
+            Some(scope) => panes_in_scope(scope),
+        };
+        ids.retain(|&id| id != source);
+        ids
+    }
+}
+
 impl super::TermWindow {
     pub fn key_event_impl(&mut self, window_key: KeyEvent, context: &dyn WindowOps) -> bool {
         if !window_key.key_is_down {
+            self.key_up_impl(&window_key);
             return false;
         }
 
@@ -66,6 +205,14 @@ impl super::TermWindow {
             _ => (false, Modifiers::NONE),
         };
 
+        let num_tables = self.key_table_state.stack.len();
+        let key_tables = self
+            .key_table_state
+            .tables_for_key_press(window_key.key.is_modifier());
+        if num_tables != self.key_table_state.stack.len() {
+            self.emit_status_event();
+        }
+
         let modifiers = window_mods_to_termwiz_mods(window_key.modifiers);
         let raw_modifiers = window_mods_to_termwiz_mods(window_key.raw_modifiers);
 
@@ -91,10 +238,11 @@ impl super::TermWindow {
                 }
             }
 
-            if let Some(assignment) = self
-                .input_map
-                .lookup_key(&raw_code_key, window_key.raw_modifiers | leader_mod)
-            {
+            if let Some(assignment) = self.lookup_key(
+                &key_tables,
+                &raw_code_key,
+                window_key.raw_modifiers | leader_mod,
+            ) {
                 self.perform_key_assignment(&pane, &assignment).ok();
                 context.invalidate();
 
@@ -120,9 +268,8 @@ impl super::TermWindow {
                 }
             }
 
-            if let Some(assignment) = self
-                .input_map
-                .lookup_key(key, window_key.raw_modifiers | leader_mod)
+            if let Some(assignment) =
+                self.lookup_key(&key_tables, key, window_key.raw_modifiers | leader_mod)
             {
                 self.perform_key_assignment(&pane, &assignment).ok();
                 context.invalidate();
@@ -161,6 +308,7 @@ impl super::TermWindow {
 
                 if let Key::Code(term_key) = self.win_key_code_to_termwiz_key_code(&key) {
                     if bypass_compose && pane.key_down(term_key, raw_modifiers).is_ok() {
+                        self.broadcast_key_down(&pane, term_key, raw_modifiers);
                         if !key.is_modifier() && self.pane_state(pane.pane_id()).overlay.is_none() {
                             self.maybe_scroll_to_bottom_for_input(&pane);
                         }
@@ -185,10 +333,11 @@ impl super::TermWindow {
             }
         }
 
-        if let Some(assignment) = self
-            .input_map
-            .lookup_key(&window_key.key, window_key.modifiers | leader_mod)
-        {
+        if let Some(assignment) = self.lookup_key(
+            &key_tables,
+            &window_key.key,
+            window_key.modifiers | leader_mod,
+        ) {
             self.perform_key_assignment(&pane, &assignment).ok();
             context.invalidate();
             if leader_active {
@@ -210,6 +359,7 @@ impl super::TermWindow {
             match key {
                 Key::Code(key) => {
                     if pane.key_down(key, modifiers).is_ok() {
+                        self.broadcast_key_down(&pane, key, modifiers);
                         if !key.is_modifier() && self.pane_state(pane.pane_id()).overlay.is_none() {
                             self.maybe_scroll_to_bottom_for_input(&pane);
                         }
@@ -228,6 +378,9 @@ impl super::TermWindow {
                         self.leader_is_down.take();
                     } else {
                         pane.writer().write_all(s.as_bytes()).ok();
+                        for target in self.broadcast_targets(&pane) {
+                            target.writer().write_all(s.as_bytes()).ok();
+                        }
                         self.maybe_scroll_to_bottom_for_input(&pane);
                         context.invalidate();
                     }
@@ -238,6 +391,130 @@ impl super::TermWindow {
         }
     }
 
+    /// Returns the panes, other than `pane`, that should receive a
+    /// copy of the input that is sent to `pane`.
+    /// Input is only broadcast from the active pane; input destined
+    /// for an overlay is never broadcast.
+    pub fn broadcast_targets(&self, pane: &Rc<dyn Pane>) -> Vec<Rc<dyn Pane>> {
+        if self.broadcast.scope.is_none() {
+            return vec![];
+        }
+        match self.get_active_pane_no_overlay() {
+            Some(active) if active.pane_id() == pane.pane_id() => {}
+            _ => return vec![],
+        }
+
+        let mux = Mux::get().unwrap();
+        let ids = self
+            .broadcast
+            .target_ids(pane.pane_id(), |scope| match scope {
+                BroadcastScope::Tab => match mux.get_active_tab_for_window(self.mux_window_id) {
+                    Some(tab) => tab
+                        .iter_panes_ignoring_zoom()
+                        .into_iter()
+                        .map(|pos| pos.pane.pane_id())
+                        .collect(),
+                    None => vec![],
+                },
+                BroadcastScope::Window => match mux.get_window(self.mux_window_id) {
+                    Some(window) => window
+                        .iter()
+                        .flat_map(|tab| tab.iter_panes_ignoring_zoom())
+                        .map(|pos| pos.pane.pane_id())
+                        .collect(),
+                    None => vec![],
+                },
+                BroadcastScope::Marked => vec![],
+            });
+
+        ids.into_iter()
+            .filter_map(|pane_id| mux.get_pane(pane_id))
+            .collect()
+    }
+
+    /// Reports a key release to the active pane and any panes that
+    /// are receiving broadcast input from it.  The terminal only
+    /// passes this on to applications that asked for key releases.
+    fn key_up_impl(&self, window_key: &KeyEvent) {
+        let pane = match self.get_active_pane_or_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let key = match self.win_key_code_to_termwiz_key_code(&window_key.key) {
+            Key::Code(key) => key,
+            _ => return,
+        };
+        let modifiers = window_mods_to_termwiz_mods(window_key.modifiers);
+        if let Err(err) = pane.key_up(key, modifiers) {
+            log::error!("key_up for pane {}: {:#}", pane.pane_id(), err);
+        }
+        for target in self.broadcast_targets(&pane) {
+            if let Err(err) = target.key_up(key, modifiers) {
+                log::error!(
+                    "broadcasting key up to pane {}: {:#}",
+                    target.pane_id(),
+                    err
+                );
+            }
+        }
+    }
+
+    fn broadcast_key_down(
+        &self,
+        pane: &Rc<dyn Pane>,
+        key: ::termwiz::input::KeyCode,
+        modifiers: ::termwiz::input::Modifiers,
+    ) {
+        for target in self.broadcast_targets(pane) {
+            if let Err(err) = target.key_down(key, modifiers) {
+                log::error!("broadcasting key to pane {}: {:#}", target.pane_id(), err);
+            }
+        }
+    }
+
+    /// Returns true if the pane is sending or receiving broadcast input,
+    /// in which case it is rendered with a border
+    pub fn is_broadcasting_pane(&self, pos: &PositionedPane) -> bool {
+        match self.broadcast.scope {
+            None => false,
+            Some(BroadcastScope::Tab) | Some(BroadcastScope::Window) => true,
+            Some(BroadcastScope::Marked) => {
+                pos.is_active || self.broadcast.marked.contains(&pos.pane.pane_id())
+            }
+        }
+    }
+
+    pub fn toggle_broadcast_input(&mut self, scope: BroadcastScope) {
+        self.broadcast.toggle_scope(scope);
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    pub fn toggle_pane_broadcast_mark(&mut self, pane: &Rc<dyn Pane>) {
+        self.broadcast.toggle_mark(pane.pane_id());
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
+    }
+
+    /// Resolves a key press to an assignment, consulting the active
+    /// key tables from the top of the stack down and then falling back
+    /// to the main key map.
+    fn lookup_key(
+        &self,
+        key_tables: &[String],
+        key: &KeyCode,
+        mods: Modifiers,
+    ) -> Option<KeyAssignment> {
+        for name in key_tables {
+            if let Some(assignment) = self.input_map.lookup_key_in_table(name, key, mods) {
+                return Some(assignment);
+            }
+        }
+        self.input_map.lookup_key(key, mods)
+    }
+
     pub fn win_key_code_to_termwiz_key_code(&self, key: &::window::KeyCode) -> Key {
         use ::termwiz::input::KeyCode as KC;
         use ::window::KeyCode as WK;
@@ -351,3 +628,110 @@ impl super::TermWindow {
         Key::Code(code)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_table_push_pop() {
+        let mut state = KeyTableState::default();
+        assert_eq!(state.current_table(), None);
+        assert!(state.tables_for_key_press(false).is_empty());
+
+        state.activate("resize", None, false);
+        state.activate("nav", None, false);
+        assert_eq!(state.current_table(), Some("nav"));
+        assert_eq!(state.tables_for_key_press(false), vec!["nav", "resize"]);
+        assert_eq!(state.current_table(), Some("nav"));
+
+        state.pop();
+        assert_eq!(state.current_table(), Some("resize"));
+        state.clear();
+        assert_eq!(state.current_table(), None);
+    }
+
+    #[test]
+    fn key_table_one_shot() {
+        let mut state = KeyTableState::default();
+        state.activate("resize", None, false);
+        state.activate("nav", None, true);
+
+        // Modifier keys don't consume a one-shot table
+        assert_eq!(state.tables_for_key_press(true), vec!["nav", "resize"]);
+        assert_eq!(state.current_table(), Some("nav"));
+
+        // but the next key does, after it has been resolved with it
+        assert_eq!(state.tables_for_key_press(false), vec!["nav", "resize"]);
+        assert_eq!(state.current_table(), Some("resize"));
+        assert_eq!(state.tables_for_key_press(false), vec!["resize"]);
+    }
+
+    #[test]
+    fn key_table_timeout() {
+        let mut state = KeyTableState::default();
+        assert_eq!(state.activate("resize", None, false), None);
+        let expiration = state.activate("nav", Some(0), false);
+        assert!(expiration.is_some());
+
+        // The expired table is skipped, leaving the one beneath it
+        assert_eq!(state.tables_for_key_press(false), vec!["resize"]);
+        assert_eq!(state.current_table(), Some("resize"));
+
+        state.activate("nav", Some(60_000), false);
+        assert_eq!(state.current_table(), Some("nav"));
+        assert!(!state.process_expiration());
+    }
+
+    #[test]
+    fn broadcast_toggle_scope() {
+        let mut state = BroadcastState::default();
+        assert_eq!(state.scope, None);
+
+        state.toggle_scope(BroadcastScope::Tab);
+        assert_eq!(state.scope, Some(BroadcastScope::Tab));
+
+        // Toggling a different scope switches to it
+        state.toggle_scope(BroadcastScope::Window);
+        assert_eq!(state.scope, Some(BroadcastScope::Window));
+
+        // Toggling the same scope again turns broadcasting off
+        state.toggle_scope(BroadcastScope::Window);
+        assert_eq!(state.scope, None);
+    }
+
+    #[test]
+    fn broadcast_target_ids() {
+        let mut state = BroadcastState::default();
+        let panes = |scope: BroadcastScope| -> Vec<PaneId> {
+            match scope {
+                BroadcastScope::Tab => vec![1, 2, 3],
+                BroadcastScope::Window => vec![1, 2, 3, 4, 5],
+                BroadcastScope::Marked => unreachable!(),
+            }
+        };
+
+        // Nothing is broadcast until a scope is enabled
+        state.toggle_mark(3);
+        assert!(state.target_ids(1, panes).is_empty());
+
+        state.toggle_scope(BroadcastScope::Tab);
+        assert_eq!(state.target_ids(1, panes), vec![2, 3]);
+
+        state.toggle_scope(BroadcastScope::Window);
+        assert_eq!(state.target_ids(2, panes), vec![1, 3, 4, 5]);
+
+        // The marked scope doesn't consult the panes in the tab or window
+        state.toggle_scope(BroadcastScope::Marked);
+        state.toggle_mark(5);
+        state.toggle_mark(1);
+        assert_eq!(state.target_ids(1, panes), vec![3, 5]);
+
+        state.toggle_mark(3);
+        assert_eq!(state.target_ids(1, panes), vec![5]);
+
+        state.pane_removed(5);
+        assert!(state.target_ids(1, panes).is_empty());
+        assert!(state.marked.contains(&1));
+    }
+}
@@ -2,11 +2,12 @@
 use super::renderstate::*;
 use super::utilsprites::RenderMetrics;
 use crate::cache::LruCache;
+use crate::commands::CommandDef;
 use crate::glium::texture::SrgbTexture2d;
 use crate::overlay::{
-    confirm_close_pane, confirm_close_tab, confirm_close_window, confirm_quit_program, launcher,
-    start_overlay, start_overlay_pane, tab_navigator, CopyOverlay, QuickSelectOverlay,
-    SearchOverlay,
+    command_palette, confirm_close_pane, confirm_close_tab, confirm_close_window,
+    confirm_quit_program, launcher, start_overlay, start_overlay_pane, tab_navigator,
+    transfer_progress, CopyOverlay, QuickSelectOverlay, SearchOverlay,
 };
 use crate::scripting::guiwin::GuiWin;
 use crate::scripting::pane::PaneObject;
@@ -20,7 +21,7 @@ use anyhow::Context;
 use anyhow::{anyhow, ensure};
 use config::keyassignment::{
     ClipboardCopyDestination, ClipboardPasteSource, InputMap, KeyAssignment, QuickSelectArguments,
-    SpawnCommand,
+    RotationDirection, SpawnCommand,
 };
 use config::{
     configuration, AudibleBell, ConfigHandle, DimensionContext, GradientOrientation, TermConfig,
@@ -31,6 +32,7 @@ use mux::domain::{DomainId, DomainState};
 use mux::pane::{CloseReason, Pane, PaneId};
 use mux::renderable::RenderableDimensions;
 use mux::tab::{PositionedPane, PositionedSplit, SplitDirection, Tab, TabId};
+use mux::transfer::{FileTransfer, TransferDirection};
 use mux::window::WindowId as MuxWindowId;
 use mux::{Mux, MuxNotification};
 use portable_pty::PtySize;
@@ -60,6 +62,7 @@ pub mod resize;
 mod selection;
 pub mod spawn;
 use clipboard::ClipboardHelper;
+use keyevent::{BroadcastState, KeyTableState};
 use prevcursor::PrevCursorPos;
 use spawn::SpawnWhere;
 
@@ -90,6 +93,7 @@ pub enum TermWindowNotif {
         tx: Sender<String>,
     },
     GetEffectiveConfig(Sender<ConfigHandle>),
+    GetActiveKeyTable(Sender<Option<String>>),
     FinishWindowEvent {
         name: String,
         again: bool,
@@ -161,6 +165,7 @@ pub struct TabInformation {
     pub tab_index: usize,
     pub is_active: bool,
     pub active_pane: Option<PaneInformation>,
+    pub tab_title: String,
 }
 
 impl UserData for TabInformation {
@@ -168,6 +173,7 @@ impl UserData for TabInformation {
         fields.add_field_method_get("tab_id", |_, this| Ok(this.tab_id));
         fields.add_field_method_get("tab_index", |_, this| Ok(this.tab_index));
         fields.add_field_method_get("is_active", |_, this| Ok(this.is_active));
+        fields.add_field_method_get("tab_title", |_, this| Ok(this.tab_title.clone()));
         fields.add_field_method_get("active_pane", |_, this| {
             if let Some(pane) = &this.active_pane {
                 Ok(Some(pane.clone()))
@@ -277,6 +283,8 @@ pub struct TermWindow {
     input_map: InputMap,
     /// If is_some, the LEADER modifier is active until the specified instant.
     leader_is_down: Option<std::time::Instant>,
+    key_table_state: KeyTableState,
+    broadcast: BroadcastState,
     show_tab_bar: bool,
     show_scroll_bar: bool,
     tab_bar: TabBarState,
@@ -694,6 +702,8 @@ impl TermWindow {
             render_state,
             input_map: InputMap::new(&config),
             leader_is_down: None,
+            key_table_state: KeyTableState::default(),
+            broadcast: BroadcastState::default(),
             show_tab_bar,
             show_scroll_bar: config.enable_scroll_bar,
             tab_bar: TabBarState::default(),
@@ -820,6 +830,10 @@ impl TermWindow {
                 Ok(true)
             }
             WindowEvent::NeedRepaint => Ok(self.do_paint(window)),
+            WindowEvent::DroppedFiles(paths) => {
+                self.dropped_files(paths);
+                Ok(true)
+            }
             WindowEvent::Notification(item) => {
                 if let Ok(notif) = item.downcast::<TermWindowNotif>() {
                     self.dispatch_notif(*notif, window)
@@ -893,6 +907,12 @@ impl TermWindow {
                     .map_err(chan_err)
                     .context("send GetEffectiveConfig response")?;
             }
+            TermWindowNotif::GetActiveKeyTable(tx) => {
+                let name = self.key_table_state.current_table().map(|s| s.to_string());
+                tx.try_send(name)
+                    .map_err(chan_err)
+                    .context("send GetActiveKeyTable response")?;
+            }
             TermWindowNotif::FinishWindowEvent { name, again } => {
                 self.finish_window_event(&name, again);
             }
@@ -955,6 +975,15 @@ impl TermWindow {
                         window.close();
                     }
                 }
+                MuxNotification::ActiveWorkspaceChanged(_) => {
+                    window.close();
+                }
+                MuxNotification::FileTransferStarted(transfer) => {
+                    self.show_transfer_progress(transfer);
+                }
+                MuxNotification::PaneRemoved(pane_id) => {
+                    self.broadcast.pane_removed(pane_id);
+                }
                 _ => {}
             },
             TermWindowNotif::EmitStatusUpdate => {
@@ -1084,6 +1113,31 @@ impl TermWindow {
                     return true;
                 }
             }
+            MuxNotification::ActiveWorkspaceChanged(ref workspace) => {
+                let mux = Mux::get().expect("mux is calling us");
+                match mux.get_window(mux_window_id) {
+                    Some(mux_window) if mux_window.get_workspace() == workspace.as_str() => {
+                        return true;
+                    }
+                    _ => {}
+                }
+                // Our window is no longer part of the active workspace,
+                // so close the gui window; the mux window lives on and
+                // will be shown again when its workspace is activated
+                dead.store(true, Ordering::Relaxed);
+                window.notify(TermWindowNotif::MuxNotification(n));
+                return false;
+            }
+            MuxNotification::PaneRemoved(_) => {
+                // The pane may have been marked for broadcast input
+            }
+            MuxNotification::FileTransferStarted(ref transfer) => {
+                let mux = Mux::get().expect("mux is calling us");
+                match mux.resolve_pane_id(transfer.pane_id()) {
+                    Some((_domain_id, window_id, _tab_id)) if window_id == mux_window_id => {}
+                    _ => return true,
+                }
+            }
             _ => return true,
         }
 
@@ -1303,6 +1357,7 @@ impl TermWindow {
         self.shape_cache.borrow_mut().clear();
         self.input_map = InputMap::new(&config);
         self.leader_is_down = None;
+        self.key_table_state.clear();
         let dimensions = self.dimensions;
 
         if let Err(err) = self.fonts.config_changed(&config) {
@@ -1653,6 +1708,58 @@ impl TermWindow {
         promise::spawn::spawn(future).detach();
     }
 
+    /// Uploads files that were dropped onto the window to the active
+    /// pane, if that pane is connected via `wezterm ssh` or an ssh domain
+    fn dropped_files(&mut self, paths: Vec<std::path::PathBuf>) {
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let mux = Mux::get().unwrap();
+        let is_ssh_pane = mux
+            .get_domain(pane.domain_id())
+            .map(|domain| domain.sftp().is_some())
+            .unwrap_or(false);
+        if !is_ssh_pane {
+            log::debug!(
+                "ignoring files dropped onto non-ssh pane {}",
+                pane.pane_id()
+            );
+            return;
+        }
+        let paths = paths
+            .into_iter()
+            .filter_map(|path| match path.to_str() {
+                Some(path) => Some(path.to_string()),
+                None => {
+                    log::error!("ignoring dropped file {}: not valid UTF-8", path.display());
+                    None
+                }
+            })
+            .collect();
+        // start_transfer emits FileTransferStarted, which brings up
+        // the progress overlay
+        if let Err(err) =
+            mux::transfer::start_transfer(pane.pane_id(), TransferDirection::Upload, paths, None)
+        {
+            log::error!("Unable to upload dropped files: {:#}", err);
+        }
+    }
+
+    fn show_transfer_progress(&mut self, transfer: Arc<FileTransfer>) {
+        let mux = Mux::get().unwrap();
+        let pane = match mux.get_pane(transfer.pane_id()) {
+            Some(pane) => pane,
+            None => return,
+        };
+
+        let (overlay, future) = start_overlay_pane(self, &pane, move |_pane_id, term| {
+            transfer_progress(term, transfer)
+        });
+        self.assign_overlay_for_pane(pane.pane_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
     fn show_tab_navigator(&mut self) {
         let mux = Mux::get().unwrap();
         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -1735,6 +1842,8 @@ impl TermWindow {
             .get_active_pane()
             .expect("tab has no panes!")
             .domain_id();
+        let workspaces = mux.iter_workspaces();
+        let active_workspace = mux.active_workspace();
         let size = self.terminal_size;
         let term_config = Arc::new(TermConfig::with_config(self.config.clone()));
 
@@ -1745,6 +1854,8 @@ impl TermWindow {
                 term,
                 mux_window_id,
                 domains,
+                workspaces,
+                active_workspace,
                 clipboard,
                 size,
                 term_config,
@@ -1754,6 +1865,47 @@ impl TermWindow {
         promise::spawn::spawn(future).detach();
     }
 
+    fn show_command_palette(&mut self) {
+        let mux = Mux::get().unwrap();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        let pane_id = match tab.get_active_pane() {
+            Some(pane) => pane.pane_id(),
+            None => return,
+        };
+
+        let user_callbacks = match config::run_immediate_with_lua_config(|lua| match lua {
+            Some(lua) => Ok(config::lua::user_callback_event_names(&*lua)?),
+            None => Ok(vec![]),
+        }) {
+            Ok(names) => names,
+            Err(err) => {
+                log::warn!("command palette: {:#}", err);
+                vec![]
+            }
+        };
+        let commands = CommandDef::compute(&self.input_map, user_callbacks);
+
+        let window = self.window.clone().unwrap();
+        let (overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
+            command_palette(tab_id, term, commands)
+        });
+        self.assign_overlay(tab.tab_id(), overlay);
+        promise::spawn::spawn(async move {
+            // The overlay must be dismissed before the action runs,
+            // so that it applies to the pane rather than the overlay
+            if let Ok(Some(assignment)) = future.await {
+                window.notify(TermWindowNotif::PerformAssignment {
+                    pane_id,
+                    assignment,
+                });
+            }
+        })
+        .detach();
+    }
+
     /// Returns the Prompt semantic zones
     fn get_semantic_zones(&mut self, pane: &Rc<dyn Pane>) -> &[SemanticZone] {
         let mut cache = self
@@ -1973,6 +2125,9 @@ impl TermWindow {
             ScrollToTop => self.scroll_to_top(pane),
             ScrollToBottom => self.scroll_to_bottom(pane),
             ShowTabNavigator => self.show_tab_navigator(),
+            ActivateCommandPalette => self.show_command_palette(),
+            ToggleBroadcastInput { scope } => self.toggle_broadcast_input(*scope),
+            TogglePaneBroadcastMark => self.toggle_pane_broadcast_mark(pane),
             ShowDebugOverlay => self.show_debug_overlay(),
             ShowLauncher => self.show_launcher(),
             HideApplication => {
@@ -2101,10 +2256,161 @@ impl TermWindow {
                 };
                 tab.toggle_zoom();
             }
+            SwapActivePaneDirection {
+                direction,
+                keep_focus,
+            } => {
+                let mux = Mux::get().unwrap();
+                let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+                    Some(tab) => tab,
+                    None => return Ok(()),
+                };
+
+                let tab_id = tab.tab_id();
+
+                if self.tab_state(tab_id).overlay.is_none() {
+                    if let Some(pane_index) = tab.get_pane_direction(*direction) {
+                        tab.swap_active_with_index(pane_index, *keep_focus);
+                    }
+                }
+            }
+            RotatePanes(direction) => {
+                let mux = Mux::get().unwrap();
+                let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+                    Some(tab) => tab,
+                    None => return Ok(()),
+                };
+
+                let tab_id = tab.tab_id();
+
+                if self.tab_state(tab_id).overlay.is_none() {
+                    match direction {
+                        RotationDirection::Clockwise => tab.rotate_clockwise(),
+                        RotationDirection::CounterClockwise => tab.rotate_counter_clockwise(),
+                    }
+                }
+            }
+            MovePaneToNewTab | MovePaneToNewWindow => {
+                let pane = match self.get_active_pane_no_overlay() {
+                    Some(pane) => pane,
+                    None => return Ok(()),
+                };
+                let window_id = match assignment {
+                    MovePaneToNewTab => Some(self.mux_window_id),
+                    _ => None,
+                };
+                let mux = Mux::get().unwrap();
+                let (tab, window_id) = mux.move_pane_to_new_tab(pane.pane_id(), window_id)?;
+                if let Some(mut window) = mux.get_window_mut(window_id) {
+                    if let Some(idx) = window.idx_by_id(tab.tab_id()) {
+                        window.save_and_then_set_active(idx);
+                    }
+                }
+            }
+            JoinPaneIntoTab { tab, direction } => {
+                let pane = match self.get_active_pane_no_overlay() {
+                    Some(pane) => pane,
+                    None => return Ok(()),
+                };
+                let mux = Mux::get().unwrap();
+                let target_tab_id = {
+                    let window = match mux.get_window(self.mux_window_id) {
+                        Some(window) => window,
+                        None => return Ok(()),
+                    };
+                    let max = window.len();
+                    let tab_idx = if *tab < 0 {
+                        max.saturating_sub(tab.abs() as usize)
+                    } else {
+                        *tab as usize
+                    };
+                    match window.get_by_idx(tab_idx) {
+                        Some(tab) => tab.tab_id(),
+                        None => return Ok(()),
+                    }
+                };
+                mux.move_pane_to_tab(pane.pane_id(), target_tab_id, *direction)?;
+                if let Some(mut window) = mux.get_window_mut(self.mux_window_id) {
+                    if let Some(idx) = window.idx_by_id(target_tab_id) {
+                        window.save_and_then_set_active(idx);
+                    }
+                }
+            }
+            ActivateKeyTable {
+                name,
+                timeout_milliseconds,
+                one_shot,
+            } => {
+                if self.input_map.has_table(name) {
+                    let expiration =
+                        self.key_table_state
+                            .activate(name, *timeout_milliseconds, *one_shot);
+                    self.emit_status_event();
+                    // Let the status reflect the table going away when
+                    // it times out, rather than at the next key press
+                    if let (Some(target), Some(window)) = (expiration, self.window.clone()) {
+                        promise::spawn::spawn(async move {
+                            Timer::at(target).await;
+                            window.notify(TermWindowNotif::EmitStatusUpdate);
+                        })
+                        .detach();
+                    }
+                } else {
+                    log::error!("ActivateKeyTable: no key_table named {}", name);
+                }
+            }
+            PopKeyTable => {
+                self.key_table_state.pop();
+                self.emit_status_event();
+            }
+            SwitchToWorkspace { name, spawn } => {
+                self.switch_to_workspace(name.clone(), spawn.as_ref());
+            }
+            SwitchWorkspaceRelative(delta) => {
+                let mux = Mux::get().unwrap();
+                let workspaces = mux.iter_workspaces();
+                let active = mux.active_workspace();
+                if let Some(idx) = workspaces.iter().position(|w| *w == active) {
+                    let max = workspaces.len() as isize;
+                    let idx = (idx as isize + delta).rem_euclid(max) as usize;
+                    mux.set_active_workspace(&workspaces[idx]);
+                }
+            }
         };
         Ok(())
     }
 
+    /// Makes `name` the active workspace, creating it if needed.
+    /// A workspace with no windows is populated by spawning `spawn`,
+    /// or the default program if `spawn` is not specified.
+    /// If `name` is not specified, a new uniquely named workspace
+    /// is created.
+    fn switch_to_workspace(&mut self, name: Option<String>, spawn: Option<&SpawnCommand>) {
+        let mux = Mux::get().unwrap();
+        let name = match name {
+            Some(name) => name,
+            None => {
+                let workspaces = mux.iter_workspaces();
+                (1..)
+                    .map(|n| format!("workspace-{}", n))
+                    .find(|name| !workspaces.contains(name))
+                    .expect("to find an unused workspace name")
+            }
+        };
+
+        if name == mux.active_workspace() {
+            return;
+        }
+
+        let needs_spawn = mux.iter_windows_in_workspace(&name).is_empty();
+        mux.set_active_workspace(&name);
+
+        if needs_spawn {
+            let spawn = spawn.cloned().unwrap_or_default();
+            self.spawn_command(&spawn, SpawnWhere::NewWindow);
+        }
+    }
+
     fn do_open_link_at_mouse_cursor(&self, pane: &Rc<dyn Pane>) {
         // They clicked on a link, so let's open it!
         // We need to ensure that we spawn the `open` call outside of the context
@@ -2365,6 +2671,7 @@ impl TermWindow {
                         .iter()
                         .find(|p| p.is_active)
                         .map(|p| self.pos_pane_to_pane_info(p)),
+                    tab_title: tab.get_title(),
                 }
             })
             .collect()
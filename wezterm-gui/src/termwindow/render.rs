@@ -1339,6 +1339,39 @@ impl super::TermWindow {
             )?;
         }
 
+        if self.is_broadcasting_pane(pos) {
+            // Outline the pane so that it is obvious which panes
+            // will receive the input that is typed
+            let color = rgbcolor_to_window_color(
+                self.config
+                    .resolved_palette
+                    .broadcast_indicator
+                    .unwrap_or(palette.colors.0[1]),
+            );
+            let cell_width = self.render_metrics.cell_size.width as f32;
+            let cell_height = self.render_metrics.cell_size.height as f32;
+            let thickness = self.render_metrics.underline_height.max(1) * 2;
+            let left = ((pos.left as f32 * cell_width) + padding_left) as isize;
+            let top = (top_pixel_y + (pos.top as f32 * cell_height)) as isize;
+            let width = (pos.width as f32 * cell_width) as isize;
+            let height = (pos.height as f32 * cell_height) as isize;
+
+            for rect in &[
+                Rect::new(Point::new(left, top), Size::new(width, thickness)),
+                Rect::new(
+                    Point::new(left, top + height - thickness),
+                    Size::new(width, thickness),
+                ),
+                Rect::new(Point::new(left, top), Size::new(thickness, height)),
+                Rect::new(
+                    Point::new(left + width - thickness, top),
+                    Size::new(thickness, height),
+                ),
+            ] {
+                self.filled_rectangle(&mut layers[2], *rect, color)?;
+            }
+        }
+
         let selrange = self.selection(pos.pane.pane_id()).range.clone();
 
         let start = Instant::now();
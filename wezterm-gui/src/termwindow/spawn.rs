@@ -78,7 +78,7 @@ impl super::TermWindow {
         let mux_builder;
 
         let target_window_id = if spawn_where == SpawnWhere::NewWindow {
-            mux_builder = mux.new_empty_window();
+            mux_builder = mux.new_empty_window(None);
             *mux_builder
         } else {
             src_window_id
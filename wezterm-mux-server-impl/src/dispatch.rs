@@ -74,7 +74,11 @@ where
             Ok(Item::WritePdu(decoded)) => {
                 decoded
                     .pdu
-                    .encode_async(&mut stream, decoded.serial)
+                    .encode_async_with_compression(
+                        &mut stream,
+                        decoded.serial,
+                        handler.compression(),
+                    )
                     .await?;
                 stream.flush().await.context("flushing PDU to client")?;
             }
@@ -84,7 +88,7 @@ where
             Ok(Item::Notif(MuxNotification::PaneAdded(_pane_id))) => {}
             Ok(Item::Notif(MuxNotification::PaneRemoved(pane_id))) => {
                 Pdu::PaneRemoved(codec::PaneRemoved { pane_id })
-                    .encode_async(&mut stream, 0)
+                    .encode_async_with_compression(&mut stream, 0, handler.compression())
                     .await?;
                 stream.flush().await.context("flushing PDU to client")?;
             }
@@ -99,6 +103,8 @@ where
             Ok(Item::Notif(MuxNotification::WindowRemoved(_window_id))) => {}
             Ok(Item::Notif(MuxNotification::WindowCreated(_window_id))) => {}
             Ok(Item::Notif(MuxNotification::WindowInvalidated(_window_id))) => {}
+            Ok(Item::Notif(MuxNotification::ActiveWorkspaceChanged(_))) => {}
+            Ok(Item::Notif(MuxNotification::FileTransferStarted(_))) => {}
             Ok(Item::Notif(MuxNotification::Empty)) => {}
             Err(err) => {
                 log::error!("process_async Err {}", err);
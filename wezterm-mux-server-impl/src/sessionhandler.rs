@@ -1,19 +1,22 @@
 use crate::PKI;
 use anyhow::anyhow;
 use codec::*;
+use config::configuration;
 use config::keyassignment::SpawnTabDomain;
 use mux::pane::{Pane, PaneId};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
+use mux::session::SessionSnapshot;
 use mux::tab::TabId;
-use mux::Mux;
+use mux::transfer::TransferDirection;
+use mux::{Mux, MuxNotification, OutputGate};
 use percent_encoding::percent_decode_str;
 use portable_pty::PtySize;
 use promise::spawn::spawn_into_main_thread;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
-use termwiz::surface::SequenceNo;
+use termwiz::surface::{SequenceNo, SEQ_ZERO};
 use url::Url;
 use wezterm_term::terminal::{Alert, Clipboard, ClipboardSelection};
 use wezterm_term::StableRowIndex;
@@ -43,12 +46,81 @@ pub(crate) struct PerPane {
     working_dir: Option<Url>,
     dimensions: RenderableDimensions,
     mouse_grabbed: bool,
+    wants_key_up_events: bool,
     sent_initial_palette: bool,
     seqno: SequenceNo,
     pub(crate) notifications: Vec<Alert>,
+    /// Set once the client acknowledges changes for this pane.
+    /// Clients that don't render the pane (eg: `wezterm cli`)
+    /// never do, and are not subject to flow control.
+    flow_controlled: bool,
+    /// The seqnos of the render changes that we've sent to the
+    /// client but which it hasn't yet acknowledged
+    unacked: VecDeque<SequenceNo>,
+    /// Holds a pause on the pane output while the client is
+    /// too far behind
+    paused: Option<Arc<OutputGate>>,
+}
+
+impl Drop for PerPane {
+    fn drop(&mut self) {
+        if let Some(gate) = self.paused.take() {
+            gate.resume();
+        }
+    }
 }
 
 impl PerPane {
+    /// Returns true if the client has fallen far enough behind
+    /// that we should hold off sending it more changes
+    fn is_over_window(&self) -> bool {
+        let window = configuration().mux_output_flow_control_window;
+        window > 0 && self.unacked.len() >= window
+    }
+
+    fn record_sent(&mut self, pane: &Rc<dyn Pane>, seqno: SequenceNo) {
+        if !self.flow_controlled {
+            return;
+        }
+        self.unacked.push_back(seqno);
+        if self.paused.is_none() && self.is_over_window() {
+            if let Some(gate) = Mux::get().and_then(|mux| mux.get_output_gate(pane.pane_id())) {
+                log::trace!(
+                    "pausing output of pane {}; {} changes are unacknowledged",
+                    pane.pane_id(),
+                    self.unacked.len()
+                );
+                gate.pause();
+                self.paused.replace(gate);
+            }
+        }
+    }
+
+    /// The client has applied the changes up to and including seqno
+    fn ack(&mut self, seqno: SequenceNo) {
+        self.flow_controlled = true;
+        while self.unacked.front().map(|&s| s <= seqno).unwrap_or(false) {
+            self.unacked.pop_front();
+        }
+        if !self.is_over_window() {
+            if let Some(gate) = self.paused.take() {
+                gate.resume();
+            }
+        }
+    }
+
+    /// A client that reconnects tells us the seqno of the most recent
+    /// changes that it applied; seeding a fresh PerPane with that value
+    /// means that we only send the lines that changed while it was away,
+    /// rather than the entire screen.
+    /// A seqno from the future implies that the client last spoke to
+    /// a different server instance, so we ignore it.
+    fn seed_seqno(&mut self, pane: &Rc<dyn Pane>, seqno: SequenceNo) {
+        if self.seqno == SEQ_ZERO && seqno <= pane.get_current_seqno() {
+            self.seqno = seqno;
+        }
+    }
+
     fn compute_changes(
         &mut self,
         pane: &Rc<dyn Pane>,
@@ -60,6 +132,11 @@ impl PerPane {
             changed = true;
         }
 
+        let wants_key_up_events = pane.wants_key_up_events();
+        if wants_key_up_events != self.wants_key_up_events {
+            changed = true;
+        }
+
         let dims = pane.get_dimensions();
         if dims != self.dimensions {
             changed = true;
@@ -117,12 +194,15 @@ impl PerPane {
         self.working_dir = working_dir.clone();
         self.dimensions = dims;
         self.mouse_grabbed = mouse_grabbed;
+        self.wants_key_up_events = wants_key_up_events;
         self.seqno = pane.get_current_seqno();
+        self.record_sent(pane, self.seqno);
 
         let bonus_lines = bonus_lines.into();
         Some(GetPaneRenderChangesResponse {
             pane_id: pane.pane_id(),
             mouse_grabbed,
+            wants_key_up_events,
             dirty_lines: all_dirty_lines.iter().cloned().collect(),
             dimensions: dims,
             cursor_position,
@@ -141,7 +221,11 @@ fn maybe_push_pane_changes(
     per_pane: Arc<Mutex<PerPane>>,
 ) -> anyhow::Result<()> {
     let mut per_pane = per_pane.lock().unwrap();
-    if let Some(resp) = per_pane.compute_changes(pane, None) {
+    if per_pane.is_over_window() {
+        // The client hasn't caught up with what we already sent;
+        // the changes accumulate in the pane and will be sent
+        // once it acknowledges them.
+    } else if let Some(resp) = per_pane.compute_changes(pane, None) {
         sender.send(DecodedPdu {
             pdu: Pdu::GetPaneRenderChangesResponse(resp),
             serial: 0,
@@ -179,6 +263,7 @@ fn maybe_push_pane_changes(
 pub struct SessionHandler {
     to_write_tx: PduSender,
     per_pane: HashMap<TabId, Arc<Mutex<PerPane>>>,
+    compression: Compression,
 }
 
 impl SessionHandler {
@@ -197,9 +282,16 @@ impl SessionHandler {
         Self {
             to_write_tx,
             per_pane: HashMap::new(),
+            compression: Compression::default(),
         }
     }
 
+    /// Returns the compression that the client asked us to use
+    /// when sending PDUs to it
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
     pub(crate) fn per_pane(&mut self, pane_id: PaneId) -> Arc<Mutex<PerPane>> {
         Arc::clone(
             self.per_pane
@@ -248,6 +340,32 @@ impl SessionHandler {
 
         match decoded.pdu {
             Pdu::Ping(Ping {}) => send_response(Ok(Pdu::Pong(Pong {}))),
+            Pdu::SetCompression(SetCompression { compression }) => {
+                self.compression = compression.normalize();
+                send_response(Ok(Pdu::SetCompressionResponse(SetCompressionResponse {
+                    compression: self.compression,
+                })))
+            }
+            Pdu::AckPaneRenderChanges(AckPaneRenderChanges { pane_id, seqno }) => {
+                let sender = self.to_write_tx.clone();
+                let per_pane = self.per_pane(pane_id);
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            per_pane.lock().unwrap().ack(seqno);
+                            // Send along anything that we held back
+                            // while waiting for the client
+                            let mux = Mux::get().unwrap();
+                            if let Some(pane) = mux.get_pane(pane_id) {
+                                maybe_push_pane_changes(&pane, sender, per_pane)?;
+                            }
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
             Pdu::ListPanes(ListPanes {}) => {
                 spawn_into_main_thread(async move {
                     catch(
@@ -377,6 +495,81 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::SetFocusedPane(SetFocusedPane { pane_id }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let (_tab, window_id) = mux.focus_pane_and_containing_tab(pane_id)?;
+                            mux.notify(MuxNotification::WindowInvalidated(window_id));
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
+            Pdu::ActivatePaneDirection(ActivatePaneDirection { pane_id, direction }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let (tab, window_id, pane_index) =
+                                mux.containing_tab_and_pane_index(pane_id)?;
+                            tab.activate_pane_direction_from(pane_index, direction);
+                            mux.notify(MuxNotification::WindowInvalidated(window_id));
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
+            Pdu::AdjustPaneSize(AdjustPaneSize {
+                pane_id,
+                direction,
+                amount,
+            }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let (tab, window_id, pane_index) =
+                                mux.containing_tab_and_pane_index(pane_id)?;
+                            tab.adjust_pane_size_at(pane_index, direction, amount);
+                            mux.notify(MuxNotification::WindowInvalidated(window_id));
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
+            Pdu::GetPaneRenderableDimensions(GetPaneRenderableDimensions { pane_id }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let pane = mux
+                                .get_pane(pane_id)
+                                .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+                            Ok(Pdu::GetPaneRenderableDimensionsResponse(
+                                GetPaneRenderableDimensionsResponse {
+                                    pane_id,
+                                    cursor_position: pane.get_cursor_position(),
+                                    dimensions: pane.get_dimensions(),
+                                },
+                            ))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
             Pdu::Resize(Resize {
                 containing_tab_id,
                 pane_id,
@@ -436,6 +629,25 @@ impl SessionHandler {
                 })
                 .detach();
             }
+            Pdu::SendKeyUp(SendKeyUp { pane_id, event }) => {
+                let sender = self.to_write_tx.clone();
+                let per_pane = self.per_pane(pane_id);
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let pane = mux
+                                .get_pane(pane_id)
+                                .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+                            pane.key_up(event.key, event.modifiers)?;
+                            maybe_push_pane_changes(&pane, sender, per_pane)?;
+                            Ok(Pdu::UnitResponse(UnitResponse {}))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
             Pdu::SendMouseEvent(SendMouseEvent { pane_id, event }) => {
                 let sender = self.to_write_tx.clone();
                 let per_pane = self.per_pane(pane_id);
@@ -480,7 +692,7 @@ impl SessionHandler {
                 .detach();
             }
 
-            Pdu::GetPaneRenderChanges(GetPaneRenderChanges { pane_id, .. }) => {
+            Pdu::GetPaneRenderChanges(GetPaneRenderChanges { pane_id, seqno }) => {
                 let sender = self.to_write_tx.clone();
                 let per_pane = self.per_pane(pane_id);
                 spawn_into_main_thread(async move {
@@ -489,6 +701,7 @@ impl SessionHandler {
                             let mux = Mux::get().unwrap();
                             let is_alive = match mux.get_pane(pane_id) {
                                 Some(pane) => {
+                                    per_pane.lock().unwrap().seed_seqno(&pane, seqno);
                                     maybe_push_pane_changes(&pane, sender, per_pane)?;
                                     true
                                 }
@@ -533,6 +746,41 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::GetSessionSnapshot(GetSessionSnapshot {}) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get().unwrap();
+                            let snapshot = SessionSnapshot::capture(&mux);
+                            Ok(Pdu::GetSessionSnapshotResponse(
+                                GetSessionSnapshotResponse { snapshot },
+                            ))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
+            Pdu::RestoreSession(RestoreSession { snapshot }) => {
+                spawn_into_main_thread(async move {
+                    schedule_restore_session(snapshot, send_response);
+                })
+                .detach();
+            }
+
+            Pdu::TransferFiles(TransferFiles {
+                pane_id,
+                direction,
+                paths,
+                destination,
+            }) => {
+                spawn_into_main_thread(async move {
+                    schedule_transfer_files(pane_id, direction, paths, destination, send_response);
+                })
+                .detach();
+            }
+
             Pdu::GetCodecVersion(_) => {
                 send_response(Ok(Pdu::GetCodecVersionResponse(GetCodecVersionResponse {
                     codec_vers: CODEC_VERSION,
@@ -568,6 +816,10 @@ impl SessionHandler {
             | Pdu::GetLinesResponse { .. }
             | Pdu::GetCodecVersionResponse { .. }
             | Pdu::GetTlsCredsResponse { .. }
+            | Pdu::GetPaneRenderableDimensionsResponse { .. }
+            | Pdu::GetSessionSnapshotResponse { .. }
+            | Pdu::TransferFilesResponse { .. }
+            | Pdu::SetCompressionResponse { .. }
             | Pdu::PaneRemoved { .. }
             | Pdu::ErrorResponse { .. } => {
                 send_response(Err(anyhow!("expected a request, got {:?}", decoded.pdu)))
@@ -602,6 +854,45 @@ where
     promise::spawn::spawn(async move { send_response(split_pane(split, sender).await) }).detach();
 }
 
+fn schedule_restore_session<SND>(snapshot: SessionSnapshot, send_response: SND)
+where
+    SND: Fn(anyhow::Result<Pdu>) + 'static,
+{
+    promise::spawn::spawn(async move {
+        send_response(
+            snapshot
+                .restore()
+                .await
+                .map(|_| Pdu::UnitResponse(UnitResponse {})),
+        )
+    })
+    .detach();
+}
+
+fn schedule_transfer_files<SND>(
+    pane_id: PaneId,
+    direction: TransferDirection,
+    paths: Vec<String>,
+    destination: Option<String>,
+    send_response: SND,
+) where
+    SND: Fn(anyhow::Result<Pdu>) + 'static,
+{
+    let transfer = match mux::transfer::start_transfer(pane_id, direction, paths, destination) {
+        Ok(transfer) => transfer,
+        Err(err) => return send_response(Err(err)),
+    };
+    promise::spawn::spawn(async move {
+        send_response(
+            transfer
+                .wait()
+                .await
+                .map(|paths| Pdu::TransferFilesResponse(TransferFilesResponse { paths })),
+        )
+    })
+    .detach();
+}
+
 struct RemoteClipboard {
     sender: PduSender,
     pane_id: PaneId,
@@ -709,7 +1000,7 @@ async fn domain_spawn(spawn: Spawn, sender: PduSender) -> anyhow::Result<Pdu> {
             .ok_or_else(|| anyhow!("window_id {} not found on this server", window_id))?;
         window_id
     } else {
-        window_builder = mux.new_empty_window();
+        window_builder = mux.new_empty_window(Some(spawn.workspace.clone()));
         *window_builder
     };
 
@@ -766,7 +1057,7 @@ async fn domain_spawn_v2(spawn: SpawnV2, sender: PduSender) -> anyhow::Result<Pd
         (window_id, size)
     } else {
         term_config = None;
-        window_builder = mux.new_empty_window();
+        window_builder = mux.new_empty_window(Some(spawn.workspace.clone()));
         (*window_builder, spawn.size)
     };
 
@@ -7,7 +7,7 @@ use std::io::{Read, Write};
 use structopt::StructOpt;
 use termwiz::cell::unicode_column_width;
 use termwiz::lineedit::*;
-use wezterm_ssh::{Config, Session, SessionEvent};
+use wezterm_ssh::{Config, HostKeyStatus, Session, SessionEvent};
 
 #[derive(Default)]
 struct PasswordPromptHost {
@@ -71,6 +71,10 @@ fn main() {
                 }
                 SessionEvent::HostVerify(verify) => {
                     eprintln!("{}", verify.message);
+                    if let HostKeyStatus::Revoked(_) = verify.status {
+                        // The session refuses to connect without an answer
+                        continue;
+                    }
                     let mut terminal = line_editor_terminal()?;
                     let mut editor = LineEditor::new(&mut terminal);
                     let mut host = PasswordPromptHost::default();
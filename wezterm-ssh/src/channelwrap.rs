@@ -99,6 +99,16 @@ impl ChannelWrap {
         }
     }
 
+    pub fn request_auth_agent_forwarding(&mut self) -> anyhow::Result<()> {
+        match self {
+            Self::Ssh2(_) => {
+                log::warn!("agent forwarding is not supported by the ssh2 backend");
+                Ok(())
+            }
+            Self::LibSsh(chan) => Ok(chan.request_auth_agent()?),
+        }
+    }
+
     pub fn request_exec(&mut self, command_line: &str) -> anyhow::Result<()> {
         match self {
             Self::Ssh2(chan) => Ok(chan.exec(command_line)?),
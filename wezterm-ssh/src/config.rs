@@ -1,13 +1,14 @@
 //! Parse an ssh_config(5) formatted config file
 use regex::{Captures, Regex};
 use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::Path;
 
 pub type ConfigMap = BTreeMap<String, String>;
 
 /// A Pattern in a `Host` list
 #[derive(Debug, PartialEq, Eq, Clone)]
-struct Pattern {
+pub(crate) struct Pattern {
     negated: bool,
     pattern: String,
 }
@@ -32,7 +33,7 @@ fn wildcard_to_pattern(s: &str) -> String {
 
 impl Pattern {
     /// Returns true if this pattern matches the provided hostname
-    fn match_text(&self, hostname: &str) -> bool {
+    pub fn match_text(&self, hostname: &str) -> bool {
         if let Ok(re) = Regex::new(&self.pattern) {
             re.is_match(hostname)
         } else {
@@ -40,7 +41,7 @@ impl Pattern {
         }
     }
 
-    fn new(text: &str, negated: bool) -> Self {
+    pub fn new(text: &str, negated: bool) -> Self {
         Self {
             pattern: wildcard_to_pattern(text),
             negated,
@@ -63,14 +64,125 @@ impl Pattern {
     }
 }
 
+/// An address range in CIDR notation, as used by `Match localnetwork`
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+struct Network {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Network {
+    /// Parses `addr/len`; a bare address is treated as a network
+    /// that contains just that address
+    fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr.parse::<IpAddr>().ok()?, Some(len.parse::<u8>().ok()?)),
+            None => (s.parse::<IpAddr>().ok()?, None),
+        };
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix_len = prefix_len.unwrap_or(max_len);
+        if prefix_len > max_len {
+            return None;
+        }
+        Some(Self { addr, prefix_len })
+    }
+
+    fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(*addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(*addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+fn parse_network_list(s: &str) -> Vec<Network> {
+    s.split(',')
+        .filter_map(|net| {
+            let parsed = Network::parse(net.trim());
+            if parsed.is_none() {
+                log::error!("invalid network `{}` in `Match localnetwork`", net);
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// Returns the addresses of the local network interfaces
+#[cfg(unix)]
+fn local_interface_addresses() -> Vec<IpAddr> {
+    let mut result = vec![];
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        log::error!(
+            "getifaddrs failed, `Match localnetwork` will not match: {}",
+            std::io::Error::last_os_error()
+        );
+        return result;
+    }
+
+    let mut ifa = addrs;
+    while !ifa.is_null() {
+        let addr = unsafe { (*ifa).ifa_addr };
+        if !addr.is_null() {
+            match unsafe { (*addr).sa_family } as libc::c_int {
+                libc::AF_INET => {
+                    let sin = unsafe { &*(addr as *const libc::sockaddr_in) };
+                    result.push(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                        sin.sin_addr.s_addr,
+                    ))));
+                }
+                libc::AF_INET6 => {
+                    let sin6 = unsafe { &*(addr as *const libc::sockaddr_in6) };
+                    result.push(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)));
+                }
+                _ => {}
+            }
+        }
+        ifa = unsafe { (*ifa).ifa_next };
+    }
+    unsafe { libc::freeifaddrs(addrs) };
+
+    result
+}
+
+#[cfg(not(unix))]
+fn local_interface_addresses() -> Vec<IpAddr> {
+    log::warn!("`Match localnetwork` is not supported on this system and will not match");
+    vec![]
+}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 enum Criteria {
+    /// A `Host` stanza; matches the host for the current pass
     Host(Vec<Pattern>),
+    /// `Match host`; matches the HostName configured so far,
+    /// or the host for the current pass if none has been set
+    MatchHost(Vec<Pattern>),
     Exec(String),
     OriginalHost(Vec<Pattern>),
     User(Vec<Pattern>),
     LocalUser(Vec<Pattern>),
+    Tagged(Vec<Pattern>),
+    /// Matches if any local interface address is in one of the networks
+    LocalNetwork(Vec<Network>),
     All,
+    Not(Box<Criteria>),
+    /// A criteria that we don't understand; it never matches
+    Unsupported(String),
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -89,42 +201,90 @@ struct MatchGroup {
     options: ConfigMap,
 }
 
-impl MatchGroup {
-    fn is_match(&self, hostname: &str, user: &str, local_user: &str, context: Context) -> bool {
-        if self.context != context {
-            return false;
+/// The values that `Match` criteria are evaluated against
+struct MatchState<'a> {
+    config: &'a Config,
+    /// The host as specified by the user in the first pass,
+    /// or its resolved HostName in the final pass
+    host: &'a str,
+    original_host: &'a str,
+    local_user: &'a str,
+    /// The options that have been resolved so far
+    options: &'a ConfigMap,
+    /// True if `Match canonical` blocks apply to the final pass
+    canonical: bool,
+}
+
+impl<'a> MatchState<'a> {
+    fn target_host(&self) -> String {
+        match self.options.get("hostname") {
+            Some(hostname) => hostname.replace("%h", self.original_host),
+            None => self.host.to_string(),
         }
-        for c in &self.criteria {
-            match c {
-                Criteria::Host(patterns) => {
-                    if !Pattern::match_group(hostname, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::Exec(_) => {
-                    log::warn!("Match Exec is not implemented");
-                }
-                Criteria::OriginalHost(patterns) => {
-                    if !Pattern::match_group(hostname, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::User(patterns) => {
-                    if !Pattern::match_group(user, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::LocalUser(patterns) => {
-                    if !Pattern::match_group(local_user, patterns) {
-                        return false;
-                    }
-                }
-                Criteria::All => {
-                    // Always matches
-                }
-            }
+    }
+
+    fn target_user(&self) -> &str {
+        self.options
+            .get("user")
+            .map(|s| s.as_str())
+            .unwrap_or(self.local_user)
+    }
+
+    /// Runs the command from a `Match exec` criteria, returning
+    /// true if it exited successfully
+    fn exec(&self, command: &str) -> bool {
+        let mut connection = ConfigMap::new();
+        connection.insert("%h".to_string(), self.target_host());
+        connection.insert("%n".to_string(), self.original_host.to_string());
+        connection.insert(
+            "%p".to_string(),
+            self.options
+                .get("port")
+                .cloned()
+                .unwrap_or_else(|| "22".to_string()),
+        );
+        connection.insert("%r".to_string(), self.target_user().to_string());
+        self.config.run_match_exec(command, &connection)
+    }
+}
+
+impl Criteria {
+    fn is_match(&self, state: &MatchState) -> bool {
+        match self {
+            Criteria::Host(patterns) => Pattern::match_group(state.host, patterns),
+            Criteria::MatchHost(patterns) => Pattern::match_group(&state.target_host(), patterns),
+            Criteria::Exec(command) => state.exec(command),
+            Criteria::OriginalHost(patterns) => Pattern::match_group(state.original_host, patterns),
+            Criteria::User(patterns) => Pattern::match_group(state.target_user(), patterns),
+            Criteria::LocalUser(patterns) => Pattern::match_group(state.local_user, patterns),
+            Criteria::Tagged(patterns) => Pattern::match_group(
+                state.options.get("tag").map(|s| s.as_str()).unwrap_or(""),
+                patterns,
+            ),
+            Criteria::LocalNetwork(networks) => state
+                .config
+                .resolve_local_addresses()
+                .iter()
+                .any(|addr| networks.iter().any(|net| net.contains(addr))),
+            Criteria::All => true,
+            Criteria::Not(criteria) => !criteria.is_match(state),
+            Criteria::Unsupported(_) => false,
         }
-        true
+    }
+}
+
+impl MatchGroup {
+    fn is_match(&self, state: &MatchState, context: Context) -> bool {
+        match self.context {
+            Context::FirstPass => {}
+            Context::Final if context == Context::Final => {}
+            Context::Canonical if context == Context::Final && state.canonical => {}
+            _ => return false,
+        }
+        // Criteria are evaluated in order, and evaluation stops at
+        // the first mismatch so that we don't run `exec` commands
+        // unnecessarily
+        self.criteria.iter().all(|c| c.is_match(state))
     }
 }
 
@@ -268,46 +428,57 @@ impl ParsedConfigFile {
                     let mut criteria = vec![];
                     let mut context = Context::FirstPass;
 
-                    let mut tokens = v.split_ascii_whitespace();
-
-                    while let Some(cname) = tokens.next() {
-                        match cname.to_lowercase().as_str() {
-                            "all" => {
-                                criteria.push(Criteria::All);
-                            }
-                            "canonical" => {
+                    let args = split_match_args(v);
+                    let mut args = args.iter().map(|s| s.as_str());
+
+                    while let Some(cname) = args.next() {
+                        let lower = cname.to_lowercase();
+                        let (negated, name) = match lower.strip_prefix('!') {
+                            Some(name) => (true, name),
+                            None => (false, lower.as_str()),
+                        };
+                        let criterion = match name {
+                            "all" => Criteria::All,
+                            "canonical" if !negated => {
                                 context = Context::Canonical;
+                                continue;
                             }
-                            "final" => {
+                            "final" if !negated => {
                                 context = Context::Final;
+                                continue;
                             }
-                            "exec" => {
-                                criteria.push(Criteria::Exec(
-                                    tokens.next().unwrap_or("false").to_string(),
-                                ));
-                            }
+                            "exec" => Criteria::Exec(args.next().unwrap_or("false").to_string()),
                             "host" => {
-                                criteria.push(Criteria::Host(parse_pattern_list(
-                                    tokens.next().unwrap_or(""),
-                                )));
+                                Criteria::MatchHost(parse_pattern_list(args.next().unwrap_or("")))
                             }
-                            "originalhost" => {
-                                criteria.push(Criteria::OriginalHost(parse_pattern_list(
-                                    tokens.next().unwrap_or(""),
-                                )));
+                            "originalhost" => Criteria::OriginalHost(parse_pattern_list(
+                                args.next().unwrap_or(""),
+                            )),
+                            "user" => Criteria::User(parse_pattern_list(args.next().unwrap_or(""))),
+                            "localuser" => {
+                                Criteria::LocalUser(parse_pattern_list(args.next().unwrap_or("")))
                             }
-                            "user" => {
-                                criteria.push(Criteria::User(parse_pattern_list(
-                                    tokens.next().unwrap_or(""),
-                                )));
+                            "tagged" => {
+                                Criteria::Tagged(parse_pattern_list(args.next().unwrap_or("")))
                             }
-                            "localuser" => {
-                                criteria.push(Criteria::LocalUser(parse_pattern_list(
-                                    tokens.next().unwrap_or(""),
-                                )));
+                            "localnetwork" => Criteria::LocalNetwork(parse_network_list(
+                                args.next().unwrap_or(""),
+                            )),
+                            _ => {
+                                log::error!(
+                                    "unsupported criteria `{}` in `Match {}`; \
+                                     this Match block will be ignored",
+                                    cname,
+                                    v
+                                );
+                                Criteria::Unsupported(cname.to_string())
                             }
-                            _ => break,
-                        }
+                        };
+                        criteria.push(if negated {
+                            Criteria::Not(Box::new(criterion))
+                        } else {
+                            criterion
+                        });
                     }
 
                     groups.push(MatchGroup {
@@ -320,13 +491,20 @@ impl ParsedConfigFile {
 
                 fn add_option(options: &mut ConfigMap, k: String, v: &str) {
                     // first option wins in ssh_config, except for identityfile
-                    // which explicitly allows multiple entries to combine together
-                    let is_identity_file = k == "identityfile";
+                    // and the port forwarding options, which explicitly allow
+                    // multiple entries to combine together.
+                    // Forwarding specs contain spaces, so we combine those
+                    // on separate lines instead.
+                    let separator = match k.as_str() {
+                        "identityfile" | "wezterm_ssh_agent_identityfile" => Some(' '),
+                        "localforward" | "remoteforward" | "dynamicforward" => Some('\n'),
+                        _ => None,
+                    };
                     options
                         .entry(k)
                         .and_modify(|e| {
-                            if is_identity_file {
-                                e.push(' ');
+                            if let Some(separator) = separator {
+                                e.push(separator);
                                 e.push_str(v);
                             }
                         })
@@ -347,30 +525,79 @@ impl ParsedConfigFile {
     /// semantics are that the first match wins
     fn apply_matches(
         &self,
-        hostname: &str,
-        user: &str,
+        config: &Config,
+        host: &str,
+        original_host: &str,
         local_user: &str,
         context: Context,
         target: &mut ConfigMap,
-    ) -> bool {
-        let mut needs_reparse = false;
-
+    ) {
         for (k, v) in &self.options {
             target.entry(k.to_string()).or_insert_with(|| v.to_string());
         }
         for group in &self.groups {
-            if group.context != Context::FirstPass {
-                needs_reparse = true;
-            }
-            if group.is_match(hostname, user, local_user, context) {
+            let state = MatchState {
+                config,
+                host,
+                original_host,
+                local_user,
+                options: target,
+                canonical: is_canonicalization_enabled(target),
+            };
+            if group.is_match(&state, context) {
                 for (k, v) in &group.options {
                     target.entry(k.to_string()).or_insert_with(|| v.to_string());
                 }
             }
         }
+    }
+
+    /// Returns true if any of the groups are evaluated in `context`
+    fn has_context(&self, context: Context) -> bool {
+        self.groups.iter().any(|group| group.context == context)
+    }
+}
 
-        needs_reparse
+/// Returns true if CanonicalizeHostname is enabled in `options`
+fn is_canonicalization_enabled(options: &ConfigMap) -> bool {
+    match options
+        .get("canonicalizehostname")
+        .map(|s| s.to_lowercase())
+    {
+        Some(value) => value == "yes" || value == "always",
+        None => false,
+    }
+}
+
+/// Splits the arguments to `Match` on whitespace, treating
+/// double quoted strings as a single argument
+fn split_match_args(s: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut in_quotes = false;
+    for c in s.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
     }
+    args
 }
 
 /// A context for resolving configuration values.
@@ -382,6 +609,7 @@ pub struct Config {
     options: ConfigMap,
     tokens: ConfigMap,
     environment: Option<ConfigMap>,
+    local_addresses: Option<Vec<IpAddr>>,
 }
 
 impl Config {
@@ -392,6 +620,7 @@ impl Config {
             options: ConfigMap::new(),
             tokens: ConfigMap::new(),
             environment: None,
+            local_addresses: None,
         }
     }
 
@@ -402,6 +631,19 @@ impl Config {
         self.environment.replace(env);
     }
 
+    /// Assign a fake set of local interface addresses, useful for testing.
+    /// The addresses are used to evaluate `Match localnetwork`.
+    pub fn assign_local_addresses(&mut self, addrs: Vec<IpAddr>) {
+        self.local_addresses.replace(addrs);
+    }
+
+    fn resolve_local_addresses(&self) -> Vec<IpAddr> {
+        match &self.local_addresses {
+            Some(addrs) => addrs.clone(),
+            None => local_interface_addresses(),
+        }
+    }
+
     /// Assigns token names and expansions for use with a number of
     /// options.  The names and expansions are specified
     /// by `man 5 ssh_config`
@@ -469,40 +711,56 @@ impl Config {
         let target_user = &local_user;
 
         let mut result = self.options.clone();
-        let mut needs_reparse = false;
 
         for config in &self.config_files {
-            if config.apply_matches(
+            config.apply_matches(
+                self,
+                host,
                 host,
-                target_user,
                 &local_user,
                 Context::FirstPass,
                 &mut result,
-            ) {
-                needs_reparse = true;
-            }
-        }
-
-        if needs_reparse {
-            log::warn!(
-                "ssh configuration uses options that require two-phase \
-                parsing, which isn't supported"
             );
         }
 
-        for (k, v) in &mut result {
-            if let Some(tokens) = self.should_expand_tokens(k) {
-                self.expand_tokens(v, tokens);
-            }
-
-            if self.should_expand_environment(k) {
-                self.expand_environment(v);
+        // `Match final` requests a second pass over the config, matching
+        // against the resolved HostName, as does `Match canonical` when
+        // CanonicalizeHostname is enabled.
+        // We don't canonicalize via DNS, so the HostName is used as-is.
+        // Values that were set in the first pass take precedence.
+        let wants_final = self.config_files.iter().any(|config| {
+            config.has_context(Context::Final)
+                || (config.has_context(Context::Canonical) && is_canonicalization_enabled(&result))
+        });
+        if wants_final {
+            let final_host = result
+                .get("hostname")
+                .map(|hostname| hostname.replace("%h", host))
+                .unwrap_or_else(|| host.to_string());
+            for config in &self.config_files {
+                config.apply_matches(
+                    self,
+                    &final_host,
+                    host,
+                    &local_user,
+                    Context::Final,
+                    &mut result,
+                );
             }
         }
 
-        result
-            .entry("hostname".to_string())
-            .or_insert_with(|| host.to_string());
+        // Resolve the connection parameters first, as the %h, %p and %r
+        // tokens that are used by other options refer to them
+        let mut connection = ConfigMap::new();
+        connection.insert("%h".to_string(), host.to_string());
+        connection.insert("%n".to_string(), host.to_string());
+
+        let mut hostname = result
+            .get("hostname")
+            .cloned()
+            .unwrap_or_else(|| host.to_string());
+        self.expand_tokens(&mut hostname, &["%h"], &connection);
+        result.insert("hostname".to_string(), hostname.clone());
 
         result
             .entry("port".to_string())
@@ -512,6 +770,24 @@ impl Config {
             .entry("user".to_string())
             .or_insert_with(|| target_user.clone());
 
+        connection.insert("%h".to_string(), hostname);
+        connection.insert("%p".to_string(), result["port"].clone());
+        connection.insert("%r".to_string(), result["user"].clone());
+
+        for (k, v) in &mut result {
+            if k == "hostname" {
+                continue;
+            }
+
+            if let Some(tokens) = self.should_expand_tokens(k) {
+                self.expand_tokens(v, tokens, &connection);
+            }
+
+            if self.should_expand_environment(k) {
+                self.expand_environment(v);
+            }
+        }
+
         if !result.contains_key("userknownhostsfile") {
             if let Some(home) = self.resolve_home() {
                 result.insert(
@@ -546,8 +822,14 @@ impl Config {
     /// expansion.
     fn should_expand_environment(&self, key: &str) -> bool {
         match key {
-            "certificatefile" | "controlpath" | "identityagent" | "identityfile"
-            | "userknownhostsfile" | "localforward" | "remoteforward" => true,
+            "certificatefile"
+            | "controlpath"
+            | "identityagent"
+            | "identityfile"
+            | "userknownhostsfile"
+            | "localforward"
+            | "remoteforward"
+            | "wezterm_ssh_agent_identityfile" => true,
             _ => false,
         }
     }
@@ -555,8 +837,15 @@ impl Config {
     /// Returns a set of tokens that should be expanded for a given option name
     fn should_expand_tokens(&self, key: &str) -> Option<&[&str]> {
         match key {
-            "certificatefile" | "controlpath" | "identityagent" | "identityfile"
-            | "localforward" | "remotecommand" | "remoteforward" | "userknownkostsfile" => {
+            "certificatefile"
+            | "controlpath"
+            | "identityagent"
+            | "identityfile"
+            | "localforward"
+            | "remotecommand"
+            | "remoteforward"
+            | "userknownkostsfile"
+            | "wezterm_ssh_agent_identityfile" => {
                 Some(&["%C", "%d", "%h", "%i", "%L", "%l", "%n", "%p", "%r", "%u"])
             }
             "hostname" => Some(&["%h"]),
@@ -568,6 +857,43 @@ impl Config {
         }
     }
 
+    /// Runs the command from a `Match exec` criteria via the shell,
+    /// returning true if it exited successfully.
+    /// `connection` holds the values of the connection related tokens.
+    fn run_match_exec(&self, command: &str, connection: &ConfigMap) -> bool {
+        let mut command = command.to_string();
+        self.expand_tokens(
+            &mut command,
+            &[
+                "%C", "%d", "%h", "%i", "%k", "%L", "%l", "%n", "%p", "%r", "%u",
+            ],
+            connection,
+        );
+
+        let mut cmd;
+        if cfg!(windows) {
+            let comspec = std::env::var("COMSPEC").unwrap_or_else(|_| "cmd".to_string());
+            cmd = std::process::Command::new(comspec);
+            cmd.args(&["/c", &command]);
+        } else {
+            cmd = std::process::Command::new("sh");
+            cmd.args(&["-c", &command]);
+        }
+        cmd.stdin(std::process::Stdio::null());
+        cmd.stdout(std::process::Stdio::null());
+
+        match cmd.status() {
+            Ok(status) => {
+                log::trace!("Match exec `{}` exited with {}", command, status);
+                status.success()
+            }
+            Err(err) => {
+                log::error!("Match exec `{}` failed: {:#}", command, err);
+                false
+            }
+        }
+    }
+
     /// Resolve the home directory.
     /// For the sake of unit testing, this will look for HOME in the provided
     /// environment override before asking the system for the home directory.
@@ -585,10 +911,13 @@ impl Config {
         None
     }
 
-    /// Perform token substitution
-    fn expand_tokens(&self, value: &mut String, tokens: &[&str]) {
+    /// Perform token substitution.
+    /// `connection` holds the values of the tokens that describe the
+    /// connection being configured; explicitly assigned tokens
+    /// take precedence over those.
+    fn expand_tokens(&self, value: &mut String, tokens: &[&str], connection: &ConfigMap) {
         for &t in tokens {
-            if let Some(v) = self.tokens.get(t) {
+            if let Some(v) = self.tokens.get(t).or_else(|| connection.get(t)) {
                 *value = value.replace(t, v);
             } else if t == "%u" {
                 *value = value.replace(t, &self.resolve_local_user());
@@ -699,6 +1028,7 @@ Config {
             "USER": "me",
         },
     ),
+    local_addresses: None,
 }
 "#
         );
@@ -752,6 +1082,81 @@ Config {
         );
     }
 
+    #[test]
+    fn multiple_forwards() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Host foo
+            HostName 10.0.0.1
+            User foo
+            IdentityFile "~/.ssh/id_pub.dsa"
+            LocalForward 8080 localhost:80
+            LocalForward 127.0.0.1:8443 localhost:443
+            RemoteForward 9000 localhost:9000
+            DynamicForward 1080
+            "#,
+        );
+
+        let opts = config.for_host("foo");
+        snapshot!(
+            opts,
+            r#"
+{
+    "dynamicforward": "1080",
+    "hostname": "10.0.0.1",
+    "identityfile": "/home/me/.ssh/id_pub.dsa",
+    "localforward": "8080 localhost:80\n127.0.0.1:8443 localhost:443",
+    "port": "22",
+    "remoteforward": "9000 localhost:9000",
+    "user": "foo",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+    }
+
+    #[test]
+    fn proxy_command_tokens() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+
+        config.add_config_string(
+            r#"
+        Host foo
+            HostName %h.example.com
+            Port 2222
+            IdentityFile "~/.ssh/id_pub.dsa"
+            ProxyCommand ssh -W %h:%p -l %r bastion
+            "#,
+        );
+
+        let opts = config.for_host("foo");
+        snapshot!(
+            opts,
+            r#"
+{
+    "hostname": "foo.example.com",
+    "identityfile": "/home/me/.ssh/id_pub.dsa",
+    "port": "2222",
+    "proxycommand": "ssh -W foo.example.com:2222 -l me bastion",
+    "user": "me",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+    }
+
     #[test]
     fn sub_tilde() {
         let mut config = Config::new();
@@ -829,7 +1234,7 @@ Config {
             groups: [
                 MatchGroup {
                     criteria: [
-                        Host(
+                        MatchHost(
                             [
                                 Pattern {
                                     negated: false,
@@ -850,7 +1255,7 @@ Config {
                 },
                 MatchGroup {
                     criteria: [
-                        Host(
+                        MatchHost(
                             [
                                 Pattern {
                                     negated: true,
@@ -879,7 +1284,7 @@ Config {
                 },
                 MatchGroup {
                     criteria: [
-                        Host(
+                        MatchHost(
                             [
                                 Pattern {
                                     negated: true,
@@ -933,6 +1338,7 @@ Config {
             "USER": "me",
         },
     ),
+    local_addresses: None,
 }
 "#
         );
@@ -1023,6 +1429,169 @@ Config {
         );
     }
 
+    #[test]
+    fn split_match() {
+        assert_eq!(
+            split_match_args(r#"host foo,bar exec "test -f ~/.vpn" !user  me"#),
+            vec!["host", "foo,bar", "exec", "test -f ~/.vpn", "!user", "me"]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn match_exec() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+        // Keep `Match localnetwork` independent of the host we run on
+        config.assign_local_addresses(vec!["192.168.1.2".parse().unwrap()]);
+
+        config.add_config_string(
+            r#"
+        Host foo
+            HostName %h.corp.example.com
+
+        Match host *.corp.example.com exec "test %h:%n:%r = foo.corp.example.com:foo:me"
+            ProxyJump bastion-a
+
+        Match exec "false"
+            ProxyJump never
+
+        Match !exec "false" originalhost bar
+            ProxyJump bastion-b
+
+        Match final host *.corp.example.com
+            User corp
+
+        Match canonical all
+            Port 2222
+
+        Match localnetwork 10.0.0.0/8
+            Port 2223
+            "#,
+        );
+
+        let opts = config.for_host("foo");
+        snapshot!(
+            opts,
+            r#"
+{
+    "hostname": "foo.corp.example.com",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "port": "22",
+    "proxyjump": "bastion-a",
+    "user": "corp",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+
+        let opts = config.for_host("bar");
+        snapshot!(
+            opts,
+            r#"
+{
+    "hostname": "bar",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "port": "22",
+    "proxyjump": "bastion-b",
+    "user": "me",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+
+        // `Match canonical` only applies when canonicalization is enabled
+        config.set_option("canonicalizehostname", "yes");
+        let opts = config.for_host("bar");
+        snapshot!(
+            opts,
+            r#"
+{
+    "canonicalizehostname": "yes",
+    "hostname": "bar",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "port": "2222",
+    "proxyjump": "bastion-b",
+    "user": "me",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+    }
+
+    #[test]
+    fn match_localnetwork() {
+        let mut config = Config::new();
+
+        let mut fake_env = ConfigMap::new();
+        fake_env.insert("HOME".to_string(), "/home/me".to_string());
+        fake_env.insert("USER".to_string(), "me".to_string());
+        config.assign_environment(fake_env);
+        config.assign_local_addresses(vec![
+            "127.0.0.1".parse().unwrap(),
+            "10.1.2.3".parse().unwrap(),
+            "fe80::1".parse().unwrap(),
+        ]);
+
+        config.add_config_string(
+            r#"
+        Match localnetwork 192.168.0.0/16,10.0.0.0/8
+            Port 2223
+
+        Match localnetwork fd00::/8,10.1.2.4
+            User nope
+
+        Match localnetwork fe80::/10
+            ProxyJump lan
+
+        Match !localnetwork 10.1.2.3/32
+            IdentitiesOnly yes
+            "#,
+        );
+
+        let opts = config.for_host("foo");
+        snapshot!(
+            opts,
+            r#"
+{
+    "hostname": "foo",
+    "identityfile": "/home/me/.ssh/id_dsa /home/me/.ssh/id_ecdsa /home/me/.ssh/id_ed25519 /home/me/.ssh/id_rsa",
+    "port": "2223",
+    "proxyjump": "lan",
+    "user": "me",
+    "userknownhostsfile": "/home/me/.ssh/known_hosts /home/me/.ssh/known_hosts2",
+}
+"#
+        );
+    }
+
+    #[test]
+    fn parse_network() {
+        let net = Network::parse("10.0.0.0/8").unwrap();
+        assert!(net.contains(&"10.255.0.1".parse().unwrap()));
+        assert!(!net.contains(&"11.0.0.1".parse().unwrap()));
+        assert!(!net.contains(&"::ffff:10.0.0.1".parse().unwrap()));
+
+        let net = Network::parse("0.0.0.0/0").unwrap();
+        assert!(net.contains(&"1.2.3.4".parse().unwrap()));
+
+        let net = Network::parse("fe80::/10").unwrap();
+        assert!(net.contains(&"febf::1".parse().unwrap()));
+        assert!(!net.contains(&"fec0::1".parse().unwrap()));
+
+        let net = Network::parse("192.168.1.1").unwrap();
+        assert!(net.contains(&"192.168.1.1".parse().unwrap()));
+        assert!(!net.contains(&"192.168.1.2".parse().unwrap()));
+
+        assert_eq!(Network::parse("10.0.0.0/33"), None);
+        assert_eq!(Network::parse("10.0.0/8"), None);
+        assert_eq!(Network::parse("nope"), None);
+    }
+
     #[test]
     fn parse_simple() {
         let mut config = Config::new();
@@ -1130,6 +1699,7 @@ Config {
             "USER": "me",
         },
     ),
+    local_addresses: None,
 }
 "#
         );
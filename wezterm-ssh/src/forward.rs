@@ -0,0 +1,752 @@
+use crate::channelwrap::ChannelWrap;
+use crate::config::ConfigMap;
+use crate::sessioninner::{ChannelId, ChannelInfo, DescriptorState};
+use crate::sessionwrap::SessionWrap;
+use anyhow::{anyhow, Context};
+use filedescriptor::{
+    socketpair, FileDescriptor, FromRawSocketDescriptor, IntoRawSocketDescriptor,
+};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How long we allow a SOCKS client to take to send its request
+const SOCKS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long we wait to connect to the local target of a remote forward
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Describes a port forwarding rule, in the same terms as the
+/// `LocalForward`, `RemoteForward` and `DynamicForward` ssh_config
+/// options.
+/// A `bind_address` of `None` listens only on the loopback interface,
+/// while `*` (or the empty string) listens on all interfaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Forward {
+    /// Listen on the local host and connect to `host:port` from
+    /// the remote host
+    Local {
+        bind_address: Option<String>,
+        bind_port: u16,
+        host: String,
+        port: u16,
+    },
+    /// Listen on the remote host and connect to `host:port` from
+    /// the local host
+    Remote {
+        bind_address: Option<String>,
+        bind_port: u16,
+        host: String,
+        port: u16,
+    },
+    /// Act as a SOCKS4/SOCKS5 proxy on the local host, connecting to
+    /// the requested destinations from the remote host
+    Dynamic {
+        bind_address: Option<String>,
+        bind_port: u16,
+    },
+}
+
+impl Forward {
+    /// Parses a `LocalForward` value: `[bind_address:]port host:hostport`
+    pub fn parse_local(spec: &str) -> anyhow::Result<Self> {
+        let (bind_address, bind_port, host, port) = parse_listen_and_target(spec)?;
+        Ok(Self::Local {
+            bind_address,
+            bind_port,
+            host,
+            port,
+        })
+    }
+
+    /// Parses a `RemoteForward` value: `[bind_address:]port host:hostport`
+    pub fn parse_remote(spec: &str) -> anyhow::Result<Self> {
+        if spec.split_whitespace().count() == 1 {
+            anyhow::bail!(
+                "RemoteForward {}: dynamic remote forwarding is not supported",
+                spec
+            );
+        }
+        let (bind_address, bind_port, host, port) = parse_listen_and_target(spec)?;
+        Ok(Self::Remote {
+            bind_address,
+            bind_port,
+            host,
+            port,
+        })
+    }
+
+    /// Parses a `DynamicForward` value: `[bind_address:]port`
+    pub fn parse_dynamic(spec: &str) -> anyhow::Result<Self> {
+        let (bind_address, bind_port) = parse_address(spec.trim())?;
+        Ok(Self::Dynamic {
+            bind_address,
+            bind_port,
+        })
+    }
+
+    /// Returns the forwarding rules that are specified in the
+    /// configuration for a host.
+    pub fn from_config(config: &ConfigMap) -> anyhow::Result<Vec<Self>> {
+        let mut forwards = vec![];
+        let options: [(&str, fn(&str) -> anyhow::Result<Self>); 3] = [
+            ("localforward", Self::parse_local),
+            ("remoteforward", Self::parse_remote),
+            ("dynamicforward", Self::parse_dynamic),
+        ];
+        for (key, parse) in &options {
+            if let Some(value) = config.get(*key) {
+                // Multiple entries are accumulated on separate lines
+                for spec in value.lines().filter(|s| !s.trim().is_empty()) {
+                    forwards.push(parse(spec)?);
+                }
+            }
+        }
+        Ok(forwards)
+    }
+}
+
+fn parse_listen_and_target(spec: &str) -> anyhow::Result<(Option<String>, u16, String, u16)> {
+    let mut words = spec.split_whitespace();
+    let (listen, target) = match (words.next(), words.next(), words.next()) {
+        (Some(listen), Some(target), None) => (listen, target),
+        _ => anyhow::bail!(
+            "invalid forward {}: expected `[bind_address:]port host:hostport`",
+            spec
+        ),
+    };
+    let (bind_address, bind_port) = parse_address(listen)?;
+    let (host, port) = parse_address(target)?;
+    let host = host.ok_or_else(|| anyhow!("invalid forward {}: missing host", spec))?;
+    Ok((bind_address, bind_port, host, port))
+}
+
+/// Parses `[host:]port`, where the host may also be separated from
+/// the port by a slash, or be an IPv6 address enclosed in square brackets
+fn parse_address(s: &str) -> anyhow::Result<(Option<String>, u16)> {
+    if s.starts_with('/') {
+        anyhow::bail!("{}: forwarding unix domain sockets is not supported", s);
+    }
+    let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+        let (host, port) = rest
+            .split_once("]:")
+            .or_else(|| rest.split_once("]/"))
+            .ok_or_else(|| anyhow!("invalid address {}", s))?;
+        (Some(host), port)
+    } else if let Some((host, port)) = s.rsplit_once('/') {
+        (Some(host), port)
+    } else if let Some((host, port)) = s.rsplit_once(':') {
+        (Some(host), port)
+    } else {
+        (None, s)
+    };
+    let port = port
+        .parse::<u16>()
+        .with_context(|| format!("invalid port in {}", s))?;
+    Ok((host.map(|h| h.to_string()), port))
+}
+
+/// Maps the ssh_config notion of a bind address to one that we
+/// can listen on
+fn resolve_bind_address(bind_address: Option<&str>) -> &str {
+    match bind_address {
+        None | Some("localhost") => "127.0.0.1",
+        Some("") | Some("*") => "0.0.0.0",
+        Some(addr) => addr,
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct LocalForward {
+    pub bind_address: Option<String>,
+    pub bind_port: u16,
+    /// Where to connect from the remote host, or None to act
+    /// as a SOCKS proxy
+    pub target: Option<(String, u16)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct DirectTcpIp {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug)]
+pub(crate) struct RemoteForward {
+    pub bind_address: Option<String>,
+    pub bind_port: u16,
+    pub host: String,
+    pub port: u16,
+}
+
+/// A local listener that is polled by the session thread
+pub(crate) struct ForwardListener {
+    pub listener: TcpListener,
+    pub target: Option<(String, u16)>,
+}
+
+/// A connection whose SOCKS handshake has completed and which
+/// is waiting for its channel to be opened
+pub(crate) struct SocksConnect {
+    stream: TcpStream,
+    host: String,
+    port: u16,
+    version: SocksVersion,
+}
+
+/// The result of connecting to the local target of a remote
+/// forward, which is waiting in pending_remote_forwards
+pub(crate) struct RemoteConnect {
+    id: ChannelId,
+    result: anyhow::Result<TcpStream>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SocksVersion {
+    V4,
+    V5,
+}
+
+impl SocksVersion {
+    /// Returns the reply to send to the client once we know whether
+    /// the connection could be established
+    fn reply(self, success: bool) -> Vec<u8> {
+        match self {
+            Self::V4 => vec![0, if success { 0x5a } else { 0x5b }, 0, 0, 0, 0, 0, 0],
+            Self::V5 => vec![5, if success { 0 } else { 1 }, 0, 1, 0, 0, 0, 0, 0, 0],
+        }
+    }
+}
+
+fn read_u8<R: Read>(r: &mut R) -> anyhow::Result<u8> {
+    let mut buf = [0u8; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: Read>(r: &mut R) -> anyhow::Result<u16> {
+    let mut buf = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_nul_terminated<R: Read>(r: &mut R) -> anyhow::Result<String> {
+    let mut bytes = vec![];
+    loop {
+        match read_u8(r)? {
+            0 => break,
+            b => bytes.push(b),
+        }
+        if bytes.len() > 255 {
+            anyhow::bail!("SOCKS4 request string is too long");
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Performs the server side of a SOCKS4, SOCKS4a or SOCKS5 handshake
+/// for a CONNECT request, returning the requested destination.
+/// The final reply to the client is left to the caller, as it
+/// depends on whether the channel can be opened.
+fn socks_handshake<S: Read + Write>(stream: &mut S) -> anyhow::Result<(String, u16, SocksVersion)> {
+    match read_u8(stream)? {
+        4 => {
+            let command = read_u8(stream)?;
+            let port = read_u16(stream)?;
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip)?;
+            let _user_id = read_nul_terminated(stream)?;
+            if command != 1 {
+                stream.write_all(&SocksVersion::V4.reply(false))?;
+                anyhow::bail!("unsupported SOCKS4 command {}", command);
+            }
+            // SOCKS4a signals that a hostname follows by using
+            // an address of the form 0.0.0.x
+            let host = if ip[0..3] == [0, 0, 0] && ip[3] != 0 {
+                read_nul_terminated(stream)?
+            } else {
+                std::net::Ipv4Addr::from(ip).to_string()
+            };
+            Ok((host, port, SocksVersion::V4))
+        }
+        5 => {
+            let num_methods = read_u8(stream)?;
+            let mut methods = vec![0u8; num_methods as usize];
+            stream.read_exact(&mut methods)?;
+            // We only support the "no authentication required" method
+            if !methods.contains(&0) {
+                stream.write_all(&[5, 0xff])?;
+                anyhow::bail!("SOCKS5 client does not support unauthenticated access");
+            }
+            stream.write_all(&[5, 0])?;
+
+            let mut header = [0u8; 4];
+            stream.read_exact(&mut header)?;
+            if header[0] != 5 {
+                anyhow::bail!("invalid SOCKS5 request version {}", header[0]);
+            }
+            let host = match header[3] {
+                1 => {
+                    let mut ip = [0u8; 4];
+                    stream.read_exact(&mut ip)?;
+                    std::net::Ipv4Addr::from(ip).to_string()
+                }
+                3 => {
+                    let len = read_u8(stream)?;
+                    let mut name = vec![0u8; len as usize];
+                    stream.read_exact(&mut name)?;
+                    String::from_utf8(name)?
+                }
+                4 => {
+                    let mut ip = [0u8; 16];
+                    stream.read_exact(&mut ip)?;
+                    std::net::Ipv6Addr::from(ip).to_string()
+                }
+                atyp => {
+                    stream.write_all(&[5, 8, 0, 1, 0, 0, 0, 0, 0, 0])?;
+                    anyhow::bail!("unsupported SOCKS5 address type {}", atyp);
+                }
+            };
+            let port = read_u16(stream)?;
+            if header[1] != 1 {
+                stream.write_all(&[5, 7, 0, 1, 0, 0, 0, 0, 0, 0])?;
+                anyhow::bail!("unsupported SOCKS5 command {}", header[1]);
+            }
+            Ok((host, port, SocksVersion::V5))
+        }
+        version => anyhow::bail!("unsupported SOCKS version {}", version),
+    }
+}
+
+fn tcp_stream_to_fd(stream: TcpStream) -> anyhow::Result<FileDescriptor> {
+    stream.set_nodelay(true)?;
+    Ok(unsafe { FileDescriptor::from_socket_descriptor(stream.into_socket_descriptor()) })
+}
+
+fn connect_with_timeout(host: &str, port: u16) -> anyhow::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("resolving {}:{}", host, port))?
+    {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    match last_err {
+        Some(err) => Err(err).with_context(|| format!("connecting to {}:{}", host, port)),
+        None => anyhow::bail!("{}:{} did not resolve to any addresses", host, port),
+    }
+}
+
+impl crate::sessioninner::SessionInner {
+    pub fn local_forward(&mut self, forward: LocalForward) -> anyhow::Result<SocketAddr> {
+        let bind_address = resolve_bind_address(forward.bind_address.as_deref());
+        let listener = TcpListener::bind((bind_address, forward.bind_port))
+            .with_context(|| format!("listening on {}:{}", bind_address, forward.bind_port))?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        self.listeners.push(ForwardListener {
+            listener,
+            target: forward.target,
+        });
+        Ok(addr)
+    }
+
+    pub fn remote_forward(
+        &mut self,
+        sess: &mut SessionWrap,
+        forward: RemoteForward,
+    ) -> anyhow::Result<u16> {
+        // Defer to the server's notion of localhost, rather than
+        // translating to an IP address as we do for local listeners
+        let bind_address = match forward.bind_address.as_deref() {
+            None => "localhost",
+            Some("*") => "",
+            Some(addr) => addr,
+        };
+        let port = sess
+            .listen_forward(bind_address, forward.bind_port)
+            .with_context(|| {
+                format!(
+                    "requesting remote forward of {}:{}",
+                    bind_address, forward.bind_port
+                )
+            })?;
+        self.remote_forwards
+            .insert(port, (forward.host, forward.port));
+        Ok(port)
+    }
+
+    /// Accepts any pending connections on our local listeners and
+    /// from the remote host, and opens channels for them.
+    /// Failing to forward an individual connection is logged but
+    /// doesn't otherwise affect the session.
+    pub fn accept_forwards(&mut self, sess: &mut SessionWrap) {
+        let mut accepted = vec![];
+        for fwd in &self.listeners {
+            loop {
+                match fwd.listener.accept() {
+                    Ok((stream, peer)) => accepted.push((stream, peer, fwd.target.clone())),
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        log::error!("accepting forwarded connection: {:#}", err);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for (stream, peer, target) in accepted {
+            match target {
+                Some((host, port)) => {
+                    if let Err(err) =
+                        self.open_direct_tcpip(sess, stream, peer, &host, port, vec![])
+                    {
+                        log::error!("forwarding {} to {}:{}: {:#}", peer, host, port, err);
+                    }
+                }
+                None => self.spawn_socks_handshake(stream),
+            }
+        }
+
+        while let Ok(connect) = self.socks_rx.try_recv() {
+            let SocksConnect {
+                mut stream,
+                host,
+                port,
+                version,
+            } = connect;
+            let (peer, relay) = match stream.peer_addr().and_then(|peer| {
+                let relay = stream.try_clone()?;
+                Ok((peer, relay))
+            }) {
+                Ok(pair) => pair,
+                Err(err) => {
+                    log::error!("SOCKS client went away: {:#}", err);
+                    continue;
+                }
+            };
+            if let Err(err) =
+                self.open_direct_tcpip(sess, relay, peer, &host, port, version.reply(true))
+            {
+                log::error!("SOCKS connect to {}:{}: {:#}", host, port, err);
+                stream.write_all(&version.reply(false)).ok();
+            }
+        }
+
+        while let Ok(RemoteConnect { id, result }) = self.remote_connect_rx.try_recv() {
+            let mut channel = match self.pending_remote_forwards.remove(&id) {
+                Some(channel) => channel,
+                None => continue,
+            };
+            match result.and_then(tcp_stream_to_fd) {
+                Ok(fd) => {
+                    if let Err(err) = self.add_forwarded_channel(channel, fd, vec![]) {
+                        log::error!("remote forward: {:#}", err);
+                    }
+                }
+                Err(err) => {
+                    log::error!("remote forward: {:#}", err);
+                    channel.close();
+                }
+            }
+        }
+
+        if !self.remote_forwards.is_empty() {
+            loop {
+                match sess.accept_forward() {
+                    Ok(Some((bound_port, channel))) => {
+                        if let Err(err) = self.connect_remote_forward(bound_port, channel) {
+                            log::error!("remote forward of port {}: {:#}", bound_port, err);
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        log::error!("accepting remote forwarded connection: {:#}", err);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The SOCKS handshake requires a few round trips with the client,
+    /// so we perform it on a separate thread and pick up the result
+    /// via socks_rx
+    fn spawn_socks_handshake(&self, mut stream: TcpStream) {
+        let tx = self.socks_tx.clone();
+        let wake = self.wake.clone();
+        std::thread::spawn(move || {
+            let result = stream
+                .set_read_timeout(Some(SOCKS_HANDSHAKE_TIMEOUT))
+                .context("setting SOCKS read timeout")
+                .and_then(|_| socks_handshake(&mut stream))
+                .and_then(|(host, port, version)| {
+                    stream.set_read_timeout(None)?;
+                    Ok((host, port, version))
+                });
+            match result {
+                Ok((host, port, version)) => {
+                    if tx
+                        .try_send(SocksConnect {
+                            stream,
+                            host,
+                            port,
+                            version,
+                        })
+                        .is_ok()
+                    {
+                        let _ = wake.lock().unwrap().write(b"x");
+                    }
+                }
+                Err(err) => log::error!("SOCKS handshake failed: {:#}", err),
+            }
+        });
+    }
+
+    /// Connecting to the local target can take a while, so we do it on
+    /// a separate thread and pick up the stream via remote_connect_rx.
+    /// The channel is parked in pending_remote_forwards until then.
+    fn connect_remote_forward(
+        &mut self,
+        bound_port: u16,
+        channel: ChannelWrap,
+    ) -> anyhow::Result<()> {
+        let (host, port) = self
+            .remote_forwards
+            .get(&bound_port)
+            .cloned()
+            .ok_or_else(|| anyhow!("no remote forward is registered for port {}", bound_port))?;
+
+        let id = self.next_channel_id;
+        self.next_channel_id += 1;
+        self.pending_remote_forwards.insert(id, channel);
+
+        let tx = self.remote_connect_tx.clone();
+        let wake = self.wake.clone();
+        std::thread::spawn(move || {
+            let result = connect_with_timeout(&host, port);
+            if tx.try_send(RemoteConnect { id, result }).is_ok() {
+                let _ = wake.lock().unwrap().write(b"x");
+            }
+        });
+        Ok(())
+    }
+
+    fn open_direct_tcpip(
+        &mut self,
+        sess: &mut SessionWrap,
+        stream: TcpStream,
+        peer: SocketAddr,
+        host: &str,
+        port: u16,
+        preamble: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        sess.set_blocking(true);
+        let channel = sess.open_direct_tcpip(host, port, &peer.ip().to_string(), peer.port());
+        sess.set_blocking(false);
+        self.add_forwarded_channel(channel?, tcp_stream_to_fd(stream)?, preamble)
+    }
+
+    /// Opens a channel to `host:port` from the remote host, returning
+    /// one end of a socket pair whose other end is relayed over it
+    pub fn direct_tcpip(
+        &mut self,
+        sess: &mut SessionWrap,
+        direct: DirectTcpIp,
+    ) -> anyhow::Result<FileDescriptor> {
+        let channel = sess
+            .open_direct_tcpip(&direct.host, direct.port, "127.0.0.1", 0)
+            .with_context(|| format!("opening channel to {}:{}", direct.host, direct.port))?;
+        let (ours, theirs) = socketpair()?;
+        self.add_forwarded_channel(channel, ours, vec![])?;
+        Ok(theirs)
+    }
+
+    /// Registers a channel that relays data to and from `stream`.
+    /// `preamble` is sent to the stream ahead of any data from the channel.
+    pub fn add_forwarded_channel(
+        &mut self,
+        channel: ChannelWrap,
+        mut read_from_stream: FileDescriptor,
+        preamble: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        read_from_stream.set_non_blocking(true)?;
+        let write_to_stream = read_from_stream.try_clone()?;
+
+        let mut to_stream = VecDeque::with_capacity(8192.max(preamble.len()));
+        to_stream.extend(preamble);
+
+        let channel_id = self.next_channel_id;
+        self.next_channel_id += 1;
+
+        self.channels.insert(
+            channel_id,
+            ChannelInfo {
+                channel_id,
+                channel,
+                exit: None,
+                forwarded: true,
+                descriptors: [
+                    DescriptorState {
+                        fd: Some(read_from_stream),
+                        buf: VecDeque::with_capacity(8192),
+                    },
+                    DescriptorState {
+                        fd: Some(write_to_stream),
+                        buf: to_stream,
+                    },
+                    DescriptorState {
+                        fd: None,
+                        buf: VecDeque::new(),
+                    },
+                ],
+            },
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A fake stream that reads from `input` and collects writes
+    struct FakeStream {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl FakeStream {
+        fn new(input: &[u8]) -> Self {
+            Self {
+                input: std::io::Cursor::new(input.to_vec()),
+                output: vec![],
+            }
+        }
+    }
+
+    impl Read for FakeStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for FakeStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parse_forwards() {
+        assert_eq!(
+            Forward::parse_local("8080 localhost:80").unwrap(),
+            Forward::Local {
+                bind_address: None,
+                bind_port: 8080,
+                host: "localhost".to_string(),
+                port: 80,
+            }
+        );
+        assert_eq!(
+            Forward::parse_local("*:8080 [::1]:80").unwrap(),
+            Forward::Local {
+                bind_address: Some("*".to_string()),
+                bind_port: 8080,
+                host: "::1".to_string(),
+                port: 80,
+            }
+        );
+        assert_eq!(
+            Forward::parse_remote("127.0.0.1/9000 db.example.com/5432").unwrap(),
+            Forward::Remote {
+                bind_address: Some("127.0.0.1".to_string()),
+                bind_port: 9000,
+                host: "db.example.com".to_string(),
+                port: 5432,
+            }
+        );
+        assert_eq!(
+            Forward::parse_dynamic("1080").unwrap(),
+            Forward::Dynamic {
+                bind_address: None,
+                bind_port: 1080,
+            }
+        );
+        assert_eq!(
+            Forward::parse_dynamic("[::1]:1080").unwrap(),
+            Forward::Dynamic {
+                bind_address: Some("::1".to_string()),
+                bind_port: 1080,
+            }
+        );
+
+        assert!(Forward::parse_local("8080").is_err());
+        assert!(Forward::parse_local("8080 80").is_err());
+        assert!(Forward::parse_local("http localhost:80").is_err());
+        assert!(Forward::parse_local("/tmp/sock localhost:80").is_err());
+        assert!(Forward::parse_remote("8080").is_err());
+    }
+
+    #[test]
+    fn forwards_from_config() {
+        let mut config = ConfigMap::new();
+        config.insert(
+            "localforward".to_string(),
+            "8080 localhost:80\n8443 localhost:443".to_string(),
+        );
+        config.insert("dynamicforward".to_string(), "1080".to_string());
+        assert_eq!(
+            Forward::from_config(&config).unwrap(),
+            vec![
+                Forward::parse_local("8080 localhost:80").unwrap(),
+                Forward::parse_local("8443 localhost:443").unwrap(),
+                Forward::parse_dynamic("1080").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn socks4a() {
+        let mut stream =
+            FakeStream::new(b"\x04\x01\x00\x50\x00\x00\x00\x01user\x00example.com\x00");
+        assert_eq!(
+            socks_handshake(&mut stream).unwrap(),
+            ("example.com".to_string(), 80, SocksVersion::V4)
+        );
+        assert!(stream.output.is_empty());
+
+        let mut stream = FakeStream::new(b"\x04\x01\x01\xbb\x0a\x00\x00\x01\x00");
+        assert_eq!(
+            socks_handshake(&mut stream).unwrap(),
+            ("10.0.0.1".to_string(), 443, SocksVersion::V4)
+        );
+    }
+
+    #[test]
+    fn socks5() {
+        let mut stream = FakeStream::new(b"\x05\x01\x00\x05\x01\x00\x03\x0bexample.com\x00\x50");
+        assert_eq!(
+            socks_handshake(&mut stream).unwrap(),
+            ("example.com".to_string(), 80, SocksVersion::V5)
+        );
+        assert_eq!(stream.output, vec![5, 0]);
+
+        // Only username/password authentication is offered
+        let mut stream = FakeStream::new(b"\x05\x01\x02");
+        assert!(socks_handshake(&mut stream).is_err());
+        assert_eq!(stream.output, vec![5, 0xff]);
+
+        // BIND is not supported
+        let mut stream = FakeStream::new(b"\x05\x01\x00\x05\x02\x00\x01\x7f\x00\x00\x01\x00\x50");
+        assert!(socks_handshake(&mut stream).is_err());
+        assert_eq!(stream.output[2..4], [5, 7]);
+    }
+}
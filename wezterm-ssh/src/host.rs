@@ -1,14 +1,54 @@
+use crate::knownhosts::{self, KnownHosts};
 use crate::session::SessionEvent;
 use anyhow::{anyhow, Context};
 use libssh_rs as libssh;
 use smol::channel::{bounded, Sender};
-use ssh2::CheckResult;
-use std::io::Write;
-use std::path::Path;
+use std::path::PathBuf;
+
+/// Identifies a line in a known_hosts file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownHostsLocation {
+    pub path: PathBuf,
+    /// The 1-based line number
+    pub line: usize,
+}
+
+impl std::fmt::Display for KnownHostsLocation {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+/// Why the user is being asked to verify a host key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostKeyStatus {
+    /// There is no known key for this host.
+    /// Trusting the host adds its key to known_hosts.
+    New,
+    /// The key differs from the one recorded at the specified location.
+    /// Trusting the host replaces that stale entry.
+    Changed(KnownHostsLocation),
+    /// There is no key of this type for the host, but a key of another
+    /// type is trusted at the specified location.  This is treated like
+    /// a changed key: the server may be offering a weaker key to an
+    /// attacker in the middle.  Trusting the host adds the new key.
+    OtherKeyTypeKnown(KnownHostsLocation),
+    /// The key is marked as revoked at the specified location.
+    /// The connection is always refused; the answer is ignored.
+    Revoked(KnownHostsLocation),
+}
 
 #[derive(Debug)]
 pub struct HostVerificationEvent {
+    /// A human readable summary of the fields below
     pub message: String,
+    pub host: String,
+    pub port: u16,
+    /// eg: `ssh-ed25519`
+    pub key_type: String,
+    /// The SHA256 fingerprint of the key, as shown by `ssh-keygen -l`
+    pub fingerprint: String,
+    pub status: HostKeyStatus,
     pub(crate) reply: Sender<bool>,
 }
 
@@ -21,6 +61,35 @@ impl HostVerificationEvent {
     }
 }
 
+/// The values of the StrictHostKeyChecking option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StrictHostKeyChecking {
+    /// Ask the user about new and changed keys
+    Ask,
+    /// Never add or replace keys; refuse to connect instead
+    Yes,
+    /// Silently add new keys, but refuse changed keys
+    AcceptNew,
+    /// Silently add new keys, and connect despite changed keys
+    No,
+}
+
+impl StrictHostKeyChecking {
+    fn from_config(value: Option<&str>) -> anyhow::Result<Self> {
+        match value.map(|s| s.to_lowercase()).as_deref() {
+            None | Some("ask") => Ok(Self::Ask),
+            Some("yes") => Ok(Self::Yes),
+            Some("accept-new") => Ok(Self::AcceptNew),
+            Some("no") | Some("off") => Ok(Self::No),
+            Some(value) => anyhow::bail!(
+                "invalid StrictHostKeyChecking value: {}, \
+                 expected one of `yes`, `accept-new`, `no` or `ask`",
+                value
+            ),
+        }
+    }
+}
+
 impl crate::sessioninner::SessionInner {
     pub fn host_verification_libssh(
         &mut self,
@@ -28,166 +97,213 @@ impl crate::sessioninner::SessionInner {
         hostname: &str,
         port: u16,
     ) -> anyhow::Result<()> {
-        let key = sess
-            .get_server_public_key()?
-            .get_public_key_hash_hexa(libssh::PublicKeyHashType::Sha256)?;
-
-        match sess.is_known_server()? {
-            libssh::KnownHosts::Ok => Ok(()),
-            libssh::KnownHosts::NotFound | libssh::KnownHosts::Unknown => {
-                let (reply, confirm) = bounded(1);
-                self.tx_event
-                    .try_send(SessionEvent::HostVerify(HostVerificationEvent {
-                        message: format!(
-                            "SSH host {}:{} is not yet trusted.\n\
-                                    Fingerprint: {}.\n\
-                                    Trust and continue connecting?",
-                            hostname, port, key
-                        ),
-                        reply,
-                    }))
-                    .context("sending HostVerify request to user")?;
-
-                let trusted = smol::block_on(confirm.recv())
-                    .context("waiting for host verification confirmation from user")?;
-
-                if !trusted {
-                    anyhow::bail!("user declined to trust host");
-                }
-
-                Ok(sess.update_known_hosts_file()?)
-            }
-            libssh::KnownHosts::Changed => {
-                anyhow::bail!(
-                    "host key mismatch for ssh server {}:{}.\n\
-                         Got fingerprint {} instead of expected value from known_hosts\n\
-                         file.\n\
-                         Refusing to connect.",
-                    hostname,
-                    port,
-                    key,
-                );
-            }
-            libssh::KnownHosts::Other => {
-                anyhow::bail!(
-                    "The host key for this server was not found, but another\n\
-            type of key exists. An attacker might change the default\n\
-            server key to confuse your client into thinking the key\n\
-            does not exist"
-                );
-            }
-        }
+        let key = sess.get_server_public_key()?.get_public_key_base64()?;
+        let key = base64::decode(key).context("decoding server host key")?;
+        self.verify_host_key(hostname, port, &key)
     }
+
     pub fn host_verification(
         &mut self,
         sess: &ssh2::Session,
         remote_host_name: &str,
         port: u16,
-        remote_address: &str,
     ) -> anyhow::Result<()> {
-        let mut known_hosts = sess.known_hosts().context("preparing known hosts")?;
+        let (key, _key_type) = sess
+            .host_key()
+            .ok_or_else(|| anyhow!("failed to get ssh host key"))?;
+        let key = key.to_vec();
+        self.verify_host_key(remote_host_name, port, &key)
+    }
+
+    fn known_hosts_files(&self, key: &str) -> Vec<PathBuf> {
+        self.config
+            .get(key)
+            .map(|files| files.split_whitespace().map(PathBuf::from).collect())
+            .unwrap_or_else(Vec::new)
+    }
 
-        let known_hosts_files = self
+    /// Checks `key` against the known_hosts files and applies the
+    /// StrictHostKeyChecking policy, asking the user if required
+    fn verify_host_key(&mut self, hostname: &str, port: u16, key: &[u8]) -> anyhow::Result<()> {
+        let policy = StrictHostKeyChecking::from_config(
+            self.config.get("stricthostkeychecking").map(|s| s.as_str()),
+        )?;
+        let hash = self
             .config
-            .get("userknownhostsfile")
-            .unwrap()
-            .split_whitespace()
-            .map(|s| s.to_string());
+            .get("hashknownhosts")
+            .map(|s| s.eq_ignore_ascii_case("yes"))
+            .unwrap_or(false);
 
-        for file in known_hosts_files {
-            let file = Path::new(&file);
+        let user_files = self.known_hosts_files("userknownhostsfile");
+        let mut files = user_files.clone();
+        files.append(&mut self.known_hosts_files("globalknownhostsfile"));
 
-            if !file.exists() {
-                continue;
-            }
+        let status = match KnownHosts::load(&files)?.check(hostname, port, key) {
+            Some(status) => status,
+            None => return Ok(()),
+        };
+
+        let key_type = knownhosts::key_type(key)?;
+        let fingerprint = knownhosts::fingerprint(key)?;
+        let remote_address = format!("{}:{}", hostname, port);
 
-            known_hosts
-                .read_file(&file, ssh2::KnownHostFileKind::OpenSSH)
-                .with_context(|| format!("reading known_hosts file {}", file.display()))?;
-
-            let (key, key_type) = sess
-                .host_key()
-                .ok_or_else(|| anyhow!("failed to get ssh host key"))?;
-
-            let fingerprint = sess
-                .host_key_hash(ssh2::HashType::Sha256)
-                .map(|fingerprint| {
-                    format!(
-                        "SHA256:{}",
-                        base64::encode_config(
-                            fingerprint,
-                            base64::Config::new(base64::CharacterSet::Standard, false)
-                        )
-                    )
-                })
-                .or_else(|| {
-                    // Querying for the Sha256 can fail if for example we were linked
-                    // against libssh < 1.9, so let's fall back to Sha1 in that case.
-                    sess.host_key_hash(ssh2::HashType::Sha1).map(|fingerprint| {
-                        let mut res = vec![];
-                        write!(&mut res, "SHA1").ok();
-                        for b in fingerprint {
-                            write!(&mut res, ":{:02x}", *b).ok();
-                        }
-                        String::from_utf8(res).unwrap()
-                    })
-                })
-                .ok_or_else(|| anyhow!("failed to get host fingerprint"))?;
-
-            match known_hosts.check_port(&remote_host_name, port, key) {
-                CheckResult::Match => {}
-                CheckResult::NotFound => {
-                    let (reply, confirm) = bounded(1);
-                    self.tx_event
-                        .try_send(SessionEvent::HostVerify(HostVerificationEvent {
-                            message: format!(
-                                "SSH host {} is not yet trusted.\n\
-                                {:?} Fingerprint: {}.\n\
-                                Trust and continue connecting?",
-                                remote_address, key_type, fingerprint
-                            ),
-                            reply,
-                        }))
-                        .context("sending HostVerify request to user")?;
-
-                    let trusted = smol::block_on(confirm.recv())
-                        .context("waiting for host verification confirmation from user")?;
-
-                    if !trusted {
-                        anyhow::bail!("user declined to trust host");
-                    }
-
-                    let host_and_port = if port != 22 {
-                        format!("[{}]:{}", remote_host_name, port)
-                    } else {
-                        remote_host_name.to_string()
-                    };
-
-                    known_hosts
-                        .add(&host_and_port, key, &remote_address, key_type.into())
-                        .context("adding known_hosts entry in memory")?;
-
-                    known_hosts
-                        .write_file(&file, ssh2::KnownHostFileKind::OpenSSH)
-                        .with_context(|| format!("writing known_hosts file {}", file.display()))?;
+        let add_new_key = || -> anyhow::Result<()> {
+            let file = user_files
+                .first()
+                .ok_or_else(|| anyhow!("no UserKnownHostsFile is configured"))?;
+            knownhosts::add_entry(file, hostname, port, key, hash)
+        };
+
+        let message = match &status {
+            HostKeyStatus::New => match policy {
+                StrictHostKeyChecking::Yes => anyhow::bail!(
+                    "No {} host key is known for {} and \
+                     StrictHostKeyChecking is enabled.\n\
+                     Fingerprint: {}",
+                    key_type,
+                    remote_address,
+                    fingerprint
+                ),
+                StrictHostKeyChecking::AcceptNew | StrictHostKeyChecking::No => {
+                    log::info!(
+                        "adding {} host key {} for {} to known_hosts",
+                        key_type,
+                        fingerprint,
+                        remote_address
+                    );
+                    return add_new_key();
                 }
-                CheckResult::Mismatch => {
-                    anyhow::bail!(
-                        "host key mismatch for ssh server {}.\n\
-                         Got fingerprint {} instead of expected value from known_hosts\n\
-                         file {}.\n\
-                         Refusing to connect.",
+                StrictHostKeyChecking::Ask => format!(
+                    "SSH host {} is not yet trusted.\n\
+                     {} Fingerprint: {}.\n\
+                     Trust and continue connecting?",
+                    remote_address, key_type, fingerprint
+                ),
+            },
+            HostKeyStatus::Changed(location) => match policy {
+                StrictHostKeyChecking::Yes | StrictHostKeyChecking::AcceptNew => anyhow::bail!(
+                    "host key mismatch for ssh server {}.\n\
+                     Got {} fingerprint {} instead of the key recorded at {}.\n\
+                     Refusing to connect.",
+                    remote_address,
+                    key_type,
+                    fingerprint,
+                    location
+                ),
+                StrictHostKeyChecking::No => {
+                    log::warn!(
+                        "host key for {} has changed to {} {}, \
+                         which doesn't match {}; connecting anyway \
+                         because StrictHostKeyChecking is disabled",
                         remote_address,
+                        key_type,
                         fingerprint,
-                        file.display()
+                        location
                     );
+                    return Ok(());
                 }
-                CheckResult::Failure => {
-                    anyhow::bail!("failed to check the known hosts");
+                StrictHostKeyChecking::Ask => format!(
+                    "WARNING: the host key for {} has changed!\n\
+                     Someone could be eavesdropping on you right now,\n\
+                     or the host key may have just been changed.\n\
+                     {} Fingerprint: {}.\n\
+                     The stale key is recorded at {}.\n\
+                     Replace it and continue connecting?",
+                    remote_address, key_type, fingerprint, location
+                ),
+            },
+            HostKeyStatus::OtherKeyTypeKnown(location) => match policy {
+                StrictHostKeyChecking::Yes | StrictHostKeyChecking::AcceptNew => anyhow::bail!(
+                    "No {} host key is known for ssh server {}, \
+                     but a key of another type is recorded at {}.\n\
+                     Got fingerprint {}.\n\
+                     Refusing to connect.",
+                    key_type,
+                    remote_address,
+                    location,
+                    fingerprint
+                ),
+                StrictHostKeyChecking::No => {
+                    log::warn!(
+                        "host {} offered {} key {} but a key of another \
+                         type is recorded at {}; connecting anyway \
+                         because StrictHostKeyChecking is disabled",
+                        remote_address,
+                        key_type,
+                        fingerprint,
+                        location
+                    );
+                    return Ok(());
                 }
+                StrictHostKeyChecking::Ask => format!(
+                    "WARNING: the host key for {} has changed!\n\
+                     Someone could be eavesdropping on you right now,\n\
+                     or the host may have been reconfigured.\n\
+                     The host offered a {} key, but only a key of another\n\
+                     type is recorded at {}.\n\
+                     {} Fingerprint: {}.\n\
+                     Add it and continue connecting?",
+                    remote_address, key_type, location, key_type, fingerprint
+                ),
+            },
+            HostKeyStatus::Revoked(location) => format!(
+                "The {} host key for {} has been revoked at {}.\n\
+                 Fingerprint: {}.\n\
+                 Refusing to connect.",
+                key_type, remote_address, location, fingerprint
+            ),
+        };
+
+        let (reply, confirm) = bounded(1);
+        self.tx_event
+            .try_send(SessionEvent::HostVerify(HostVerificationEvent {
+                message: message.clone(),
+                host: hostname.to_string(),
+                port,
+                key_type,
+                fingerprint,
+                status: status.clone(),
+                reply,
+            }))
+            .context("sending HostVerify request to user")?;
+
+        if let HostKeyStatus::Revoked(_) = status {
+            anyhow::bail!("{}", message);
+        }
+
+        let trusted = smol::block_on(confirm.recv())
+            .context("waiting for host verification confirmation from user")?;
+        if !trusted {
+            anyhow::bail!("user declined to trust host");
+        }
+
+        match &status {
+            HostKeyStatus::Changed(location) => {
+                knownhosts::replace_entry(location, hostname, port, key, hash)
             }
+            _ => add_new_key(),
         }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        Ok(())
+    #[test]
+    fn parse_strict_host_key_checking() {
+        assert_eq!(
+            StrictHostKeyChecking::from_config(None).unwrap(),
+            StrictHostKeyChecking::Ask
+        );
+        assert_eq!(
+            StrictHostKeyChecking::from_config(Some("Accept-New")).unwrap(),
+            StrictHostKeyChecking::AcceptNew
+        );
+        assert_eq!(
+            StrictHostKeyChecking::from_config(Some("off")).unwrap(),
+            StrictHostKeyChecking::No
+        );
+        assert!(StrictHostKeyChecking::from_config(Some("maybe")).is_err());
     }
 }
@@ -0,0 +1,400 @@
+//! Parse and update OpenSSH known_hosts files.
+//! We check host keys ourselves rather than relying on the ssh
+//! library so that both backends behave the same way.
+use crate::config::Pattern;
+use crate::host::{HostKeyStatus, KnownHostsLocation};
+use anyhow::Context;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Marker {
+    None,
+    CertAuthority,
+    Revoked,
+}
+
+#[derive(Debug)]
+struct Entry {
+    marker: Marker,
+    hosts: String,
+    key_type: String,
+    key: Vec<u8>,
+    location: KnownHostsLocation,
+}
+
+/// Returns the name under which a host is recorded in known_hosts
+fn host_pattern(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+fn hmac_sha1(salt: &[u8], data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let key = PKey::hmac(salt)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+    signer.update(data)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+/// Produces a `|1|salt|hash` hashed hostname, as used when
+/// HashKnownHosts is enabled
+fn hash_host(host: &str) -> anyhow::Result<String> {
+    let mut salt = [0u8; 20];
+    openssl::rand::rand_bytes(&mut salt)?;
+    let hash = hmac_sha1(&salt, host.as_bytes())?;
+    Ok(format!(
+        "|1|{}|{}",
+        base64::encode(&salt),
+        base64::encode(&hash)
+    ))
+}
+
+/// Returns the SHA256 fingerprint of a key, in the same format
+/// as `ssh-keygen -l`
+pub(crate) fn fingerprint(key: &[u8]) -> anyhow::Result<String> {
+    let hash = openssl::hash::hash(MessageDigest::sha256(), key)?;
+    Ok(format!(
+        "SHA256:{}",
+        base64::encode_config(
+            &hash,
+            base64::Config::new(base64::CharacterSet::Standard, false)
+        )
+    ))
+}
+
+/// Extracts the key type that is encoded at the start of a key blob
+pub(crate) fn key_type(key: &[u8]) -> anyhow::Result<String> {
+    if key.len() < 4 {
+        anyhow::bail!("truncated host key");
+    }
+    let len = u32::from_be_bytes([key[0], key[1], key[2], key[3]]) as usize;
+    let name = key
+        .get(4..4 + len)
+        .ok_or_else(|| anyhow::anyhow!("truncated host key"))?;
+    Ok(String::from_utf8(name.to_vec())?)
+}
+
+impl Entry {
+    fn parse(line: &str, location: KnownHostsLocation) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let mut fields = line.split_whitespace();
+        let mut hosts = fields.next()?;
+        let marker = match hosts {
+            "@cert-authority" => Marker::CertAuthority,
+            "@revoked" => Marker::Revoked,
+            _ => Marker::None,
+        };
+        if marker != Marker::None {
+            hosts = fields.next()?;
+        }
+        let key_type = fields.next()?.to_string();
+        let key = base64::decode(fields.next()?).ok()?;
+        Some(Self {
+            marker,
+            hosts: hosts.to_string(),
+            key_type,
+            key,
+            location,
+        })
+    }
+
+    /// Returns true if this entry applies to `host`, which is the
+    /// name produced by `host_pattern`
+    fn matches_host(&self, host: &str) -> bool {
+        if let Some(hashed) = self.hosts.strip_prefix("|1|") {
+            let (salt, hash) = match hashed.split_once('|') {
+                Some(parts) => parts,
+                None => return false,
+            };
+            return match (base64::decode(salt), base64::decode(hash)) {
+                (Ok(salt), Ok(hash)) => hmac_sha1(&salt, host.as_bytes())
+                    .map(|computed| computed == hash)
+                    .unwrap_or(false),
+                _ => false,
+            };
+        }
+
+        let mut matched = false;
+        for pattern in self.hosts.split(',') {
+            let (pattern, negated) = match pattern.strip_prefix('!') {
+                Some(pattern) => (pattern, true),
+                None => (pattern, false),
+            };
+            if Pattern::new(pattern, negated).match_text(host) {
+                if negated {
+                    // A negated match overrides any other match
+                    return false;
+                }
+                matched = true;
+            }
+        }
+        matched
+    }
+}
+
+/// The entries loaded from a set of known_hosts files
+#[derive(Debug, Default)]
+pub(crate) struct KnownHosts {
+    entries: Vec<Entry>,
+}
+
+impl KnownHosts {
+    fn parse(path: &Path, text: &str) -> Vec<Entry> {
+        text.lines()
+            .enumerate()
+            .filter_map(|(idx, line)| {
+                Entry::parse(
+                    line,
+                    KnownHostsLocation {
+                        path: path.to_path_buf(),
+                        line: idx + 1,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Loads the entries from the specified files.
+    /// Files that don't exist are skipped.
+    pub fn load(files: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut entries = vec![];
+        for path in files {
+            match std::fs::read_to_string(path) {
+                Ok(text) => entries.append(&mut Self::parse(path, &text)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("reading known_hosts file {}", path.display()))
+                }
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// Checks the key presented by `host:port`.
+    /// Returns None if the key is trusted.
+    pub fn check(&self, host: &str, port: u16, key: &[u8]) -> Option<HostKeyStatus> {
+        let host = host_pattern(host, port);
+        let key_type = key_type(key).unwrap_or_default();
+        let candidates: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.marker != Marker::CertAuthority && entry.matches_host(&host))
+            .collect();
+
+        if let Some(entry) = candidates
+            .iter()
+            .find(|entry| entry.marker == Marker::Revoked && entry.key == key)
+        {
+            return Some(HostKeyStatus::Revoked(entry.location.clone()));
+        }
+        if candidates
+            .iter()
+            .any(|entry| entry.marker == Marker::None && entry.key == key)
+        {
+            return None;
+        }
+        if let Some(entry) = candidates
+            .iter()
+            .find(|entry| entry.marker == Marker::None && entry.key_type == key_type)
+        {
+            return Some(HostKeyStatus::Changed(entry.location.clone()));
+        }
+        // Any other trusted key means the host is known; accepting a key
+        // of a different type here could be a downgrade by an attacker
+        if let Some(entry) = candidates.iter().find(|entry| entry.marker == Marker::None) {
+            return Some(HostKeyStatus::OtherKeyTypeKnown(entry.location.clone()));
+        }
+        Some(HostKeyStatus::New)
+    }
+}
+
+/// Formats the known_hosts line that records `key` for `host:port`
+fn format_entry(host: &str, port: u16, key: &[u8], hash: bool) -> anyhow::Result<String> {
+    let host = host_pattern(host, port);
+    let host = if hash { hash_host(&host)? } else { host };
+    Ok(format!(
+        "{} {} {}",
+        host,
+        key_type(key)?,
+        base64::encode(key)
+    ))
+}
+
+/// Appends an entry for `host:port` to the known_hosts file at `path`
+pub(crate) fn add_entry(
+    path: &Path,
+    host: &str,
+    port: u16,
+    key: &[u8],
+    hash: bool,
+) -> anyhow::Result<()> {
+    let line = format_entry(host, port, key, hash)?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating directory {}", parent.display()))?;
+    }
+    let mut text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(err).with_context(|| format!("reading known_hosts file {}", path.display()))
+        }
+    };
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(&line);
+    text.push('\n');
+    std::fs::write(path, text)
+        .with_context(|| format!("writing known_hosts file {}", path.display()))
+}
+
+/// Replaces the stale entry at `location` with one that records `key`
+pub(crate) fn replace_entry(
+    location: &KnownHostsLocation,
+    host: &str,
+    port: u16,
+    key: &[u8],
+    hash: bool,
+) -> anyhow::Result<()> {
+    let path = &location.path;
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading known_hosts file {}", path.display()))?;
+    let replacement = format_entry(host, port, key, hash)?;
+
+    let mut lines: Vec<&str> = text.lines().collect();
+    let line = lines
+        .get_mut(location.line - 1)
+        .ok_or_else(|| anyhow::anyhow!("{} has changed since it was read", path.display()))?;
+    *line = &replacement;
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    std::fs::write(path, text)
+        .with_context(|| format!("writing known_hosts file {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ED25519: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIDUb4ZYw5rVxWH/xYMqVmelL7MpJNoZiokhdjmnJoM3P";
+    const ECDSA: &str = "AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBJhEspls3RhzM2pHRGChKRzVMrtqAu71YLHOLcICS6+kxvW38oi6fQ/QduUSy4Xveh2CReoLj6fZ4UgjocLov/o=";
+    const OTHER_ED25519: &str =
+        "AAAAC3NzaC1lZDI1NTE5AAAAIPa8sOlg/W98lzFVmZFmEfxelykiW+6MG9ufr4H7t0Bu";
+
+    fn known_hosts(text: &str) -> KnownHosts {
+        KnownHosts {
+            entries: KnownHosts::parse(Path::new("known_hosts"), text),
+        }
+    }
+
+    fn location(line: usize) -> KnownHostsLocation {
+        KnownHostsLocation {
+            path: PathBuf::from("known_hosts"),
+            line,
+        }
+    }
+
+    #[test]
+    fn check_keys() {
+        let key = base64::decode(ED25519).unwrap();
+        let hosts = known_hosts(&format!(
+            "# comment\n\
+             foo.example.com,10.0.0.1 ssh-ed25519 {ed}\n\
+             [bar.example.com]:2222 ssh-ed25519 {other}\n\
+             *.example.com,!baz.example.com ecdsa-sha2-nistp256 {ecdsa}\n",
+            ed = ED25519,
+            other = OTHER_ED25519,
+            ecdsa = ECDSA
+        ));
+
+        assert_eq!(hosts.check("foo.example.com", 22, &key), None);
+        assert_eq!(hosts.check("10.0.0.1", 22, &key), None);
+        assert_eq!(
+            hosts.check("bar.example.com", 2222, &key),
+            Some(HostKeyStatus::Changed(location(3)))
+        );
+        assert_eq!(
+            hosts.check("qux.example.com", 22, &key),
+            Some(HostKeyStatus::OtherKeyTypeKnown(location(4)))
+        );
+        assert_eq!(
+            hosts.check("bar.example.com", 22, &key),
+            Some(HostKeyStatus::OtherKeyTypeKnown(location(4)))
+        );
+
+        let ecdsa = base64::decode(ECDSA).unwrap();
+        assert_eq!(hosts.check("qux.example.com", 22, &ecdsa), None);
+        assert_eq!(
+            hosts.check("baz.example.com", 22, &ecdsa),
+            Some(HostKeyStatus::New)
+        );
+    }
+
+    #[test]
+    fn other_key_type_known() {
+        let key = base64::decode(ED25519).unwrap();
+        let hosts = known_hosts(&format!(
+            "@revoked foo.example.com ecdsa-sha2-nistp256 {ecdsa}\n\
+             bar.example.com ecdsa-sha2-nistp256 {ecdsa}\n",
+            ecdsa = ECDSA
+        ));
+        assert_eq!(
+            hosts.check("bar.example.com", 22, &key),
+            Some(HostKeyStatus::OtherKeyTypeKnown(location(2)))
+        );
+        // A revoked key of another type doesn't make the host known
+        assert_eq!(
+            hosts.check("foo.example.com", 22, &key),
+            Some(HostKeyStatus::New)
+        );
+    }
+
+    #[test]
+    fn revoked_keys() {
+        let key = base64::decode(ED25519).unwrap();
+        let hosts = known_hosts(&format!(
+            "foo.example.com ssh-ed25519 {ed}\n\
+             @revoked * ssh-ed25519 {ed}\n",
+            ed = ED25519
+        ));
+        assert_eq!(
+            hosts.check("foo.example.com", 22, &key),
+            Some(HostKeyStatus::Revoked(location(2)))
+        );
+    }
+
+    #[test]
+    fn hashed_hosts() {
+        let key = base64::decode(ED25519).unwrap();
+        let line = format_entry("foo.example.com", 2222, &key, true).unwrap();
+        assert!(line.starts_with("|1|"));
+        let hosts = known_hosts(&line);
+        assert_eq!(hosts.check("foo.example.com", 2222, &key), None);
+        assert_eq!(
+            hosts.check("foo.example.com", 22, &key),
+            Some(HostKeyStatus::New)
+        );
+    }
+
+    #[test]
+    fn key_fingerprint() {
+        let key = base64::decode(ED25519).unwrap();
+        assert_eq!(key_type(&key).unwrap(), "ssh-ed25519");
+        assert_eq!(
+            fingerprint(&key).unwrap(),
+            "SHA256:DFt19pL2TS0glCmmpTL9KMtQ+EavyiMwrmm8VH2wTlY"
+        );
+    }
+}
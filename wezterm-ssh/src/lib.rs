@@ -1,9 +1,13 @@
+mod agent;
 mod auth;
 mod channelwrap;
 mod config;
 mod dirwrap;
 mod filewrap;
+mod forward;
 mod host;
+mod knownhosts;
+mod proxy;
 mod pty;
 mod session;
 mod sessioninner;
@@ -13,6 +17,7 @@ mod sftpwrap;
 
 pub use auth::*;
 pub use config::*;
+pub use forward::Forward;
 pub use host::*;
 pub use pty::*;
 pub use session::*;
@@ -0,0 +1,287 @@
+use crate::config::{Config, ConfigMap};
+use crate::session::{Session, SessionEvent};
+use anyhow::Context;
+use filedescriptor::{socketpair, FileDescriptor};
+use std::net::TcpStream;
+
+/// The maximum number of jump hosts that we'll chain through before
+/// assuming that the configuration contains a ProxyJump loop
+const MAX_JUMP_DEPTH: usize = 8;
+
+/// A single hop parsed from a ProxyJump specification:
+/// `[ssh://][user@]host[:port]`
+#[derive(Debug, PartialEq, Eq)]
+struct JumpHost {
+    user: Option<String>,
+    host: String,
+    port: Option<u16>,
+}
+
+impl JumpHost {
+    fn parse(spec: &str) -> anyhow::Result<Self> {
+        let spec = spec.trim();
+        let spec = spec.strip_prefix("ssh://").unwrap_or(spec);
+        let (user, host_port) = match spec.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user.to_string()), host_port),
+            None => (None, spec),
+        };
+        let (host, port) = if let Some(rest) = host_port.strip_prefix('[') {
+            match rest.split_once(']') {
+                Some((host, "")) => (host, None),
+                Some((host, port)) => match port.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => anyhow::bail!("invalid jump host {}", spec),
+                },
+                None => anyhow::bail!("invalid jump host {}", spec),
+            }
+        } else {
+            match host_port.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (host_port, None),
+            }
+        };
+        if host.is_empty() {
+            anyhow::bail!("invalid jump host {}: missing host", spec);
+        }
+        let port = match port {
+            Some(port) => Some(
+                port.parse::<u16>()
+                    .with_context(|| format!("invalid port in jump host {}", spec))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            user,
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+/// Splits a ProxyJump value into the hosts that precede the
+/// final hop, and the final hop itself.
+/// The final hop is the host that we connect through, and it is in
+/// turn reached via the preceding hosts, if any.
+fn split_proxy_jump(proxy_jump: &str) -> anyhow::Result<(Option<&str>, JumpHost)> {
+    match proxy_jump.rsplit_once(',') {
+        Some((earlier, last)) => Ok((Some(earlier), JumpHost::parse(last)?)),
+        None => Ok((None, JumpHost::parse(proxy_jump)?)),
+    }
+}
+
+/// Returns the value of a ProxyCommand/ProxyJump option,
+/// treating `none` as though it were not set
+fn proxy_option<'a>(config: &'a ConfigMap, key: &str) -> Option<&'a str> {
+    config
+        .get(key)
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty() && *s != "none")
+}
+
+/// Computes the configuration for the session to the jump host
+fn jump_host_config(config: &ConfigMap, proxy_jump: &str) -> anyhow::Result<(String, ConfigMap)> {
+    let (earlier, jump) = split_proxy_jump(proxy_jump)?;
+
+    let depth = config
+        .get("wezterm_ssh_jump_depth")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(0)
+        + 1;
+    if depth > MAX_JUMP_DEPTH {
+        anyhow::bail!(
+            "ProxyJump via {} exceeds {} hops; is there a loop in your ssh config?",
+            jump.host,
+            MAX_JUMP_DEPTH
+        );
+    }
+
+    let mut jump_config = Config::new();
+    jump_config.add_default_config_files();
+    if let Some(user) = &jump.user {
+        jump_config.set_option("user", user);
+    }
+    if let Some(port) = jump.port {
+        jump_config.set_option("port", port.to_string());
+    }
+    // When multiple hops are specified, the hosts that precede
+    // this one replace any proxy configured for it
+    if let Some(earlier) = earlier {
+        jump_config.set_option("proxyjump", earlier);
+        jump_config.set_option("proxycommand", "none");
+    }
+    for key in &["wezterm_ssh_backend", "wezterm_ssh_verbose"] {
+        if let Some(value) = config.get(*key) {
+            jump_config.set_option(key, value);
+        }
+    }
+    jump_config.set_option("wezterm_ssh_jump_depth", depth.to_string());
+
+    Ok((jump.host.clone(), jump_config.for_host(&jump.host)))
+}
+
+/// Converts a socket into a TcpStream so that it can be used as the
+/// transport for a session, even though it isn't a TCP connection
+fn socket_into_tcp_stream(fd: FileDescriptor) -> TcpStream {
+    #[cfg(unix)]
+    unsafe {
+        use std::os::unix::io::{FromRawFd, IntoRawFd};
+        TcpStream::from_raw_fd(fd.into_raw_fd())
+    }
+    #[cfg(windows)]
+    unsafe {
+        use std::os::windows::io::{FromRawSocket, IntoRawSocket};
+        TcpStream::from_raw_socket(fd.into_raw_socket())
+    }
+}
+
+impl crate::sessioninner::SessionInner {
+    /// If the configuration specifies a ProxyCommand or ProxyJump,
+    /// establishes the corresponding transport to `hostname:port`.
+    /// Returns None if we should connect directly.
+    /// A ProxyCommand takes precedence over a ProxyJump.
+    pub fn connect_via_proxy(
+        &mut self,
+        hostname: &str,
+        port: u16,
+    ) -> anyhow::Result<Option<TcpStream>> {
+        if let Some(proxy_command) = proxy_option(&self.config, "proxycommand") {
+            let proxy_command = proxy_command.to_string();
+            return self.spawn_proxy_command(&proxy_command).map(Some);
+        }
+        if let Some(proxy_jump) = proxy_option(&self.config, "proxyjump") {
+            let proxy_jump = proxy_jump.to_string();
+            return self
+                .connect_via_jump_host(&proxy_jump, hostname, port)
+                .with_context(|| format!("connecting via ProxyJump {}", proxy_jump))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Runs the ProxyCommand with its stdio connected to a socket
+    /// that we use as the transport for the session
+    fn spawn_proxy_command(&mut self, proxy_command: &str) -> anyhow::Result<TcpStream> {
+        let mut cmd;
+        if cfg!(windows) {
+            let comspec = std::env::var("COMSPEC").unwrap_or_else(|_| "cmd".to_string());
+            cmd = std::process::Command::new(comspec);
+            cmd.args(&["/c", proxy_command]);
+        } else {
+            cmd = std::process::Command::new("sh");
+            cmd.args(&["-c", &format!("exec {}", proxy_command)]);
+        }
+
+        let (a, b) = socketpair()?;
+
+        cmd.stdin(b.as_stdio()?);
+        cmd.stdout(b.as_stdio()?);
+        cmd.stderr(std::process::Stdio::inherit());
+        let child = cmd
+            .spawn()
+            .with_context(|| format!("spawning ProxyCommand {}", proxy_command))?;
+
+        // Keep the child so that it is killed and reaped along with the session
+        self.proxy_command.replace(child);
+
+        Ok(socket_into_tcp_stream(a))
+    }
+
+    /// Connects and authenticates a session to the jump host, and then
+    /// asks it to connect us to `hostname:port`.
+    /// The prompts for the jump host are relayed via our own events,
+    /// so the user is able to respond to them as usual.
+    fn connect_via_jump_host(
+        &mut self,
+        proxy_jump: &str,
+        hostname: &str,
+        port: u16,
+    ) -> anyhow::Result<TcpStream> {
+        let (jump_host, jump_config) = jump_host_config(&self.config, proxy_jump)?;
+
+        self.tx_event
+            .try_send(SessionEvent::Banner(Some(format!(
+                "Connecting to {}:{} via jump host {}",
+                hostname, port, jump_host
+            ))))
+            .context("notifying user of banner")?;
+
+        let (session, events) = Session::connect(jump_config)?;
+        loop {
+            let event = smol::block_on(events.recv())
+                .with_context(|| format!("jump host {} closed the connection", jump_host))?;
+            match event {
+                SessionEvent::Authenticated => break,
+                SessionEvent::Error(err) => {
+                    anyhow::bail!("jump host {}: {}", jump_host, err);
+                }
+                event => {
+                    smol::block_on(self.tx_event.send(event))
+                        .context("relaying jump host event to user")?;
+                }
+            }
+        }
+
+        let stream = smol::block_on(session.direct_tcpip(hostname, port))?;
+
+        // Keep the jump session alive for as long as we are using it
+        self.jump.replace(session);
+
+        Ok(socket_into_tcp_stream(stream))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn jump(user: Option<&str>, host: &str, port: Option<u16>) -> JumpHost {
+        JumpHost {
+            user: user.map(|s| s.to_string()),
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    #[test]
+    fn parse_jump_host() {
+        assert_eq!(
+            JumpHost::parse("bastion").unwrap(),
+            jump(None, "bastion", None)
+        );
+        assert_eq!(
+            JumpHost::parse("me@bastion:2222").unwrap(),
+            jump(Some("me"), "bastion", Some(2222))
+        );
+        assert_eq!(
+            JumpHost::parse("ssh://me@bastion:2222").unwrap(),
+            jump(Some("me"), "bastion", Some(2222))
+        );
+        assert_eq!(
+            JumpHost::parse("[::1]:2222").unwrap(),
+            jump(None, "::1", Some(2222))
+        );
+        assert_eq!(JumpHost::parse("[::1]").unwrap(), jump(None, "::1", None));
+        assert!(JumpHost::parse("bastion:ssh").is_err());
+        assert!(JumpHost::parse("me@").is_err());
+    }
+
+    #[test]
+    fn split_hops() {
+        let (earlier, last) = split_proxy_jump("a,me@b:22,c").unwrap();
+        assert_eq!(earlier, Some("a,me@b:22"));
+        assert_eq!(last, jump(None, "c", None));
+
+        let (earlier, last) = split_proxy_jump("me@b:22").unwrap();
+        assert_eq!(earlier, None);
+        assert_eq!(last, jump(Some("me"), "b", Some(22)));
+    }
+
+    #[test]
+    fn none_disables_proxy() {
+        let mut config = ConfigMap::new();
+        config.insert("proxyjump".to_string(), "none".to_string());
+        config.insert("proxycommand".to_string(), "nc %h %p".to_string());
+        assert_eq!(proxy_option(&config, "proxyjump"), None);
+        assert_eq!(proxy_option(&config, "proxycommand"), Some("nc %h %p"));
+    }
+}
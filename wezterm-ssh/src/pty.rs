@@ -209,17 +209,7 @@ impl crate::sessioninner::SessionInner {
 
         let mut channel = sess.open_session()?;
 
-        /* libssh2 doesn't properly support agent forwarding
-         * at this time:
-         * <https://github.com/libssh2/libssh2/issues/535>
-        if let Some("yes") = self.config.get("forwardagent").map(|s| s.as_str()) {
-            log::info!("requesting agent forwarding");
-            if let Err(err) = channel.request_auth_agent_forwarding() {
-                log::error!("Failed to establish agent forwarding: {:#}", err);
-            }
-            log::info!("agent forwarding OK!");
-        }
-        */
+        self.request_agent_forwarding(&mut channel);
 
         channel.request_pty(&newpty)?;
 
@@ -277,6 +267,7 @@ impl crate::sessioninner::SessionInner {
             channel_id,
             channel,
             exit: Some(exit_tx),
+            forwarded: false,
             descriptors: [
                 DescriptorState {
                     fd: Some(read_from_stdin),
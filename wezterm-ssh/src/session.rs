@@ -1,14 +1,16 @@
 use crate::auth::*;
 use crate::config::ConfigMap;
+use crate::forward::{DirectTcpIp, Forward, LocalForward, RemoteForward};
 use crate::host::*;
 use crate::pty::*;
 use crate::sessioninner::*;
 use crate::sftp::{Sftp, SftpRequest};
 use filedescriptor::{socketpair, FileDescriptor};
 use portable_pty::PtySize;
-use smol::channel::{bounded, Receiver, Sender};
+use smol::channel::{bounded, unbounded, Receiver, Sender};
 use std::collections::HashMap;
 use std::io::Write;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
@@ -52,6 +54,9 @@ pub(crate) enum SessionRequest {
     Exec(Exec, Sender<anyhow::Result<ExecResult>>),
     Sftp(SftpRequest),
     SignalChannel(SignalChannel),
+    LocalForward(LocalForward, Sender<anyhow::Result<SocketAddr>>),
+    RemoteForward(RemoteForward, Sender<anyhow::Result<u16>>),
+    DirectTcpIp(DirectTcpIp, Sender<anyhow::Result<FileDescriptor>>),
 }
 
 #[derive(Debug)]
@@ -89,6 +94,8 @@ impl Session {
             tx: tx_req,
             pipe: Arc::new(Mutex::new(sender_write)),
         };
+        let (socks_tx, socks_rx) = bounded(8);
+        let (remote_connect_tx, remote_connect_rx) = unbounded();
 
         let mut inner = SessionInner {
             config,
@@ -100,6 +107,17 @@ impl Session {
             next_channel_id: 1,
             next_file_id: 1,
             sender_read,
+            wake: Arc::clone(&session_sender.pipe),
+            listeners: vec![],
+            remote_forwards: HashMap::new(),
+            socks_tx,
+            socks_rx,
+            pending_remote_forwards: HashMap::new(),
+            remote_connect_tx,
+            remote_connect_rx,
+            jump: None,
+            proxy_command: None,
+            agent: None,
         };
         std::thread::spawn(move || inner.run());
         Ok((Self { tx: session_sender }, rx_event))
@@ -150,6 +168,135 @@ impl Session {
         Ok(exec)
     }
 
+    /// Listens on the local `bind_address:bind_port` and relays each
+    /// connection to `host:port`, as seen from the remote host.
+    /// Returns the local address that is being listened on.
+    /// The listener remains active until the session is closed.
+    pub async fn forward_local(
+        &self,
+        bind_address: Option<&str>,
+        bind_port: u16,
+        host: &str,
+        port: u16,
+    ) -> anyhow::Result<SocketAddr> {
+        self.listen_local(LocalForward {
+            bind_address: bind_address.map(|s| s.to_string()),
+            bind_port,
+            target: Some((host.to_string(), port)),
+        })
+        .await
+    }
+
+    /// Runs a SOCKS4/SOCKS5 proxy on the local `bind_address:bind_port`,
+    /// making connections to the requested destinations from the
+    /// remote host.
+    /// Returns the local address that is being listened on.
+    pub async fn forward_dynamic(
+        &self,
+        bind_address: Option<&str>,
+        bind_port: u16,
+    ) -> anyhow::Result<SocketAddr> {
+        self.listen_local(LocalForward {
+            bind_address: bind_address.map(|s| s.to_string()),
+            bind_port,
+            target: None,
+        })
+        .await
+    }
+
+    async fn listen_local(&self, forward: LocalForward) -> anyhow::Result<SocketAddr> {
+        let (reply, rx) = bounded(1);
+        self.tx
+            .send(SessionRequest::LocalForward(forward, reply))
+            .await?;
+        rx.recv().await?
+    }
+
+    /// Asks the remote host to listen on `bind_address:bind_port` and
+    /// relays each connection to `host:port`, as seen from the local host.
+    /// Returns the port that the remote host is listening on; this
+    /// is useful when `bind_port` is 0.
+    pub async fn forward_remote(
+        &self,
+        bind_address: Option<&str>,
+        bind_port: u16,
+        host: &str,
+        port: u16,
+    ) -> anyhow::Result<u16> {
+        let (reply, rx) = bounded(1);
+        self.tx
+            .send(SessionRequest::RemoteForward(
+                RemoteForward {
+                    bind_address: bind_address.map(|s| s.to_string()),
+                    bind_port,
+                    host: host.to_string(),
+                    port,
+                },
+                reply,
+            ))
+            .await?;
+        rx.recv().await?
+    }
+
+    /// Opens a connection to `host:port` from the remote host.
+    /// Returns a socket that relays data over that connection.
+    pub async fn direct_tcpip(&self, host: &str, port: u16) -> anyhow::Result<FileDescriptor> {
+        let (reply, rx) = bounded(1);
+        self.tx
+            .send(SessionRequest::DirectTcpIp(
+                DirectTcpIp {
+                    host: host.to_string(),
+                    port,
+                },
+                reply,
+            ))
+            .await?;
+        rx.recv().await?
+    }
+
+    /// Establishes the forwarding rule described by `forward`
+    pub async fn forward(&self, forward: &Forward) -> anyhow::Result<()> {
+        match forward {
+            Forward::Local {
+                bind_address,
+                bind_port,
+                host,
+                port,
+            } => {
+                let addr = self
+                    .forward_local(bind_address.as_deref(), *bind_port, host, *port)
+                    .await?;
+                log::info!("forwarding local {} to remote {}:{}", addr, host, port);
+            }
+            Forward::Remote {
+                bind_address,
+                bind_port,
+                host,
+                port,
+            } => {
+                let bound_port = self
+                    .forward_remote(bind_address.as_deref(), *bind_port, host, *port)
+                    .await?;
+                log::info!(
+                    "forwarding remote port {} to local {}:{}",
+                    bound_port,
+                    host,
+                    port
+                );
+            }
+            Forward::Dynamic {
+                bind_address,
+                bind_port,
+            } => {
+                let addr = self
+                    .forward_dynamic(bind_address.as_deref(), *bind_port)
+                    .await?;
+                log::info!("SOCKS proxy listening on {}", addr);
+            }
+        }
+        Ok(())
+    }
+
     /// Creates a new reference to the sftp channel for filesystem operations
     ///
     /// ### Note
@@ -1,9 +1,11 @@
+use crate::agent::AgentTarget;
 use crate::channelwrap::ChannelWrap;
 use crate::config::ConfigMap;
 use crate::dirwrap::DirWrap;
 use crate::filewrap::FileWrap;
+use crate::forward::{ForwardListener, RemoteConnect, SocksConnect};
 use crate::pty::*;
-use crate::session::{Exec, ExecResult, SessionEvent, SessionRequest, SignalChannel};
+use crate::session::{Exec, ExecResult, Session, SessionEvent, SessionRequest, SignalChannel};
 use crate::sessionwrap::SessionWrap;
 use crate::sftp::dir::{Dir, DirId, DirRequest};
 use crate::sftp::file::{File, FileId, FileRequest};
@@ -12,7 +14,8 @@ use crate::sftpwrap::SftpWrap;
 use anyhow::{anyhow, Context};
 use camino::Utf8PathBuf;
 use filedescriptor::{
-    poll, pollfd, socketpair, AsRawSocketDescriptor, FileDescriptor, POLLIN, POLLOUT,
+    poll, pollfd, socketpair, AsRawSocketDescriptor, FileDescriptor, IntoRawSocketDescriptor,
+    POLLIN, POLLOUT,
 };
 use libssh_rs as libssh;
 use portable_pty::ExitStatus;
@@ -21,6 +24,7 @@ use std::collections::{HashMap, VecDeque};
 use std::ffi::CStr;
 use std::io::{Read, Write};
 use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 #[derive(Debug)]
@@ -33,6 +37,9 @@ pub(crate) struct ChannelInfo {
     pub channel_id: ChannelId,
     pub channel: ChannelWrap,
     pub exit: Option<Sender<ExitStatus>>,
+    /// True if this channel relays a forwarded TCP connection,
+    /// in which case it is discarded once either end has closed
+    pub forwarded: bool,
     pub descriptors: [DescriptorState; 3],
 }
 
@@ -48,11 +55,40 @@ pub(crate) struct SessionInner {
     pub next_channel_id: ChannelId,
     pub next_file_id: FileId,
     pub sender_read: FileDescriptor,
+    /// Used to wake up the request loop from helper threads
+    pub wake: Arc<Mutex<FileDescriptor>>,
+    pub listeners: Vec<ForwardListener>,
+    /// Maps the port of a remote forward to the local host and
+    /// port that connections should be relayed to
+    pub remote_forwards: HashMap<u16, (String, u16)>,
+    pub socks_tx: Sender<SocksConnect>,
+    pub socks_rx: Receiver<SocksConnect>,
+    /// Remote forward channels that are waiting for the connection
+    /// to their local target to be established
+    pub pending_remote_forwards: HashMap<ChannelId, ChannelWrap>,
+    pub remote_connect_tx: Sender<RemoteConnect>,
+    pub remote_connect_rx: Receiver<RemoteConnect>,
+    /// The session that we are tunnelled through when using ProxyJump
+    pub jump: Option<Session>,
+    /// The process that provides our transport when using ProxyCommand
+    pub proxy_command: Option<std::process::Child>,
+    /// Where agent connections are relayed to when ForwardAgent is enabled
+    pub agent: Option<AgentTarget>,
 }
 
 impl Drop for SessionInner {
     fn drop(&mut self) {
         log::trace!("Dropping SessionInner");
+        if let Some(mut child) = self.proxy_command.take() {
+            // The proxy may outlive the connection, so make sure that
+            // it goes away along with the session, and reap it
+            if let Err(err) = child.kill() {
+                log::trace!("killing ProxyCommand: {:#}", err);
+            }
+            if let Err(err) = child.wait() {
+                log::error!("waiting for ProxyCommand to exit: {:#}", err);
+            }
+        }
     }
 }
 
@@ -66,6 +102,8 @@ impl SessionInner {
     }
 
     fn run_impl(&mut self) -> anyhow::Result<()> {
+        self.agent = AgentTarget::from_config(&self.config).context("agent forwarding")?;
+
         let backend = self
             .config
             .get("wezterm_ssh_backend")
@@ -168,13 +206,19 @@ impl SessionInner {
                 break;
             }
         }
-        if let Some(cmd) = self.config.get("proxycommand") {
-            sess.set_option(libssh::SshOption::ProxyCommand(Some(cmd.to_string())))?;
-        }
         if let Some(types) = self.config.get("pubkeyacceptedtypes") {
             sess.set_option(libssh::SshOption::PublicKeyAcceptedTypes(types.to_string()))?;
         }
 
+        if self.agent.is_some() {
+            sess.enable_accept_agent_forward(true);
+        }
+
+        if let Some(stream) = self.connect_via_proxy(&hostname, port)? {
+            // libssh takes ownership of the socket
+            sess.set_option(libssh::SshOption::Socket(stream.into_socket_descriptor()))?;
+        }
+
         sess.connect()?;
 
         let banner = sess.get_server_banner()?;
@@ -225,50 +269,16 @@ impl SessionInner {
             ))))
             .context("notifying user of banner")?;
 
-        let tcp: TcpStream = if let Some(proxy_command) =
-            self.config.get("proxycommand").and_then(|c| {
-                if !c.is_empty() && c != "none" {
-                    Some(c)
-                } else {
-                    None
-                }
-            }) {
-            let mut cmd;
-            if cfg!(windows) {
-                let comspec = std::env::var("COMSPEC").unwrap_or_else(|_| "cmd".to_string());
-                cmd = std::process::Command::new(comspec);
-                cmd.args(&["/c", proxy_command]);
-            } else {
-                cmd = std::process::Command::new("sh");
-                cmd.args(&["-c", &format!("exec {}", proxy_command)]);
+        let tcp: TcpStream = match self.connect_via_proxy(&hostname, port)? {
+            Some(stream) => stream,
+            None => {
+                let socket = TcpStream::connect((hostname.as_str(), port))
+                    .with_context(|| format!("connecting to {}", remote_address))?;
+                socket
+                    .set_nodelay(true)
+                    .context("setting TCP NODELAY on ssh connection")?;
+                socket
             }
-
-            let (a, b) = socketpair()?;
-
-            cmd.stdin(b.as_stdio()?);
-            cmd.stdout(b.as_stdio()?);
-            cmd.stderr(std::process::Stdio::inherit());
-            let _child = cmd
-                .spawn()
-                .with_context(|| format!("spawning ProxyCommand {}", proxy_command))?;
-
-            #[cfg(unix)]
-            unsafe {
-                use std::os::unix::io::{FromRawFd, IntoRawFd};
-                TcpStream::from_raw_fd(a.into_raw_fd())
-            }
-            #[cfg(windows)]
-            unsafe {
-                use std::os::windows::io::{FromRawSocket, IntoRawSocket};
-                TcpStream::from_raw_socket(a.into_raw_socket())
-            }
-        } else {
-            let socket = TcpStream::connect((hostname.as_str(), port))
-                .with_context(|| format!("connecting to {}", remote_address))?;
-            socket
-                .set_nodelay(true)
-                .context("setting TCP NODELAY on ssh connection")?;
-            socket
         };
 
         let mut sess = ssh2::Session::new()?;
@@ -290,7 +300,7 @@ impl SessionInner {
             .try_send(SessionEvent::Banner(sess.banner().map(|s| s.to_string())))
             .context("notifying user of banner")?;
 
-        self.host_verification(&sess, &hostname, port, &remote_address)
+        self.host_verification(&sess, &hostname, port)
             .context("host verification")?;
 
         self.authenticate(&sess, &user, &hostname)
@@ -313,6 +323,8 @@ impl SessionInner {
             self.tick_io()?;
             self.drain_request_pipe();
             self.dispatch_pending_requests(sess)?;
+            self.accept_forwards(sess);
+            self.accept_agent_forwards(sess);
 
             let mut poll_array = vec![
                 pollfd {
@@ -326,6 +338,14 @@ impl SessionInner {
                     revents: 0,
                 },
             ];
+            for fwd in &self.listeners {
+                poll_array.push(pollfd {
+                    fd: fwd.listener.as_socket_descriptor(),
+                    events: POLLIN,
+                    revents: 0,
+                });
+            }
+            let first_channel_idx = poll_array.len();
             let mut mapping = vec![];
 
             for info in self.channels.values() {
@@ -354,10 +374,10 @@ impl SessionInner {
                 if poll.revents != 0 {
                     sleep_delay = Duration::from_millis(100);
                 }
-                if idx == 0 || idx == 1 {
+                if idx < first_channel_idx {
                     // Dealt with at the top of the loop
                 } else if poll.revents != 0 {
-                    let (channel_id, fd_num) = mapping[idx - 2];
+                    let (channel_id, fd_num) = mapping[idx - first_channel_idx];
                     let info = self.channels.get_mut(&channel_id).unwrap();
                     let state = &mut info.descriptors[fd_num];
                     let fd = state.fd.as_mut().unwrap();
@@ -446,6 +466,11 @@ impl SessionInner {
                 }
             }
         }
+
+        // Once the remote end of a forwarded connection has closed and
+        // we've relayed everything it sent, close the local end too
+        self.channels
+            .retain(|_, chan| !(chan.forwarded && chan.descriptors[1].fd.is_none()));
         Ok(())
     }
 
@@ -481,6 +506,17 @@ impl SessionInner {
                     SessionRequest::Exec(exec, reply) => {
                         dispatch(reply, || self.exec(sess, exec), "exec")
                     }
+                    SessionRequest::LocalForward(forward, reply) => {
+                        dispatch(reply, || self.local_forward(forward), "LocalForward")
+                    }
+                    SessionRequest::DirectTcpIp(direct, reply) => {
+                        dispatch(reply, || self.direct_tcpip(sess, direct), "DirectTcpIp")
+                    }
+                    SessionRequest::RemoteForward(forward, reply) => dispatch(
+                        reply,
+                        || self.remote_forward(sess, forward),
+                        "RemoteForward",
+                    ),
                     SessionRequest::SignalChannel(info) => {
                         if let Err(err) = self.signal_channel(&info) {
                             log::error!("{:?} -> error: {:#}", info, err);
@@ -687,6 +723,8 @@ impl SessionInner {
     pub fn exec(&mut self, sess: &mut SessionWrap, exec: Exec) -> anyhow::Result<ExecResult> {
         let mut channel = sess.open_session()?;
 
+        self.request_agent_forwarding(&mut channel);
+
         if let Some(env) = &exec.env {
             for (key, val) in env {
                 if let Err(err) = channel.request_env(key, val) {
@@ -737,6 +775,7 @@ impl SessionInner {
             channel_id,
             channel,
             exit: Some(exit_tx),
+            forwarded: false,
             descriptors: [
                 DescriptorState {
                     fd: Some(read_from_stdin),
@@ -3,10 +3,17 @@ use crate::sftpwrap::SftpWrap;
 use filedescriptor::{AsRawSocketDescriptor, SocketDescriptor, POLLIN, POLLOUT};
 use libssh_rs as libssh;
 use ssh2::BlockDirections;
+use std::time::Duration;
+
+/// The libssh2 error code that indicates that a non-blocking
+/// operation would block
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
 
 pub(crate) struct Ssh2Session {
     pub sess: ssh2::Session,
     pub sftp: Option<SftpWrap>,
+    /// Remote forward listeners, along with the port that they are bound to
+    pub listeners: Vec<(u16, ssh2::Listener)>,
 }
 
 pub(crate) struct LibSshSession {
@@ -21,7 +28,11 @@ pub(crate) enum SessionWrap {
 
 impl SessionWrap {
     pub fn with_ssh2(sess: ssh2::Session) -> Self {
-        Self::Ssh2(Ssh2Session { sess, sftp: None })
+        Self::Ssh2(Ssh2Session {
+            sess,
+            sftp: None,
+            listeners: vec![],
+        })
     }
 
     pub fn with_libssh(sess: libssh::Session) -> Self {
@@ -75,4 +86,80 @@ impl SessionWrap {
             }
         }
     }
+
+    pub fn open_direct_tcpip(
+        &self,
+        host: &str,
+        port: u16,
+        originator_host: &str,
+        originator_port: u16,
+    ) -> anyhow::Result<ChannelWrap> {
+        match self {
+            Self::Ssh2(sess) => {
+                let channel = sess.sess.channel_direct_tcpip(
+                    host,
+                    port,
+                    Some((originator_host, originator_port)),
+                )?;
+                Ok(ChannelWrap::Ssh2(channel))
+            }
+            Self::LibSsh(sess) => {
+                let channel = sess.sess.new_channel()?;
+                channel.open_forward(host, port, originator_host, originator_port)?;
+                Ok(ChannelWrap::LibSsh(channel))
+            }
+        }
+    }
+
+    /// Asks the server to listen on `bind_address:port` and returns
+    /// the port that it is listening on, which is useful when
+    /// port 0 is requested
+    pub fn listen_forward(&mut self, bind_address: &str, port: u16) -> anyhow::Result<u16> {
+        match self {
+            Self::Ssh2(sess) => {
+                let (listener, bound_port) =
+                    sess.sess
+                        .channel_forward_listen(port, Some(bind_address), None)?;
+                sess.listeners.push((bound_port, listener));
+                Ok(bound_port)
+            }
+            Self::LibSsh(sess) => Ok(sess.sess.listen_forward(Some(bind_address), port)?),
+        }
+    }
+
+    /// Returns the next connection that the server has accepted on one
+    /// of our remote forwards, along with the port on which it arrived.
+    /// Returns None if there are no pending connections.
+    pub fn accept_forward(&mut self) -> anyhow::Result<Option<(u16, ChannelWrap)>> {
+        match self {
+            Self::Ssh2(sess) => {
+                for (port, listener) in &mut sess.listeners {
+                    match listener.accept() {
+                        Ok(channel) => return Ok(Some((*port, ChannelWrap::Ssh2(channel)))),
+                        Err(err)
+                            if err.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => {}
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                Ok(None)
+            }
+            Self::LibSsh(sess) => match sess.sess.accept_forward(Duration::from_millis(0)) {
+                Ok((port, channel)) => Ok(Some((port, ChannelWrap::LibSsh(channel)))),
+                // libssh reports a timeout as an error
+                Err(err) => {
+                    log::trace!("accept_forward: {:#}", err);
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    /// Returns an agent channel that was opened by the remote host, if any
+    pub fn accept_agent_forward(&mut self) -> Option<ChannelWrap> {
+        match self {
+            // libssh2 doesn't support agent channels opened by the server
+            Self::Ssh2(_) => None,
+            Self::LibSsh(sess) => sess.sess.accept_agent_forward().map(ChannelWrap::LibSsh),
+        }
+    }
 }
@@ -1,14 +1,17 @@
 use anyhow::{anyhow, Context};
-use config::keyassignment::SpawnTabDomain;
+use config::keyassignment::{PaneDirection, SpawnTabDomain};
 use config::wezterm_version;
 use mux::activity::Activity;
 use mux::pane::PaneId;
-use mux::tab::SplitDirection;
+use mux::session::SessionSnapshot;
+use mux::tab::{PaneEntry, SplitDirection, TabId};
+use mux::transfer::TransferDirection;
 use mux::window::WindowId;
 use mux::Mux;
 use portable_pty::cmdbuilder::CommandBuilder;
 use std::ffi::OsString;
 use std::io::{Read, Write};
+use std::path::PathBuf;
 use std::rc::Rc;
 use structopt::StructOpt;
 use tabout::{tabulate_output, Alignment, Column};
@@ -94,7 +97,12 @@ struct CliCommand {
 #[derive(Debug, StructOpt, Clone)]
 enum CliSubCommand {
     #[structopt(name = "list", about = "list windows, tabs and panes")]
-    List,
+    List {
+        /// Controls the output format.
+        /// "table" and "json" are possible formats.
+        #[structopt(long = "format", default_value = "table")]
+        format: CliOutputFormatKind,
+    },
 
     #[structopt(name = "proxy", about = "start rpc proxy pipe")]
     Proxy,
@@ -157,6 +165,12 @@ Outputs the pane-id for the newly created pane on success"
         #[structopt(long = "new-window", conflicts_with = "window_id")]
         new_window: bool,
 
+        /// When creating a new window, override the default workspace name
+        /// with the provided name.  The default name is "default".
+        /// Requires --new-window.
+        #[structopt(long = "workspace", requires = "new_window")]
+        workspace: Option<String>,
+
         /// Specify the current working directory for the initially
         /// spawned program
         #[structopt(long = "cwd", parse(from_os_str))]
@@ -168,11 +182,278 @@ Outputs the pane-id for the newly created pane on success"
         #[structopt(parse(from_os_str))]
         prog: Vec<OsString>,
     },
+
+    #[structopt(
+        name = "send-text",
+        about = "Send text to a pane as though it were pasted.
+If bracketed paste mode is enabled in the pane, then the
+text will be sent as a bracketed paste."
+    )]
+    SendText {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// Send the text directly, rather than as a bracketed paste.
+        #[structopt(long = "no-paste")]
+        no_paste: bool,
+
+        /// The text to send. If omitted, will read the text from stdin.
+        text: Option<String>,
+    },
+
+    #[structopt(
+        name = "get-text",
+        about = "Retrieves the textual content of a pane and output it to stdout"
+    )]
+    GetText {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// The starting line number.
+        /// 0 is the first line of terminal screen.
+        /// Negative numbers proceed backwards into the scrollback.
+        /// The default value if unspecified is 0, the first line of
+        /// the terminal screen.
+        #[structopt(long = "start-line", allow_hyphen_values = true)]
+        start_line: Option<isize>,
+
+        /// The ending line number, inclusive.
+        /// 0 is the first line of terminal screen.
+        /// Negative numbers proceed backwards into the scrollback.
+        /// The default value if unspecified is the bottom of
+        /// the terminal screen.
+        #[structopt(long = "end-line", allow_hyphen_values = true)]
+        end_line: Option<isize>,
+
+        /// Include escape sequences that color and style the text.
+        /// If omitted, unattributed text will be returned.
+        #[structopt(long = "escapes")]
+        escapes: bool,
+    },
+
+    #[structopt(name = "activate-pane", about = "Activate (focus) a pane")]
+    ActivatePane {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+    },
+
+    #[structopt(
+        name = "activate-pane-direction",
+        about = "Activate an adjacent pane in the specified direction."
+    )]
+    ActivatePaneDirection {
+        /// Specify the current pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// The direction to switch to.
+        /// Possible values are Up, Down, Left, Right, Next and Prev.
+        direction: PaneDirection,
+    },
+
+    #[structopt(name = "kill-pane", about = "Kill a pane")]
+    KillPane {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+    },
+
+    #[structopt(
+        name = "zoom-pane",
+        about = "Zoom, unzoom, or toggle zoom state of a pane"
+    )]
+    ZoomPane {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// Zooms the pane if it wasn't already zoomed
+        #[structopt(long = "zoom", conflicts_with_all=&["unzoom", "toggle"])]
+        zoom: bool,
+
+        /// Unzooms the pane if it was zoomed
+        #[structopt(long = "unzoom", conflicts_with_all=&["zoom", "toggle"])]
+        unzoom: bool,
+
+        /// Toggles the zoom state of the pane.
+        /// This is the default if none of the other options are given.
+        #[structopt(long = "toggle", conflicts_with_all=&["zoom", "unzoom"])]
+        toggle: bool,
+    },
+
+    #[structopt(
+        name = "adjust-pane-size",
+        about = "Adjust the size of a pane directionally"
+    )]
+    AdjustPaneSize {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// Specify the number of cells to resize by, defaults to 1.
+        #[structopt(long = "amount", default_value = "1")]
+        amount: usize,
+
+        /// Specify the direction to resize in.
+        /// Possible values are Up, Down, Left and Right.
+        direction: PaneDirection,
+    },
+
+    #[structopt(
+        name = "save-session",
+        about = "Save the layout of the windows, tabs and panes to a file"
+    )]
+    SaveSession {
+        /// The file to write the session to.
+        /// The default is `session.json` in the wezterm data directory.
+        #[structopt(long = "path", parse(from_os_str))]
+        path: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "restore-session",
+        about = "Respawn the windows, tabs and panes described by a saved session"
+    )]
+    RestoreSession {
+        /// The file to read the session from.
+        /// The default is `session.json` in the wezterm data directory.
+        #[structopt(long = "path", parse(from_os_str))]
+        path: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "upload",
+        about = "Copy local files to the remote host of an ssh pane.
+Directories are copied recursively.  The files are
+transferred over the existing ssh session of the pane,
+using sftp."
+    )]
+    Upload {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// The remote directory to copy the files into.
+        /// Relative paths are resolved against the current working
+        /// directory of the pane, which is also the default.
+        #[structopt(long = "destination")]
+        destination: Option<String>,
+
+        /// The local files to upload
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    #[structopt(
+        name = "download",
+        about = "Copy files from the remote host of an ssh pane.
+Directories are copied recursively.  The files are
+transferred over the existing ssh session of the pane,
+using sftp."
+    )]
+    Download {
+        /// Specify the target pane.
+        /// The default is to use the current pane based on the
+        /// environment variable WEZTERM_PANE.
+        #[structopt(long = "pane-id")]
+        pane_id: Option<PaneId>,
+
+        /// The local directory to copy the files into.
+        /// The default is the current directory.
+        #[structopt(long = "destination", parse(from_os_str))]
+        destination: Option<PathBuf>,
+
+        /// The remote files to download.
+        /// Relative paths are resolved against the current working
+        /// directory of the pane.
+        #[structopt(required = true)]
+        files: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CliOutputFormatKind {
+    Table,
+    Json,
+}
+
+impl std::str::FromStr for CliOutputFormatKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!(
+                "{} is not a valid output format, possible values are \"table\" and \"json\"",
+                s
+            )),
+        }
+    }
 }
 
+/// The representation of a pane that is emitted by `wezterm cli list --format json`
+#[derive(serde::Serialize)]
+struct CliListResultItem {
+    window_id: WindowId,
+    tab_id: TabId,
+    pane_id: PaneId,
+    workspace: String,
+    rows: u16,
+    cols: u16,
+    title: String,
+    cwd: String,
+    is_active: bool,
+    is_zoomed: bool,
+}
+
+impl From<PaneEntry> for CliListResultItem {
+    fn from(entry: PaneEntry) -> Self {
+        Self {
+            window_id: entry.window_id,
+            tab_id: entry.tab_id,
+            pane_id: entry.pane_id,
+            workspace: entry.workspace,
+            rows: entry.size.rows,
+            cols: entry.size.cols,
+            title: entry.title,
+            cwd: entry
+                .working_dir
+                .as_ref()
+                .map(|url| url.url.as_str())
+                .unwrap_or("")
+                .to_string(),
+            is_active: entry.is_active_pane,
+            is_zoomed: entry.is_zoomed_pane,
+        }
+    }
+}
+
+use termwiz::cell::{Blink, CellAttributes, Intensity, Underline};
+use termwiz::color::{ColorAttribute, ColorSpec};
+use termwiz::escape::csi::{Sgr, CSI};
 use termwiz::escape::osc::{
     ITermDimension, ITermFileData, ITermProprietary, OperatingSystemCommand,
 };
+use termwiz::surface::Line;
 
 #[derive(Debug, StructOpt, Clone)]
 struct ImgCatCommand {
@@ -374,63 +655,70 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
     let mut ui = mux::connui::ConnectionUI::new_headless();
     let client = Client::new_default_unix_domain(initial, &mut ui, cli.no_auto_start)?;
     match cli.sub {
-        CliSubCommand::List => {
-            let cols = vec![
-                Column {
-                    name: "WINID".to_string(),
-                    alignment: Alignment::Right,
-                },
-                Column {
-                    name: "TABID".to_string(),
-                    alignment: Alignment::Right,
-                },
-                Column {
-                    name: "PANEID".to_string(),
-                    alignment: Alignment::Right,
-                },
-                Column {
-                    name: "SIZE".to_string(),
-                    alignment: Alignment::Left,
-                },
-                Column {
-                    name: "TITLE".to_string(),
-                    alignment: Alignment::Left,
-                },
-                Column {
-                    name: "CWD".to_string(),
-                    alignment: Alignment::Left,
-                },
-            ];
-            let mut data = vec![];
-            let panes = client.list_panes().await?;
-
-            for tabroot in panes.tabs {
-                let mut cursor = tabroot.into_tree().cursor();
-
-                loop {
-                    if let Some(entry) = cursor.leaf_mut() {
-                        data.push(vec![
-                            entry.window_id.to_string(),
-                            entry.tab_id.to_string(),
-                            entry.pane_id.to_string(),
-                            format!("{}x{}", entry.size.cols, entry.size.rows),
-                            entry.title.clone(),
-                            entry
-                                .working_dir
-                                .as_ref()
-                                .map(|url| url.url.as_str())
-                                .unwrap_or("")
-                                .to_string(),
-                        ]);
-                    }
-                    match cursor.preorder_next() {
-                        Ok(c) => cursor = c,
-                        Err(_) => break,
-                    }
+        CliSubCommand::List { format } => {
+            let entries = list_pane_entries(&client).await?;
+            match format {
+                CliOutputFormatKind::Json => {
+                    let items: Vec<CliListResultItem> =
+                        entries.into_iter().map(Into::into).collect();
+                    let mut out = std::io::stdout();
+                    serde_json::to_writer_pretty(&mut out, &items)?;
+                    writeln!(out)?;
+                }
+                CliOutputFormatKind::Table => {
+                    let cols = vec![
+                        Column {
+                            name: "WINID".to_string(),
+                            alignment: Alignment::Right,
+                        },
+                        Column {
+                            name: "TABID".to_string(),
+                            alignment: Alignment::Right,
+                        },
+                        Column {
+                            name: "PANEID".to_string(),
+                            alignment: Alignment::Right,
+                        },
+                        Column {
+                            name: "WORKSPACE".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                        Column {
+                            name: "SIZE".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                        Column {
+                            name: "TITLE".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                        Column {
+                            name: "CWD".to_string(),
+                            alignment: Alignment::Left,
+                        },
+                    ];
+                    let data: Vec<Vec<String>> = entries
+                        .into_iter()
+                        .map(|entry| {
+                            vec![
+                                entry.window_id.to_string(),
+                                entry.tab_id.to_string(),
+                                entry.pane_id.to_string(),
+                                entry.workspace.clone(),
+                                format!("{}x{}", entry.size.cols, entry.size.rows),
+                                entry.title.clone(),
+                                entry
+                                    .working_dir
+                                    .as_ref()
+                                    .map(|url| url.url.as_str())
+                                    .unwrap_or("")
+                                    .to_string(),
+                            ]
+                        })
+                        .collect();
+
+                    tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
                 }
             }
-
-            tabulate_output(&cols, &data, &mut std::io::stdout().lock())?;
         }
         CliSubCommand::SplitPane {
             pane_id,
@@ -438,17 +726,7 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
             prog,
             horizontal,
         } => {
-            let pane_id: PaneId = match pane_id {
-                Some(p) => p,
-                None => std::env::var("WEZTERM_PANE")
-                    .map_err(|_| {
-                        anyhow!(
-                            "--pane-id was not specified and $WEZTERM_PANE
-                                    is not set in the environment"
-                        )
-                    })?
-                    .parse()?,
-            };
+            let pane_id = resolve_pane_id(pane_id)?;
 
             let spawned = client
                 .split_pane(codec::SplitPane {
@@ -479,6 +757,7 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
             domain_name,
             window_id,
             new_window,
+            workspace,
         } => {
             let window_id = if new_window {
                 None
@@ -486,36 +765,12 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
                 match window_id {
                     Some(w) => Some(w),
                     None => {
-                        let pane_id: PaneId = match pane_id {
-                            Some(p) => p,
-                            None => std::env::var("WEZTERM_PANE")
-                                .map_err(|_| {
-                                    anyhow!(
-                                        "--pane-id was not specified and $WEZTERM_PANE
-                                    is not set in the environment"
-                                    )
-                                })?
-                                .parse()?,
-                        };
-
-                        let panes = client.list_panes().await?;
-                        let mut window_id = None;
-                        'outer: for tabroot in panes.tabs {
-                            let mut cursor = tabroot.into_tree().cursor();
-
-                            loop {
-                                if let Some(entry) = cursor.leaf_mut() {
-                                    if entry.pane_id == pane_id {
-                                        window_id.replace(entry.window_id);
-                                        break 'outer;
-                                    }
-                                }
-                                match cursor.preorder_next() {
-                                    Ok(c) => cursor = c,
-                                    Err(_) => break,
-                                }
-                            }
-                        }
+                        let pane_id = resolve_pane_id(pane_id)?;
+                        let window_id = list_pane_entries(&client)
+                            .await?
+                            .into_iter()
+                            .find(|entry| entry.pane_id == pane_id)
+                            .map(|entry| entry.window_id);
                         window_id
                     }
                 }
@@ -534,6 +789,7 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
                         Some(builder)
                     },
                     command_dir: canon_cwd(cwd)?,
+                    workspace: workspace.unwrap_or_else(|| mux::DEFAULT_WORKSPACE.to_string()),
                     size: config::configuration().initial_size(),
                 })
                 .await?;
@@ -576,10 +832,334 @@ async fn run_cli_async(config: config::ConfigHandle, cli: CliCommand) -> anyhow:
             let creds = client.get_tls_creds().await?;
             codec::Pdu::GetTlsCredsResponse(creds).encode(std::io::stdout().lock(), 0)?;
         }
+        CliSubCommand::SendText {
+            pane_id,
+            no_paste,
+            text,
+        } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            let data = match text {
+                Some(text) => text,
+                None => {
+                    let mut text = String::new();
+                    std::io::stdin()
+                        .read_to_string(&mut text)
+                        .context("reading text from stdin")?;
+                    text
+                }
+            };
+
+            if no_paste {
+                client
+                    .write_to_pane(codec::WriteToPane {
+                        pane_id,
+                        data: data.into_bytes(),
+                    })
+                    .await?;
+            } else {
+                client
+                    .send_paste(codec::SendPaste { pane_id, data })
+                    .await?;
+            }
+        }
+        CliSubCommand::GetText {
+            pane_id,
+            start_line,
+            end_line,
+            escapes,
+        } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            let info = client
+                .get_dimensions(codec::GetPaneRenderableDimensions { pane_id })
+                .await?;
+
+            let start_line = info.dimensions.physical_top + start_line.unwrap_or(0);
+            let end_line = info.dimensions.physical_top
+                + end_line.unwrap_or(info.dimensions.viewport_rows as isize - 1);
+
+            let mut text = String::new();
+            if start_line <= end_line {
+                let lines = client
+                    .get_lines(codec::GetLines {
+                        pane_id,
+                        lines: vec![start_line..end_line + 1],
+                    })
+                    .await?
+                    .lines
+                    .lines();
+
+                let mut last_attr = CellAttributes::default();
+                for (_idx, line) in lines {
+                    if escapes {
+                        render_line_with_escapes(&line, &mut last_attr, &mut text);
+                    } else {
+                        text.push_str(line.as_str().trim_end());
+                    }
+                    if !line.last_cell_was_wrapped() {
+                        text.push('\n');
+                    }
+                }
+                if escapes && last_attr != CellAttributes::default() {
+                    text.push_str(&CSI::Sgr(Sgr::Reset).to_string());
+                }
+            }
+
+            let mut out = std::io::stdout();
+            out.write_all(text.as_bytes())?;
+            out.flush()?;
+        }
+        CliSubCommand::ActivatePane { pane_id } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            client
+                .set_focused_pane(codec::SetFocusedPane { pane_id })
+                .await?;
+        }
+        CliSubCommand::ActivatePaneDirection { pane_id, direction } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            client
+                .activate_pane_direction(codec::ActivatePaneDirection { pane_id, direction })
+                .await?;
+        }
+        CliSubCommand::KillPane { pane_id } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            client.kill_pane(codec::KillPane { pane_id }).await?;
+        }
+        CliSubCommand::ZoomPane {
+            pane_id,
+            zoom,
+            unzoom,
+            toggle: _,
+        } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            let entry = list_pane_entries(&client)
+                .await?
+                .into_iter()
+                .find(|entry| entry.pane_id == pane_id)
+                .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+
+            let zoomed = if zoom {
+                true
+            } else if unzoom {
+                false
+            } else {
+                !entry.is_zoomed_pane
+            };
+
+            client
+                .set_zoomed(codec::SetPaneZoomed {
+                    containing_tab_id: entry.tab_id,
+                    pane_id,
+                    zoomed,
+                })
+                .await?;
+        }
+        CliSubCommand::AdjustPaneSize {
+            pane_id,
+            amount,
+            direction,
+        } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            match direction {
+                PaneDirection::Next | PaneDirection::Prev => {
+                    anyhow::bail!("{:?} is not a valid direction to resize in", direction);
+                }
+                PaneDirection::Up
+                | PaneDirection::Down
+                | PaneDirection::Left
+                | PaneDirection::Right => {}
+            }
+            client
+                .adjust_pane_size(codec::AdjustPaneSize {
+                    pane_id,
+                    direction,
+                    amount,
+                })
+                .await?;
+        }
+        CliSubCommand::SaveSession { path } => {
+            let path = path.unwrap_or_else(mux::session::default_session_path);
+            let snapshot = client
+                .get_session_snapshot(codec::GetSessionSnapshot {})
+                .await?
+                .snapshot;
+            snapshot.save(&path)?;
+        }
+        CliSubCommand::RestoreSession { path } => {
+            let path = path.unwrap_or_else(mux::session::default_session_path);
+            let snapshot = SessionSnapshot::load(&path)?;
+            client
+                .restore_session(codec::RestoreSession { snapshot })
+                .await?;
+        }
+        CliSubCommand::Upload {
+            pane_id,
+            destination,
+            files,
+        } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            // The transfer is carried out by the mux server, whose
+            // working directory may differ from ours
+            let cwd = std::env::current_dir()?;
+            let paths = files
+                .into_iter()
+                .map(|path| {
+                    let path = cwd.join(path);
+                    path.to_str()
+                        .map(|s| s.to_string())
+                        .ok_or_else(|| anyhow!("{} is not valid UTF-8", path.display()))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let created = client
+                .transfer_files(codec::TransferFiles {
+                    pane_id,
+                    direction: TransferDirection::Upload,
+                    paths,
+                    destination,
+                })
+                .await?;
+            for path in created.paths {
+                println!("{}", path);
+            }
+        }
+        CliSubCommand::Download {
+            pane_id,
+            destination,
+            files,
+        } => {
+            let pane_id = resolve_pane_id(pane_id)?;
+            let cwd = std::env::current_dir()?;
+            let destination = match destination {
+                Some(dest) => cwd.join(dest),
+                None => cwd,
+            };
+            let destination = destination
+                .to_str()
+                .ok_or_else(|| anyhow!("{} is not valid UTF-8", destination.display()))?
+                .to_string();
+            let created = client
+                .transfer_files(codec::TransferFiles {
+                    pane_id,
+                    direction: TransferDirection::Download,
+                    paths: files,
+                    destination: Some(destination),
+                })
+                .await?;
+            for path in created.paths {
+                println!("{}", path);
+            }
+        }
     }
     Ok(())
 }
 
+/// Returns the pane id specified on the command line, falling
+/// back to the WEZTERM_PANE environment variable
+fn resolve_pane_id(pane_id: Option<PaneId>) -> anyhow::Result<PaneId> {
+    match pane_id {
+        Some(p) => Ok(p),
+        None => Ok(std::env::var("WEZTERM_PANE")
+            .map_err(|_| {
+                anyhow!(
+                    "--pane-id was not specified and $WEZTERM_PANE
+                    is not set in the environment"
+                )
+            })?
+            .parse()?),
+    }
+}
+
+/// Returns the flattened list of panes known to the mux server
+async fn list_pane_entries(client: &Client) -> anyhow::Result<Vec<PaneEntry>> {
+    let panes = client.list_panes().await?;
+    let mut entries = vec![];
+
+    for tabroot in panes.tabs {
+        let mut cursor = tabroot.into_tree().cursor();
+
+        loop {
+            if let Some(entry) = cursor.leaf_mut() {
+                entries.push(entry.clone());
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(_) => break,
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Appends the text of `line` to `out`, emitting SGR sequences
+/// whenever the attributes differ from those of the prior cell.
+/// `last_attr` tracks the attributes across successive lines.
+fn render_line_with_escapes(line: &Line, last_attr: &mut CellAttributes, out: &mut String) {
+    use std::fmt::Write as _;
+
+    fn color_spec(color: ColorAttribute) -> ColorSpec {
+        match color {
+            ColorAttribute::TrueColorWithPaletteFallback(rgb, _)
+            | ColorAttribute::TrueColorWithDefaultFallback(rgb) => ColorSpec::TrueColor(rgb),
+            ColorAttribute::PaletteIndex(idx) => ColorSpec::PaletteIndex(idx),
+            ColorAttribute::Default => ColorSpec::Default,
+        }
+    }
+
+    let text = line.as_str();
+    let trailing_blank = text.len() - text.trim_end().len();
+    let mut remaining = text.len() - trailing_blank;
+
+    for cell in line.visible_cells().map(|(_, cell)| cell) {
+        if remaining == 0 {
+            break;
+        }
+        remaining = remaining.saturating_sub(cell.str().len());
+
+        let attrs = cell.attrs();
+        if *attrs != *last_attr {
+            let mut sgr = vec![Sgr::Reset];
+            if attrs.intensity() != Intensity::Normal {
+                sgr.push(Sgr::Intensity(attrs.intensity()));
+            }
+            if attrs.underline() != Underline::None {
+                sgr.push(Sgr::Underline(attrs.underline()));
+            }
+            if attrs.underline_color() != ColorAttribute::Default {
+                sgr.push(Sgr::UnderlineColor(color_spec(attrs.underline_color())));
+            }
+            if attrs.blink() != Blink::None {
+                sgr.push(Sgr::Blink(attrs.blink()));
+            }
+            if attrs.italic() {
+                sgr.push(Sgr::Italic(true));
+            }
+            if attrs.reverse() {
+                sgr.push(Sgr::Inverse(true));
+            }
+            if attrs.invisible() {
+                sgr.push(Sgr::Invisible(true));
+            }
+            if attrs.strikethrough() {
+                sgr.push(Sgr::StrikeThrough(true));
+            }
+            if attrs.overline() {
+                sgr.push(Sgr::Overline(true));
+            }
+            if attrs.foreground() != ColorAttribute::Default {
+                sgr.push(Sgr::Foreground(color_spec(attrs.foreground())));
+            }
+            if attrs.background() != ColorAttribute::Default {
+                sgr.push(Sgr::Background(color_spec(attrs.background())));
+            }
+            for s in sgr {
+                write!(out, "{}", CSI::Sgr(s)).ok();
+            }
+            *last_attr = attrs.clone();
+        }
+        out.push_str(cell.str());
+    }
+}
+
 fn run_cli(config: config::ConfigHandle, cli: CliCommand) -> anyhow::Result<()> {
     let executor = promise::spawn::SimpleExecutor::new();
     promise::spawn::spawn(async move {
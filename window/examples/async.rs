@@ -72,6 +72,7 @@ impl MyWindow {
             }
             WindowEvent::AppearanceChanged(_)
             | WindowEvent::Notification(_)
+            | WindowEvent::DroppedFiles(_)
             | WindowEvent::FocusChanged(_) => {}
         }
     }
@@ -2,6 +2,7 @@ use async_trait::async_trait;
 use bitflags::bitflags;
 use promise::Future;
 use std::any::Any;
+use std::path::PathBuf;
 use std::rc::Rc;
 use thiserror::Error;
 pub mod bitmaps;
@@ -150,6 +151,10 @@ pub enum WindowEvent {
 
     AppearanceChanged(Appearance),
 
+    /// Called when files are dragged from another application
+    /// and dropped onto the window
+    DroppedFiles(Vec<PathBuf>),
+
     Notification(Box<dyn Any + Send + Sync>),
 }
 
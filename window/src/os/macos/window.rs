@@ -471,6 +471,13 @@ impl Window {
             window.setContentView_(*view);
             window.setDelegate_(*view);
 
+            // Allow files to be dropped onto the window
+            let () = msg_send![
+                *view,
+                registerForDraggedTypes:
+                    NSArray::arrayWithObject(nil, appkit::NSFilenamesPboardType)
+            ];
+
             let frame = NSView::frame(*view);
             let backing_frame = NSView::convertRectToBacking(*view, frame);
             let width = backing_frame.size.width;
@@ -1218,6 +1225,9 @@ impl Inner {
     }
 }
 
+/// NSDragOperationCopy from NSDragging.h
+const NS_DRAG_OPERATION_COPY: NSUInteger = 1;
+
 const VIEW_CLS_NAME: &str = "WezTermWindowView";
 const WINDOW_CLS_NAME: &str = "WezTermWindow";
 
@@ -1559,6 +1569,35 @@ impl WindowView {
         }
     }
 
+    extern "C" fn dragging_entered(_this: &mut Object, _sel: Sel, _sender: id) -> NSUInteger {
+        // We only registered for file names, so we can always copy them
+        NS_DRAG_OPERATION_COPY
+    }
+
+    extern "C" fn perform_drag_operation(this: &mut Object, _sel: Sel, sender: id) -> BOOL {
+        let paths = unsafe {
+            let pasteboard: id = msg_send![sender, draggingPasteboard];
+            let filenames: id =
+                msg_send![pasteboard, propertyListForType: appkit::NSFilenamesPboardType];
+            if filenames.is_null() {
+                return NO;
+            }
+            (0..filenames.count())
+                .map(|idx| std::path::PathBuf::from(nsstring_to_str(filenames.objectAtIndex(idx))))
+                .collect::<Vec<_>>()
+        };
+
+        if let Some(this) = Self::get_this(this) {
+            this.inner
+                .borrow_mut()
+                .events
+                .dispatch(WindowEvent::DroppedFiles(paths));
+            YES
+        } else {
+            NO
+        }
+    }
+
     // Switch the coordinate system to have 0,0 in the top left
     extern "C" fn is_flipped(_this: &Object, _sel: Sel) -> BOOL {
         YES
@@ -2177,6 +2216,15 @@ impl WindowView {
                 Self::accepts_first_mouse as extern "C" fn(&mut Object, Sel, id) -> BOOL,
             );
 
+            cls.add_method(
+                sel!(draggingEntered:),
+                Self::dragging_entered as extern "C" fn(&mut Object, Sel, id) -> NSUInteger,
+            );
+            cls.add_method(
+                sel!(performDragOperation:),
+                Self::perform_drag_operation as extern "C" fn(&mut Object, Sel, id) -> BOOL,
+            );
+
             cls.add_method(
                 sel!(viewDidChangeEffectiveAppearance),
                 Self::view_did_change_effective_appearance as extern "C" fn(&mut Object, Sel),
@@ -3,6 +3,7 @@ pub mod windows;
 #[cfg(windows)]
 pub use windows::*;
 
+mod uri_list;
 #[cfg(feature = "wayland")]
 pub mod wayland;
 pub mod x11;
@@ -0,0 +1,54 @@
+#![cfg(all(unix, not(target_os = "macos")))]
+use std::path::PathBuf;
+
+/// Parses the `text/uri-list` data that is offered by the source
+/// of a drag and drop operation, returning the local file paths.
+/// Lines are separated by CRLF and those beginning with `#` are
+/// comments; anything that isn't a local `file:` URL is ignored.
+pub(crate) fn parse_uri_list(data: &str) -> Vec<PathBuf> {
+    data.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| match url::Url::parse(line) {
+            Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+            Ok(_) => {
+                log::debug!("ignoring dropped non-file URL {}", line);
+                None
+            }
+            Err(err) => {
+                log::debug!("ignoring invalid dropped URL {}: {}", line, err);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_file_urls() {
+        let data = "# dragged from the file manager\r\n\
+                    file:///home/user/notes.txt\r\n\
+                    file:///home/user/with%20space.png\r\n\
+                    \r\n\
+                    https://example.com/index.html\r\n\
+                    not a url\r\n\
+                    file:///tmp/dir\n";
+        assert_eq!(
+            parse_uri_list(data),
+            vec![
+                PathBuf::from("/home/user/notes.txt"),
+                PathBuf::from("/home/user/with space.png"),
+                PathBuf::from("/tmp/dir"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert!(parse_uri_list("").is_empty());
+        assert!(parse_uri_list("# just a comment\r\n").is_empty());
+    }
+}
@@ -1,7 +1,10 @@
 use super::copy_and_paste::*;
+use super::window::read_pipe_with_timeout;
 use crate::os::wayland::connection::WaylandConnection;
+use filedescriptor::Pipe;
 use smithay_client_toolkit as toolkit;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::os::unix::io::AsRawFd;
 use std::sync::{Arc, Mutex};
 use toolkit::reexports::client::protocol::wl_data_device::{
     Event as DataDeviceEvent, WlDataDevice,
@@ -11,17 +14,26 @@ use toolkit::reexports::client::protocol::wl_pointer::{Axis, ButtonState, Event
 use toolkit::reexports::client::protocol::wl_surface::WlSurface;
 use toolkit::seat::pointer::{ThemeManager, ThemeSpec, ThemedPointer};
 use wayland_client::protocol::wl_compositor::WlCompositor;
-use wayland_client::protocol::wl_data_device_manager::WlDataDeviceManager;
+use wayland_client::protocol::wl_data_device_manager::{DndAction, WlDataDeviceManager};
 use wayland_client::protocol::wl_seat::WlSeat;
 use wayland_client::protocol::wl_shm::WlShm;
 use wayland_client::{Attached, Main};
 use wezterm_input_types::*;
 
+/// A drag and drop operation that is hovering over one of our windows
+struct DragOffer {
+    offer: WlDataOffer,
+    window_id: usize,
+}
+
 #[derive(Default)]
 struct Inner {
     active_surface_id: u32,
     surface_to_pending: HashMap<u32, Arc<Mutex<PendingMouse>>>,
     serial: u32,
+    /// The ids of the data offers that include text/uri-list
+    uri_list_offers: HashSet<u32>,
+    drag_offer: Option<DragOffer>,
 }
 
 impl Inner {
@@ -52,6 +64,11 @@ impl Inner {
     }
 
     fn route_data_offer(&mut self, event: DataOfferEvent, offer: WlDataOffer) {
+        if let DataOfferEvent::Offer { mime_type } = &event {
+            if mime_type == URI_LIST_MIME_TYPE {
+                self.uri_list_offers.insert(offer.as_ref().id());
+            }
+        }
         if let Some(copy_and_paste) = self.resolve_copy_and_paste() {
             copy_and_paste
                 .lock()
@@ -71,12 +88,50 @@ impl Inner {
                     }
                 });
             }
-            DataDeviceEvent::Enter { .. }
-            | DataDeviceEvent::Leave { .. }
-            | DataDeviceEvent::Motion { .. }
-            | DataDeviceEvent::Drop => {}
+            DataDeviceEvent::Enter {
+                serial,
+                surface,
+                id,
+                ..
+            } => {
+                self.drag_offer.take();
+                // The offer's mime types are announced immediately
+                // before it is entered, so we only need to keep
+                // track of the most recent offers
+                let uri_list_offers = std::mem::take(&mut self.uri_list_offers);
+                if let Some(offer) = id {
+                    let window_id = self
+                        .surface_to_pending
+                        .get(&surface.as_ref().id())
+                        .map(|pending| pending.lock().unwrap().window_id);
+                    match window_id {
+                        Some(window_id) if uri_list_offers.contains(&offer.as_ref().id()) => {
+                            offer.accept(serial, Some(URI_LIST_MIME_TYPE.to_string()));
+                            offer.set_actions(DndAction::Copy, DndAction::Copy);
+                            self.drag_offer.replace(DragOffer { offer, window_id });
+                        }
+                        _ => {
+                            offer.accept(serial, None);
+                        }
+                    }
+                }
+            }
+            DataDeviceEvent::Leave => {
+                if let Some(drag) = self.drag_offer.take() {
+                    drag.offer.destroy();
+                }
+            }
+            DataDeviceEvent::Motion { .. } => {}
+            DataDeviceEvent::Drop => {
+                if let Some(drag) = self.drag_offer.take() {
+                    if let Err(err) = receive_dropped_files(drag) {
+                        log::error!("while receiving dropped files: {:#}", err);
+                    }
+                }
+            }
 
             DataDeviceEvent::Selection { id } => {
+                self.uri_list_offers.clear();
                 if let Some(offer) = id {
                     if let Some(copy_and_paste) = self.resolve_copy_and_paste() {
                         copy_and_paste.lock().unwrap().confirm_selection(offer);
@@ -88,6 +143,37 @@ impl Inner {
     }
 }
 
+/// Reads the text/uri-list from the source of the drag in a
+/// background thread, then dispatches the dropped files to the window
+fn receive_dropped_files(drag: DragOffer) -> anyhow::Result<()> {
+    let pipe = Pipe::new().map_err(anyhow::Error::msg)?;
+    drag.offer
+        .receive(URI_LIST_MIME_TYPE.to_string(), pipe.write.as_raw_fd());
+    // Close our copy of the write end, so that we see EOF when
+    // the source has finished writing
+    drop(pipe.write);
+
+    let DragOffer { offer, window_id } = drag;
+    std::thread::spawn(move || {
+        let result = read_pipe_with_timeout(pipe.read);
+        offer.finish();
+        offer.destroy();
+        match result {
+            Ok(data) => {
+                let paths = crate::os::uri_list::parse_uri_list(&data);
+                if !paths.is_empty() {
+                    WaylandConnection::with_window_inner(window_id, move |inner| {
+                        inner.dispatch_dropped_files(paths);
+                        Ok(())
+                    });
+                }
+            }
+            Err(err) => log::error!("while reading dropped files: {:#}", err),
+        }
+    });
+    Ok(())
+}
+
 pub struct PointerDispatcher {
     inner: Arc<Mutex<Inner>>,
     pub(crate) data_device: Main<WlDataDevice>,
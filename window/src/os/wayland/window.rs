@@ -443,6 +443,10 @@ impl WaylandWindowInner {
         self.events.dispatch(WindowEvent::FocusChanged(focused));
     }
 
+    pub(crate) fn dispatch_dropped_files(&mut self, paths: Vec<std::path::PathBuf>) {
+        self.events.dispatch(WindowEvent::DroppedFiles(paths));
+    }
+
     pub(crate) fn dispatch_pending_mouse(&mut self) {
         // Dancing around the borrow checker and the call to self.refresh_frame()
         let pending_mouse = Arc::clone(&self.pending_mouse);
@@ -938,7 +942,7 @@ fn write_pipe_with_timeout(mut file: FileDescriptor, data: &[u8]) -> anyhow::Res
     Ok(())
 }
 
-fn read_pipe_with_timeout(mut file: FileDescriptor) -> anyhow::Result<String> {
+pub(crate) fn read_pipe_with_timeout(mut file: FileDescriptor) -> anyhow::Result<String> {
     let mut result = Vec::new();
 
     file.set_non_blocking(true)?;
@@ -355,7 +355,8 @@ impl Window {
         let name = wide_string(name);
         let hwnd = unsafe {
             CreateWindowExW(
-                0,
+                // Allow files to be dropped onto the window
+                WS_EX_ACCEPTFILES,
                 class_name.as_ptr(),
                 name.as_ptr(),
                 style,
@@ -993,6 +994,30 @@ unsafe fn wm_kill_focus(
     None
 }
 
+unsafe fn wm_dropfiles(hwnd: HWND, _msg: UINT, wparam: WPARAM, _lparam: LPARAM) -> Option<LRESULT> {
+    use winapi::um::shellapi::{DragFinish, DragQueryFileW, HDROP};
+
+    let hdrop = wparam as HDROP;
+    let count = DragQueryFileW(hdrop, 0xFFFF_FFFF, null_mut(), 0);
+    let mut paths = vec![];
+    for idx in 0..count {
+        // Query the length, then allow space for the terminating NUL
+        let len = DragQueryFileW(hdrop, idx, null_mut(), 0) as usize;
+        let mut buf = vec![0u16; len + 1];
+        let len = DragQueryFileW(hdrop, idx, buf.as_mut_ptr(), buf.len() as UINT) as usize;
+        paths.push(std::path::PathBuf::from(OsString::from_wide(&buf[..len])));
+    }
+    DragFinish(hdrop);
+
+    if let Some(inner) = rc_from_hwnd(hwnd) {
+        inner
+            .borrow_mut()
+            .events
+            .dispatch(WindowEvent::DroppedFiles(paths));
+    }
+    Some(0)
+}
+
 unsafe fn wm_paint(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) -> Option<LRESULT> {
     if let Some(inner) = rc_from_hwnd(hwnd) {
         let mut inner = inner.borrow_mut();
@@ -2004,6 +2029,7 @@ unsafe fn do_wnd_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
             mouse_button(hwnd, msg, wparam, lparam)
         }
         WM_ERASEBKGND => Some(1),
+        WM_DROPFILES => wm_dropfiles(hwnd, msg, wparam, lparam),
         WM_CLOSE => {
             if let Some(inner) = rc_from_hwnd(hwnd) {
                 let mut inner = inner.borrow_mut();
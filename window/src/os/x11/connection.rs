@@ -39,6 +39,17 @@ pub struct XConnection {
     pub atom_state_hidden: xcb::Atom,
     pub atom_state_fullscreen: xcb::Atom,
     pub atom_net_wm_state: xcb::Atom,
+    pub atom_xdnd_aware: xcb::Atom,
+    pub atom_xdnd_enter: xcb::Atom,
+    pub atom_xdnd_position: xcb::Atom,
+    pub atom_xdnd_status: xcb::Atom,
+    pub atom_xdnd_leave: xcb::Atom,
+    pub atom_xdnd_drop: xcb::Atom,
+    pub atom_xdnd_finished: xcb::Atom,
+    pub atom_xdnd_selection: xcb::Atom,
+    pub atom_xdnd_type_list: xcb::Atom,
+    pub atom_xdnd_action_copy: xcb::Atom,
+    pub atom_text_uri_list: xcb::Atom,
     keysyms: *mut xcb_key_symbols_t,
     pub(crate) xrm: RefCell<HashMap<String, String>>,
     pub(crate) windows: RefCell<HashMap<xcb::xproto::Window, Arc<Mutex<XWindowInner>>>>,
@@ -409,6 +420,39 @@ impl XConnection {
         let atom_net_wm_state = xcb::intern_atom(&conn, false, "_NET_WM_STATE")
             .get_reply()?
             .atom();
+        let atom_xdnd_aware = xcb::intern_atom(&conn, false, "XdndAware")
+            .get_reply()?
+            .atom();
+        let atom_xdnd_enter = xcb::intern_atom(&conn, false, "XdndEnter")
+            .get_reply()?
+            .atom();
+        let atom_xdnd_position = xcb::intern_atom(&conn, false, "XdndPosition")
+            .get_reply()?
+            .atom();
+        let atom_xdnd_status = xcb::intern_atom(&conn, false, "XdndStatus")
+            .get_reply()?
+            .atom();
+        let atom_xdnd_leave = xcb::intern_atom(&conn, false, "XdndLeave")
+            .get_reply()?
+            .atom();
+        let atom_xdnd_drop = xcb::intern_atom(&conn, false, "XdndDrop")
+            .get_reply()?
+            .atom();
+        let atom_xdnd_finished = xcb::intern_atom(&conn, false, "XdndFinished")
+            .get_reply()?
+            .atom();
+        let atom_xdnd_selection = xcb::intern_atom(&conn, false, "XdndSelection")
+            .get_reply()?
+            .atom();
+        let atom_xdnd_type_list = xcb::intern_atom(&conn, false, "XdndTypeList")
+            .get_reply()?
+            .atom();
+        let atom_xdnd_action_copy = xcb::intern_atom(&conn, false, "XdndActionCopy")
+            .get_reply()?
+            .atom();
+        let atom_text_uri_list = xcb::intern_atom(&conn, false, "text/uri-list")
+            .get_reply()?
+            .atom();
 
         let keysyms = unsafe { xcb_key_symbols_alloc((*conn).get_raw_conn()) };
 
@@ -502,6 +546,17 @@ impl XConnection {
             atom_state_hidden,
             atom_state_fullscreen,
             atom_net_wm_state,
+            atom_xdnd_aware,
+            atom_xdnd_enter,
+            atom_xdnd_position,
+            atom_xdnd_status,
+            atom_xdnd_leave,
+            atom_xdnd_drop,
+            atom_xdnd_finished,
+            atom_xdnd_selection,
+            atom_xdnd_type_list,
+            atom_xdnd_action_copy,
+            atom_text_uri_list,
             keysyms,
             keyboard,
             kbd_ev,
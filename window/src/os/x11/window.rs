@@ -54,6 +54,20 @@ impl CopyAndPaste {
     }
 }
 
+/// The version of the XDND protocol that we implement
+const XDND_VERSION: u32 = 5;
+
+/// State for an in-progress XDND drag and drop operation
+#[derive(Default)]
+struct DragAndDrop {
+    /// The window that is the source of the drag
+    source: Option<xcb::xproto::Window>,
+    /// The XDND version spoken by the source
+    version: u32,
+    /// Whether the source offers text/uri-list
+    has_uri_list: bool,
+}
+
 pub(crate) struct XWindowInner {
     window_id: xcb::xproto::Window,
     conn: Weak<XConnection>,
@@ -63,6 +77,7 @@ pub(crate) struct XWindowInner {
     dpi: f64,
     cursors: CursorInfo,
     copy_and_paste: CopyAndPaste,
+    drag_and_drop: DragAndDrop,
     config: ConfigHandle,
     appearance: Appearance,
     title: String,
@@ -351,8 +366,12 @@ impl XWindowInner {
             xcb::CLIENT_MESSAGE => {
                 let msg: &xcb::ClientMessageEvent = unsafe { xcb::cast_event(event) };
 
-                if msg.data().data32()[0] == conn.atom_delete() {
+                if msg.type_() == conn.atom_protocols
+                    && msg.data().data32()[0] == conn.atom_delete()
+                {
                     self.events.dispatch(WindowEvent::CloseRequested);
+                } else {
+                    self.xdnd_client_message(msg)?;
                 }
             }
             xcb::DESTROY_NOTIFY => {
@@ -579,6 +598,10 @@ impl XWindowInner {
             selection.property()
         );
 
+        if selection.selection() == conn.atom_xdnd_selection {
+            return self.xdnd_selection_notify(selection);
+        }
+
         if let Some(clipboard) = self.selection_atom_to_clipboard(selection.selection()) {
             if selection.property() != xcb::NONE {
                 match xcb_util::icccm::get_text_property(
@@ -606,6 +629,160 @@ impl XWindowInner {
         Ok(())
     }
 
+    /// Handles the client messages that make up the target side
+    /// of the XDND protocol.
+    /// <https://freedesktop.org/wiki/Specifications/XDND/>
+    fn xdnd_client_message(&mut self, msg: &xcb::ClientMessageEvent) -> anyhow::Result<()> {
+        let conn = self.conn();
+        let data = msg.data().data32();
+        let msg_type = msg.type_();
+
+        if msg_type == conn.atom_xdnd_enter {
+            let source = data[0];
+            let version = data[1] >> 24;
+            let types = if data[1] & 1 != 0 {
+                // More than 3 types are offered; the full list
+                // is in the XdndTypeList property of the source
+                xcb::xproto::get_property(
+                    &conn,
+                    false,
+                    source,
+                    conn.atom_xdnd_type_list,
+                    xcb::xproto::ATOM_ATOM,
+                    0,
+                    1024,
+                )
+                .get_reply()
+                .map(|reply| reply.value::<u32>().to_vec())
+                .unwrap_or_else(|_| vec![])
+            } else {
+                data[2..5].to_vec()
+            };
+            log::trace!(
+                "XdndEnter from {} version {} types {:?}",
+                source,
+                version,
+                types
+            );
+            self.drag_and_drop = DragAndDrop {
+                source: Some(source),
+                version,
+                has_uri_list: types.contains(&conn.atom_text_uri_list),
+            };
+        } else if msg_type == conn.atom_xdnd_position {
+            let source = data[0];
+            let accept =
+                self.drag_and_drop.source == Some(source) && self.drag_and_drop.has_uri_list;
+            let action = if accept {
+                conn.atom_xdnd_action_copy
+            } else {
+                xcb::NONE
+            };
+            self.xdnd_send(
+                source,
+                conn.atom_xdnd_status,
+                [self.window_id, accept as u32, 0, 0, action],
+            );
+        } else if msg_type == conn.atom_xdnd_leave {
+            self.drag_and_drop = DragAndDrop::default();
+        } else if msg_type == conn.atom_xdnd_drop {
+            let source = data[0];
+            if self.drag_and_drop.source == Some(source) && self.drag_and_drop.has_uri_list {
+                // Timestamps were only added in version 1
+                let time = if self.drag_and_drop.version >= 1 {
+                    data[2]
+                } else {
+                    xcb::CURRENT_TIME
+                };
+                xcb::convert_selection(
+                    &conn,
+                    self.window_id,
+                    conn.atom_xdnd_selection,
+                    conn.atom_text_uri_list,
+                    conn.atom_xsel_data,
+                    time,
+                );
+            } else {
+                self.xdnd_send(
+                    source,
+                    conn.atom_xdnd_finished,
+                    [self.window_id, 0, xcb::NONE, 0, 0],
+                );
+                self.drag_and_drop = DragAndDrop::default();
+            }
+        }
+        Ok(())
+    }
+
+    fn xdnd_send(&self, target: xcb::xproto::Window, msg_type: xcb::Atom, data: [u32; 5]) {
+        let conn = self.conn();
+        xcb::xproto::send_event(
+            &conn,
+            false,
+            target,
+            xcb::xproto::EVENT_MASK_NO_EVENT,
+            &xcb::xproto::ClientMessageEvent::new(
+                32,
+                target,
+                msg_type,
+                xcb::ClientMessageData::from_data32(data),
+            ),
+        );
+        conn.flush();
+    }
+
+    /// The dropped data has been transferred into our property
+    fn xdnd_selection_notify(
+        &mut self,
+        selection: &xcb::SelectionNotifyEvent,
+    ) -> anyhow::Result<()> {
+        let conn = self.conn();
+        let source = match self.drag_and_drop.source.take() {
+            Some(source) => source,
+            None => return Ok(()),
+        };
+
+        let mut paths = vec![];
+        if selection.property() != xcb::NONE {
+            let reply = xcb::xproto::get_property(
+                &conn,
+                true,
+                self.window_id,
+                selection.property(),
+                xcb::xproto::ATOM_ANY,
+                0,
+                u32::MAX / 4,
+            )
+            .get_reply()
+            .context("reading dropped text/uri-list")?;
+            let data = String::from_utf8_lossy(reply.value::<u8>());
+            paths = crate::os::uri_list::parse_uri_list(&data);
+        }
+
+        let accepted = !paths.is_empty();
+        self.xdnd_send(
+            source,
+            conn.atom_xdnd_finished,
+            [
+                self.window_id,
+                accepted as u32,
+                if accepted {
+                    conn.atom_xdnd_action_copy
+                } else {
+                    xcb::NONE
+                },
+                0,
+                0,
+            ],
+        );
+        self.drag_and_drop = DragAndDrop::default();
+
+        if accepted {
+            self.events.dispatch(WindowEvent::DroppedFiles(paths));
+        }
+        Ok(())
+    }
+
     fn get_window_state(&self) -> anyhow::Result<WindowState> {
         let conn = self.conn();
 
@@ -846,6 +1023,7 @@ impl XWindow {
                 height: height.try_into()?,
                 dpi: conn.default_dpi(),
                 copy_and_paste: CopyAndPaste::default(),
+                drag_and_drop: DragAndDrop::default(),
                 cursors: CursorInfo::new(&conn),
                 config: config.clone(),
                 has_focus: false,
@@ -871,6 +1049,17 @@ impl XWindow {
             &[conn.atom_delete],
         );
 
+        // Advertise that we accept files via drag and drop
+        xcb::change_property(
+            &*conn,
+            xcb::PROP_MODE_REPLACE as u8,
+            window_id,
+            conn.atom_xdnd_aware,
+            xcb::xproto::ATOM_ATOM,
+            32,
+            &[XDND_VERSION],
+        );
+
         window
             .lock()
             .unwrap()